@@ -127,13 +127,18 @@ impl Encoder for Bincode {
 
 pub struct CompressedBincode;
 
+impl CompressedBincode {
+    /// Compresses data that is already [`Bincode`] encoded
+    pub fn compress(encoded: &[u8]) -> Vec<u8> {
+        miniz_oxide::deflate::compress_to_vec_zlib(encoded, 1) // bigger level values take far too long and only compress a bit better (about 5%)
+    }
+}
+
 impl Encoder for CompressedBincode {
     const EXTENSION: &'static str = "zip";
 
     fn encode(x: &impl Serialize) -> Result<Vec<u8>> {
-        let encoded = &*Bincode::encode(x)?;
-        let compressed = miniz_oxide::deflate::compress_to_vec_zlib(encoded, 1); // bigger level values take far too long and only compress a bit better (about 5%)
-        Ok(compressed)
+        Ok(Self::compress(&Bincode::encode(x)?))
     }
 
     fn decode<T: DeserializeOwned>(x: &[u8]) -> Result<T> {
@@ -143,6 +148,62 @@ impl Encoder for CompressedBincode {
     }
 }
 
+/// Magic bytes at the start of a checksummed save, followed by the checksum
+const CHECKSUM_MAGIC: &[u8; 4] = b"EGCK";
+const CHECKSUM_HEADER_LEN: usize = CHECKSUM_MAGIC.len() + 4;
+
+/// Same as [`CompressedBincode`] with a checksum of the payload in front, to detect saves corrupted on disk.
+/// Payloads without the checksum header (older saves) are decoded unchecked.
+pub struct CheckedCompressedBincode;
+
+impl CheckedCompressedBincode {
+    /// CRC32 of the payload, which stays the same across builds and platforms
+    fn checksum(payload: &[u8]) -> u32 {
+        crc32fast::hash(payload)
+    }
+
+    /// Returns the payload if the checksum matches, or the whole input if there is no checksum
+    pub fn verify(x: &[u8]) -> Result<&[u8]> {
+        if x.len() < CHECKSUM_HEADER_LEN || &x[..CHECKSUM_MAGIC.len()] != CHECKSUM_MAGIC {
+            log::warn!("save has no checksum, loading it unchecked");
+            return Ok(x);
+        }
+        let mut expected = [0; 4];
+        expected.copy_from_slice(&x[CHECKSUM_MAGIC.len()..CHECKSUM_HEADER_LEN]);
+        let payload = &x[CHECKSUM_HEADER_LEN..];
+        if Self::checksum(payload) != u32::from_le_bytes(expected) {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                "corrupt save: checksum mismatch",
+            ));
+        }
+        Ok(payload)
+    }
+
+    /// Compresses and checksums data that is already [`Bincode`] encoded,
+    /// so that the expensive part of saving can be done away from the saved value
+    pub fn from_bincode(encoded: &[u8]) -> Vec<u8> {
+        let payload = CompressedBincode::compress(encoded);
+        let mut v = Vec::with_capacity(CHECKSUM_HEADER_LEN + payload.len());
+        v.extend_from_slice(CHECKSUM_MAGIC);
+        v.extend_from_slice(&Self::checksum(&payload).to_le_bytes());
+        v.extend_from_slice(&payload);
+        v
+    }
+}
+
+impl Encoder for CheckedCompressedBincode {
+    const EXTENSION: &'static str = "zip";
+
+    fn encode(x: &impl Serialize) -> Result<Vec<u8>> {
+        Ok(Self::from_bincode(&Bincode::encode(x)?))
+    }
+
+    fn decode<T: DeserializeOwned>(x: &[u8]) -> Result<T> {
+        CompressedBincode::decode(Self::verify(x)?)
+    }
+}
+
 pub struct JSON;
 
 impl Encoder for JSON {
@@ -193,3 +254,22 @@ pub fn load_raw(p: impl AsRef<Path>) -> Result<Vec<u8>> {
 pub fn load_string(p: impl AsRef<Path>) -> Result<String> {
     std::fs::read_to_string(p)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{CheckedCompressedBincode, CHECKSUM_HEADER_LEN, CHECKSUM_MAGIC};
+
+    #[test]
+    fn checksum_is_stable() {
+        // the standard CRC32 check value, saves written by another build must still verify
+        assert_eq!(
+            CheckedCompressedBincode::checksum(b"123456789"),
+            0xCBF4_3926
+        );
+
+        let save = CheckedCompressedBincode::from_bincode(&[1, 2, 3]);
+        assert_eq!(&save[..CHECKSUM_MAGIC.len()], CHECKSUM_MAGIC);
+        let payload = CheckedCompressedBincode::verify(&save).unwrap();
+        assert_eq!(payload, &save[CHECKSUM_HEADER_LEN..]);
+    }
+}
@@ -14,7 +14,7 @@ pub use multispritebatch::*;
 pub use spritebatch::*;
 pub use water::*;
 
-use geom::Matrix4;
+use geom::{Matrix4, Vec3};
 use std::sync::Arc;
 
 pub type IndexType = u32;
@@ -30,6 +30,43 @@ pub trait Drawable: Send + Sync {
         shadow_cascade: Option<&Matrix4>,
     ) {
     }
+
+    /// Transparent drawables are drawn after the opaque ones, sorted back-to-front
+    /// Only taken into account if the drawable also has a position
+    fn transparent(&self) -> bool {
+        false
+    }
+
+    /// Position used to sort drawables by distance to the camera
+    fn position(&self) -> Option<Vec3> {
+        None
+    }
+}
+
+/// Returns the indices of the drawables in the order they should be drawn.
+/// Opaque drawables come first, front-to-back to benefit from early-z, keeping push order for the ones without a position.
+/// Transparent drawables come last, back-to-front so that alpha blending is correct.
+/// Transparent drawables without a position are considered opaque.
+pub fn draw_order(objs: impl Iterator<Item = (bool, Option<Vec3>)>, cam_pos: Vec3) -> Vec<usize> {
+    let mut opaque = vec![];
+    let mut transparent = vec![];
+
+    for (i, (is_transparent, pos)) in objs.enumerate() {
+        let dist = pos.map(|p| p.distance2(cam_pos));
+        match (is_transparent, dist) {
+            (true, Some(d)) => transparent.push((i, d)),
+            (_, d) => opaque.push((i, d.unwrap_or(0.0))),
+        }
+    }
+
+    opaque.sort_by(|a, b| a.1.total_cmp(&b.1));
+    transparent.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    opaque
+        .into_iter()
+        .chain(transparent)
+        .map(|(i, _)| i)
+        .collect()
 }
 
 impl<T: ?Sized + Drawable> Drawable for Arc<T> {
@@ -47,6 +84,16 @@ impl<T: ?Sized + Drawable> Drawable for Arc<T> {
         let s: &T = self;
         s.draw_depth(gfx, rp, shadow_cascade);
     }
+
+    fn transparent(&self) -> bool {
+        let s: &T = self;
+        s.transparent()
+    }
+
+    fn position(&self) -> Option<Vec3> {
+        let s: &T = self;
+        s.position()
+    }
 }
 
 impl<T: Drawable> Drawable for Option<T> {
@@ -66,6 +113,14 @@ impl<T: Drawable> Drawable for Option<T> {
             s.draw_depth(gfx, rp, shadow_cascade);
         }
     }
+
+    fn transparent(&self) -> bool {
+        self.as_ref().map(|s| s.transparent()).unwrap_or(false)
+    }
+
+    fn position(&self) -> Option<Vec3> {
+        self.as_ref().and_then(|s| s.position())
+    }
 }
 
 impl<T: Drawable> Drawable for [T] {
@@ -122,3 +177,27 @@ impl<T: Drawable, U: Drawable> Drawable for (T, U) {
         self.1.draw_depth(gfx, rp, shadow_cascade);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::draw_order;
+    use geom::{vec3, Vec3};
+
+    #[test]
+    fn draw_order_sorts_by_distance() {
+        let cam = Vec3::ZERO;
+        let objs = [
+            (false, Some(vec3(10.0, 0.0, 0.0))),
+            (true, Some(vec3(5.0, 0.0, 0.0))),
+            (false, Some(vec3(2.0, 0.0, 0.0))),
+            (true, Some(vec3(0.0, 20.0, 0.0))),
+            (true, None),
+            (false, None),
+        ];
+
+        let order = draw_order(objs.into_iter(), cam);
+
+        // opaque front-to-back (no position first, in push order), then transparent back-to-front
+        assert_eq!(order, vec![4, 5, 2, 0, 3, 1]);
+    }
+}
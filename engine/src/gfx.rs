@@ -24,15 +24,17 @@ use crate::meshload::{load_mesh, LoadMeshError};
 use crate::passes::{BackgroundPipeline, Pbr};
 use crate::perf_counters::PerfCounters;
 use crate::{
-    bg_layout_litmesh, passes, CompiledModule, Drawable, IndexType, LampLights, Material,
-    MaterialID, MaterialMap, Mesh, MetallicRoughness, MipmapGenerator, PipelineKey, Pipelines,
-    Texture, TextureBuildError, TextureBuilder, Uniform, UvVertex, WaterPipeline, TL,
+    bg_layout_litmesh, draw_order, passes, CompiledModule, Drawable, IndexType, LampLights,
+    Material, MaterialID, MaterialMap, Mesh, MetallicRoughness, MipmapGenerator, PipelineKey,
+    Pipelines, Texture, TextureBuildError, TextureBuilder, Uniform, UvVertex, WaterPipeline, TL,
 };
 
 pub struct FBOs {
     pub(crate) depth: Texture,
     pub(crate) depth_bg: wgpu::BindGroup,
     pub(crate) color_msaa: TextureView,
+    /// Multisampled target of the ui pass, independent of the scene's msaa
+    pub(crate) ui_color_msaa: Option<TextureView>,
     pub(crate) ssao: Texture,
     pub(crate) fog: Texture,
     pub(crate) ui_blur: Texture,
@@ -57,6 +59,9 @@ pub struct GfxContext {
     pub tick: u64,
     pub(crate) pipelines: RwLock<Pipelines>,
     pub frustrum: InfiniteFrustrum,
+    /// Multiplies the screen coverage of meshes when picking their lod,
+    /// under 1 coarser lods are used closer to the camera and far meshes are culled sooner
+    pub lod_bias: f32,
     pub(crate) sun_params: [Uniform<RenderParams>; N_CASCADES],
     pub render_params: Uniform<RenderParams>,
     pub(crate) texture_cache_paths: FastMap<PathBuf, Arc<Texture>>,
@@ -68,6 +73,7 @@ pub struct GfxContext {
     pub(crate) mesh_errors: FastMap<PathBuf, LoadMeshError>,
 
     pub(crate) samples: u32,
+    pub(crate) ui_samples: u32,
     pub(crate) screen_uv_vertices: wgpu::Buffer,
     pub(crate) rect_indices: wgpu::Buffer,
     pub sun_shadowmap: Texture,
@@ -134,6 +140,7 @@ impl ShadowQuality {
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct GfxSettings {
     pub vsync: bool,
     pub fullscreen: bool,
@@ -146,6 +153,8 @@ pub struct GfxSettings {
     pub fog_shader_debug: bool,
     pub parallel_render: bool,
     pub msaa: bool,
+    /// Anti-aliasing of the ui, works even if the scene msaa is off
+    pub ui_msaa: bool,
 }
 
 impl Default for GfxSettings {
@@ -162,6 +171,7 @@ impl Default for GfxSettings {
             fog_shader_debug: false,
             parallel_render: false,
             msaa: false,
+            ui_msaa: true,
         }
     }
 }
@@ -201,6 +211,24 @@ pub struct RenderParams {
     pub terraforming_mode_radius: f32,
 }
 
+/// Size of the ui target for a surface of the given size.
+/// The surface can be empty when the window is minimized but textures can't.
+pub(crate) fn ui_target_size(width: u32, height: u32) -> (u32, u32) {
+    (width.max(1), height.max(1))
+}
+
+#[cfg(test)]
+#[test]
+fn test_ui_target_follows_resize() {
+    let mut size = ui_target_size(1280, 720);
+    assert_eq!(size, (1280, 720));
+    for resize in [(1920, 1080), (640, 480), (0, 0), (800, 0)] {
+        size = ui_target_size(resize.0, resize.1);
+        assert_eq!(size, (resize.0.max(1), resize.1.max(1)));
+    }
+    assert_eq!(size, (800, 1));
+}
+
 #[cfg(test)]
 #[test]
 fn test_renderparam_size() {
@@ -325,7 +353,8 @@ impl GfxContext {
         };
         //        let samples = if cfg!(target_arch = "wasm32") { 1 } else { 4 };
         let samples = 1;
-        let fbos = Self::create_textures(&device, &sc_desc, samples);
+        let ui_samples = 1;
+        let fbos = Self::create_textures(&device, &sc_desc, samples, ui_samples);
         surface.configure(&device, &sc_desc);
 
         let screen_uv_vertices = device.create_buffer_init(&BufferInitDescriptor {
@@ -429,6 +458,7 @@ impl GfxContext {
             mesh_cache: Default::default(),
             mesh_errors: Default::default(),
             samples,
+            ui_samples,
             screen_uv_vertices,
             rect_indices,
             simplelit_bg: Uniform::new([0.0f32; 4], &device).bg, // bogus
@@ -443,6 +473,7 @@ impl GfxContext {
             defines: Default::default(),
             defines_changed: false,
             settings: None,
+            lod_bias: 1.0,
             perf: Default::default(),
             mipmap_gen,
         };
@@ -625,6 +656,10 @@ impl GfxContext {
             true => 4,
             false => 1,
         };
+        let ui_samples = match settings.ui_msaa {
+            true => 4,
+            false => 1,
+        };
 
         self.set_define_flag("FOG", settings.fog);
         self.set_define_flag("SSAO", settings.ssao);
@@ -636,9 +671,14 @@ impl GfxContext {
 
         if self.samples != samples {
             self.samples = samples;
+            self.ui_samples = ui_samples;
             self.pipelines.write().unwrap().invalidate_all();
-            self.fbos = Self::create_textures(&self.device, &self.sc_desc, samples);
+            self.fbos = Self::create_textures(&self.device, &self.sc_desc, samples, ui_samples);
             self.update_simplelit_bg();
+        } else if self.ui_samples != ui_samples {
+            self.ui_samples = ui_samples;
+            self.fbos.ui_color_msaa =
+                Self::create_ui_target(&self.device, &self.sc_desc, ui_samples);
         }
 
         self.settings = Some(settings);
@@ -847,8 +887,15 @@ impl GfxContext {
         });
         render_pass.set_bind_group(0, &self.render_params.bg, &[]);
 
-        for obj in objsref.iter() {
-            obj.draw(self, &mut render_pass);
+        let order = draw_order(
+            objsref
+                .iter()
+                .map(|obj| (obj.transparent(), obj.position())),
+            self.render_params.value().cam_pos,
+        );
+
+        for i in order {
+            objsref[i].draw(self, &mut render_pass);
         }
 
         drop(render_pass);
@@ -974,7 +1021,12 @@ impl GfxContext {
         self.tick += 1;
     }
 
-    pub fn create_textures(device: &Device, desc: &SurfaceConfiguration, samples: u32) -> FBOs {
+    pub fn create_textures(
+        device: &Device,
+        desc: &SurfaceConfiguration,
+        samples: u32,
+        ui_samples: u32,
+    ) -> FBOs {
         let size = (desc.width, desc.height);
         let ssao = Texture::create_fbo(
             device,
@@ -1014,6 +1066,7 @@ impl GfxContext {
             } else {
                 ssao.mip_view(0) // bogus
             },
+            ui_color_msaa: Self::create_ui_target(device, desc, ui_samples),
             ssao,
             fog,
             ui_blur,
@@ -1021,13 +1074,28 @@ impl GfxContext {
         }
     }
 
+    /// Creates the multisampled ui target, None if the ui is rendered directly to the frame
+    fn create_ui_target(
+        device: &Device,
+        desc: &SurfaceConfiguration,
+        ui_samples: u32,
+    ) -> Option<TextureView> {
+        if ui_samples <= 1 {
+            return None;
+        }
+        let mut desc = desc.clone();
+        (desc.width, desc.height) = ui_target_size(desc.width, desc.height);
+        Some(Texture::create_color_msaa(device, &desc, ui_samples))
+    }
+
     pub fn resize(&mut self, size: (u32, u32, f64)) {
         self.size = size;
         self.sc_desc.width = self.size.0;
         self.sc_desc.height = self.size.1;
 
         self.surface.configure(&self.device, &self.sc_desc);
-        self.fbos = Self::create_textures(&self.device, &self.sc_desc, self.samples);
+        self.fbos =
+            Self::create_textures(&self.device, &self.sc_desc, self.samples, self.ui_samples);
         self.update_simplelit_bg();
     }
 
@@ -266,7 +266,7 @@ impl MeshLod {
 
     #[inline]
     pub fn passes_culling(&self, gfx: &GfxContext) -> bool {
-        let screen_area = crate::screen_coverage(gfx, self.bounding_sphere);
+        let screen_area = crate::screen_coverage(gfx, self.bounding_sphere) * gfx.lod_bias;
         screen_area >= self.screen_coverage
     }
 }
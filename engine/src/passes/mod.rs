@@ -3,9 +3,11 @@ mod blur;
 mod fog;
 mod pbr;
 mod ssao;
+mod ui_msaa;
 
 pub use background::*;
 pub use blur::*;
 pub use fog::*;
 pub use pbr::*;
 pub use ssao::*;
+pub use ui_msaa::*;
@@ -0,0 +1,241 @@
+use wgpu::{
+    CommandEncoder, Device, FragmentState, PipelineLayoutDescriptor, PrimitiveState,
+    RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor,
+    Sampler, ShaderModule, TextureFormat, TextureView, VertexState,
+};
+
+use crate::{CompiledModule, GfxContext, PipelineKey, Texture, TL};
+
+/// The multisampled ui target is resolved into the frame after the ui is drawn, which overwrites
+/// the whole frame. So the scene is first drawn into the ui target for the ui to be drawn over it.
+pub fn load_frame_into_ui_target(gfx: &GfxContext, enc: &mut CommandEncoder, frame: &TextureView) {
+    let Some(ref target) = gfx.fbos.ui_color_msaa else {
+        return;
+    };
+    profiling::scope!("ui msaa load");
+
+    let pipe = gfx.get_pipeline(UIMsaaLoadPipeline {
+        samples: gfx.ui_samples,
+    });
+    copy_pass(&gfx.device, enc, pipe, &gfx.linear_sampler, frame, target);
+}
+
+fn copy_pass(
+    device: &Device,
+    enc: &mut CommandEncoder,
+    pipe: &RenderPipeline,
+    sampler: &Sampler,
+    src_view: &TextureView,
+    dst_view: &TextureView,
+) {
+    let bg = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("ui msaa load bindgroup"),
+        layout: &pipe.get_bind_group_layout(0),
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(src_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+    });
+
+    let mut pass = enc.begin_render_pass(&RenderPassDescriptor {
+        label: Some("ui msaa load pass"),
+        color_attachments: &[Some(RenderPassColorAttachment {
+            view: dst_view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    });
+
+    pass.set_pipeline(pipe);
+    pass.set_bind_group(0, &bg, &[]);
+    pass.draw(0..3, 0..1);
+}
+
+fn copy_pipeline(
+    device: &Device,
+    module: &ShaderModule,
+    format: TextureFormat,
+    samples: u32,
+) -> RenderPipeline {
+    let l = Texture::bindgroup_layout(device, [TL::Float]);
+
+    let render_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: Some("ui msaa load"),
+        bind_group_layouts: &[&l],
+        push_constant_ranges: &[],
+    });
+
+    let color_states = [Some(wgpu::ColorTargetState {
+        format,
+        blend: None,
+        write_mask: wgpu::ColorWrites::ALL,
+    })];
+
+    device.create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some("ui msaa load pipeline"),
+        layout: Some(&render_pipeline_layout),
+        vertex: VertexState {
+            module,
+            entry_point: "vert",
+            compilation_options: Default::default(),
+            buffers: &[],
+        },
+        fragment: Some(FragmentState {
+            module,
+            entry_point: "frag",
+            compilation_options: Default::default(),
+            targets: &color_states,
+        }),
+        primitive: PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState {
+            count: samples,
+            ..Default::default()
+        },
+        multiview: None,
+    })
+}
+
+#[derive(Copy, Clone, Debug, Hash)]
+pub struct UIMsaaLoadPipeline {
+    samples: u32,
+}
+
+impl PipelineKey for UIMsaaLoadPipeline {
+    fn build(
+        &self,
+        gfx: &GfxContext,
+        mut mk_module: impl FnMut(&str, &[&str]) -> CompiledModule,
+    ) -> RenderPipeline {
+        let module = &mk_module("mipmap", &[]);
+        copy_pipeline(&gfx.device, module, gfx.sc_desc.format, self.samples)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{copy_pass, copy_pipeline};
+    use wgpu::{Extent3d, TextureFormat, TextureUsages, TextureViewDescriptor};
+
+    const SIZE: u32 = 64;
+    const FORMAT: TextureFormat = TextureFormat::Rgba8Unorm;
+
+    /// Renders a red scene, then an empty ui pass through the msaa target like the yakui pass does,
+    /// and reads the frame back. Skipped when there is no gpu.
+    #[test]
+    fn scene_survives_the_msaa_ui_pass() {
+        let instance = wgpu::Instance::default();
+        let Some(adapter) = beul::execute(instance.request_adapter(&Default::default())) else {
+            return;
+        };
+        let (device, queue) =
+            beul::execute(adapter.request_device(&Default::default(), None)).unwrap();
+
+        let texture = |usage: TextureUsages, samples: u32| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size: Extent3d {
+                    width: SIZE,
+                    height: SIZE,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: samples,
+                dimension: wgpu::TextureDimension::D2,
+                format: FORMAT,
+                usage,
+                view_formats: &[],
+            })
+        };
+        let frame = texture(
+            TextureUsages::RENDER_ATTACHMENT
+                | TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_SRC,
+            1,
+        );
+        let frame_view = frame.create_view(&TextureViewDescriptor::default());
+        let msaa = texture(TextureUsages::RENDER_ATTACHMENT, 4)
+            .create_view(&TextureViewDescriptor::default());
+
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(
+                include_str!("../../../assets/shaders/mipmap.wgsl").into(),
+            ),
+        });
+        let pipe = copy_pipeline(&device, &module, FORMAT, 4);
+        let sampler = device.create_sampler(&Default::default());
+
+        let mut enc = device.create_command_encoder(&Default::default());
+        // the scene
+        enc.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &frame_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::RED),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            ..Default::default()
+        });
+
+        copy_pass(&device, &mut enc, &pipe, &sampler, &frame_view, &msaa);
+
+        // the ui, nothing drawn
+        enc.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &msaa,
+                resolve_target: Some(&frame_view),
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            ..Default::default()
+        });
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (SIZE * SIZE * 4) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        enc.copy_texture_to_buffer(
+            frame.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(SIZE * 4),
+                    rows_per_image: None,
+                },
+            },
+            frame.size(),
+        );
+        queue.submit(Some(enc.finish()));
+
+        buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, |r| r.unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        let pixels = buffer.slice(..).get_mapped_range();
+        for pixel in pixels.chunks_exact(4) {
+            assert_eq!(pixel, [255, 0, 0, 255]);
+        }
+    }
+}
@@ -110,3 +110,37 @@ impl PerfCounters {
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::PerfCounters;
+
+    #[test]
+    fn counters_accumulate_and_reset() {
+        let mut perf = PerfCounters::new();
+
+        // a frame drawing two meshes of 12 and 36 indices, plus their shadows
+        for n_indices in [12u32, 36] {
+            perf.drawcall(n_indices / 3);
+            perf.depth_drawcall(n_indices / 3, true);
+        }
+        perf.heightmap_drawcall(100);
+
+        let s = perf.as_static();
+        assert_eq!(s.total_drawcalls, 2);
+        assert_eq!(s.total_triangles, 16);
+        assert_eq!(s.shadows_drawcalls, 2);
+        assert_eq!(s.shadows_triangles, 16);
+        assert_eq!(s.depth_drawcalls, 0);
+        assert_eq!(s.heightmap_triangles, 100);
+
+        // the next frame starts from zero
+        perf.clear();
+        perf.drawcall(4);
+        let s = perf.as_static();
+        assert_eq!(s.total_drawcalls, 1);
+        assert_eq!(s.total_triangles, 4);
+        assert_eq!(s.shadows_drawcalls, 0);
+        assert_eq!(s.heightmap_triangles, 0);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{GfxContext, GuiRenderContext, Texture};
+use crate::{passes, GfxContext, GuiRenderContext, Texture};
 use std::path::PathBuf;
 use std::sync::Arc;
 use wgpu::{TextureFormat, TextureViewDescriptor};
@@ -82,11 +82,12 @@ impl YakuiWrapper {
             self.yakui.finish();
         }
 
-        let surface_info = if gfx.gfx.samples > 1 {
+        let surface_info = if let Some(ref ui_msaa) = gfx.gfx.fbos.ui_color_msaa {
+            passes::load_frame_into_ui_target(gfx.gfx, gfx.encoder, gfx.view);
             yakui_wgpu::SurfaceInfo {
                 format: self.format,
-                sample_count: gfx.gfx.samples,
-                color_attachment: &gfx.gfx.fbos.color_msaa,
+                sample_count: gfx.gfx.ui_samples,
+                color_attachment: ui_msaa,
                 resolve_target: Some(gfx.view),
             }
         } else {
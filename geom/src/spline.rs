@@ -1,9 +1,20 @@
 use super::Vec2;
-use crate::polyline::PolyLine;
 use crate::AABB;
 use ordered_float::OrderedFloat;
 use serde::{Deserialize, Serialize};
 
+/// Nodes and weights of the 5 point Gauss-Legendre quadrature on [-1; 1]
+const GAUSS_LEGENDRE_5: [(f32, f32); 5] = [
+    (0.0, 0.568_888_9),
+    (-0.538_469_3, 0.478_628_67),
+    (0.538_469_3, 0.478_628_67),
+    (-0.906_179_8, 0.236_926_88),
+    (0.906_179_8, 0.236_926_88),
+];
+
+/// Number of pieces the spline is cut into for arc length integration
+const ARC_LENGTH_PIECES: usize = 16;
+
 #[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct Spline {
     pub from: Vec2,
@@ -270,8 +281,69 @@ impl Spline {
         })
     }
 
-    pub fn length(&self, detail: f32) -> f32 {
-        PolyLine::new(self.smart_points(detail, 0.0, 1.0).collect()).length()
+    /// Arc length of the spline, numerically integrated
+    pub fn length(&self) -> f32 {
+        (0..ARC_LENGTH_PIECES)
+            .map(|i| {
+                let (t0, t1) = Self::piece(i);
+                self.arc_length(t0, t1)
+            })
+            .sum()
+    }
+
+    /// Returns the point at the given arc length from the start.
+    /// Distances outside of [0; length] are clamped to the endpoints.
+    pub fn point_at_distance(&self, d: f32) -> Vec2 {
+        self.get(self.t_at_distance(d))
+    }
+
+    /// Returns the parameter t at the given arc length from the start, clamped to [0; 1]
+    pub fn t_at_distance(&self, mut d: f32) -> f32 {
+        if d <= 0.0 {
+            return 0.0;
+        }
+        for i in 0..ARC_LENGTH_PIECES {
+            let (t0, t1) = Self::piece(i);
+            let l = self.arc_length(t0, t1);
+            if d > l {
+                d -= l;
+                continue;
+            }
+            if l <= f32::EPSILON {
+                return t0;
+            }
+
+            // Newton's method on the arc length, starting from the linear guess
+            let mut t = t0 + (t1 - t0) * d / l;
+            for _ in 0..8 {
+                let err = self.arc_length(t0, t) - d;
+                let speed = self.derivative(t).mag();
+                if err.abs() < 1e-4 || speed <= f32::EPSILON {
+                    break;
+                }
+                t = (t - err / speed).clamp(t0, t1);
+            }
+            return t;
+        }
+        1.0
+    }
+
+    /// Arc length between t0 and t1 using Gauss-Legendre quadrature
+    fn arc_length(&self, t0: f32, t1: f32) -> f32 {
+        let half = (t1 - t0) * 0.5;
+        let mid = (t1 + t0) * 0.5;
+        GAUSS_LEGENDRE_5
+            .iter()
+            .map(|&(x, w)| w * self.derivative(mid + half * x).mag())
+            .sum::<f32>()
+            * half
+    }
+
+    fn piece(i: usize) -> (f32, f32) {
+        (
+            i as f32 / ARC_LENGTH_PIECES as f32,
+            (i + 1) as f32 / ARC_LENGTH_PIECES as f32,
+        )
     }
 
     #[inline]
@@ -323,3 +395,39 @@ impl Iterator for OwnedSmartPoints {
         Some(self.t)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{vec2, PolyLine, Spline};
+
+    fn test_spline() -> Spline {
+        Spline {
+            from: vec2(0.0, 0.0),
+            to: vec2(100.0, 50.0),
+            from_derivative: vec2(60.0, 0.0),
+            to_derivative: vec2(0.0, 60.0),
+        }
+    }
+
+    #[test]
+    fn test_length() {
+        let s = test_spline();
+        let sampled = PolyLine::new(s.points(10000).collect()).length();
+        assert!((s.length() - sampled).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_point_at_distance() {
+        let s = test_spline();
+        let l = s.length();
+
+        assert!(s.point_at_distance(0.0).is_close(s.from, 0.001));
+        assert!(s.point_at_distance(l).is_close(s.to, 0.01));
+        assert!(s.point_at_distance(l + 100.0).is_close(s.to, 0.001));
+        assert!(s.point_at_distance(-5.0).is_close(s.from, 0.001));
+
+        let t = s.t_at_distance(l * 0.3);
+        let sampled = PolyLine::new(s.smart_points(0.001, 0.0, t).collect()).length();
+        assert!((sampled - l * 0.3).abs() < 0.05);
+    }
+}
@@ -54,6 +54,15 @@ impl_slidable!(u8; 1.0,
                f32; 0.01, 
                f64; 0.01);
 
+/// What happened to a [`DragValue`] this frame
+#[derive(Debug, Default, Copy, Clone)]
+pub struct DragResponse {
+    /// The value was changed
+    pub changed: bool,
+    /// The value stopped being dragged
+    pub released: bool,
+}
+
 pub struct DragValue {
     min: Option<f64>,
     max: Option<f64>,
@@ -83,14 +92,19 @@ impl DragValue {
 
     /// Returns true if the value was changed.
     pub fn show<T: Draggable>(self, value: &mut T) -> bool {
-        let mut changed = false;
+        self.show_response(value).changed
+    }
+
+    /// Like [`DragValue::show`], also tells when the drag is released.
+    pub fn show_response<T: Draggable>(self, value: &mut T) -> DragResponse {
+        let mut resp = DragResponse::default();
         let step = self.step.unwrap_or(T::default_step());
 
         let mut l = List::column();
         l.cross_axis_alignment = CrossAxisAlignment::Center;
         l.main_axis_size = MainAxisSize::Min;
         l.show(|| {
-            let dragged = draggable_delta(|| {
+            let (dragged, released) = draggable_delta(|| {
                 RoundRect::new(2.0)
                     .outline(outline(), 2.0)
                     .color(secondary())
@@ -121,19 +135,22 @@ impl DragValue {
                     self.max.unwrap_or(T::DEFAULT_MAX),
                 ));
 
-                changed = true;
+                resp.changed = true;
             }
+            resp.released = released;
         });
 
-        changed
+        resp
     }
 }
 
-fn draggable_delta(children: impl FnOnce()) -> Option<Vec2> {
+/// Returns the delta dragged this frame, and whether the drag was released this frame
+fn draggable_delta(children: impl FnOnce()) -> (Option<Vec2>, bool) {
     let last_val_state = use_state(|| None);
     let Some(mut d) = draggable(children).dragging else {
+        let released = last_val_state.get().is_some();
         last_val_state.set(None);
-        return None;
+        return (None, released);
     };
 
     let last_val = last_val_state.get().unwrap_or(d.current);
@@ -147,7 +164,7 @@ fn draggable_delta(children: impl FnOnce()) -> Option<Vec2> {
         delta.y = 0.0;
     }
     last_val_state.set(Some(d.current));
-    Some(delta)
+    (Some(delta), false)
 }
 
 pub fn dragvalue() -> DragValue {
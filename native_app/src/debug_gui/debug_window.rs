@@ -3,7 +3,9 @@
 use crate::game_loop::Timings;
 use crate::gui::{GuiState, InspectedEntity};
 use crate::uiworld::UiWorld;
-use simulation::map_dynamic::ParkingManagement;
+use simulation::map_dynamic::{
+    LaneCongestion, LaneHeatmap, LanePollution, LaneSlowdown, ParkingManagement,
+};
 use simulation::transportation::TransportGrid;
 use simulation::{Simulation, TrainID};
 use std::time::{Duration, Instant};
@@ -43,10 +45,18 @@ impl Default for DebugObjs {
             (false, "Debug connectivity", debug_connectivity),
             (false, "Debug electricity", debug_electricity),
             (false, "Debug spatialmap", debug_spatialmap),
+            (
+                false,
+                "Debug spatialmap occupancy",
+                debug_spatialmap_occupancy,
+            ),
             (false, "Debug transport grid", debug_transport_grid),
             (false, "Debug lots", debug_lots),
             (false, "Debug road points", debug_road_points),
             (false, "Debug parking", debug_parking),
+            (false, "Debug congestion heatmap", debug_congestion_heatmap),
+            (false, "Debug slowdown heatmap", debug_slowdown_heatmap),
+            (false, "Debug pollution heatmap", debug_pollution_heatmap),
         ])
     }
 }
@@ -55,6 +65,7 @@ impl Default for DebugObjs {
 pub struct TestFieldProperties {
     size: u32,
     spacing: f32,
+    osm_path: String,
 }
 
 impl Default for TestFieldProperties {
@@ -62,6 +73,7 @@ impl Default for TestFieldProperties {
         Self {
             size: 10,
             spacing: 150.0,
+            osm_path: String::new(),
         }
     }
 }
@@ -255,6 +267,36 @@ fn debug(window: egui::Window<'_>, ui: &egui::Context, uiworld: &UiWorld, sim: &
                 state.spacing,
             );
         }
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut state.osm_path);
+            ui.label("OSM extract (.osm or .osm.pbf)");
+        });
+
+        if ui.small_button("load OSM extract").clicked() {
+            match std::fs::read(&state.osm_path) {
+                Ok(extract) => uiworld.commands().map_load_osm(extract),
+                Err(e) => log::error!("could not read {}: {}", state.osm_path, e),
+            }
+        }
+
+        #[cfg(feature = "trace")]
+        {
+            ui.separator();
+            if crate::trace::is_tracing() {
+                if ui.small_button("stop trace").clicked() {
+                    match crate::trace::stop_trace() {
+                        Ok(n) => log::info!("wrote {} trace events to trace.json", n),
+                        Err(e) => log::error!("could not write trace: {}", e),
+                    }
+                }
+            } else if ui.small_button("start trace").clicked()
+                && !crate::trace::start_trace("trace.json")
+            {
+                log::error!("could not start tracing");
+            }
+        }
 
         ui.label(format!("{} pedestrians", sim.world().humans.len()));
         ui.label(format!("{} vehicles", sim.world().vehicles.len()));
@@ -486,6 +528,45 @@ pub fn debug_obb(tess: &mut Tesselator<true, sim: &Simulation, uiworld: &UiWorld
 }
 */
 
+/// Draws every lane colored by the heatmap's value, lanes without data are gray
+pub fn draw_lane_heatmap(tess: &mut Tesselator, sim: &Simulation, heatmap: &impl LaneHeatmap) {
+    let values = heatmap.values(sim);
+    let map = sim.map();
+
+    for (id, lane) in map.lanes() {
+        tess.set_color(heatmap.color(values.get(&id).copied()));
+        tess.draw_polyline(
+            &lane
+                .points
+                .as_slice()
+                .iter()
+                .map(|x| x.up(0.02))
+                .collect::<Vec<_>>(),
+            lane.width * 0.5,
+            false,
+        );
+    }
+}
+
+pub fn debug_congestion_heatmap(
+    tess: &mut Tesselator,
+    sim: &Simulation,
+    _: &UiWorld,
+) -> Option<()> {
+    draw_lane_heatmap(tess, sim, &LaneCongestion);
+    Some(())
+}
+
+pub fn debug_slowdown_heatmap(tess: &mut Tesselator, sim: &Simulation, _: &UiWorld) -> Option<()> {
+    draw_lane_heatmap(tess, sim, &LaneSlowdown);
+    Some(())
+}
+
+pub fn debug_pollution_heatmap(tess: &mut Tesselator, sim: &Simulation, _: &UiWorld) -> Option<()> {
+    draw_lane_heatmap(tess, sim, &LanePollution);
+    Some(())
+}
+
 pub fn debug_parking(tess: &mut Tesselator, sim: &Simulation, _: &UiWorld) -> Option<()> {
     let map: &Map = &sim.map();
     let pm = sim.read::<ParkingManagement>();
@@ -671,6 +752,30 @@ pub fn debug_rays(tess: &mut Tesselator<true, sim: &Simulation, uiworld: &UiWorl
     Some(())
 }*/
 
+/// Grid cells colored by how many objects overlap them, to find hotspots
+pub fn debug_spatialmap_occupancy(
+    tess: &mut Tesselator,
+    sim: &Simulation,
+    _: &UiWorld,
+) -> Option<()> {
+    let map: &Map = &sim.map();
+    let cells = map.spatial_map().cell_occupancy().collect::<Vec<_>>();
+    let max = cells.iter().map(|(_, n)| *n).max()? as f32;
+
+    for (r, n) in cells {
+        tess.set_color(LinearColor::RED.a(0.1 + 0.6 * n as f32 / max));
+        tess.draw_rect_cos_sin(
+            r.center()
+                .z(map.environment.height(r.center()).unwrap_or(0.0) + 0.5),
+            r.w(),
+            r.h(),
+            Vec2::X,
+        );
+    }
+
+    Some(())
+}
+
 pub fn debug_spatialmap(tess: &mut Tesselator, sim: &Simulation, _: &UiWorld) -> Option<()> {
     let map: &Map = &sim.map();
     for r in map.spatial_map().debug_grid() {
@@ -21,10 +21,13 @@ use crate::gui::windows::settings::{manage_settings, Settings};
 use crate::gui::UiTextures;
 use crate::gui::{render_newgui, ExitState, GuiState, TimeAlways, Tool};
 use crate::inputmap::{Bindings, InputAction, InputMap};
+use crate::rendering::quality::{QualityLevel, QualityManager};
 use crate::rendering::{InstancedRender, MapRenderOptions, MapRenderer, OrbitCamera};
 use crate::uiworld::{SaveLoadState, UiWorld};
 use prototypes::GameTime;
+use simulation::utils::autosave;
 use simulation::utils::scheduler::SeqSchedule;
+use std::path::Path;
 
 pub const VERSION: &str = include_str!("../../VERSION");
 
@@ -51,7 +54,7 @@ impl engine::framework::State for State {
         log::info!("loaded egui_render");
 
         let sim: Simulation =
-            Simulation::load_from_disk("world").unwrap_or_else(|| Simulation::new(true));
+            autosave::load_newest("world").unwrap_or_else(|| Simulation::new(true));
         let game_schedule = Simulation::schedule();
         let mut uiworld = UiWorld::init();
 
@@ -75,7 +78,7 @@ impl engine::framework::State for State {
 
         {
             let s = uiworld.read::<Settings>();
-            manage_settings(ctx, &s);
+            manage_settings(ctx, &s, QualityLevel::Full);
         }
 
         defer!(log::info!("finished init of game loop"));
@@ -105,6 +108,16 @@ impl engine::framework::State for State {
             timings.total_cpu_time.add_value(ctx.times.total_cpu_time);
         }
 
+        let quality = {
+            let mut manager = self.uiw.write::<QualityManager>();
+            if self.uiw.read::<Settings>().adaptive_quality {
+                manager.update(ctx.times.render_time)
+            } else {
+                manager.reset();
+                QualityLevel::Full
+            }
+        };
+
         let mut slstate = self.uiw.write::<SaveLoadState>();
         if slstate.please_save && !slstate.saving_status.load(Ordering::SeqCst) {
             slstate.please_save = false;
@@ -119,6 +132,8 @@ impl engine::framework::State for State {
         }
         drop(slstate);
 
+        self.autosave(ctx.delta);
+
         crate::network::sim_update(self);
 
         if std::mem::take(&mut self.uiw.write::<SaveLoadState>().render_reset) {
@@ -171,7 +186,7 @@ impl engine::framework::State for State {
             .just_act
             .contains(&InputAction::HideInterface);
 
-        manage_settings(ctx, &self.uiw.read::<Settings>());
+        manage_settings(ctx, &self.uiw.read::<Settings>(), quality);
         self.manage_io(ctx);
 
         self.map_renderer.update(&self.sim.read().unwrap(), ctx);
@@ -204,7 +219,8 @@ impl engine::framework::State for State {
             time.seconds,
             &camera.camera,
             MapRenderOptions {
-                show_arrows: self.uiw.read::<Tool>().show_arrows(),
+                show_arrows: self.uiw.read::<Tool>().show_arrows()
+                    && self.uiw.read::<QualityManager>().level().overlays(),
                 show_lots: self.uiw.read::<Tool>().show_lots(),
             },
             &mut self.uiw.write::<ImmediateDraw>(),
@@ -260,6 +276,38 @@ impl engine::framework::State for State {
 }
 
 impl State {
+    /// Only holds the simulation for the in-memory snapshot, compression and disk writes happen on a background thread
+    fn autosave(&mut self, delta: f32) {
+        let config = self.uiw.read::<Settings>().autosave();
+        let tick = self.sim.read().unwrap().get_tick();
+        let due = self.uiw.write::<GuiState>().autosave.advance(
+            &config,
+            Duration::from_secs_f32(delta),
+            tick,
+        );
+        if !due {
+            return;
+        }
+
+        let slstate = self.uiw.read::<SaveLoadState>();
+        if slstate.saving_status.load(Ordering::SeqCst) {
+            return;
+        }
+        let Some(snapshot) = autosave::snapshot(&self.sim.read().unwrap()) else {
+            return;
+        };
+        self.uiw.save_to_disk();
+        self.uiw.write::<GuiState>().last_save = Instant::now();
+
+        slstate.saving_status.store(true, Ordering::SeqCst);
+        let status = slstate.saving_status.clone();
+        std::thread::spawn(move || {
+            profiling::scope!("game_loop::autosave");
+            autosave::write_autosave(&snapshot, Path::new("world"), config.keep);
+            status.store(false, Ordering::SeqCst);
+        });
+    }
+
     fn reset(&mut self, ctx: &mut Context) {
         ctx.gfx.lamplights.reset(&ctx.gfx.device, &ctx.gfx.queue);
         self.map_renderer = MapRenderer::new(&mut ctx.gfx, &self.sim.read().unwrap());
@@ -272,7 +320,13 @@ impl State {
             * (ctx.gfx.render_params.value().time - 8.0 * GameTime::HOUR as f32)
             / GameTime::DAY as f32;
 
-        let sun = vec3(t.cos(), t.sin() * 0.5, t.sin() + 0.5).normalize();
+        let season = self.sim.read().unwrap().read::<GameTime>().season();
+        let sun = vec3(
+            t.cos(),
+            t.sin() * 0.5,
+            t.sin() + 0.5 + season.sun_height_offset(),
+        )
+        .normalize();
 
         self.uiw.insert(ctx.gfx.perf.as_static());
 
@@ -377,7 +431,7 @@ impl State {
             }
 
             immediate.apply(&mut tess, ctx);
-            immediate.orders.clear();
+            immediate.end_frame();
         }
 
         if let Some(mut x) = self.immediate_renderer.build(ctx.gfx) {
@@ -1,5 +1,3 @@
-use std::time::{Duration, Instant};
-
 use goryak::{image_button, minrow, on_secondary_container, textc};
 use ordered_float::OrderedFloat;
 use prototypes::ItemID;
@@ -13,9 +11,8 @@ use crate::gui::hud::time_controls::time_controls;
 use crate::gui::hud::toolbox::new_toolbox;
 use crate::gui::inspect::new_inspector;
 use crate::gui::textures::UiTextures;
-use crate::gui::windows::settings::Settings;
 use crate::gui::GuiState;
-use crate::uiworld::{SaveLoadState, UiWorld};
+use crate::uiworld::UiWorld;
 
 pub mod chat;
 pub mod keybinds;
@@ -27,8 +24,6 @@ pub mod windows;
 /// Root GUI entrypoint
 pub fn render_newgui(uiworld: &UiWorld, sim: &Simulation) {
     profiling::scope!("hud::render");
-    auto_save(uiworld);
-
     if uiworld.read::<GuiState>().hidden {
         return;
     }
@@ -46,18 +41,6 @@ pub fn render_newgui(uiworld: &UiWorld, sim: &Simulation) {
     //goryak::debug_layout();
 }
 
-fn auto_save(uiworld: &UiWorld) {
-    let every: Option<Duration> = uiworld.read::<Settings>().auto_save_every.into();
-    let mut gui = uiworld.write::<GuiState>();
-    if let Some(every) = every {
-        if gui.last_save.elapsed() > every {
-            uiworld.write::<SaveLoadState>().please_save = true;
-            uiworld.save_to_disk();
-            gui.last_save = Instant::now();
-        }
-    }
-}
-
 fn power_errors(uiworld: &UiWorld, sim: &Simulation) {
     profiling::scope!("hud::power_errors");
     let map = sim.map();
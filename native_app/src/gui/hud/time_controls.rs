@@ -18,7 +18,7 @@ use crate::uiworld::UiWorld;
 
 pub fn time_controls(uiworld: &UiWorld, sim: &Simulation) {
     profiling::scope!("hud::time_controls");
-    let time = sim.read::<GameTime>().daytime;
+    let time = *sim.read::<GameTime>();
     let warp = &mut uiworld.write::<Settings>().time_warp;
     let mut gui = uiworld.write::<GuiState>();
     let depause_warp = &mut gui.depause_warp;
@@ -50,12 +50,9 @@ pub fn time_controls(uiworld: &UiWorld, sim: &Simulation) {
     let time_text = || {
         padx(5.0, || {
             row(|| {
-                monospace(on_secondary_container(), format!("Day {}", time.day));
+                monospace(on_secondary_container(), time.weekday().short_name());
                 spacer(1);
-                monospace(
-                    on_secondary_container(),
-                    format!("{:02}:{:02}:{:02}", time.hour, time.minute, time.second),
-                );
+                monospace(on_secondary_container(), time.formatted());
             });
         });
         let mut l = List::row();
@@ -178,6 +178,7 @@ pub fn special_building_properties(uiw: &UiWorld) {
                                             )
                                         }),
                                         connected_road: args.connected_road,
+                                        door_offset: 0,
                                     }]
                                 }),
                                 size: descr.size,
@@ -4,11 +4,11 @@ use yakui::{
     Pivot, Vec2,
 };
 
-use goryak::{image_button, mincolumn, minrow, padxy, primary};
-use simulation::map::LanePatternBuilder;
+use goryak::{button_primary, button_secondary, image_button, mincolumn, minrow, padxy, primary};
+use simulation::map::{LanePatternBuilder, RoundaboutPolicy};
 
 use crate::gui::hud::toolbox::updown_value;
-use crate::gui::roadbuild::{HeightReference, RoadBuildResource, Snapping};
+use crate::gui::roadbuild::{HeightReference, RoadBuildResource, Snapping, MIN_GRID_SIZE};
 use crate::gui::textures::UiTextures;
 use crate::uiworld::UiWorld;
 
@@ -131,80 +131,37 @@ pub fn roadbuild_properties(uiw: &UiWorld) {
             // Road elevation
             updown_value(&mut state.height_offset, 2.0, "m");
 
-            // image name, label, builder
-            let builders: &[(&str, &str, LanePatternBuilder)] = &[
-                ("roadtypes_street", "Street", LanePatternBuilder::new()),
-                (
-                    "roadtypes_street_1way",
-                    "Street one-way",
-                    LanePatternBuilder::new().one_way(true),
-                ),
-                (
-                    "roadtypes_avenue",
-                    "Avenue",
-                    LanePatternBuilder::new().n_lanes(2).speed_limit(13.0),
-                ),
-                (
-                    "roadtypes_avenue_1way",
-                    "Avenue one-way",
-                    LanePatternBuilder::new()
-                        .n_lanes(2)
-                        .one_way(true)
-                        .speed_limit(13.0),
-                ),
-                (
-                    "roadtypes_drive",
-                    "Drive",
-                    LanePatternBuilder::new()
-                        .parking(false)
-                        .sidewalks(false)
-                        .speed_limit(13.0),
-                ),
-                (
-                    "roadtypes_drive_1way",
-                    "Drive one-way",
-                    LanePatternBuilder::new()
-                        .parking(false)
-                        .sidewalks(false)
-                        .one_way(true)
-                        .speed_limit(13.0),
-                ),
-                (
-                    "roadtypes_highway",
-                    "Highway",
-                    LanePatternBuilder::new()
-                        .n_lanes(3)
-                        .speed_limit(25.0)
-                        .parking(false)
-                        .sidewalks(false),
-                ),
-                (
-                    "roadtypes_highway_1way",
-                    "Highway one-way",
-                    LanePatternBuilder::new()
-                        .n_lanes(3)
-                        .speed_limit(25.0)
-                        .parking(false)
-                        .sidewalks(false)
-                        .one_way(true),
-                ),
-                (
-                    "roadtypes_rail",
-                    "Rail",
-                    LanePatternBuilder::new().rail(true),
-                ),
-                (
-                    "roadtypes_rail_1way",
-                    "Rail one-way",
-                    LanePatternBuilder::new().rail(true).one_way(true),
-                ),
-            ];
+            // Grid spacing, used by both snapping modes
+            if updown_value(&mut state.grid_size, 5.0, "m") {
+                state.grid_size = state.grid_size.max(MIN_GRID_SIZE);
+            }
+
+            // Clicking intersections makes them roundabouts of this radius
+            mincolumn(4.0, || {
+                let button = if state.roundabout_radius.is_some() {
+                    button_primary("Roundabout")
+                } else {
+                    button_secondary("Roundabout")
+                };
+                if button.show().clicked {
+                    state.roundabout_radius = match state.roundabout_radius {
+                        Some(_) => None,
+                        None => Some(RoundaboutPolicy::default().radius),
+                    };
+                }
+                if let Some(ref mut radius) = state.roundabout_radius {
+                    if updown_value(radius, 5.0, "m") {
+                        *radius = radius
+                            .clamp(RoundaboutPolicy::MIN_RADIUS, RoundaboutPolicy::MAX_RADIUS);
+                    }
+                }
+            });
 
-            for (icon, label, builder) in builders {
+            for &(name, label, builder) in LanePatternBuilder::PRESETS {
                 let mut l = List::column();
                 l.main_axis_size = MainAxisSize::Min;
                 l.show(|| {
-                    let is_active = &state.pattern_builder == builder;
+                    let is_active = state.pattern_builder == builder;
                     let (default_col, hover_col) = if is_active {
                         let c = Color::WHITE.adjust(0.5);
                         (c, c)
@@ -212,16 +169,16 @@ pub fn roadbuild_properties(uiw: &UiWorld) {
                         (Color::WHITE, Color::WHITE.with_alpha(0.7))
                     };
                     if image_button(
-                        uiw.read::<UiTextures>().get(icon),
+                        uiw.read::<UiTextures>().get(&format!("roadtypes_{}", name)),
                         Vec2::new(64.0, 64.0),
                         default_col,
                         hover_col,
                         primary(),
-                        *label,
+                        label,
                     )
                     .clicked
                     {
-                        state.pattern_builder = *builder;
+                        state.pattern_builder = builder;
                     }
 
                     if is_active {
@@ -5,12 +5,14 @@ pub mod settings;
 use crate::inputmap::{InputAction, InputMap};
 use crate::uiworld::UiWorld;
 use goryak::button_primary;
+use serde::{Deserialize, Serialize};
 use simulation::Simulation;
 
 #[cfg(feature = "multiplayer")]
 pub mod network;
 
-#[derive(Default)]
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct GUIWindows {
     economy_open: bool,
     settings_open: bool,
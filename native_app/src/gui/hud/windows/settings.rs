@@ -13,11 +13,13 @@ use goryak::{
     on_secondary_container, outline, padx, padxy, textc, VertScrollSize, Window,
 };
 use serde::{Deserialize, Serialize};
+use simulation::utils::autosave::{Autosave, AutosaveClock};
 use simulation::Simulation;
 
 use crate::game_loop::Timings;
 use crate::gui::keybinds::{KeybindState, KeybindStateInner};
 use crate::inputmap::{Bindings, InputMap};
+use crate::rendering::quality::{QualityLevel, QualityManager};
 use crate::uiworld::UiWorld;
 
 const SETTINGS_SAVE_NAME: &str = "settings";
@@ -31,6 +33,8 @@ pub struct Settings {
     pub camera_fov: f32,
 
     pub gfx: GfxSettings,
+    /// Lowers the graphics settings when frames take too long, see [`QualityManager`]
+    pub adaptive_quality: bool,
 
     pub gui_scale: f32,
 
@@ -42,6 +46,22 @@ pub struct Settings {
     #[serde(skip)]
     pub time_warp: u32,
     pub auto_save_every: AutoSaveEvery,
+    pub auto_save_keep: u32,
+    pub auto_save_game_time: bool,
+}
+
+impl Settings {
+    pub fn autosave(&self) -> Autosave {
+        Autosave {
+            interval: self.auto_save_every.into(),
+            clock: if self.auto_save_game_time {
+                AutosaveClock::Game
+            } else {
+                AutosaveClock::Real
+            },
+            keep: self.auto_save_keep,
+        }
+    }
 }
 
 impl Default for Settings {
@@ -55,10 +75,13 @@ impl Default for Settings {
             ui_volume_percent: 100.0,
             time_warp: 1,
             auto_save_every: AutoSaveEvery::FiveMinutes,
+            auto_save_keep: 3,
+            auto_save_game_time: false,
             camera_smooth_tightness: 1.0,
             camera_fov: 60.0,
             gui_scale: 1.0,
             gfx: GfxSettings::default(),
+            adaptive_quality: true,
         }
     }
 }
@@ -156,6 +179,18 @@ pub fn settings(uiw: &UiWorld, _: &Simulation, opened: &mut bool) {
                         settings.auto_save_every = AutoSaveEvery::from(id as u8);
                     }
                 });
+                minrow(5.0, || {
+                    textc(on_secondary_container(), "Autosaves kept");
+                    dragvalue()
+                        .min(1.0)
+                        .max(20.0)
+                        .show(&mut settings.auto_save_keep);
+                });
+                checkbox_value(
+                    &mut settings.auto_save_game_time,
+                    on_secondary_container(),
+                    "Auto save in game time",
+                );
 
                 divider(outline(), 10.0, 1.0);
                 textc(on_secondary_container(), "Input");
@@ -232,7 +267,24 @@ pub fn settings(uiw: &UiWorld, _: &Simulation, opened: &mut bool) {
                     on_secondary_container(),
                     "MSAA 4x Anti-aliasing",
                 );
+                checkbox_value(
+                    &mut settings.gfx.ui_msaa,
+                    on_secondary_container(),
+                    "UI Anti-aliasing",
+                );
                 checkbox_value(&mut settings.gfx.vsync, on_secondary_container(), "VSync");
+                checkbox_value(
+                    &mut settings.adaptive_quality,
+                    on_secondary_container(),
+                    "Lower quality when the framerate drops",
+                );
+                let level = uiw.read::<QualityManager>().level();
+                if level != QualityLevel::Full {
+                    textc(
+                        on_secondary_container(),
+                        format!("Quality currently lowered: {:?}", level),
+                    );
+                }
                 checkbox_value(
                     &mut settings.gfx.parallel_render,
                     on_secondary_container(),
@@ -372,8 +424,10 @@ pub fn settings(uiw: &UiWorld, _: &Simulation, opened: &mut bool) {
     })
 }
 
-pub fn manage_settings(ctx: &mut engine::Context, settings: &Settings) {
-    ctx.gfx.update_settings(settings.gfx);
+/// Applies the settings, with the graphics scaled down to the quality level
+pub fn manage_settings(ctx: &mut engine::Context, settings: &Settings, quality: QualityLevel) {
+    ctx.gfx.update_settings(quality.apply(settings.gfx));
+    ctx.gfx.lod_bias = quality.lod_bias();
 
     ctx.egui.zoom_factor = settings.gui_scale;
 
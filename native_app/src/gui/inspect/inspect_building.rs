@@ -1,6 +1,4 @@
-use goryak::{
-    dragvalue, fixed_spacer, minrow, on_secondary_container, primary, textc, ProgressBar, Window,
-};
+use goryak::{fixed_spacer, minrow, on_secondary_container, primary, textc, ProgressBar, Window};
 use prototypes::{ItemID, Recipe};
 use simulation::economy::Market;
 use simulation::map::{Building, BuildingID, BuildingKind, Zone, MAX_ZONE_AREA};
@@ -12,7 +10,7 @@ use std::borrow::Cow;
 use yakui::widgets::Pad;
 use yakui::Vec2;
 
-use crate::gui::inspect::entity_link;
+use crate::gui::inspect::{entity_link, inspect_quantity, Unit};
 use crate::gui::item_icon_yakui;
 use crate::uiworld::UiWorld;
 
@@ -61,23 +59,16 @@ pub fn inspect_building(uiworld: &UiWorld, sim: &Simulation, id: BuildingID) ->
         };
 
         if let Some(ref zone) = building.zone {
-            let mut cpy = zone.filldir;
-            minrow(5.0, || {
-                let mut ang = cpy.angle_cossin().to_degrees();
-
-                if dragvalue().min(-180.0).max(180.0).show(&mut ang.0) {
-                    cpy = ang.to_radians().vec2();
-                    uiworld.commands().push(WorldCommand::UpdateZone {
-                        building: id,
-                        zone: Zone {
-                            filldir: cpy,
-                            ..zone.clone()
-                        },
-                    })
-                }
-
-                label("Fill angle");
-            });
+            let mut ang = zone.filldir.angle_cossin().to_degrees();
+            if inspect_quantity("Fill angle", &mut ang.0, -180.0..180.0, Unit::Degrees) {
+                uiworld.commands().push(WorldCommand::UpdateZone {
+                    building: id,
+                    zone: Zone {
+                        filldir: ang.to_radians().vec2(),
+                        ..zone.clone()
+                    },
+                })
+            }
 
             ProgressBar {
                 value: zone.area / MAX_ZONE_AREA,
@@ -180,6 +171,14 @@ fn render_goodscompany(uiworld: &UiWorld, sim: &Simulation, b: &Building) {
             entity_link(uiworld, sim, driver);
         });
     }
+    if !goods.trucks.is_empty() {
+        label(format!(
+            "trucks out: {}/{}, deliveries waiting: {}",
+            goods.truck_states.len(),
+            goods.trucks.len(),
+            goods.deliveries.len()
+        ));
+    }
     let productivity = c.productivity(proto, b.zone.as_ref(), map, elec_flow);
     if productivity < 1.0 {
         ProgressBar {
@@ -0,0 +1,232 @@
+use crate::gui::inspect::{inspect_quantity_response, Unit};
+use crate::uiworld::UiWorld;
+use goryak::{button_primary, on_secondary_container, textc, DragResponse, Window};
+use prototypes::GameTime;
+use simulation::map::{IntersectionID, LightPolicy, LightTiming, Map, TrafficBehavior};
+use simulation::Simulation;
+use yakui::use_state;
+use yakui::widgets::Pad;
+
+/// Light timing being dragged in the inspector.
+/// It is only sent to the simulation once the drag is released, not every frame.
+#[derive(Debug, Default, Copy, Clone)]
+struct TimingEdit {
+    pending: Option<(IntersectionID, LightTiming)>,
+}
+
+impl TimingEdit {
+    /// The timing to show: the one being dragged, or the current one of the intersection
+    fn timing(&self, id: IntersectionID, current: LightTiming) -> LightTiming {
+        match self.pending {
+            Some((pid, timing)) if pid == id => timing,
+            _ => current,
+        }
+    }
+
+    /// Records this frame's edit, returns the timing to send once the drag is released
+    fn update(
+        &mut self,
+        id: IntersectionID,
+        timing: LightTiming,
+        resp: DragResponse,
+    ) -> Option<LightTiming> {
+        if resp.changed {
+            self.pending = Some((id, timing));
+        }
+        if !resp.released {
+            return None;
+        }
+        match self.pending.take() {
+            Some((pid, timing)) if pid == id => Some(timing),
+            _ => None,
+        }
+    }
+}
+
+/// Live state and timing of the traffic lights of an intersection.
+/// Returns false once the window is closed or the intersection is gone.
+pub fn inspect_intersection(uiworld: &UiWorld, sim: &Simulation, id: IntersectionID) -> bool {
+    let map = sim.map();
+    let Some(inter) = map.intersections().get(id) else {
+        return false;
+    };
+    let Some(lines) = light_lines(&map, id, sim.read::<GameTime>().seconds) else {
+        return true;
+    };
+    let current = inter.effective_light_timing(map.lanes(), map.roads());
+    let automatic = inter.light_timing.is_none();
+
+    let mut is_open = true;
+    Window {
+        title: "Traffic lights".into(),
+        pad: Pad::all(10.0),
+        radius: 10.0,
+        opened: &mut is_open,
+        child_spacing: 5.0,
+    }
+    .show(|| {
+        if cfg!(debug_assertions) {
+            textc(on_secondary_container(), format!("{:?}", id));
+        }
+
+        for line in lines {
+            textc(on_secondary_container(), line);
+        }
+
+        if automatic {
+            textc(on_secondary_container(), "Automatic timing");
+        } else if button_primary("Back to automatic timing").show().clicked {
+            uiworld.commands().set_light_timing(id, None);
+        }
+
+        let edit = use_state(TimingEdit::default);
+        let mut timing = edit.get().timing(id, current);
+
+        let mut resp = DragResponse::default();
+        for r in [
+            timing_field(
+                "green",
+                &mut timing.green,
+                LightTiming::MIN_GREEN,
+                LightTiming::MAX_GREEN,
+            ),
+            timing_field("orange", &mut timing.orange, 0, LightTiming::MAX_ORANGE),
+            timing_field("all red", &mut timing.all_red, 0, LightTiming::MAX_ALL_RED),
+        ] {
+            resp.changed |= r.changed;
+            resp.released |= r.released;
+        }
+
+        let mut e = edit.get();
+        let done = e.update(id, timing, resp);
+        edit.set(e);
+        if let Some(timing) = done {
+            uiworld.commands().set_light_timing(id, Some(timing));
+        }
+    });
+
+    is_open
+}
+
+fn timing_field(label: &str, value: &mut u16, min: u16, max: u16) -> DragResponse {
+    let mut v = *value as f32;
+    let resp = inspect_quantity_response(label, &mut v, min as f32..max as f32, Unit::Seconds);
+    if resp.changed {
+        *value = v.round() as u16;
+    }
+    resp
+}
+
+/// Text describing the current phase and the light of each incoming road,
+/// None if the intersection has no traffic lights
+fn light_lines(map: &Map, id: IntersectionID, seconds: u32) -> Option<Vec<String>> {
+    let inter = map.intersections().get(id)?;
+    let state = LightPolicy::lights_state(inter, map.lanes(), map.roads(), seconds)?;
+
+    let mut lines = vec![format!(
+        "Phase {}/{}, next in {}s",
+        state.phase + 1,
+        state.n_phases,
+        state.remaining
+    )];
+    for (i, (_, behavior, remaining)) in state.roads.iter().enumerate() {
+        let color = match behavior {
+            TrafficBehavior::GREEN => "green",
+            TrafficBehavior::ORANGE => "orange",
+            TrafficBehavior::RED => "red",
+            TrafficBehavior::STOP => "stop",
+        };
+        lines.push(format!("Road {}: {} for {}s", i + 1, color, remaining));
+    }
+    Some(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{light_lines, TimingEdit};
+    use geom::vec2;
+    use goryak::DragResponse;
+    use simulation::map::{LanePatternBuilder, LightPolicy, LightTiming, MapBuilder};
+
+    #[test]
+    fn timing_is_sent_on_release() {
+        let mut b = MapBuilder::new();
+        let a = b.add_inter(vec2(0.0, 0.0));
+        let c = b.add_inter(vec2(100.0, 0.0));
+        let current = LightTiming::default();
+        let dragging = DragResponse {
+            changed: true,
+            released: false,
+        };
+        let released = DragResponse {
+            changed: false,
+            released: true,
+        };
+
+        let mut edit = TimingEdit::default();
+        assert_eq!(edit.timing(a, current), current);
+
+        // nothing is sent while dragging, but the dragged value is shown
+        let mut timing = current;
+        for _ in 0..10 {
+            timing.green += 10;
+            assert_eq!(edit.update(a, timing, dragging), None);
+            assert_eq!(edit.timing(a, current), timing);
+        }
+        // another intersection doesn't show it
+        assert_eq!(edit.timing(c, current), current);
+
+        // sent once on release
+        assert_eq!(edit.update(a, timing, released), Some(timing));
+        assert_eq!(edit.timing(a, current), current);
+        assert_eq!(edit.update(a, timing, released), None);
+        assert_eq!(edit.update(a, timing, DragResponse::default()), None);
+    }
+
+    #[test]
+    fn reads_timing_of_signalized_intersection() {
+        let pat = LanePatternBuilder::new().parking(false).build();
+        let mut b = MapBuilder::new();
+        let center = b.add_inter(vec2(0.0, 0.0));
+        let mut ends = vec![];
+        for dir in [
+            vec2(1.0, 0.0),
+            vec2(0.0, 1.0),
+            vec2(-1.0, 0.0),
+            vec2(0.0, -1.0),
+        ] {
+            let end = b.add_inter(dir * 100.0);
+            b.connect(center, end, &pat).unwrap();
+            ends.push(end);
+        }
+        let mut map = b.build();
+        let timing = LightTiming {
+            green: 200,
+            orange: 30,
+            all_red: 20,
+        };
+        map.update_intersection(center, move |i| {
+            i.light_policy = LightPolicy::Lights;
+            i.light_timing = Some(timing);
+        });
+
+        // a dead end has no lights to show
+        assert!(light_lines(&map, ends[0], 0).is_none());
+
+        let phase_length = timing.phase_length() as u32;
+        for seconds in (0..3 * phase_length).step_by(7) {
+            let lines = light_lines(&map, center, seconds).unwrap();
+            assert_eq!(lines.len(), 5);
+            assert!(lines[0].starts_with("Phase "), "{}", lines[0]);
+
+            let inter = &map.intersections()[center];
+            let state =
+                LightPolicy::lights_state(inter, map.lanes(), map.roads(), seconds).unwrap();
+            assert_eq!(state.n_phases, 2);
+            assert!(state.remaining >= 1 && state.remaining <= phase_length);
+            for &(_, _, remaining) in &state.roads {
+                assert!(remaining >= 1 && remaining <= phase_length * 2);
+            }
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use crate::gui::inspect::{inspect_quantity, Unit};
+use crate::uiworld::UiWorld;
+use goryak::{on_secondary_container, textc, Window};
+use simulation::map::{LaneID, RoadID, TraverseKind};
+use simulation::Simulation;
+use std::collections::BTreeMap;
+use yakui::widgets::Pad;
+
+/// Lanes of a road with their speed limit and the vehicles on them.
+/// The selected lane's speed limit can be edited.
+/// Returns false once the window is closed or the road is gone.
+pub fn inspect_road(
+    uiworld: &UiWorld,
+    sim: &Simulation,
+    id: RoadID,
+    selected_lane: Option<LaneID>,
+) -> bool {
+    let map = sim.map();
+    let Some(road) = map.roads().get(id) else {
+        return false;
+    };
+
+    let mut traffic: BTreeMap<LaneID, usize> = BTreeMap::new();
+    for v in sim.world().vehicles.values() {
+        if let Some(TraverseKind::Lane(l)) = v.it.get_travers().map(|t| t.kind) {
+            *traffic.entry(l).or_default() += 1;
+        }
+    }
+
+    let mut is_open = true;
+    Window {
+        title: "Road".into(),
+        pad: Pad::all(10.0),
+        radius: 10.0,
+        opened: &mut is_open,
+        child_spacing: 5.0,
+    }
+    .show(|| {
+        if cfg!(debug_assertions) {
+            textc(on_secondary_container(), format!("{:?}", id));
+        }
+        textc(
+            on_secondary_container(),
+            format!(
+                "{:.0}m long, {:.1}m wide, {:?}",
+                road.length(),
+                road.width,
+                road.material
+            ),
+        );
+        if road.closed {
+            textc(on_secondary_container(), "Closed");
+        }
+
+        for (lane_id, kind) in road.lanes_iter() {
+            let Some(lane) = map.lanes().get(lane_id) else {
+                continue;
+            };
+            let marker = if selected_lane == Some(lane_id) {
+                "> "
+            } else {
+                ""
+            };
+            let mut line = format!("{}{:?}", marker, kind);
+            if lane.reversed {
+                line += " (reversed)";
+            }
+            if kind.vehicles() {
+                line += &format!(
+                    ": {:.0}km/h, {} vehicles",
+                    lane.speed_limit * Unit::KmPerHour.factor(),
+                    traffic.get(&lane_id).copied().unwrap_or(0)
+                );
+            }
+            textc(on_secondary_container(), line);
+        }
+
+        let Some(lane) = selected_lane.and_then(|l| map.lanes().get(l)) else {
+            return;
+        };
+        let mut limit = lane.speed_limit;
+        if inspect_quantity("speed limit", &mut limit, 1.0..40.0, Unit::KmPerHour) {
+            uiworld.commands().set_lane_speed_limit(lane.id, limit);
+        }
+    });
+
+    is_open
+}
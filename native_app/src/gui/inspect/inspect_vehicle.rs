@@ -1,7 +1,10 @@
 use crate::gui::inspect::{entity_link, follow_button};
+use crate::rendering::immediate::ImmediateDraw;
 use crate::uiworld::UiWorld;
 use goryak::{minrow, on_secondary_container, textc, Window};
-use simulation::transportation::VehicleState;
+use simulation::map::TraverseKind;
+use simulation::map_dynamic::{congestion_color, Itinerary, LaneCongestion, LaneHeatmap};
+use simulation::transportation::{BusRoutes, VehicleState};
 use simulation::{Simulation, VehicleID};
 use yakui::widgets::Pad;
 
@@ -11,6 +14,7 @@ pub fn inspect_vehicle(uiworld: &UiWorld, sim: &Simulation, id: VehicleID) -> bo
     };
 
     let name = format!("{:?}", v.vehicle.kind);
+    draw_route(uiworld, sim, &v.it);
 
     let mut is_open = true;
     Window {
@@ -25,6 +29,13 @@ pub fn inspect_vehicle(uiworld: &UiWorld, sim: &Simulation, id: VehicleID) -> bo
             textc(on_secondary_container(), format!("{:?}", id));
         }
 
+        if let Some((_, route)) = sim.read::<BusRoutes>().route_of(id) {
+            textc(
+                on_secondary_container(),
+                format!("Line {}", route.line_number),
+            );
+        }
+
         match v.vehicle.state {
             VehicleState::Parked(_) => {
                 textc(on_secondary_container(), "Parked");
@@ -35,14 +46,38 @@ pub fn inspect_vehicle(uiworld: &UiWorld, sim: &Simulation, id: VehicleID) -> bo
                     format!("Driving at {:.0}km/h", v.speed.0 * 3.6),
                 );
             }
+            VehicleState::WaitingAtLight => {
+                textc(on_secondary_container(), "Waiting at light");
+            }
+            VehicleState::Yielding => {
+                textc(on_secondary_container(), "Yielding");
+            }
             VehicleState::Panicking(_) => {
                 textc(on_secondary_container(), "Panicking");
             }
             VehicleState::RoadToPark(_, _, _) => {
                 textc(on_secondary_container(), "Parking");
             }
+            VehicleState::ParkToRoad(_, _) => {
+                textc(on_secondary_container(), "Leaving parking");
+            }
+            VehicleState::Overtaking => {
+                textc(
+                    on_secondary_container(),
+                    format!("Overtaking at {:.0}km/h", v.speed.0 * 3.6),
+                );
+            }
         }
 
+        textc(
+            on_secondary_container(),
+            format!(
+                "Passengers: {}/{}",
+                v.vehicle.passengers.len(),
+                v.vehicle.capacity
+            ),
+        );
+
         for (human_id, human) in &sim.world().humans {
             if human.router.personal_car == Some(id) {
                 minrow(5.0, || {
@@ -57,3 +92,29 @@ pub fn inspect_vehicle(uiworld: &UiWorld, sim: &Simulation, id: VehicleID) -> bo
 
     is_open
 }
+
+/// Draws what is left of the route, each lane colored by how congested it is right now
+fn draw_route(uiworld: &UiWorld, sim: &Simulation, it: &Itinerary) {
+    let Some(route) = it.get_route() else {
+        return;
+    };
+    let congestion = LaneCongestion.values(sim);
+    let map = sim.map();
+    let mut draw = uiworld.write::<ImmediateDraw>();
+
+    for t in std::iter::once(&route.cur).chain(route.reversed_route.iter().rev()) {
+        let Some(points) = t.points(&map) else {
+            continue;
+        };
+        let value = match t.kind {
+            TraverseKind::Lane(id) => congestion.get(&id).copied(),
+            TraverseKind::Turn(_) => None,
+        };
+        draw.polyline(
+            points.iter().map(|p| p.up(0.3)).collect::<Vec<_>>(),
+            1.5,
+            false,
+        )
+        .color(congestion_color(value));
+    }
+}
@@ -1,18 +1,26 @@
 use crate::debug_gui::debug_window::DebugState;
 use crate::gui::follow::FollowEntity;
-use crate::gui::{InspectedBuilding, InspectedEntity};
+use crate::gui::roadeditor::RoadEditorResource;
+use crate::gui::{InspectedBuilding, InspectedEntity, MapObject, SelectedMapObject};
 use crate::uiworld::UiWorld;
-use goryak::{button_primary, primary_link};
+use goryak::{
+    button_primary, dragvalue, minrow, on_secondary_container, primary_link, textc, DragResponse,
+};
 use inspect_building::inspect_building;
 use inspect_human::inspect_human;
+use inspect_intersection::inspect_intersection;
+use inspect_road::inspect_road;
 use inspect_train::inspect_train;
 use inspect_vehicle::inspect_vehicle;
 use simulation::map::BuildingID;
 use simulation::{AnyEntity, Simulation};
 use slotmapd::Key;
+use std::ops::Range;
 
 mod inspect_building;
 mod inspect_human;
+mod inspect_intersection;
+mod inspect_road;
 mod inspect_train;
 mod inspect_vehicle;
 
@@ -26,6 +34,28 @@ pub fn new_inspector(uiworld: &UiWorld, sim: &Simulation) {
         }
     }
 
+    let inspected_inter = uiworld
+        .read::<RoadEditorResource>()
+        .inspect
+        .as_ref()
+        .map(|x| x.id);
+    if let Some(id) = inspected_inter {
+        if !inspect_intersection(uiworld, sim, id) {
+            uiworld.write::<RoadEditorResource>().inspect = None;
+        }
+    }
+
+    let selected_map = uiworld.read::<SelectedMapObject>().obj;
+    if let Some(obj) = selected_map {
+        let is_open = match obj {
+            MapObject::Road { road, lane } => inspect_road(uiworld, sim, road, lane),
+            MapObject::Intersection(id) => inspect_intersection(uiworld, sim, id),
+        };
+        if !is_open {
+            uiworld.write::<SelectedMapObject>().obj = None;
+        }
+    }
+
     let e = unwrap_or!(uiworld.read::<InspectedEntity>().e, return);
 
     let force_debug_inspect = uiworld.read::<DebugState>().debug_inspector;
@@ -102,3 +132,91 @@ fn follow_button_inner(uiworld: &UiWorld, id: AnyEntity) {
         follow.0 = Some(id);
     }
 }
+
+/// Unit a quantity is shown in, the quantity itself is stored in SI units
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Unit {
+    Meters,
+    KmPerHour,
+    Seconds,
+    Degrees,
+    Count,
+}
+
+impl Unit {
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Unit::Meters => "m",
+            Unit::KmPerHour => "km/h",
+            Unit::Seconds => "s",
+            Unit::Degrees => "°",
+            Unit::Count => "",
+        }
+    }
+
+    /// Multiplier from the stored value to the shown one
+    pub fn factor(self) -> f32 {
+        match self {
+            Unit::KmPerHour => 3.6,
+            _ => 1.0,
+        }
+    }
+
+    fn step(self) -> f64 {
+        match self {
+            Unit::Meters | Unit::Seconds => 0.1,
+            Unit::KmPerHour | Unit::Degrees | Unit::Count => 1.0,
+        }
+    }
+}
+
+/// Draggable quantity with its unit. Returns true if the value was edited.
+/// `range` is in stored units. A value already out of range is shown as is and only clamped once edited.
+pub fn inspect_quantity(label: &str, value: &mut f32, range: Range<f32>, unit: Unit) -> bool {
+    inspect_quantity_response(label, value, range, unit).changed
+}
+
+/// Like [`inspect_quantity`], also tells when the drag is released
+pub fn inspect_quantity_response(
+    label: &str,
+    value: &mut f32,
+    range: Range<f32>,
+    unit: Unit,
+) -> DragResponse {
+    let mut resp = DragResponse::default();
+    minrow(5.0, || {
+        let factor = unit.factor();
+        let mut shown = *value * factor;
+        resp = dragvalue().step(unit.step()).show_response(&mut shown);
+        if resp.changed {
+            *value = clamp_quantity(shown / factor, &range);
+        }
+        textc(on_secondary_container(), unit.suffix());
+        textc(on_secondary_container(), label.to_string());
+    });
+    resp
+}
+
+fn clamp_quantity(v: f32, range: &Range<f32>) -> f32 {
+    v.clamp(range.start, range.end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{clamp_quantity, Unit};
+
+    #[test]
+    fn quantity_clamps_to_range() {
+        let range = 0.0..30.0;
+        assert_eq!(clamp_quantity(0.0, &range), 0.0);
+        assert_eq!(clamp_quantity(30.0, &range), 30.0);
+        assert_eq!(clamp_quantity(-0.1, &range), 0.0);
+        assert_eq!(clamp_quantity(30.1, &range), 30.0);
+        assert_eq!(clamp_quantity(12.5, &range), 12.5);
+
+        // 120km/h dragged from a 30m/s limit is clamped back to it
+        let f = Unit::KmPerHour.factor();
+        assert_eq!(clamp_quantity(120.0 / f, &range), 30.0);
+        assert!((clamp_quantity(50.0 / f, &range) * f - 50.0).abs() < 1e-4);
+    }
+}
@@ -1,7 +1,9 @@
 use crate::gui::windows::GUIWindows;
+use crate::inputmap::{InputAction, InputMap};
 use crate::uiworld::UiWorld;
 use serde::{Deserialize, Serialize};
-use simulation::map::BuildingID;
+use simulation::map::{BuildingID, IntersectionID, LaneID, RoadID};
+use simulation::utils::autosave::AutosaveTimer;
 use simulation::world_command::WorldCommand;
 use simulation::{AnyEntity, Simulation};
 use std::borrow::Cow;
@@ -17,11 +19,18 @@ pub use hud::*;
 pub use textures::*;
 pub use tools::*;
 
+/// Saved alongside the game so that the UI comes back as it was left
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
 pub struct GuiState {
     pub debug_window: bool,
     pub windows: GUIWindows,
+    #[serde(skip)]
     pub last_save: Instant,
+    #[serde(skip)]
+    pub autosave: AutosaveTimer,
     pub depause_warp: u32,
+    #[serde(skip)]
     pub hidden: bool,
 }
 
@@ -31,6 +40,7 @@ impl Default for GuiState {
             debug_window: false,
             windows: Default::default(),
             last_save: Instant::now(),
+            autosave: AutosaveTimer::default(),
             depause_warp: 1,
             hidden: false,
         }
@@ -48,11 +58,22 @@ pub fn run_ui_systems(sim: &Simulation, uiworld: &UiWorld) {
     addtrain::addtrain(sim, uiworld);
     zoneedit::zoneedit(sim, uiworld);
     terraforming::terraforming(sim, uiworld);
+    undo_redo(uiworld);
 
     // run last so other systems can have the chance to cancel select
     selectable::selectable(sim, uiworld);
 }
 
+fn undo_redo(uiworld: &UiWorld) {
+    let inp = uiworld.read::<InputMap>();
+    if inp.just_act.contains(&InputAction::Undo) {
+        uiworld.commands().undo();
+    }
+    if inp.just_act.contains(&InputAction::Redo) {
+        uiworld.commands().redo();
+    }
+}
+
 #[derive(Default, Clone, Debug)]
 pub struct ErrorTooltip {
     pub msg: Option<Cow<'static, str>>,
@@ -88,6 +109,19 @@ impl PotentialCommands {
     }
 }
 
+/// A part of the map that isn't an entity, selected by clicking on it
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MapObject {
+    Road { road: RoadID, lane: Option<LaneID> },
+    Intersection(IntersectionID),
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SelectedMapObject {
+    pub obj: Option<MapObject>,
+    pub dontclear: bool,
+}
+
 #[derive(Copy, Clone, Debug, Default)]
 pub struct InspectedBuilding {
     pub e: Option<BuildingID>,
@@ -165,3 +199,39 @@ impl Default for ExitState {
         Self::NoExit
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{GuiState, Tool};
+    use crate::init::load_resource;
+    use crate::uiworld::UiWorld;
+    use common::saveload::{Encoder, JSON};
+
+    #[test]
+    fn gui_and_tool_roundtrip() {
+        let gui = GuiState {
+            debug_window: true,
+            depause_warp: 3,
+            ..Default::default()
+        };
+        let tool = Tool::Terraforming;
+
+        let encoded = JSON::encode(&(&gui, tool)).unwrap();
+        let (gui2, tool2): (GuiState, Tool) = JSON::decode(&encoded).unwrap();
+
+        assert!(gui2.debug_window);
+        assert_eq!(gui2.depause_warp, 3);
+        assert!(tool2 == tool);
+        assert_eq!(JSON::encode(&(&gui2, tool2)).unwrap(), encoded);
+
+        // a tool that doesn't exist anymore can't be decoded, the loader keeps the default hand tool
+        let mut uiw = UiWorld::default();
+        uiw.insert(Tool::default());
+        load_resource(&mut uiw, JSON::decode::<Tool>(b"\"Teleporter\""));
+        assert!(*uiw.read::<Tool>() == Tool::Hand);
+
+        let bulldozer = JSON::encode(&Tool::Bulldozer).unwrap();
+        load_resource(&mut uiw, JSON::decode::<Tool>(&bulldozer));
+        assert!(*uiw.read::<Tool>() == Tool::Bulldozer);
+    }
+}
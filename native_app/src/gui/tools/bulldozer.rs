@@ -4,16 +4,24 @@ use crate::inputmap::{InputAction, InputMap};
 use crate::rendering::immediate::ImmediateDraw;
 use crate::uiworld::UiWorld;
 use egui_inspect::Inspect;
+use geom::{Vec2, AABB};
 use simulation::map::{BuildingKind, Map, ProjectFilter, ProjectKind};
 use simulation::Simulation;
 
+/// Dragged rectangles smaller than this on either side are ignored, in meters
+const MIN_AREA_SIDE: f32 = 2.0;
+
 #[derive(Copy, Clone, Default, Inspect)]
 pub struct BulldozerState {
     hold: bool,
+    /// Where the rectangle being dragged on the ground started
+    #[inspect(skip)]
+    drag_start: Option<Vec2>,
 }
 
 /// Bulldozer tool
-/// Allows to remove roads, intersections and buildings
+/// Allows to remove roads, intersections and buildings,
+/// or every road and intersection in a rectangle dragged from the ground
 pub fn bulldozer(sim: &Simulation, uiworld: &UiWorld) {
     profiling::scope!("gui::bulldozer");
     let tool: &Tool = &uiworld.read::<Tool>();
@@ -26,10 +34,35 @@ pub fn bulldozer(sim: &Simulation, uiworld: &UiWorld) {
     let map: &Map = &sim.map();
     let draw: &mut ImmediateDraw = &mut uiworld.write::<ImmediateDraw>();
     let mut commands = uiworld.commands();
-    let state: &BulldozerState = &uiworld.read::<BulldozerState>();
+    let state: &mut BulldozerState = &mut uiworld.write::<BulldozerState>();
 
     let cur_proj = map.project(unwrap_ret!(inp.unprojected), 0.0, ProjectFilter::ALL);
 
+    if let Some(start) = state.drag_start {
+        let cur = cur_proj.pos.xy();
+        let aabb = AABB::new_ll_ur(start.min(cur), start.max(cur));
+        if inp.act.contains(&InputAction::Select) {
+            draw.aabb(aabb, cur_proj.pos.z + 0.5)
+                .color(simulation::colors().gui_danger.a(0.3));
+            return;
+        }
+        state.drag_start = None;
+        if aabb.w() >= MIN_AREA_SIDE && aabb.h() >= MIN_AREA_SIDE {
+            log::info!("bulldozer area {:?}", aabb);
+            uiworld.write::<SpecialBuildingResource>().last_obb = None;
+            commands.map_remove_area(aabb);
+        }
+        return;
+    }
+
+    if !state.hold
+        && inp.just_act.contains(&InputAction::Select)
+        && matches!(cur_proj.kind, ProjectKind::Ground)
+    {
+        state.drag_start = Some(cur_proj.pos.xy());
+        return;
+    }
+
     let col = if matches!(
         cur_proj.kind,
         ProjectKind::Intersection(_) | ProjectKind::Road(_) | ProjectKind::Building(_)
@@ -4,7 +4,8 @@ use geom::{PolyLine3, Vec2, Vec3};
 use itertools::Itertools;
 use ordered_float::OrderedFloat;
 use simulation::map::{
-    LanePatternBuilder, Map, MapProject, ProjectFilter, ProjectKind, PylonPosition, RoadSegmentKind,
+    IntersectionID, LanePatternBuilder, Map, MapProject, ProjectFilter, ProjectKind, PylonPosition,
+    RoadSegmentKind, RoundaboutPolicy,
 };
 use simulation::world_command::{WorldCommand, WorldCommands};
 use simulation::Simulation;
@@ -16,6 +17,11 @@ use crate::inputmap::{InputAction, InputMap};
 use crate::rendering::immediate::{ImmediateDraw, ImmediateSound};
 use crate::uiworld::UiWorld;
 
+/// Spacing of the snapping grid by default, in meters
+const DEFAULT_GRID_SIZE: f32 = 20.0;
+/// Below this the grid is so fine that snapping to it is the same as not snapping
+pub const MIN_GRID_SIZE: f32 = 2.0;
+
 #[derive(Copy, Clone, Debug, Default)]
 pub enum BuildState {
     #[default]
@@ -49,10 +55,18 @@ pub fn roadbuild(sim: &Simulation, uiworld: &UiWorld) {
     if !tool.is_roadbuild() {
         state.build_state = Hover;
         state.height_offset = 0.0;
+        state.roundabout_edit = None;
+        return;
+    }
+
+    if state.roundabout_radius.is_some() {
+        state.build_state = Hover;
+        roundabout_edit(state, map, &mut inp, commands, immdraw);
         return;
     }
+    state.roundabout_edit = None;
 
-    let grid_size = 20.0;
+    let grid_size = state.grid_size.max(MIN_GRID_SIZE);
     let unproj = unwrap_ret!(inp.unprojected);
     let mut interpolation_points: Vec<Vec3> = Vec::new();
     let nosnapping = inp.act.contains(&InputAction::NoSnapping);
@@ -357,13 +371,89 @@ pub fn roadbuild(sim: &Simulation, uiworld: &UiWorld) {
     }
 }
 
-#[derive(Default)]
+/// Dragging the circle closer than this to the center of the intersection removes the roundabout
+const ROUNDABOUT_REMOVE_RADIUS: f32 = RoundaboutPolicy::MIN_RADIUS * 0.5;
+
+/// Clicking an intersection starts dragging its roundabout circle, clicking again applies the radius
+fn roundabout_edit(
+    state: &mut RoadBuildResource,
+    map: &Map,
+    inp: &mut InputMap,
+    commands: &mut WorldCommands,
+    immdraw: &mut ImmediateDraw,
+) {
+    let unproj = unwrap_ret!(inp.unprojected);
+
+    if inp.just_act.contains(&InputAction::Close) && state.roundabout_edit.is_some() {
+        inp.just_act.remove(&InputAction::Close);
+        state.roundabout_edit = None;
+    }
+
+    let Some(id) = state.roundabout_edit else {
+        let hovered = match map.project(unproj, 10.0, ProjectFilter::INTER).kind {
+            Intersection(id) => Some(id),
+            _ => None,
+        };
+        state.update_drawing_roundabout(map, immdraw, hovered);
+        if let Some(id) = hovered {
+            if inp.just_act.contains(&InputAction::Select) {
+                state.roundabout_edit = Some(id);
+            }
+        }
+        return;
+    };
+
+    let Some(inter) = map.intersections().get(id) else {
+        state.roundabout_edit = None;
+        return;
+    };
+
+    let dist = unproj.xy().distance(inter.pos.xy());
+    let radius = (dist >= ROUNDABOUT_REMOVE_RADIUS)
+        .then(|| dist.clamp(RoundaboutPolicy::MIN_RADIUS, RoundaboutPolicy::MAX_RADIUS));
+    if let Some(r) = radius {
+        state.roundabout_radius = Some(r);
+    }
+    state.update_drawing_roundabout(map, immdraw, Some(id));
+    if radius.is_none() {
+        immdraw
+            .circle(inter.pos.up(0.5), ROUNDABOUT_REMOVE_RADIUS)
+            .color(simulation::colors().gui_danger);
+    }
+
+    if inp.just_act.contains(&InputAction::Select) {
+        commands.map_set_roundabout(id, radius);
+        state.roundabout_edit = None;
+    }
+}
+
 pub struct RoadBuildResource {
     pub build_state: BuildState,
     pub pattern_builder: LanePatternBuilder,
     pub snapping: Snapping,
     pub height_offset: f32,
     pub height_reference: HeightReference,
+    /// When set, clicking an intersection turns it into a roundabout instead of building a road
+    pub roundabout_radius: Option<f32>,
+    /// Intersection whose roundabout circle is being dragged
+    pub roundabout_edit: Option<IntersectionID>,
+    /// Spacing of the snapping grid in meters, also how close the mouse has to be to snap to an angle
+    pub grid_size: f32,
+}
+
+impl Default for RoadBuildResource {
+    fn default() -> Self {
+        Self {
+            build_state: Default::default(),
+            pattern_builder: Default::default(),
+            snapping: Default::default(),
+            height_offset: 0.0,
+            height_reference: Default::default(),
+            roundabout_radius: None,
+            roundabout_edit: None,
+            grid_size: DEFAULT_GRID_SIZE,
+        }
+    }
 }
 
 #[derive(Default, Clone, Copy)]
@@ -550,6 +640,35 @@ impl RoadBuildResource {
             .color(col);
     }
 
+    /// The roundabout circle around the intersection, red if the roads wouldn't fit around it
+    pub fn update_drawing_roundabout(
+        &self,
+        map: &Map,
+        immdraw: &mut ImmediateDraw,
+        inter: Option<IntersectionID>,
+    ) {
+        let (Some(radius), Some(inter)) = (
+            self.roundabout_radius,
+            inter.and_then(|id| map.intersections().get(id)),
+        ) else {
+            return;
+        };
+        let col = if radius >= RoundaboutPolicy::min_radius(inter, map.roads()) {
+            simulation::colors().gui_primary
+        } else {
+            simulation::colors().gui_danger
+        };
+
+        let center = inter.pos.up(0.5);
+        let poly: Vec<Vec3> = (0..=32)
+            .map(|i| {
+                let ang = std::f32::consts::TAU * i as f32 / 32.0;
+                center + Vec3::from_angle(ang, 0.0) * radius
+            })
+            .collect();
+        immdraw.polyline(poly, 1.5, true).color(col);
+    }
+
     pub fn possible_interpolations(&self, map: &Map, mousepos: Vec3) -> Option<Vec<Vec3>> {
         let (start, end) = match self.build_state {
             Hover | Curved(_, _) => {
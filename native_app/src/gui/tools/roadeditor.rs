@@ -54,6 +54,20 @@ pub fn roadeditor(sim: &Simulation, uiworld: &UiWorld) {
 
                 imm_draw.polyline(p, 1.0, false).color(col);
             }
+
+            for &road in &inter.roads {
+                let road = unwrap_cont!(map.roads().get(road));
+                for lane in road.reversible_lanes() {
+                    let lane = unwrap_cont!(lanes.get(lane));
+                    imm_draw
+                        .polyline(
+                            lane.points.iter().map(|p| p.up(0.05)).collect::<Vec<_>>(),
+                            0.5,
+                            false,
+                        )
+                        .color(simulation::colors().gui_primary.a(0.5));
+                }
+            }
         } else {
             state.inspect = None;
         }
@@ -89,6 +103,34 @@ pub fn roadeditor(sim: &Simulation, uiworld: &UiWorld) {
 
     imm_draw.circle(proj_pos.up(0.5), 10.0).color(proj_col);
 
+    // the lanes next to the middle of the inspected intersection's roads can be reversed
+    if let (Some(interc), ProjectKind::Ground) = (&state.inspect, cur_proj.kind) {
+        let hovered = map.lane_at(proj_pos.xy(), 3.0).and_then(|(id, _)| {
+            let lane = map.lanes().get(id)?;
+            let road = map.roads().get(lane.parent)?;
+            (road.src == interc.id || road.dst == interc.id).then_some((lane, road))
+        });
+        if let Some((lane, road)) = hovered {
+            let reversible = road.reversible_lanes().any(|id| id == lane.id);
+            let col = if reversible {
+                simulation::colors().gui_primary
+            } else {
+                simulation::colors().gui_disabled
+            };
+            imm_draw
+                .polyline(
+                    lane.points.iter().map(|p| p.up(0.1)).collect::<Vec<_>>(),
+                    lane.width,
+                    false,
+                )
+                .color(col);
+
+            if reversible && inp.just_act.contains(&InputAction::Select) {
+                commands.map_set_lane_direction(lane.id, lane.direction(road).opposite());
+            }
+        }
+    }
+
     if state.dirty {
         if let Some(interc) = &state.inspect {
             commands.map_update_intersection_policy(
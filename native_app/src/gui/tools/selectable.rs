@@ -1,10 +1,13 @@
-use crate::gui::{InspectedBuilding, InspectedEntity, Tool};
+use crate::gui::{InspectedBuilding, InspectedEntity, MapObject, SelectedMapObject, Tool};
 use crate::inputmap::{InputAction, InputMap};
 use crate::uiworld::UiWorld;
 use geom::Vec2;
-use simulation::map::ProjectFilter;
+use simulation::map::{Map, ProjectFilter, ProjectKind, RoadID};
 use simulation::{AnyEntity, Simulation};
 
+/// How far from a lane a click still selects it, in meters
+const LANE_SELECT_DIST: f32 = 3.0;
+
 pub fn select_radius(id: AnyEntity) -> f32 {
     match id {
         AnyEntity::VehicleID(_) => 5.0,
@@ -16,11 +19,34 @@ pub fn select_radius(id: AnyEntity) -> f32 {
     }
 }
 
-/// Selectable allows to select entities by clicking on them
+/// The road or intersection at pos. Intersections come first since roads end inside of them.
+pub fn map_object_at(map: &Map, pos: Vec2) -> Option<MapObject> {
+    let mut road = None;
+    for kind in map
+        .spatial_map()
+        .query(pos, ProjectFilter::INTER | ProjectFilter::ROAD)
+    {
+        match kind {
+            ProjectKind::Intersection(id) => return Some(MapObject::Intersection(id)),
+            ProjectKind::Road(id) => road = Some(road.map_or(id, |r: RoadID| r.min(id))),
+            _ => {}
+        }
+    }
+    let road = road?;
+    let lane = map
+        .lane_at(pos, LANE_SELECT_DIST)
+        .map(|(lane, _)| lane)
+        .filter(|&lane| map.lanes()[lane].parent == road);
+    Some(MapObject::Road { road, lane })
+}
+
+/// Selectable allows to select entities by clicking on them,
+/// and roads or intersections when there is no entity or building there
 pub fn selectable(sim: &Simulation, uiworld: &UiWorld) {
     profiling::scope!("gui::selectable");
     let mut inspected = uiworld.write::<InspectedEntity>();
     let mut inspected_b = uiworld.write::<InspectedBuilding>();
+    let mut selected_map = uiworld.write::<SelectedMapObject>();
     let inp = uiworld.read::<InputMap>();
     let tool = uiworld.read::<Tool>();
 
@@ -61,8 +87,20 @@ pub fn selectable(sim: &Simulation, uiworld: &UiWorld) {
                 .find_map(|x| x.as_building());
         }
     }
+
+    if inp.just_act.contains(&InputAction::Select)
+        && matches!(*tool, Tool::Hand)
+        && !selected_map.dontclear
+    {
+        selected_map.obj = None;
+        if inspected.e.is_none() && inspected_b.e.is_none() {
+            let unproj = unwrap_ret!(inp.unprojected);
+            selected_map.obj = map_object_at(&sim.map(), unproj.xy());
+        }
+    }
     inspected.dontclear = false;
     inspected_b.dontclear = false;
+    selected_map.dontclear = false;
 
     if let Some(e) = inspected.e {
         if !sim.world().contains(e) {
@@ -76,8 +114,61 @@ pub fn selectable(sim: &Simulation, uiworld: &UiWorld) {
         }
     }
 
+    if let Some(obj) = selected_map.obj {
+        let map = sim.map();
+        let exists = match obj {
+            MapObject::Road { road, .. } => map.roads().contains_key(road),
+            MapObject::Intersection(id) => map.intersections().contains_key(id),
+        };
+        if !exists {
+            selected_map.obj = None;
+        }
+    }
+
     if inp.just_act.contains(&InputAction::Close) || matches!(*tool, Tool::Bulldozer) {
         inspected.e = None;
         inspected_b.e = None;
+        selected_map.obj = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::map_object_at;
+    use crate::gui::MapObject;
+    use geom::vec2;
+    use simulation::map::{LaneKind, LanePatternBuilder, MapBuilder};
+
+    #[test]
+    fn click_selects_road_or_intersection() {
+        let pat = LanePatternBuilder::new().parking(false).build();
+        let mut b = MapBuilder::new();
+        let a = b.add_inter(vec2(0.0, 0.0));
+        let end = b.add_inter(vec2(200.0, 0.0));
+        let road = b.connect(a, end, &pat).unwrap();
+        let map = b.build();
+
+        // mid-span is the road, on the lane that was clicked
+        let lane = map.roads()[road]
+            .lanes_iter()
+            .find(|&(_, kind)| kind == LaneKind::Driving)
+            .map(|(id, _)| id)
+            .unwrap();
+        let on_lane = map.lanes()[lane].points.point_along(100.0).xy();
+        assert_eq!(
+            map_object_at(&map, on_lane),
+            Some(MapObject::Road {
+                road,
+                lane: Some(lane),
+            })
+        );
+
+        // the center of the intersection is the intersection, even though the road reaches it
+        assert_eq!(
+            map_object_at(&map, vec2(200.0, 0.0)),
+            Some(MapObject::Intersection(end))
+        );
+
+        assert_eq!(map_object_at(&map, vec2(100.0, 300.0)), None);
     }
 }
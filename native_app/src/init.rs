@@ -17,11 +17,12 @@ use crate::gui::windows::settings::{Settings, SettingsState};
 use crate::gui::zoneedit::ZoneEditState;
 use crate::gui::{
     ErrorTooltip, ExitState, GuiState, InspectedBuilding, InspectedEntity, PotentialCommands,
-    TimeAlways, Tool,
+    SelectedMapObject, TimeAlways, Tool,
 };
 use crate::inputmap::{Bindings, InputMap};
 use crate::network::NetworkState;
 use crate::rendering::immediate::{ImmediateDraw, ImmediateSound};
+use crate::rendering::quality::QualityManager;
 use crate::uiworld::{ReceivedCommands, SaveLoadState, UiWorld};
 use common::saveload::Encoder;
 use serde::de::DeserializeOwned;
@@ -37,8 +38,10 @@ pub fn init() {
     register_resource::<crate::gui::windows::network::NetworkConnectionInfo>("netinfo");
     register_resource::<LotBrushResource>("lot_brush");
     register_resource::<Bindings>("bindings");
+    register_resource::<GuiState>("gui_state");
+    // if the saved tool doesn't exist anymore, loading fails and the default hand tool is kept
+    register_resource::<Tool>("tool");
 
-    register_resource_noserialize::<GuiState>();
     register_resource_noserialize::<TerraformingResource>();
     register_resource_noserialize::<BulldozerState>();
     register_resource_noserialize::<DebugObjs>();
@@ -53,6 +56,7 @@ pub fn init() {
     register_resource_noserialize::<InputMap>();
     register_resource_noserialize::<InspectedEntity>();
     register_resource_noserialize::<InspectedBuilding>();
+    register_resource_noserialize::<SelectedMapObject>();
     register_resource_noserialize::<NetworkState>();
     register_resource_noserialize::<PotentialCommands>();
     register_resource_noserialize::<ZoneEditState>();
@@ -63,7 +67,7 @@ pub fn init() {
     register_resource_noserialize::<SpecialBuildingResource>();
     register_resource_noserialize::<TrainSpawnResource>();
     register_resource_noserialize::<Timings>();
-    register_resource_noserialize::<Tool>();
+    register_resource_noserialize::<QualityManager>();
     register_resource_noserialize::<WorldCommands>();
     register_resource_noserialize::<LoadState>();
     register_resource_noserialize::<SaveLoadState>();
@@ -103,10 +107,19 @@ fn register_resource<T: 'static + Default + Serialize + DeserializeOwned>(name:
                 <common::saveload::JSONPretty as Encoder>::save(&*uiworld.read::<T>(), name);
             }),
             load: Box::new(move |uiworld| {
-                if let Ok(res) = <common::saveload::JSON as Encoder>::load::<T>(name) {
-                    uiworld.insert(res);
-                }
+                load_resource(
+                    uiworld,
+                    <common::saveload::JSON as Encoder>::load::<T>(name),
+                );
             }),
         });
     }
 }
+
+/// Replaces the resource with the loaded one, the current one (the default at startup) is kept
+/// if it couldn't be loaded
+pub(crate) fn load_resource<T: 'static>(uiworld: &mut UiWorld, loaded: std::io::Result<T>) {
+    if let Ok(res) = loaded {
+        uiworld.insert(res);
+    }
+}
@@ -43,6 +43,8 @@ pub enum InputAction {
     OpenDebugMenu,
     PausePlay,
     OpenChat,
+    Undo,
+    Redo,
 }
 
 // All unit inputs need to match
@@ -107,6 +109,8 @@ const DEFAULT_BINDINGS: &[(InputAction, &[&[UnitInput]])] = &[
     (OpenDebugMenu,   &[&[Key(K::F3)]]),
     (PausePlay,       &[&[Key(K::Space)]]),
     (OpenChat,        &[&[Key(K::c("T"))]]),
+    (Undo,            &[&[Key(K::Control), Key(K::c("Z"))]]),
+    (Redo,            &[&[Key(K::Control), Key(K::c("Y"))]]),
 ];
 
 impl Default for Bindings {
@@ -394,6 +398,8 @@ impl Display for InputAction {
                 SizeUp => "Size Up",
                 SizeDown => "Size Down",
                 OpenDebugMenu => "Debug Menu",
+                Undo => "Undo",
+                Redo => "Redo",
             }
         )
     }
@@ -20,6 +20,8 @@ mod init;
 mod inputmap;
 mod network;
 mod rendering;
+#[cfg(feature = "trace")]
+mod trace;
 
 fn main() {
     #[cfg(feature = "profile")]
@@ -1,8 +1,10 @@
 pub use self::inner::*;
 use crate::game_loop::{State, Timings};
 use crate::gui::windows::settings::Settings;
+use crate::rendering::OrbitCamera;
 use crate::uiworld::{ReceivedCommands, SaveLoadState};
 use common::timestep::Timestep;
+use simulation::souls::decision_lod::DecisionLod;
 use simulation::utils::scheduler::SeqSchedule;
 use simulation::world_command::{WorldCommand, WorldCommands};
 use simulation::Simulation;
@@ -43,6 +45,18 @@ fn handle_singleplayer(state: &mut State) {
         return;
     }
 
+    // souls far from the camera decide less often. The focus is only sent when the camera moved enough
+    // so it doesn't flood replays
+    let cam = state.uiw.read::<OrbitCamera>().targetpos;
+    let lod = sim.read::<DecisionLod>();
+    if !lod
+        .focus
+        .is_some_and(|focus| focus.is_close(cam, lod.near_dist * 0.25))
+    {
+        commands.set_decision_focus(Some(cam));
+    }
+    drop(lod);
+
     let sched = &mut state.game_schedule;
     let mut timings = state.uiw.write::<Timings>();
 
@@ -109,6 +123,8 @@ mod inner {
         ConnectConf, Frame, PollResult, ServerConfiguration, ServerPollResult, VirtualClientConf,
     };
     use prototypes::DELTA_F64;
+    use simulation::multiplayer::PlayerID;
+    use simulation::souls::decision_lod::DecisionLod;
     use simulation::world_command::WorldCommands;
     use simulation::Simulation;
     use std::net::ToSocketAddrs;
@@ -136,7 +152,11 @@ mod inner {
 
         let mut sim = unwrap_orr!(state.sim.try_write(), return); // mut for tick
 
-        let commands = std::mem::take(&mut *state.uiw.write::<WorldCommands>());
+        let mut commands = std::mem::take(&mut *state.uiw.write::<WorldCommands>());
+        // each player has their own camera, so all souls are simulated in full detail
+        if sim.read::<DecisionLod>().focus.is_some() {
+            commands.set_decision_focus(None);
+        }
         *state.uiw.write::<ReceivedCommands>() = ReceivedCommands::default();
 
         if handle_replay(
@@ -200,7 +220,7 @@ mod inner {
                 let commands: WorldCommands = frame_commands
                     .inputs
                     .iter()
-                    .map(|x| x.inp.clone())
+                    .map(|x| x.inp.clone().sent_by(PlayerID(x.sent_by)))
                     .collect();
                 let t = sim.tick(&mut state.game_schedule, commands.as_ref());
                 state
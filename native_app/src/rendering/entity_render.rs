@@ -67,16 +67,16 @@ impl InstancedRender {
         self.pedestrians.instances.clear();
         for v in sim.world().vehicles.values() {
             let trans = &v.trans;
+            let (scale, _) = v.vehicle.kind.appearance();
             let instance = MeshInstance {
                 pos: trans.pos,
-                dir: trans.dir,
+                dir: trans.dir * scale.x,
                 tint: v.vehicle.tint.into(),
             };
 
             match v.vehicle.kind {
-                VehicleKind::Car => self.cars.instances.push(instance),
-                VehicleKind::Truck => self.trucks.instances.push(instance),
-                _ => {}
+                VehicleKind::Car | VehicleKind::Emergency => self.cars.instances.push(instance),
+                VehicleKind::Truck | VehicleKind::Bus => self.trucks.instances.push(instance),
             }
         }
 
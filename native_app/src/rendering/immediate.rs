@@ -67,10 +67,17 @@ pub struct ImmediateOrder {
     pub color: LinearColor,
 }
 
+/// Handle to an order added with [`ImmediateDraw::persistent`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DebugHandle(u64);
+
 #[derive(Default)]
 pub struct ImmediateDraw {
     pub orders: Vec<ImmediateOrder>,
     pub persistent_orders: Vec<ImmediateOrder>,
+    /// Orders kept for a number of frames, with the number of frames left
+    timed_orders: Vec<(DebugHandle, u32, ImmediateOrder)>,
+    next_handle: u64,
     pub mesh_cache: FastMap<PathBuf, InstancedMeshBuilder<true>>,
 }
 
@@ -199,9 +206,42 @@ impl ImmediateDraw {
         self.persistent_orders.clear();
     }
 
+    /// Draws the order for the next `frames` frames, or until it is removed
+    pub fn persistent(&mut self, order: ImmediateOrder, frames: u32) -> DebugHandle {
+        let handle = DebugHandle(self.next_handle);
+        self.next_handle += 1;
+        if frames > 0 {
+            self.timed_orders.push((handle, frames, order));
+        }
+        handle
+    }
+
+    /// Removes a persistent order, returns false if it already expired
+    pub fn remove(&mut self, handle: DebugHandle) -> bool {
+        let len = self.timed_orders.len();
+        self.timed_orders.retain(|(h, _, _)| *h != handle);
+        self.timed_orders.len() != len
+    }
+
+    pub fn contains(&self, handle: DebugHandle) -> bool {
+        self.timed_orders.iter().any(|(h, _, _)| *h == handle)
+    }
+
+    /// Clears the orders of this frame and expires the persistent ones
+    pub fn end_frame(&mut self) {
+        self.orders.clear();
+        self.timed_orders.retain_mut(|(_, frames, _)| {
+            *frames -= 1;
+            *frames > 0
+        });
+    }
+
     pub fn apply(&mut self, tess: &mut Tesselator, ctx: &mut FrameContext<'_>) {
-        for ImmediateOrder { kind, color } in
-            self.persistent_orders.iter().chain(self.orders.iter())
+        for ImmediateOrder { kind, color } in self
+            .persistent_orders
+            .iter()
+            .chain(self.timed_orders.iter().map(|(_, _, order)| order))
+            .chain(self.orders.iter())
         {
             tess.set_color(*color);
             match *kind {
@@ -280,3 +320,37 @@ impl ImmediateDraw {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ImmediateDraw, ImmediateOrder, OrderKind};
+    use geom::{LinearColor, Vec3};
+
+    fn order() -> ImmediateOrder {
+        ImmediateOrder {
+            kind: OrderKind::Circle {
+                pos: Vec3::ZERO,
+                radius: 1.0,
+            },
+            color: LinearColor::RED,
+        }
+    }
+
+    #[test]
+    fn persistent_expires() {
+        let mut draw = ImmediateDraw::default();
+        let h = draw.persistent(order(), 3);
+
+        for _ in 0..3 {
+            assert!(draw.contains(h));
+            draw.end_frame();
+        }
+        assert!(!draw.contains(h));
+        assert!(draw.timed_orders.is_empty());
+
+        let h = draw.persistent(order(), 10);
+        assert!(draw.remove(h));
+        assert!(!draw.contains(h));
+        assert!(!draw.remove(h));
+    }
+}
@@ -10,8 +10,9 @@ use geom::{minmax, vec2, vec3, Color, LinearColor, PolyLine3, Polygon, Radians,
 use prototypes::{FreightStationPrototype, GoodsCompanyPrototype, RenderAsset};
 use simulation::map::{
     Building, BuildingKind, CanonicalPosition, Environment, Intersection, LaneKind, Lanes, LotKind,
-    Map, MapSubscriber, ProjectFilter, ProjectKind, PylonPosition, Road, Roads, SubscriberChunkID,
-    Turn, TurnKind, UpdateType, CROSSWALK_WIDTH, ROAD_Z_OFFSET,
+    Map, MapSubscriber, ProjectFilter, ProjectKind, PylonPosition, Road, RoadMaterial, Roads,
+    SubscriberChunkID, Turn, TurnKind, UpdateType, CROSSWALK_WIDTH, REFUGE_LENGTH, REFUGE_WIDTH,
+    ROAD_Z_OFFSET,
 };
 use simulation::Simulation;
 use std::ops::{Mul, Neg};
@@ -328,7 +329,7 @@ impl MapBuilders {
         for turn in inter.turns() {
             let id = turn.id;
 
-            if matches!(turn.kind, TurnKind::Crosswalk) {
+            if turn.kind.is_crosswalk() {
                 let from = lanes[id.src].get_inter_node_pos(inter.id).up(0.01);
                 let to = lanes[id.dst].get_inter_node_pos(inter.id).up(0.01);
 
@@ -640,6 +641,10 @@ impl MapBuilders {
             let last_dir = unwrap_cont!(cut.last_dir());
 
             road_pylons(&mut tess_map, env, road);
+            if road.closed {
+                road_barriers(&mut tess_map, road);
+            }
+            let mid_col = material_col(road.material, mid_col);
 
             tess_map.normal.z = -1.0;
             tess_map.draw_polyline_full(
@@ -680,6 +685,7 @@ impl MapBuilders {
                     );
                     start = false;
                 }
+                let off = l.dist_from_bottom - road.width * 0.5 + l.width * 0.5;
                 draw_off(
                     &mut tess_map,
                     match l.kind {
@@ -687,14 +693,17 @@ impl MapBuilders {
                         LaneKind::Parking => low_col,
                         _ => mid_col,
                     },
-                    l.kind.width() - 0.25,
-                    l.dist_from_bottom - road.width * 0.5 + l.kind.width() * 0.5,
+                    l.width - 0.25,
+                    off,
                 );
+                if l.kind.is_tram() {
+                    Self::draw_rail(&mut tess_map, cut, off, true);
+                }
                 draw_off(
                     &mut tess_map,
                     line_col,
                     0.25,
-                    l.dist_from_bottom - road.width * 0.5 + l.kind.width(),
+                    l.dist_from_bottom - road.width * 0.5 + l.width,
                 );
             }
         }
@@ -718,6 +727,27 @@ impl MapBuilders {
 
             Self::crosswalks(&mut self.crosswalk_builder, inter, lanes);
 
+            // Refuge islands of two-stage crossings
+            for turn in inter.turns() {
+                let Some(refuge) = turn.refuge() else {
+                    continue;
+                };
+                let Some(across) = (turn.points.last() - turn.points.first())
+                    .xy()
+                    .try_normalize()
+                else {
+                    continue;
+                };
+                let along = across.perpendicular();
+                let half = along.z0() * REFUGE_LENGTH * 0.5;
+                let island = [refuge - half, refuge + half];
+
+                tess_map.set_color(line_col);
+                tess_map.draw_polyline_with_dir(&island, along, along, REFUGE_WIDTH + 0.5);
+                tess_map.set_color(hig_col);
+                tess_map.draw_polyline_with_dir(&island, along, along, REFUGE_WIDTH);
+            }
+
             inter_pylon(&mut tess_map, env, inter, roads);
             intersection_mesh(&mut tess_map, &hig_col, inter, roads);
 
@@ -729,7 +759,7 @@ impl MapBuilders {
                 tess_map.set_color(line_col);
                 let id = turn.id;
 
-                let w = lanes[id.src].kind.width();
+                let w = lanes[id.src].width;
 
                 let first_dir = -lanes[id.src].orientation_from(id.parent);
                 let last_dir = lanes[id.dst].orientation_from(id.parent);
@@ -849,12 +879,47 @@ fn add_polyon(
     quad(3, 0, 7, 4, d2p);
 }
 
+/// Color of the driving surface depending on what the road is made of
+fn material_col(material: RoadMaterial, asphalt: LinearColor) -> LinearColor {
+    match material {
+        RoadMaterial::Asphalt => asphalt,
+        RoadMaterial::Cobblestone => LinearColor::from(Color::from_hex(0x6b_63_5a)),
+        RoadMaterial::Dirt => LinearColor::from(Color::from_hex(0x7a_5c_3e)),
+        RoadMaterial::Gravel => LinearColor::from(Color::from_hex(0x8c_86_7a)),
+    }
+}
+
 fn road_pylons(meshb: &mut Tesselator, env: &Environment, road: &Road) {
     for pylon in Road::pylons_positions(road.interfaced_points(), env) {
         add_polyon(meshb, road.width * 0.5, pylon);
     }
 }
 
+/// Red and white stripes across both ends of a road closed for maintenance
+fn road_barriers(tess: &mut Tesselator, road: &Road) {
+    const N_STRIPES: usize = 6;
+    let red: LinearColor = LinearColor::new(0.8, 0.1, 0.1, 1.0);
+    let white: LinearColor = LinearColor::WHITE;
+
+    let cut = road.interfaced_points();
+    let ends = [
+        cut.first_dir().map(|d| (cut.first(), d)),
+        cut.last_dir().map(|d| (cut.last(), -d)),
+    ];
+
+    for (pos, dir) in ends.into_iter().flatten() {
+        let center = pos + dir * 1.5;
+        let perp = dir.xy().perpendicular().z0() * road.width;
+        let start = center - perp * 0.5;
+        let step = perp / N_STRIPES as f32;
+        for i in 0..N_STRIPES {
+            tess.set_color(if i % 2 == 0 { red } else { white });
+            let a = start + step * i as f32;
+            tess.draw_stroke(a.up(0.05), (a + step).up(0.05), 0.6);
+        }
+    }
+}
+
 fn inter_pylon(tess: &mut Tesselator, env: &Environment, inter: &Intersection, roads: &Roads) {
     let interpos = inter.pos.up(ROAD_Z_OFFSET);
 
@@ -905,6 +970,12 @@ fn intersection_mesh(
         }
     };
 
+    if let Some(radius) = inter.turnaround() {
+        tess.set_color(LinearColor::from(simulation::colors().road_mid_col));
+        tess.draw_circle(interpos.xy().z(interpos.z - 0.001), radius);
+        return;
+    }
+
     let mut polygon = Polygon::default();
 
     for (i, &road) in inter.roads.iter().enumerate() {
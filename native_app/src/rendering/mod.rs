@@ -6,3 +6,4 @@ mod entity_render;
 pub mod immediate;
 mod map_rendering;
 mod orbit_camera;
+pub mod quality;
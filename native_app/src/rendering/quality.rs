@@ -0,0 +1,187 @@
+use engine::{GfxSettings, ShadowQuality};
+
+/// Frame time budget in seconds, 30 FPS
+const DEFAULT_BUDGET: f32 = 1.0 / 30.0;
+/// Weight of the new frame in the smoothed frame time
+const SMOOTHING: f32 = 0.1;
+/// Quality is restored only once frames take less than this fraction of the budget
+const RECOVER_RATIO: f32 = 0.6;
+/// Consecutive frames over budget before the quality goes down
+const FRAMES_TO_DEGRADE: u32 = 30;
+/// Consecutive frames well under budget before the quality goes up, longer so it doesn't flicker
+const FRAMES_TO_RECOVER: u32 = 180;
+
+/// How much rendering is scaled down from the user's settings, from the best to the cheapest
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum QualityLevel {
+    Full,
+    Reduced,
+    Minimal,
+}
+
+impl QualityLevel {
+    fn lower(self) -> Self {
+        match self {
+            QualityLevel::Full => QualityLevel::Reduced,
+            QualityLevel::Reduced | QualityLevel::Minimal => QualityLevel::Minimal,
+        }
+    }
+
+    fn higher(self) -> Self {
+        match self {
+            QualityLevel::Full | QualityLevel::Reduced => QualityLevel::Full,
+            QualityLevel::Minimal => QualityLevel::Reduced,
+        }
+    }
+
+    /// The user's settings scaled down, nothing the user disabled is ever enabled
+    pub fn apply(self, mut settings: GfxSettings) -> GfxSettings {
+        if self >= QualityLevel::Reduced {
+            settings.msaa = false;
+            settings.ssao = false;
+        }
+        if self >= QualityLevel::Minimal {
+            settings.terrain_grid = false;
+            if settings.shadows != ShadowQuality::NoShadows {
+                settings.shadows = ShadowQuality::Low;
+            }
+        }
+        settings
+    }
+
+    /// See [`engine::GfxContext::lod_bias`]
+    pub fn lod_bias(self) -> f32 {
+        match self {
+            QualityLevel::Full => 1.0,
+            QualityLevel::Reduced => 0.5,
+            QualityLevel::Minimal => 0.25,
+        }
+    }
+
+    /// Whether optional overlays such as the lane arrows are drawn
+    pub fn overlays(self) -> bool {
+        self < QualityLevel::Minimal
+    }
+}
+
+/// Lowers the rendering quality when frames take too long and restores it once they're fast again
+pub struct QualityManager {
+    /// Frame time budget in seconds
+    pub budget: f32,
+    level: QualityLevel,
+    /// Smoothed render time
+    avg: f32,
+    over: u32,
+    under: u32,
+}
+
+impl Default for QualityManager {
+    fn default() -> Self {
+        Self {
+            budget: DEFAULT_BUDGET,
+            level: QualityLevel::Full,
+            avg: 0.0,
+            over: 0,
+            under: 0,
+        }
+    }
+}
+
+impl QualityManager {
+    pub fn level(&self) -> QualityLevel {
+        self.level
+    }
+
+    /// Feeds the render time of the last frame in seconds, returns the level to render the next one at.
+    /// Between the budget and its recover ratio the level doesn't change, so it doesn't go back and forth.
+    pub fn update(&mut self, render_time: f32) -> QualityLevel {
+        self.avg += (render_time - self.avg) * SMOOTHING;
+
+        if self.avg > self.budget {
+            self.over += 1;
+            self.under = 0;
+        } else if self.avg < self.budget * RECOVER_RATIO {
+            self.under += 1;
+            self.over = 0;
+        } else {
+            self.over = 0;
+            self.under = 0;
+        }
+
+        if self.over >= FRAMES_TO_DEGRADE {
+            self.level = self.level.lower();
+            self.over = 0;
+        }
+        if self.under >= FRAMES_TO_RECOVER {
+            self.level = self.level.higher();
+            self.under = 0;
+        }
+        self.level
+    }
+
+    /// Back to full quality, for when the manager is disabled
+    pub fn reset(&mut self) {
+        *self = Self {
+            budget: self.budget,
+            ..Self::default()
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{QualityLevel, QualityManager};
+
+    #[test]
+    fn steps_down_under_load_and_recovers() {
+        let mut q = QualityManager::default();
+        let fast = q.budget * 0.3;
+        let slow = q.budget * 2.0;
+        let borderline = q.budget * 0.8;
+
+        for _ in 0..100 {
+            assert_eq!(q.update(fast), QualityLevel::Full);
+        }
+
+        // sustained load goes down one level at a time, down to the minimum
+        let mut levels = vec![];
+        for _ in 0..200 {
+            let l = q.update(slow);
+            if levels.last() != Some(&l) {
+                levels.push(l);
+            }
+        }
+        assert_eq!(
+            levels,
+            vec![
+                QualityLevel::Full,
+                QualityLevel::Reduced,
+                QualityLevel::Minimal
+            ]
+        );
+
+        // a single fast frame changes nothing
+        assert_eq!(q.update(fast), QualityLevel::Minimal);
+
+        // frames just under budget are not fast enough to go back up
+        for _ in 0..1000 {
+            assert_eq!(q.update(borderline), QualityLevel::Minimal);
+        }
+
+        let mut levels = vec![];
+        for _ in 0..1000 {
+            let l = q.update(fast);
+            if levels.last() != Some(&l) {
+                levels.push(l);
+            }
+        }
+        assert_eq!(
+            levels,
+            vec![
+                QualityLevel::Minimal,
+                QualityLevel::Reduced,
+                QualityLevel::Full
+            ]
+        );
+    }
+}
@@ -0,0 +1,210 @@
+//! Records the `profiling::scope!` spans and exports them as a chrome://tracing JSON file,
+//! which can also be opened in https://ui.perfetto.dev.
+//! Only compiled with the `trace` feature, which routes the profiling macros to `tracing`.
+//! Without it the scopes compile to nothing, so tracing costs nothing in normal builds.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+use tracing::span::{Attributes, Id, Record};
+use tracing::subscriber::Interest;
+use tracing::{Event, Metadata, Subscriber};
+
+/// Checked by the subscriber for every span, so no span is created while not tracing
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+static TRACE: Mutex<Option<Trace>> = Mutex::new(None);
+/// Whether the subscriber could be installed, there can only be one global subscriber
+static INSTALLED: OnceLock<bool> = OnceLock::new();
+static NEXT_THREAD: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    static THREAD_ID: u64 = NEXT_THREAD.fetch_add(1, Ordering::Relaxed);
+}
+
+struct Trace {
+    path: PathBuf,
+    start: Instant,
+    events: Vec<TraceEvent>,
+}
+
+struct TraceEvent {
+    name: &'static str,
+    begin: bool,
+    thread: u64,
+    /// Microseconds since the start of the trace
+    ts: f64,
+}
+
+/// Starts recording the spans, they are written to `path` by [`stop_trace`].
+/// Returns false if a trace is already being recorded or the recorder couldn't be installed.
+pub fn start_trace(path: impl AsRef<Path>) -> bool {
+    let installed = *INSTALLED.get_or_init(|| {
+        tracing::subscriber::set_global_default(ChromeSubscriber::default()).is_ok()
+    });
+    if !installed {
+        return false;
+    }
+
+    let mut trace = TRACE.lock().unwrap();
+    if trace.is_some() {
+        return false;
+    }
+    *trace = Some(Trace {
+        path: path.as_ref().to_path_buf(),
+        start: Instant::now(),
+        events: vec![],
+    });
+    ACTIVE.store(true, Ordering::SeqCst);
+    true
+}
+
+pub fn is_tracing() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+/// Stops recording and writes the trace file, returns the number of events written
+pub fn stop_trace() -> std::io::Result<usize> {
+    ACTIVE.store(false, Ordering::SeqCst);
+    let Some(trace) = TRACE.lock().unwrap().take() else {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            "no trace is being recorded",
+        ));
+    };
+
+    let mut w = BufWriter::new(File::create(&trace.path)?);
+    write!(w, "{{\"traceEvents\":[")?;
+    for (i, e) in trace.events.iter().enumerate() {
+        if i > 0 {
+            write!(w, ",")?;
+        }
+        write!(
+            w,
+            "{{\"name\":\"{}\",\"ph\":\"{}\",\"ts\":{:.3},\"pid\":1,\"tid\":{}}}",
+            e.name.replace('\\', "\\\\").replace('"', "\\\""),
+            if e.begin { "B" } else { "E" },
+            e.ts,
+            e.thread
+        )?;
+    }
+    write!(w, "]}}")?;
+    w.flush()?;
+
+    Ok(trace.events.len())
+}
+
+#[derive(Default)]
+struct ChromeSubscriber {
+    next_id: AtomicU64,
+    /// Names of the spans alive, by id
+    names: Mutex<HashMap<u64, &'static str>>,
+}
+
+impl ChromeSubscriber {
+    fn push(&self, span: &Id, begin: bool) {
+        let Some(&name) = self.names.lock().unwrap().get(&span.into_u64()) else {
+            return;
+        };
+        let mut trace = TRACE.lock().unwrap();
+        let Some(trace) = trace.as_mut() else {
+            return;
+        };
+        let ts = trace.start.elapsed().as_secs_f64() * 1_000_000.0;
+        trace.events.push(TraceEvent {
+            name,
+            begin,
+            thread: THREAD_ID.with(|id| *id),
+            ts,
+        });
+    }
+}
+
+impl Subscriber for ChromeSubscriber {
+    fn register_callsite(&self, _: &'static Metadata<'static>) -> Interest {
+        // tracing can be turned on and off, so every span has to ask
+        Interest::sometimes()
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.is_span() && ACTIVE.load(Ordering::Relaxed)
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        self.names
+            .lock()
+            .unwrap()
+            .insert(id, span.metadata().name());
+        Id::from_u64(id)
+    }
+
+    fn record(&self, _: &Id, _: &Record<'_>) {}
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, _: &Event<'_>) {}
+
+    fn enter(&self, span: &Id) {
+        self.push(span, true);
+    }
+
+    fn exit(&self, span: &Id) {
+        self.push(span, false);
+    }
+
+    fn try_close(&self, span: Id) -> bool {
+        self.names.lock().unwrap().remove(&span.into_u64());
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{start_trace, stop_trace};
+    use simulation::world_command::WorldCommands;
+    use simulation::{Simulation, SimulationOptions};
+
+    #[test]
+    fn trace_simulation_ticks() {
+        simulation::init::init();
+        let mut sim = Simulation::new_with_options(SimulationOptions {
+            terrain_size: 0,
+            save_replay: false,
+            ..Default::default()
+        });
+        let mut schedule = Simulation::schedule();
+        let path = std::env::temp_dir().join("native_app_trace_test.json");
+
+        // not recorded
+        sim.tick(&mut schedule, WorldCommands::default().as_ref());
+
+        assert!(start_trace(&path));
+        assert!(!start_trace(&path));
+        for _ in 0..5 {
+            sim.tick(&mut schedule, WorldCommands::default().as_ref());
+        }
+        let n = stop_trace().unwrap();
+        assert!(stop_trace().is_err());
+
+        let trace = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(n > 0);
+        assert!(trace.starts_with("{\"traceEvents\":[{"));
+        assert!(trace.ends_with("}]}"));
+        assert_eq!(trace.matches("\"ph\":\"B\"").count() * 2, n);
+        assert_eq!(trace.matches("\"ph\":\"E\"").count() * 2, n);
+
+        assert_eq!(
+            trace
+                .matches("\"name\":\"simulation::tick\",\"ph\":\"B\"")
+                .count(),
+            5
+        );
+        assert!(trace.contains("\"name\":\"transportation::give_way_system\""));
+    }
+}
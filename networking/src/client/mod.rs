@@ -28,6 +28,8 @@ pub struct FrameInputs<I> {
 #[derive(Debug)]
 pub struct ServerInput<I> {
     pub sent_by_me: bool,
+    /// Identifies the player who sent the input, the same on every client
+    pub sent_by: u32,
     pub inp: I,
 }
 
@@ -106,6 +106,7 @@ fn decode_merged<I: DeserializeOwned>(
             .flat_map(|(id, x)| {
                 Some(ServerInput {
                     sent_by_me: id == me,
+                    sent_by: id.0,
                     inp: decode(&x.0)?,
                 })
             })
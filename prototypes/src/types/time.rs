@@ -13,6 +13,9 @@ pub const SECONDS_PER_MINUTE: i32 = 60;
 pub const MINUTES_PER_HOUR: i32 = 60;
 pub const HOURS_PER_DAY: i32 = 24;
 pub const SECONDS_PER_DAY: i32 = SECONDS_PER_HOUR * HOURS_PER_DAY;
+pub const DAYS_PER_WEEK: i32 = 7;
+pub const DAYS_PER_SEASON: i32 = 7;
+pub const DAYS_PER_YEAR: i32 = DAYS_PER_SEASON * 4;
 pub const TICKS_PER_REALTIME_SECOND: u64 = 50;
 pub const TICKS_PER_SECOND: u64 = TICKS_PER_REALTIME_SECOND / SECONDS_PER_REALTIME_SECOND as u64;
 pub const TICKS_PER_MINUTE: u64 = TICKS_PER_SECOND * SECONDS_PER_MINUTE as u64;
@@ -71,6 +74,89 @@ pub struct DayTime {
     pub second: i32,
 }
 
+/// The season of the year, derived from the day of the year
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Season {
+    Spring,
+    Summer,
+    Autumn,
+    Winter,
+}
+
+impl Season {
+    /// Returns the season the given day of the year falls in
+    pub fn from_day_of_year(day_of_year: i32) -> Season {
+        match day_of_year.rem_euclid(DAYS_PER_YEAR) / DAYS_PER_SEASON {
+            0 => Season::Spring,
+            1 => Season::Summer,
+            2 => Season::Autumn,
+            _ => Season::Winter,
+        }
+    }
+
+    /// How much the sun is raised (or lowered) compared to spring/autumn
+    /// Used to make daylight longer in summer and shorter in winter
+    pub fn sun_height_offset(self) -> f32 {
+        match self {
+            Season::Spring | Season::Autumn => 0.0,
+            Season::Summer => 0.2,
+            Season::Winter => -0.2,
+        }
+    }
+}
+
+impl Display for Season {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
+
+/// The day of the week
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Weekday {
+    /// Returns the day of the week of the given day since the start of the game.
+    /// The game starts on day 1, which is a monday
+    pub fn from_day(day: i32) -> Weekday {
+        match (day - 1).rem_euclid(DAYS_PER_WEEK) {
+            0 => Weekday::Monday,
+            1 => Weekday::Tuesday,
+            2 => Weekday::Wednesday,
+            3 => Weekday::Thursday,
+            4 => Weekday::Friday,
+            5 => Weekday::Saturday,
+            _ => Weekday::Sunday,
+        }
+    }
+
+    pub fn short_name(self) -> &'static str {
+        match self {
+            Weekday::Monday => "Mon",
+            Weekday::Tuesday => "Tue",
+            Weekday::Wednesday => "Wed",
+            Weekday::Thursday => "Thu",
+            Weekday::Friday => "Fri",
+            Weekday::Saturday => "Sat",
+            Weekday::Sunday => "Sun",
+        }
+    }
+}
+
+impl Display for Weekday {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
+
 /// An interval of in-game time
 /// The interval is inclusive on the start and exclusive on the end
 #[derive(Copy, Clone, Serialize, Deserialize)]
@@ -237,14 +323,36 @@ impl GameTime {
     pub fn daysec(&self) -> f64 {
         self.timestamp % Self::DAY as f64
     }
+
+    /// Returns the day of the current year, in [0; DAYS_PER_YEAR)
+    /// Only derived from the tick so it stays the same across save/load
+    pub fn day_of_year(&self) -> i32 {
+        self.daytime.day.rem_euclid(DAYS_PER_YEAR)
+    }
+
+    pub fn season(&self) -> Season {
+        Season::from_day_of_year(self.day_of_year())
+    }
+
+    pub fn weekday(&self) -> Weekday {
+        Weekday::from_day(self.daytime.day)
+    }
+
+    /// The date and time of day as shown to the player, e.g. "Day 3, 14:25"
+    pub fn formatted(&self) -> String {
+        format!(
+            "Day {}, {:02}:{:02}",
+            self.daytime.day, self.daytime.hour, self.daytime.minute
+        )
+    }
 }
 
 impl GameDuration {
-    pub fn from_secs(secs: u64) -> Self {
+    pub const fn from_secs(secs: u64) -> Self {
         GameDuration(Tick(secs * TICKS_PER_SECOND))
     }
 
-    pub fn from_minutes(mins: u64) -> Self {
+    pub const fn from_minutes(mins: u64) -> Self {
         GameDuration(Tick(mins * TICKS_PER_MINUTE))
     }
 
@@ -724,4 +832,73 @@ mod tests {
         assert_eq!(parse_rectime("1h30 -> invalid"), Err(InvalidEnd(InvalidNumber)));
         assert_eq!(parse_rectime("1h30 -> 2h30 -> invalid"), Err(UnexpectedSuffix("-> invalid".into())));
     }
+
+    #[test]
+    fn seasons_cycle_over_a_year() {
+        use super::*;
+
+        let mut t = GameTime::new(Tick(0));
+        let mut seen = vec![t.season()];
+        for _ in 0..DAYS_PER_YEAR {
+            t = t + GameDuration::from_secs(SECONDS_PER_DAY as u64);
+            if seen.last() != Some(&t.season()) {
+                seen.push(t.season());
+            }
+        }
+
+        assert_eq!(seen.len(), 5);
+        assert_eq!(seen[0], seen[4]);
+        for s in [
+            Season::Spring,
+            Season::Summer,
+            Season::Autumn,
+            Season::Winter,
+        ] {
+            assert!(seen.contains(&s));
+        }
+
+        let reloaded = GameTime::new(t.tick);
+        assert_eq!(reloaded.day_of_year(), t.day_of_year());
+        assert_eq!(reloaded.season(), t.season());
+    }
+
+    #[test]
+    fn formatted_time() {
+        use super::*;
+
+        let at = |secs: u64| GameTime::new(Tick(secs * TICKS_PER_SECOND));
+
+        // the game starts on day 1 at 8:00
+        assert_eq!(at(0).formatted(), "Day 1, 08:00");
+        assert_eq!(at(59).formatted(), "Day 1, 08:00");
+        assert_eq!(at(60).formatted(), "Day 1, 08:01");
+        let to_midnight = (16 * SECONDS_PER_HOUR) as u64;
+        assert_eq!(at(to_midnight - 1).formatted(), "Day 1, 23:59");
+        assert_eq!(at(to_midnight).formatted(), "Day 2, 00:00");
+        assert_eq!(
+            at(to_midnight + SECONDS_PER_DAY as u64 + 14 * 3600 + 25 * 60).formatted(),
+            "Day 3, 14:25"
+        );
+
+        assert_eq!(at(0).weekday(), Weekday::Monday);
+        assert_eq!(at(to_midnight).weekday(), Weekday::Tuesday);
+        assert_eq!(
+            at(to_midnight + 6 * SECONDS_PER_DAY as u64).weekday(),
+            Weekday::Monday
+        );
+    }
+
+    #[test]
+    fn first_day_is_a_monday() {
+        use super::*;
+
+        assert_eq!(GameTime::new(Tick(1)).weekday(), Weekday::Monday);
+        assert_eq!(Weekday::from_day(1), Weekday::Monday);
+        assert_eq!(Weekday::from_day(DAYS_PER_WEEK), Weekday::Sunday);
+        assert_eq!(Weekday::from_day(DAYS_PER_WEEK + 1), Weekday::Monday);
+        assert_eq!(Weekday::from_day(10 * DAYS_PER_WEEK + 5), Weekday::Friday);
+        // days before the start of the game keep going backward
+        assert_eq!(Weekday::from_day(0), Weekday::Sunday);
+        assert_eq!(Weekday::from_day(-1), Weekday::Saturday);
+    }
 }
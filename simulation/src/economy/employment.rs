@@ -0,0 +1,114 @@
+use crate::economy::Workers;
+use crate::souls::human::PersonalInfo;
+use crate::utils::resources::Resources;
+use crate::World;
+use egui_inspect::Inspect;
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+/// Ages at which a soul is expected to be looking for a job.
+pub const WORKING_AGE: RangeInclusive<u8> = 18..=65;
+
+/// Employment metrics, recomputed every tick
+#[derive(Inspect, Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EconomyStats {
+    /// Souls that have a job
+    pub employed: u32,
+    /// Employable souls without a job
+    pub unemployed: u32,
+    /// Sum of the unfilled positions of every company
+    pub open_jobs: u32,
+}
+
+/// Children and retired souls are not counted as unemployed
+pub fn employable(info: &PersonalInfo) -> bool {
+    WORKING_AGE.contains(&info.age)
+}
+
+impl EconomyStats {
+    /// `souls` yields whether each soul has a job, `companies` yields the maximum number of workers
+    /// of each company alongside its current workers.
+    pub fn compute<'a>(
+        souls: impl Iterator<Item = (&'a PersonalInfo, bool)>,
+        companies: impl Iterator<Item = (u32, &'a Workers)>,
+    ) -> Self {
+        let mut stats = Self::default();
+        for (info, has_work) in souls {
+            if has_work {
+                stats.employed += 1;
+            } else if employable(info) {
+                stats.unemployed += 1;
+            }
+        }
+        for (max_workers, workers) in companies {
+            stats.open_jobs += max_workers.saturating_sub(workers.0.len() as u32);
+        }
+        stats
+    }
+}
+
+pub fn economy_stats_update(world: &mut World, resources: &mut Resources) {
+    profiling::scope!("economy::economy_stats_update");
+    *resources.write::<EconomyStats>() = EconomyStats::compute(
+        world
+            .humans
+            .values()
+            .map(|h| (&*h.personal_info, h.work.is_some())),
+        world
+            .companies
+            .values()
+            .map(|c| (c.comp.max_workers, &c.workers)),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::souls::human::Gender;
+    use crate::world::HumanID;
+
+    fn info(age: u8) -> PersonalInfo {
+        PersonalInfo {
+            name: String::new(),
+            age,
+            gender: Gender::F,
+        }
+    }
+
+    #[test]
+    fn employment_metrics() {
+        let souls = [
+            (info(30), true),
+            (info(45), true),
+            (info(25), false),
+            (info(40), false),
+            (info(10), false), // child
+            (info(80), false), // retired
+            (info(70), true),  // still working
+        ];
+
+        let full = Workers(vec![HumanID::default(); 3]);
+        let half = Workers(vec![HumanID::default(); 2]);
+        let overfull = Workers(vec![HumanID::default(); 5]);
+        let companies = [
+            (3, &full),
+            (4, &half),
+            (2, &overfull),
+            (1, &Workers::default()),
+        ];
+
+        let stats = EconomyStats::compute(
+            souls.iter().map(|(i, w)| (i, *w)),
+            companies.iter().copied(),
+        );
+
+        assert_eq!(
+            stats,
+            EconomyStats {
+                employed: 3,
+                unemployed: 2,
+                open_jobs: 3,
+            }
+        );
+    }
+}
@@ -21,6 +21,7 @@ impl Default for Government {
 impl Government {
     pub fn action_cost(action: &WorldCommand, sim: &Simulation) -> Money {
         Money::new_bucks(match action {
+            WorldCommand::FromPlayer(_, command) => return Self::action_cost(command, sim),
             WorldCommand::MapBuildHouse(_) => 100,
             WorldCommand::AddTrain { n_wagons, .. } => 1000 + 100 * (*n_wagons as i64),
             WorldCommand::MapMakeConnection { from, to, pat, .. } => {
@@ -56,6 +57,17 @@ impl Government {
                 }
                 total
             }
+            WorldCommand::BatchRoadGrid {
+                nx,
+                ny,
+                spacing,
+                pattern,
+                ..
+            } => {
+                let (nx, ny) = (*nx as i64, *ny as i64);
+                let n_roads = (nx - 1).max(0) * ny + (ny - 1).max(0) * nx;
+                n_roads * Self::road_cost(*spacing, pattern)
+            }
             WorldCommand::MapBuildSpecialBuilding { kind: x, .. } => match x {
                 BuildingKind::GoodsCompany(x) => {
                     let descr = x.prototype();
@@ -76,7 +88,10 @@ impl Government {
     }
 
     fn connection_cost(p1: &MapProject, p2: &MapProject, pat: &LanePattern) -> i64 {
-        let dist = p1.pos.distance(p2.pos);
+        Self::road_cost(p1.pos.distance(p2.pos), pat)
+    }
+
+    fn road_cost(dist: f32, pat: &LanePattern) -> i64 {
         50 + ((0.03 * dist) as i64).max(1)
             * (pat.lanes_forward.len() + pat.lanes_backward.len()) as i64
     }
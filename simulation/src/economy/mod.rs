@@ -17,12 +17,14 @@ use std::collections::BTreeMap;
 use std::fmt::Debug;
 
 mod ecostats;
+mod employment;
 mod government;
 mod market;
 
 use crate::map::Map;
 use crate::world::HumanID;
 pub use ecostats::*;
+pub use employment::*;
 pub use government::*;
 pub use market::*;
 use prototypes::{GameTime, ItemID, Money, TICKS_PER_MINUTE};
@@ -0,0 +1,143 @@
+//! Expectations are conditions a headless run of the simulation must meet before a given tick,
+//! such as "by tick 1000, at least 80% of souls are at work". They are used to catch gameplay
+//! regressions and can be declared in scenarios.
+
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use serde::{Deserialize, Serialize};
+
+use geom::Vec3;
+
+use crate::map::BuildingID;
+use crate::transportation::Location;
+use crate::world::VehicleID;
+use crate::world_command::WorldCommands;
+use crate::Simulation;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Expectation {
+    /// The condition must hold at some tick up to this one included
+    pub by_tick: u64,
+    pub condition: Condition,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Condition {
+    /// At least this fraction of the souls having a job are in their workplace
+    SoulsAtWork { min_ratio: f32 },
+    /// At least this many vehicles exist
+    MinVehicles(usize),
+    /// At least this many souls are inside the building
+    BuildingOccupancy { building: BuildingID, min: usize },
+    /// The vehicle is less than `dist` meters away from the position
+    VehicleNear {
+        vehicle: VehicleID,
+        pos: Vec3,
+        dist: f32,
+    },
+}
+
+impl Condition {
+    /// Whether the condition holds right now.
+    /// Errors if it references something that doesn't exist.
+    pub fn check(&self, sim: &Simulation) -> Result<bool, String> {
+        match *self {
+            Condition::SoulsAtWork { min_ratio } => {
+                let mut workers = 0;
+                let mut at_work = 0;
+                for h in sim.world.humans.values() {
+                    let Some(ref work) = h.work else {
+                        continue;
+                    };
+                    workers += 1;
+                    if h.location == Location::Building(work.workplace) {
+                        at_work += 1;
+                    }
+                }
+                Ok(workers > 0 && at_work as f32 >= min_ratio * workers as f32)
+            }
+            Condition::MinVehicles(min) => Ok(sim.world.vehicles.len() >= min),
+            Condition::BuildingOccupancy { building, min } => {
+                if !sim.map().buildings().contains_key(building) {
+                    return Err(format!("building {building:?} doesn't exist"));
+                }
+                let inside = sim
+                    .world
+                    .humans
+                    .values()
+                    .filter(|h| h.location == Location::Building(building))
+                    .count();
+                Ok(inside >= min)
+            }
+            Condition::VehicleNear { vehicle, pos, dist } => {
+                let Some(v) = sim.world.vehicles.get(vehicle) else {
+                    return Err(format!("vehicle {vehicle:?} doesn't exist"));
+                };
+                Ok(v.trans.pos.distance(pos) < dist)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ExpectationFailure {
+    /// Index of the expectation in the list that was run
+    pub index: usize,
+    /// Tick at which the expectation was given up on
+    pub tick: u64,
+    pub reason: String,
+}
+
+impl Display for ExpectationFailure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "expectation #{} failed at tick {}: {}",
+            self.index, self.tick, self.reason
+        )
+    }
+}
+
+impl Error for ExpectationFailure {}
+
+/// Ticks the simulation until every expectation is either met or past its deadline.
+/// Expectations already met are not checked again, so conditions only have to hold once.
+pub fn run_expectations(
+    sim: &mut Simulation,
+    expectations: &[Expectation],
+) -> Result<(), Vec<ExpectationFailure>> {
+    let mut schedule = Simulation::schedule();
+    let mut pending: Vec<usize> = (0..expectations.len()).collect();
+    let mut failures = vec![];
+
+    loop {
+        let tick = sim.get_tick();
+        pending.retain(|&index| {
+            let e = &expectations[index];
+            let reason = match e.condition.check(sim) {
+                Ok(true) => return false,
+                Ok(false) if tick < e.by_tick => return true,
+                Ok(false) => format!("{:?} not met by tick {}", e.condition, e.by_tick),
+                Err(reason) => reason,
+            };
+            failures.push(ExpectationFailure {
+                index,
+                tick,
+                reason,
+            });
+            false
+        });
+
+        if pending.is_empty() {
+            break;
+        }
+        sim.step(&mut schedule, WorldCommands::default().as_ref());
+    }
+
+    if failures.is_empty() {
+        return Ok(());
+    }
+    failures.sort_by_key(|f| f.index);
+    Err(failures)
+}
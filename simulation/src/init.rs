@@ -3,26 +3,41 @@ use serde::Serialize;
 
 #[allow(unused_imports)]
 use common::saveload::{Bincode, Encoder, JSONPretty, JSON};
+use common::FastMap;
 use prototypes::{GameTime, Tick};
 
-use crate::economy::{market_update, EcoStats, Government, Market};
-use crate::map::Map;
+use crate::economy::{
+    economy_stats_update, market_update, EcoStats, EconomyStats, Government, Market,
+};
+use crate::map::{serializing, Map};
 use crate::map_dynamic::{
-    dispatch_system, electricity_flow_system, itinerary_update, routing_changed_system,
-    routing_update_system, BuildingInfos, Dispatcher, ElectricityFlow, ParkingManagement,
+    dispatch_system, electricity_flow_system, itinerary_update, lane_traffic_system,
+    pollution_system, routing_changed_system, routing_update_system, BuildingInfos, Dispatcher,
+    ElectricityFlow, ParkingManagement, Pollution, TripDistanceSettings, TripHistorySettings,
 };
 use crate::multiplayer::MultiplayerState;
+use crate::souls::decision_lod::DecisionLod;
+use crate::souls::delivery::delivery_system;
 use crate::souls::freight_station::freight_station_system;
 use crate::souls::goods_company::company_system;
 use crate::souls::human::update_decision_system;
+use crate::souls::migration::{migration_system, Migration};
 use crate::transportation::pedestrian_decision_system;
-use crate::transportation::road::{vehicle_decision_system, vehicle_state_update_system};
+use crate::transportation::road::{
+    vehicle_decision_system, vehicle_state_update_system, PhysicsSettings,
+};
 use crate::transportation::testing_vehicles::{random_vehicles_update, RandomVehicles};
 use crate::transportation::train::{
     locomotive_system, train_reservations_update, TrainReservations,
 };
-use crate::transportation::{transport_grid_synchronize, TransportGrid};
+use crate::transportation::{
+    edge_portal_system, give_way_system, overtaking_system, platoon_system, ramp_meter_system,
+    spawn_queue_system, stuck_vehicle_system, transport_grid_synchronize, BusRoutes, EdgePortals,
+    GiveWay, Overtakes, Platoons, RampMeters, SharedSpaces, SpawnQueue, StuckVehicles,
+    TransportGrid, WalkingComfort, WalkingSpeedDistribution,
+};
 use crate::utils::resources::Resources;
+use crate::utils::undo::UndoStack;
 use crate::world::{CompanyEnt, FreightStationEnt, HumanEnt, TrainEnt, VehicleEnt, WagonEnt};
 use crate::World;
 use crate::{
@@ -50,24 +65,36 @@ pub fn init() {
     }
 
     register_system("electricity_flow_system", electricity_flow_system);
+    register_system("lane_traffic_system", lane_traffic_system);
     register_system("dispatch_system", dispatch_system);
     register_system("update_decision_system", update_decision_system);
     register_system("company_system", company_system);
+    register_system("delivery_system", delivery_system);
     register_system("pedestrian_decision_system", pedestrian_decision_system);
     register_system("transport_grid_synchronize", transport_grid_synchronize);
     register_system("locomotive_system", locomotive_system);
+    register_system("ramp_meter_system", ramp_meter_system);
+    register_system("platoon_system", platoon_system);
+    register_system("overtaking_system", overtaking_system);
+    register_system("give_way_system", give_way_system);
     register_system("vehicle_decision_system", vehicle_decision_system);
     register_system("vehicle_state_update_system", vehicle_state_update_system);
+    register_system("pollution_system", pollution_system);
     register_system("routing_changed_system", routing_changed_system);
     register_system("routing_update_system", routing_update_system);
     register_system("itinerary_update", itinerary_update);
     register_system("market_update", market_update);
+    register_system("economy_stats_update", economy_stats_update);
     register_system("train_reservations_update", train_reservations_update);
     register_system("freight_station", freight_station_system);
     register_system("random_vehicles", random_vehicles_update);
     register_system("update_map", |_, res| res.write::<Map>().update());
 
     register_system_sim("add_souls_to_empty_buildings", add_souls_to_empty_buildings);
+    register_system_sim("spawn_queue", spawn_queue_system);
+    register_system_sim("edge_portals", edge_portal_system);
+    register_system_sim("migration", migration_system);
+    register_system_sim("stuck_vehicles", stuck_vehicle_system);
 
     register_resource_noserialize::<ParCommandBuffer<VehicleEnt>>();
     register_resource_noserialize::<ParCommandBuffer<TrainEnt>>();
@@ -75,6 +102,8 @@ pub fn init() {
     register_resource_noserialize::<ParCommandBuffer<WagonEnt>>();
     register_resource_noserialize::<ParCommandBuffer<FreightStationEnt>>();
     register_resource_noserialize::<ParCommandBuffer<CompanyEnt>>();
+    register_resource_noserialize::<EconomyStats>();
+    register_resource_noserialize::<GiveWay>();
     register_resource_noinit::<SimulationOptions, Bincode>("simoptions");
 
     register_resource_default::<ElectricityFlow, Bincode>("electricity_flow");
@@ -91,7 +120,31 @@ pub fn init() {
     register_resource::<TransportGrid, Bincode>("transport_grid", || TransportGrid::new(100));
     register_resource::<RandProvider, Bincode>("randprovider", || RandProvider::new(RNG_SEED));
     register_resource_default::<Dispatcher, Bincode>("dispatcher");
+    register_resource_default::<SharedSpaces, Bincode>("shared_spaces");
+    register_resource_default::<RampMeters, Bincode>("ramp_meters");
+    register_resource_default::<Platoons, Bincode>("platoons");
+    register_resource_default::<Overtakes, Bincode>("overtakes");
+    register_resource_default::<WalkingSpeedDistribution, Bincode>("walking_speeds");
+    register_resource_default::<WalkingComfort, Bincode>("walking_comfort");
+    register_resource_default::<TripHistorySettings, Bincode>("trip_history_settings");
+    register_resource_default::<DecisionLod, Bincode>("decision_lod");
+    register_resource_default::<TripDistanceSettings, Bincode>("trip_distance_settings");
+    register_resource_default::<SpawnQueue, Bincode>("spawn_queue");
+    register_resource_default::<EdgePortals, Bincode>("edge_portals");
+    register_resource_default::<Pollution, Bincode>("pollution");
+    register_resource_default::<Migration, Bincode>("migration");
+    register_resource_default::<StuckVehicles, Bincode>("stuck_vehicles");
+    register_resource_default::<BusRoutes, Bincode>("bus_routes");
+    register_resource_default::<PhysicsSettings, Bincode>("physics_settings");
+    register_resource_default::<UndoStack, Bincode>("undo_stack");
     register_resource_default::<Replay, JSON>("replay");
+
+    register_migration::<serializing::v0::SerializedMap, serializing::SerializedMap, Bincode>(
+        2,
+        "map",
+        "map",
+        serializing::SerializedMap::from,
+    );
 }
 
 pub struct InitFunc {
@@ -102,6 +155,8 @@ pub(crate) struct SaveLoadFunc {
     pub name: &'static str,
     pub save: Box<dyn Fn(&Simulation) -> Vec<u8> + 'static>,
     pub load: Box<dyn Fn(&mut Simulation, Vec<u8>) + 'static>,
+    pub save_json: Box<dyn Fn(&Simulation) -> serde_json::Result<serde_json::Value> + 'static>,
+    pub load_json: Box<dyn Fn(&mut Simulation, serde_json::Value) + 'static>,
 }
 
 pub(crate) struct GSystem {
@@ -111,6 +166,95 @@ pub(crate) struct GSystem {
 pub(crate) static mut INIT_FUNCS: Vec<InitFunc> = Vec::new();
 pub(crate) static mut SAVELOAD_FUNCS: Vec<SaveLoadFunc> = Vec::new();
 pub(crate) static mut GSYSTEMS: Vec<GSystem> = Vec::new();
+pub(crate) static mut MIGRATION_FUNCS: Vec<MigrationFunc> = Vec::new();
+
+/// Version of the save layout, bumped along with a migration when a saved resource changes.
+/// The world itself is not migrated: saves older than 3 can only be loaded if it is empty,
+/// as the vehicles' routers gained fields since.
+pub const SAVE_VERSION: u32 = 3;
+/// Version 1 saves kept their version in the resources under this name, saves from before
+/// versioning don't have it and are version 0
+pub(crate) const LEGACY_SAVE_VERSION_KEY: &str = "save_version";
+
+pub(crate) struct MigrationFunc {
+    /// Saves older than this version are migrated
+    pub version: u32,
+    /// Name of the resource in saves older than `version`
+    pub from: &'static str,
+    /// Name of the resource from `version` onward, the same unless it was renamed
+    pub to: &'static str,
+    /// Turns the old encoded resource into the new one, None if it can't be migrated
+    pub migrate: Box<dyn Fn(Vec<u8>) -> Option<Vec<u8>> + 'static>,
+    /// Same as `migrate` for resources of json saves
+    pub migrate_json: Box<dyn Fn(serde_json::Value) -> Option<serde_json::Value> + 'static>,
+}
+
+impl MigrationFunc {
+    /// Reads the resource as `Old` and converts it with `f`
+    pub fn new<Old: DeserializeOwned + 'static, New: Serialize + 'static, E: Encoder + 'static>(
+        version: u32,
+        from: &'static str,
+        to: &'static str,
+        f: fn(Old) -> New,
+    ) -> Self {
+        Self {
+            version,
+            from,
+            to,
+            migrate: Box::new(move |data| E::encode(&f(E::decode::<Old>(&data).ok()?)).ok()),
+            migrate_json: Box::new(move |v| {
+                // json is self-describing, values already in the new layout are kept as is
+                match serde_json::from_value::<Old>(v.clone()) {
+                    Ok(old) => serde_json::to_value(f(old)).ok(),
+                    Err(_) => Some(v),
+                }
+            }),
+        }
+    }
+}
+
+/// A saved resource that can go through a [`MigrationFunc`]
+pub(crate) trait Migrate: Sized {
+    fn migrate(self, m: &MigrationFunc) -> Option<Self>;
+}
+
+impl Migrate for Vec<u8> {
+    fn migrate(self, m: &MigrationFunc) -> Option<Self> {
+        (m.migrate)(self)
+    }
+}
+
+/// Runs the migrations newer than the save on its resources, oldest first.
+/// Resources that fail to migrate are dropped and get their default value.
+pub(crate) fn migrate<R: Migrate>(
+    res: &mut FastMap<String, R>,
+    save_version: u32,
+    migrations: &[MigrationFunc],
+) {
+    let mut todo: Vec<&MigrationFunc> = migrations
+        .iter()
+        .filter(|m| m.version > save_version)
+        .collect();
+    todo.sort_by_key(|m| m.version);
+
+    for m in todo {
+        let Some(data) = res.remove(m.from) else {
+            continue;
+        };
+        match data.migrate(m) {
+            Some(data) => {
+                log::info!(
+                    "migrated resource {} to {} (save version {})",
+                    m.from,
+                    m.to,
+                    m.version
+                );
+                res.insert(m.to.to_string(), data);
+            }
+            None => log::error!("could not migrate resource {}, it is reset", m.from),
+        }
+    }
+}
 
 /*fn register_init(s: fn(&mut World, &mut Resources)) {
     unsafe {
@@ -141,6 +285,22 @@ fn register_system_sim(name: &'static str, s: fn(&mut Simulation)) {
     }
 }
 
+/// Registers how to read a resource saved before `version`, see [`MigrationFunc`]
+fn register_migration<
+    Old: DeserializeOwned + 'static,
+    New: Serialize + 'static,
+    E: Encoder + 'static,
+>(
+    version: u32,
+    from: &'static str,
+    to: &'static str,
+    f: fn(Old) -> New,
+) {
+    unsafe {
+        MIGRATION_FUNCS.push(MigrationFunc::new::<Old, New, E>(version, from, to, f));
+    }
+}
+
 fn register_resource_noserialize<T: 'static + Default + Send + Sync>() {
     unsafe {
         INIT_FUNCS.push(InitFunc {
@@ -185,6 +345,74 @@ fn register_resource_noinit<T: 'static + Send + Sync + Serialize + DeserializeOw
                     log::error!("Error loading resource {}: {}", name, e);
                 }
             }),
+            save_json: Box::new(move |uiworld| serde_json::to_value(&*uiworld.read::<T>())),
+            load_json: Box::new(
+                move |uiworld, data| match serde_json::from_value::<T>(data) {
+                    Ok(res) => {
+                        uiworld.insert(res);
+                    }
+                    Err(e) => {
+                        log::error!("Error loading resource {}: {}", name, e);
+                    }
+                },
+            ),
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{migrate, MigrationFunc};
+    use common::saveload::{Bincode, Encoder};
+    use common::FastMap;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize)]
+    struct SpeedV1 {
+        limit: f32,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct SpeedV2 {
+        limit: f32,
+        enabled: bool,
+    }
+
+    #[test]
+    fn old_resources_are_migrated() {
+        // a version 1 save
+        let mut res: FastMap<String, Vec<u8>> = FastMap::default();
+        res.insert(
+            "speed".to_string(),
+            Bincode::encode(&SpeedV1 { limit: 13.0 }).unwrap(),
+        );
+        res.insert("other".to_string(), vec![1, 2, 3]);
+
+        // version 2 renamed and extended it
+        let migrations = [MigrationFunc::new::<SpeedV1, SpeedV2, Bincode>(
+            2,
+            "speed",
+            "speed_settings",
+            |old| SpeedV2 {
+                limit: old.limit,
+                enabled: true,
+            },
+        )];
+        assert!(Bincode::decode::<SpeedV2>(&res["speed"]).is_err());
+
+        let mut up_to_date = res.clone();
+        migrate(&mut up_to_date, 2, &migrations);
+        assert_eq!(up_to_date, res);
+
+        migrate(&mut res, 1, &migrations);
+        assert!(!res.contains_key("speed"));
+        assert_eq!(
+            Bincode::decode::<SpeedV2>(&res["speed_settings"]).unwrap(),
+            SpeedV2 {
+                limit: 13.0,
+                enabled: true,
+            }
+        );
+        assert_eq!(res["other"], vec![1, 2, 3]);
+    }
+}
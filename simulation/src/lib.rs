@@ -2,10 +2,14 @@
 #![allow(clippy::type_complexity)]
 #![warn(clippy::iter_over_hash_type)]
 
-use crate::init::{GSYSTEMS, INIT_FUNCS, SAVELOAD_FUNCS};
+use crate::init::{
+    migrate, Migrate, MigrationFunc, GSYSTEMS, INIT_FUNCS, LEGACY_SAVE_VERSION_KEY,
+    MIGRATION_FUNCS, SAVELOAD_FUNCS, SAVE_VERSION,
+};
 use crate::map::{BuildingKind, Map};
 use crate::map_dynamic::{Itinerary, ItineraryLeader};
 use crate::souls::add_souls_to_empty_buildings;
+use crate::transportation::TransportGrid;
 use crate::utils::resources::{Ref, RefMut, Resources};
 use crate::utils::scheduler::RunnableSystem;
 use crate::world_command::WorldCommand;
@@ -13,8 +17,9 @@ use crate::world_command::WorldCommand::Init;
 use common::saveload::Encoder;
 use common::FastMap;
 use derive_more::{From, TryInto};
-use geom::Vec3;
+use geom::{Vec3, AABB};
 use prototypes::{prototype, ColorsPrototype, ColorsPrototypeID, GameTime, Tick};
+use serde::de::{SeqAccess, Visitor};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::any::Any;
 use std::collections::BTreeMap;
@@ -39,11 +44,13 @@ extern crate egui_inspect;
 extern crate log as extern_log;
 
 pub mod economy;
+pub mod expectation;
 pub mod init;
 pub mod map;
 pub mod map_dynamic;
 pub mod multiplayer;
 mod rerun;
+pub mod scenario;
 pub mod souls;
 #[cfg(test)]
 mod tests;
@@ -108,6 +115,13 @@ debug_inspect_impl!(SoulID);
 pub struct Simulation {
     pub(crate) world: World,
     resources: Resources,
+    /// Debugging helper, not serialized: ticks only happen through [`Simulation::step`]
+    paused: bool,
+    /// Commands received while paused, applied on the next step
+    pending_commands: Vec<WorldCommand>,
+    /// Debugging helper, not serialized: every N ticks the state hash is logged and kept to find desyncs
+    checksum_interval: Option<u32>,
+    checksums: Vec<(Tick, u64)>,
 }
 
 const RNG_SEED: u64 = 123;
@@ -117,6 +131,13 @@ const VERSION: &str = include_str!("../../VERSION");
 pub struct SimulationOptions {
     pub terrain_size: u16,
     pub save_replay: bool,
+    /// Seed of the simulation's random stream, which is then saved along with the simulation
+    #[serde(default = "default_seed")]
+    pub seed: u64,
+}
+
+fn default_seed() -> u64 {
+    RNG_SEED
 }
 
 impl Default for SimulationOptions {
@@ -124,6 +145,7 @@ impl Default for SimulationOptions {
         SimulationOptions {
             terrain_size: 50,
             save_replay: true,
+            seed: RNG_SEED,
         }
     }
 }
@@ -147,10 +169,23 @@ impl Simulation {
         })
     }
 
+    /// Empty simulation without terrain, like `new(false)`, whose random stream starts from `seed`
+    pub fn from_seed(seed: u64) -> Simulation {
+        Self::new_with_options(SimulationOptions {
+            terrain_size: 0,
+            seed,
+            ..Default::default()
+        })
+    }
+
     pub fn from_replay(replay: Replay) -> (Simulation, SimulationReplayLoader) {
         let mut sim = Simulation {
             world: Default::default(),
             resources: Default::default(),
+            paused: false,
+            pending_commands: Vec::new(),
+            checksum_interval: None,
+            checksums: Vec::new(),
         };
 
         info!("Seed is {}", RNG_SEED);
@@ -177,9 +212,13 @@ impl Simulation {
         let mut sim = Simulation {
             world: Default::default(),
             resources: Default::default(),
+            paused: false,
+            pending_commands: Vec::new(),
+            checksum_interval: None,
+            checksums: Vec::new(),
         };
 
-        info!("Seed is {}", RNG_SEED);
+        info!("Seed is {}", opts.seed);
         info!("{:?}", opts);
 
         unsafe {
@@ -234,10 +273,47 @@ impl Simulation {
         true
     }
 
+    /// Commands queued while paused are applied first on the first tick after unpausing
     pub fn tick<'a>(
         &mut self,
         game_schedule: &mut SeqSchedule,
         commands: impl IntoIterator<Item = &'a WorldCommand>,
+    ) -> Duration {
+        if self.paused {
+            self.pending_commands.extend(commands.into_iter().cloned());
+            return Duration::ZERO;
+        }
+        if !self.pending_commands.is_empty() {
+            return self.step(game_schedule, commands);
+        }
+        self.tick_inner(game_schedule, commands)
+    }
+
+    /// While paused, [`Simulation::tick`] does nothing and commands are kept until the next tick or step
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Advances exactly one tick, even when paused.
+    /// Commands queued while paused are applied first.
+    pub fn step<'a>(
+        &mut self,
+        game_schedule: &mut SeqSchedule,
+        commands: impl IntoIterator<Item = &'a WorldCommand>,
+    ) -> Duration {
+        let mut pending = std::mem::take(&mut self.pending_commands);
+        pending.extend(commands.into_iter().cloned());
+        self.tick_inner(game_schedule, &pending)
+    }
+
+    fn tick_inner<'a>(
+        &mut self,
+        game_schedule: &mut SeqSchedule,
+        commands: impl IntoIterator<Item = &'a WorldCommand>,
     ) -> Duration {
         profiling::scope!("simulation::tick");
         let t = Instant::now();
@@ -260,7 +336,38 @@ impl Simulation {
         self.resources.write::<Replay>().last_tick_recorded =
             self.resources.read::<GameTime>().tick;
 
-        t.elapsed()
+        let elapsed = t.elapsed();
+        self.record_checksum();
+        elapsed
+    }
+
+    /// Every `interval` ticks, logs the state hash with the tick number so that a divergence
+    /// between two runs can be found. None disables it.
+    pub fn set_checksum_interval(&mut self, interval: Option<u32>) {
+        self.checksum_interval = interval.filter(|&n| n > 0);
+    }
+
+    /// The checksums computed since checksums were enabled, in tick order
+    pub fn checksums(&self) -> &[(Tick, u64)] {
+        &self.checksums
+    }
+
+    /// Hash of the whole simulation state, only reads the state so it can be computed at any time
+    pub fn state_hash(&self) -> u64 {
+        common::hash_u64(self.hashes())
+    }
+
+    fn record_checksum(&mut self) {
+        let Some(interval) = self.checksum_interval else {
+            return;
+        };
+        let tick = self.read::<GameTime>().tick;
+        if tick.0 % interval as u64 != 0 {
+            return;
+        }
+        let hash = self.state_hash();
+        log::info!("checksum at tick {}: {:016x}", tick.0, hash);
+        self.checksums.push((tick, hash));
     }
 
     pub fn get_tick(&self) -> u64 {
@@ -289,7 +396,9 @@ impl Simulation {
     }
 
     pub fn load_from_disk(save_name: &str) -> Option<Self> {
-        let sim: Simulation = common::saveload::CompressedBincode::load(save_name).ok()?;
+        let sim: Simulation = common::saveload::CheckedCompressedBincode::load(save_name)
+            .map_err(|e| log::error!("{}", e))
+            .ok()?;
         if sim.resources.try_read::<Map>().ok()?.environment.size().0 == 0 {
             return None;
         }
@@ -297,7 +406,7 @@ impl Simulation {
     }
 
     pub fn save_to_disk(&self, save_name: &str) {
-        common::saveload::CompressedBincode::save(&self, save_name);
+        common::saveload::CheckedCompressedBincode::save(&self, save_name);
         let rep = self.resources.read::<Replay>();
         if rep.enabled {
             common::saveload::JSONPretty::save(&*rep, &format!("{save_name}_replay"));
@@ -312,6 +421,24 @@ impl Simulation {
         self.world.pos_any(id)
     }
 
+    /// Entities with a collider (moving vehicles and pedestrians outside) inside the box, boundary included.
+    /// Uses the transport grid instead of going through every entity.
+    pub fn entities_in_aabb(&self, aabb: AABB) -> Vec<AnyEntity> {
+        let grid = self.read::<TransportGrid>();
+        let mut found = vec![];
+        // slightly bigger so that positions exactly on the boundary are seen by the grid
+        let query = aabb.expand(0.01);
+        grid.query_aabb_visitor(query.ll, query.ur, |(h, pos)| {
+            if !aabb.contains(pos) {
+                return;
+            }
+            if let Some(owner) = grid.get(h).and_then(|(_, state)| state.owner) {
+                found.push(owner);
+            }
+        });
+        found
+    }
+
     pub fn get<E: EntityID>(&self, id: E) -> Option<&E::Entity> {
         self.world.get(id)
     }
@@ -320,6 +447,8 @@ impl Simulation {
         self.world.contains(id)
     }
 
+    /// Inserts the default value of the resource if it is missing (e.g optional resources added by mods)
+    /// An existing resource is never overwritten
     pub fn write_or_default<T: Any + Send + Sync + Default>(&mut self) -> RefMut<T> {
         self.resources.write_or_default::<T>()
     }
@@ -371,6 +500,7 @@ impl Serialize for Simulation {
             world: &self.world,
             version: VERSION.to_string(),
             res: m,
+            save_version: SAVE_VERSION,
         }
         .serialize(serializer);
         log::info!("took {}s to serialize in total", t.elapsed().as_secs_f32());
@@ -383,13 +513,62 @@ struct SimulationSer<'a> {
     world: &'a World,
     version: String,
     res: FastMap<String, Vec<u8>>,
+    /// Last so that older saves, which end before it, can still be read
+    save_version: u32,
 }
 
-#[derive(Deserialize)]
 struct SimulationDeser {
     world: World,
     version: String,
     res: FastMap<String, Vec<u8>>,
+    /// None for saves from before it was saved, see [`LEGACY_SAVE_VERSION_KEY`]
+    save_version: Option<u32>,
+}
+
+impl<'de> Deserialize<'de> for SimulationDeser {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct SimulationVisitor;
+
+        impl<'de> Visitor<'de> for SimulationVisitor {
+            type Value = SimulationDeser;
+
+            fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+                f.write_str("a saved simulation")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                use serde::de::Error;
+
+                let world = seq
+                    .next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(0, &self))?;
+                let version = seq
+                    .next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(1, &self))?;
+                let res = seq
+                    .next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(2, &self))?;
+                // bincode fails to read past the end of older saves
+                let save_version = seq.next_element().ok().flatten();
+
+                Ok(SimulationDeser {
+                    world,
+                    version,
+                    res,
+                    save_version,
+                })
+            }
+        }
+
+        deserializer.deserialize_struct(
+            "SimulationDeser",
+            &["world", "version", "res", "save_version"],
+            SimulationVisitor,
+        )
+    }
 }
 
 impl<'de> Deserialize<'de> for Simulation {
@@ -407,15 +586,58 @@ impl<'de> Deserialize<'de> for Simulation {
             t.elapsed().as_secs_f32()
         );
 
+        let legacy_save_version = simdeser
+            .res
+            .remove(LEGACY_SAVE_VERSION_KEY)
+            .and_then(|v| common::saveload::Bincode::decode::<u32>(&v).ok());
+        let save_version = simdeser.save_version.or(legacy_save_version).unwrap_or(0);
+        Simulation::migrate(&mut simdeser.res, save_version);
+
+        let mut sim = Simulation::from_saved_world(simdeser.world, &simdeser.version);
+
+        unsafe {
+            for l in &*addr_of!(SAVELOAD_FUNCS) {
+                if let Some(data) = simdeser.res.remove(l.name) {
+                    (l.load)(&mut sim, data);
+                }
+            }
+        }
+
+        log::info!(
+            "took {}s to deserialize in total",
+            t.elapsed().as_secs_f32()
+        );
+
+        Ok(sim)
+    }
+}
+
+impl Simulation {
+    /// Brings resources saved by an older version of the game to the current layout
+    fn migrate<R: Migrate>(res: &mut FastMap<String, R>, save_version: u32) {
+        if save_version > SAVE_VERSION {
+            log::warn!(
+                "save is from a newer version of the game ({} > {}), it might not load correctly",
+                save_version,
+                SAVE_VERSION
+            );
+        }
+        unsafe {
+            migrate(res, save_version, &*addr_of!(MIGRATION_FUNCS));
+        }
+    }
+
+    /// A simulation with default resources around a loaded world, resources are loaded afterward
+    fn from_saved_world(world: World, version: &str) -> Self {
         let cur_version_parts = VERSION.split('.').collect::<Vec<_>>();
-        let deser_parts = simdeser.version.split('.').collect::<Vec<_>>();
+        let deser_parts = version.split('.').collect::<Vec<_>>();
 
         if cur_version_parts[0] != deser_parts[0]
             || (cur_version_parts[0] == "0" && cur_version_parts[1] != deser_parts[1])
         {
             log::warn!(
                 "incompatible version, save might be corrupted! save is: {} - game is: {}",
-                simdeser.version,
+                version,
                 VERSION
             );
         }
@@ -423,6 +645,10 @@ impl<'de> Deserialize<'de> for Simulation {
         let mut sim = Self {
             world: World::default(),
             resources: Resources::default(),
+            paused: false,
+            pending_commands: Vec::new(),
+            checksum_interval: None,
+            checksums: Vec::new(),
         };
 
         unsafe {
@@ -431,25 +657,139 @@ impl<'de> Deserialize<'de> for Simulation {
             }
         }
 
-        sim.world = simdeser.world;
+        sim.world = world;
+        sim
+    }
+
+    /// Encodes the whole simulation, see [`SaveFormat`]
+    pub fn encode(&self, format: SaveFormat) -> std::io::Result<Vec<u8>> {
+        match format {
+            SaveFormat::Binary => common::saveload::CheckedCompressedBincode::encode(self),
+            SaveFormat::JSON => common::saveload::JSONPretty::encode(&self.to_json()),
+        }
+    }
+
+    pub fn decode(data: &[u8], format: SaveFormat) -> std::io::Result<Self> {
+        match format {
+            SaveFormat::Binary => common::saveload::CheckedCompressedBincode::decode(data),
+            SaveFormat::JSON => {
+                Self::from_json(common::saveload::JSON::decode::<SimulationJson>(data)?)
+            }
+        }
+    }
+
+    fn to_json(&self) -> SimulationJson {
+        let world = match serde_json::to_value(&self.world) {
+            Ok(v) => JsonBlob::Json(v),
+            Err(_) => JsonBlob::from_bincode(
+                &common::saveload::Bincode::encode(&self.world).unwrap_or_default(),
+            ),
+        };
 
+        let mut res = BTreeMap::new();
         unsafe {
             for l in &*addr_of!(SAVELOAD_FUNCS) {
-                if let Some(data) = simdeser.res.remove(l.name) {
-                    (l.load)(&mut sim, data);
-                }
+                let blob = match (l.save_json)(self) {
+                    Ok(v) => JsonBlob::Json(v),
+                    Err(e) => {
+                        log::info!("resource {} is saved as bincode in json: {}", l.name, e);
+                        JsonBlob::from_bincode(&(l.save)(self))
+                    }
+                };
+                res.insert(l.name.to_string(), blob);
             }
         }
 
-        log::info!(
-            "took {}s to deserialize in total",
-            t.elapsed().as_secs_f32()
-        );
+        SimulationJson {
+            version: VERSION.to_string(),
+            save_version: SAVE_VERSION,
+            world,
+            res,
+        }
+    }
+
+    fn from_json(json: SimulationJson) -> std::io::Result<Self> {
+        let world = match json.world {
+            JsonBlob::Json(v) => serde_json::from_value(v)?,
+            JsonBlob::Bincode(s) => {
+                common::saveload::Bincode::decode(&JsonBlob::decode_bincode(&s)?)?
+            }
+        };
+
+        let mut res: FastMap<String, JsonBlob> = json.res.into_iter().collect();
+        Simulation::migrate(&mut res, json.save_version);
+
+        let mut sim = Simulation::from_saved_world(world, &json.version);
+
+        unsafe {
+            for l in &*addr_of!(SAVELOAD_FUNCS) {
+                match res.remove(l.name) {
+                    Some(JsonBlob::Json(v)) => (l.load_json)(&mut sim, v),
+                    Some(JsonBlob::Bincode(s)) => (l.load)(&mut sim, JsonBlob::decode_bincode(&s)?),
+                    None => {}
+                }
+            }
+        }
 
         Ok(sim)
     }
 }
 
+/// How a simulation is encoded by [`Simulation::encode`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SaveFormat {
+    /// Compressed bincode with a checksum, what the game saves with
+    Binary,
+    /// Readable and diffable, much bigger and slower
+    JSON,
+}
+
+/// A value saved in a json save
+#[derive(Serialize, Deserialize)]
+enum JsonBlob {
+    Json(serde_json::Value),
+    /// Base64 encoded bincode, for values json can't represent (e.g maps keyed by entity ids)
+    Bincode(String),
+}
+
+impl JsonBlob {
+    fn from_bincode(data: &[u8]) -> Self {
+        JsonBlob::Bincode(base64::encode(data))
+    }
+
+    fn decode_bincode(s: &str) -> std::io::Result<Vec<u8>> {
+        base64::decode(s).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+}
+
+impl Migrate for JsonBlob {
+    fn migrate(self, m: &MigrationFunc) -> Option<Self> {
+        match self {
+            JsonBlob::Json(v) => (m.migrate_json)(v).map(JsonBlob::Json),
+            JsonBlob::Bincode(s) => {
+                let data = (m.migrate)(JsonBlob::decode_bincode(&s).ok()?)?;
+                Some(JsonBlob::from_bincode(&data))
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SimulationJson {
+    version: String,
+    /// Json saves were first versioned in version 2, the ones before are laid out as version 1
+    #[serde(default = "SimulationJson::unversioned")]
+    save_version: u32,
+    world: JsonBlob,
+    res: BTreeMap<String, JsonBlob>,
+}
+
+impl SimulationJson {
+    fn unversioned() -> u32 {
+        1
+    }
+}
+
 const START_COMMANDS: &str = r#"
 [
   [
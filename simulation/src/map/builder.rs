@@ -0,0 +1,176 @@
+use crate::map::{IntersectionID, LanePattern, Map, RoadID, RoadSegmentKind, ROAD_Z_OFFSET};
+use geom::Vec2;
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MapBuilderError {
+    /// The intersection was not added with [`MapBuilder::add_inter`]
+    UnknownIntersection(IntersectionID),
+    /// A road can't start and end at the same intersection
+    SameIntersection(IntersectionID),
+    /// Parallel roads between two intersections are not supported, this is the existing one
+    AlreadyConnected(RoadID),
+    /// The intersections are too close to fit a road between them
+    TooClose,
+}
+
+impl Display for MapBuilderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MapBuilderError::UnknownIntersection(id) => write!(f, "unknown intersection {id:?}"),
+            MapBuilderError::SameIntersection(id) => {
+                write!(f, "cannot connect {id:?} to itself")
+            }
+            MapBuilderError::AlreadyConnected(road) => {
+                write!(f, "intersections are already connected by {road:?}")
+            }
+            MapBuilderError::TooClose => write!(f, "intersections are too close"),
+        }
+    }
+}
+
+impl Error for MapBuilderError {}
+
+/// Builds maps for tests without going through map projections.
+/// Turns and traffic control are generated once everything is connected, in [`MapBuilder::build`].
+///
+/// ```ignore
+/// let mut b = MapBuilder::new();
+/// let a = b.add_inter(vec2(0.0, 0.0));
+/// let c = b.add_inter(vec2(100.0, 0.0));
+/// b.connect(a, c, &LanePatternBuilder::new().build())?;
+/// let map = b.build();
+/// ```
+pub struct MapBuilder {
+    map: Map,
+    touched: BTreeSet<IntersectionID>,
+}
+
+impl Default for MapBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MapBuilder {
+    pub fn new() -> Self {
+        Self {
+            map: Map::empty(),
+            touched: BTreeSet::new(),
+        }
+    }
+
+    /// Adds an intersection on the ground at pos
+    pub fn add_inter(&mut self, pos: Vec2) -> IntersectionID {
+        let h = self.map.environment.height(pos).unwrap_or(0.0);
+        let id = self.map.add_intersection(pos.z(h + ROAD_Z_OFFSET));
+        self.touched.insert(id);
+        id
+    }
+
+    /// Adds a straight road from a to b
+    pub fn connect(
+        &mut self,
+        a: IntersectionID,
+        b: IntersectionID,
+        pattern: &LanePattern,
+    ) -> Result<RoadID, MapBuilderError> {
+        let inters = self.map.intersections();
+        let (Some(ia), Some(ib)) = (inters.get(a), inters.get(b)) else {
+            let unknown = if inters.contains_key(a) { b } else { a };
+            return Err(MapBuilderError::UnknownIntersection(unknown));
+        };
+        if a == b {
+            return Err(MapBuilderError::SameIntersection(a));
+        }
+        if ia.pos.distance(ib.pos) < 1.0 {
+            return Err(MapBuilderError::TooClose);
+        }
+        if let Some(road) = self
+            .map
+            .find_road(a, b)
+            .or_else(|| self.map.find_road(b, a))
+        {
+            return Err(MapBuilderError::AlreadyConnected(road));
+        }
+
+        let road = self
+            .map
+            .connect(a, b, pattern, RoadSegmentKind::Straight)
+            .ok_or(MapBuilderError::UnknownIntersection(a))?;
+        self.touched.insert(a);
+        self.touched.insert(b);
+        Ok(road)
+    }
+
+    /// The map being built, turns are not generated yet
+    pub fn map(&self) -> &Map {
+        &self.map
+    }
+
+    /// Generates the turns and traffic control of every intersection.
+    /// Intersections left without roads are removed.
+    pub fn build(mut self) -> Map {
+        for id in std::mem::take(&mut self.touched) {
+            self.map.invalidate(id);
+        }
+        self.map.check_invariants();
+        self.map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MapBuilder, MapBuilderError};
+    use crate::map::{LanePatternBuilder, TurnKind};
+    use geom::vec2;
+
+    #[test]
+    fn four_way_intersection() {
+        let pat = LanePatternBuilder::new().parking(false).build();
+        let mut b = MapBuilder::new();
+        let center = b.add_inter(vec2(0.0, 0.0));
+        for dir in [
+            vec2(1.0, 0.0),
+            vec2(0.0, 1.0),
+            vec2(-1.0, 0.0),
+            vec2(0.0, -1.0),
+        ] {
+            let end = b.add_inter(dir * 100.0);
+            b.connect(center, end, &pat).unwrap();
+        }
+
+        let end = b
+            .map()
+            .roads()
+            .values()
+            .next()
+            .unwrap()
+            .other_end(center)
+            .unwrap();
+        let existing = b.map().find_road(center, end).unwrap();
+        assert_eq!(
+            b.connect(end, center, &pat),
+            Err(MapBuilderError::AlreadyConnected(existing))
+        );
+        assert_eq!(
+            b.connect(center, center, &pat),
+            Err(MapBuilderError::SameIntersection(center))
+        );
+
+        let map = b.build();
+        assert_eq!(map.intersections().len(), 5);
+        assert_eq!(map.roads().len(), 4);
+
+        let inter = &map.intersections()[center];
+        let count = |kind: TurnKind| inter.turns().filter(|t| t.kind == kind).count();
+        // one lane each way: every incoming lane goes to the 3 other roads
+        assert_eq!(count(TurnKind::Driving), 12);
+        // a crosswalk across each road and a corner between each pair of adjacent roads
+        assert_eq!(count(TurnKind::Crosswalk), 4);
+        assert_eq!(count(TurnKind::WalkingCorner), 4);
+        assert_eq!(inter.turns().count(), 20);
+    }
+}
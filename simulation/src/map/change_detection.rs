@@ -2,7 +2,7 @@
 //! This should not be used inside the simulation as change subscribers are not serialized.
 //! It is mostly for rendering purposes by decoupling it from the simulation.
 
-use crate::map::{Building, Intersection, Lot, Road};
+use crate::map::{Building, BuildingID, Intersection, IntersectionID, Lot, Road, RoadID};
 use common::{ChunkID, ChunkID_1024};
 use geom::Vec2;
 use std::collections::BTreeSet;
@@ -80,6 +80,63 @@ impl MapSubscribers {
     }
 }
 
+/// Net changes to the map objects since the last [`crate::map::Map::take_changes`] call.
+/// An object added then removed in between doesn't appear at all.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MapChanges {
+    pub added_roads: BTreeSet<RoadID>,
+    pub removed_roads: BTreeSet<RoadID>,
+    pub added_intersections: BTreeSet<IntersectionID>,
+    pub removed_intersections: BTreeSet<IntersectionID>,
+    /// Existing intersections whose shape, turns or roads changed
+    pub changed_intersections: BTreeSet<IntersectionID>,
+    pub added_buildings: BTreeSet<BuildingID>,
+    pub removed_buildings: BTreeSet<BuildingID>,
+}
+
+impl MapChanges {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    pub(crate) fn road_added(&mut self, id: RoadID) {
+        self.added_roads.insert(id);
+    }
+
+    pub(crate) fn road_removed(&mut self, id: RoadID) {
+        if !self.added_roads.remove(&id) {
+            self.removed_roads.insert(id);
+        }
+    }
+
+    pub(crate) fn intersection_added(&mut self, id: IntersectionID) {
+        self.added_intersections.insert(id);
+    }
+
+    pub(crate) fn intersection_removed(&mut self, id: IntersectionID) {
+        self.changed_intersections.remove(&id);
+        if !self.added_intersections.remove(&id) {
+            self.removed_intersections.insert(id);
+        }
+    }
+
+    pub(crate) fn intersection_changed(&mut self, id: IntersectionID) {
+        if !self.added_intersections.contains(&id) {
+            self.changed_intersections.insert(id);
+        }
+    }
+
+    pub(crate) fn building_added(&mut self, id: BuildingID) {
+        self.added_buildings.insert(id);
+    }
+
+    pub(crate) fn building_removed(&mut self, id: BuildingID) {
+        if !self.added_buildings.remove(&id) {
+            self.removed_buildings.insert(id);
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct MapSubscriberInner {
     pub updated_chunks: BTreeSet<SubscriberChunkID>,
@@ -111,6 +168,15 @@ impl MapSubscriber {
         inner.updated_chunks.pop_first()
     }
 
+    /// Returns true if anything was dispatched since the last call
+    pub fn take_changed(&self) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        let changed = inner.cleared || !inner.updated_chunks.is_empty();
+        inner.cleared = false;
+        inner.updated_chunks.clear();
+        changed
+    }
+
     pub fn take_cleared(&mut self) -> bool {
         let mut inner = self.inner.lock().unwrap();
         std::mem::take(&mut inner.cleared)
@@ -148,3 +214,57 @@ impl CanonicalPosition for Lot {
         self.shape.center()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::map::{LanePatternBuilder, Map, MapProject, ProjectFilter, ProjectKind};
+    use geom::{vec3, Vec3};
+
+    #[test]
+    fn changes_are_netted_out() {
+        let mut map = Map::empty();
+        let pat = LanePatternBuilder::new().build();
+
+        let mut road = |map: &mut Map, from: Vec3, to: Vec3| {
+            let a = map.project(from, 0.0, ProjectFilter::ALL);
+            let b = map.project(to, 0.0, ProjectFilter::ALL);
+            map.make_connection(a, b, None, &pat).unwrap().1
+        };
+
+        let kept = road(&mut map, vec3(0.0, 0.0, 0.0), vec3(100.0, 0.0, 0.0));
+        let changes = map.take_changes();
+        assert_eq!(changes.added_roads.len(), 1);
+        assert!(changes.added_roads.contains(&kept));
+        assert_eq!(changes.added_intersections.len(), 2);
+        assert!(map.take_changes().is_empty());
+
+        // added and removed within the same frame
+        let temp = road(&mut map, vec3(0.0, 200.0, 0.0), vec3(100.0, 200.0, 0.0));
+        map.remove_road(temp);
+        let changes = map.take_changes();
+        assert!(changes.added_roads.is_empty());
+        assert!(changes.removed_roads.is_empty());
+        assert!(changes.added_intersections.is_empty());
+        assert!(changes.removed_intersections.is_empty());
+
+        // branching off the middle of the kept road splits it in two
+        let a = map.project(vec3(50.0, 0.0, 0.0), 0.0, ProjectFilter::ALL);
+        assert!(matches!(a.kind, ProjectKind::Road(_)));
+        let b = MapProject {
+            pos: vec3(50.0, 100.0, 0.0),
+            kind: ProjectKind::Ground,
+        };
+        let (_, branch) = map.make_connection(a, b, None, &pat).unwrap();
+        let changes = map.take_changes();
+        assert_eq!(changes.removed_roads.len(), 1);
+        assert!(changes.removed_roads.contains(&kept));
+        assert_eq!(changes.added_roads.len(), 3);
+        assert!(changes.added_roads.contains(&branch));
+        assert_eq!(changes.added_intersections.len(), 2);
+        assert!(changes.removed_intersections.is_empty());
+        assert_eq!(changes.changed_intersections.len(), 2);
+        for r in &changes.added_roads {
+            assert!(map.roads().contains_key(*r));
+        }
+    }
+}
@@ -484,6 +484,7 @@ mod tests {
                 },
                 None,
                 Some(r),
+                0,
             )
             .unwrap();
 
@@ -0,0 +1,110 @@
+use crate::map::Map;
+use geom::PolyLine3;
+use serde_json::{json, Value};
+
+/// Exports the roads and intersections of the map as a GeoJSON FeatureCollection, with the lanes
+/// too if `lanes` is true.
+/// The coordinates are in meters in the map's local space (x east, y north, z up) and not
+/// in WGS84 as the GeoJSON spec expects, GIS tools should load them with a local/engineering CRS.
+pub fn export_geojson(map: &Map, lanes: bool) -> String {
+    let mut features = vec![];
+
+    for inter in map.intersections().values() {
+        features.push(json!({
+            "type": "Feature",
+            "geometry": {
+                "type": "Point",
+                "coordinates": [inter.pos.x, inter.pos.y, inter.pos.z],
+            },
+            "properties": {
+                "feature": "intersection",
+                "id": format!("{:?}", inter.id),
+                "roads": inter.roads.len(),
+            },
+        }));
+    }
+
+    for road in map.roads().values() {
+        let kinds: Vec<String> = road
+            .lanes_iter()
+            .map(|(_, kind)| format!("{:?}", kind))
+            .collect();
+        features.push(json!({
+            "type": "Feature",
+            "geometry": line_string(road.points()),
+            "properties": {
+                "feature": "road",
+                "id": format!("{:?}", road.id),
+                "src": format!("{:?}", road.src),
+                "dst": format!("{:?}", road.dst),
+                "width": road.width,
+                "n_lanes": kinds.len(),
+                "lane_kinds": kinds,
+            },
+        }));
+    }
+
+    if lanes {
+        for lane in map.lanes().values() {
+            features.push(json!({
+                "type": "Feature",
+                "geometry": line_string(&lane.points),
+                "properties": {
+                    "feature": "lane",
+                    "id": format!("{:?}", lane.id),
+                    "road": format!("{:?}", lane.parent),
+                    "kind": format!("{:?}", lane.kind),
+                    "speed_limit": lane.speed_limit,
+                },
+            }));
+        }
+    }
+
+    json!({
+        "type": "FeatureCollection",
+        "features": features,
+    })
+    .to_string()
+}
+
+fn line_string(points: &PolyLine3) -> Value {
+    json!({
+        "type": "LineString",
+        "coordinates": points.iter().map(|p| [p.x, p.y, p.z]).collect::<Vec<_>>(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::export_geojson;
+    use crate::map::procgen::load_testfield;
+    use crate::map::Map;
+    use geom::Vec2;
+
+    #[test]
+    fn testfield_to_geojson() {
+        let mut map = Map::empty();
+        load_testfield(&mut map, Vec2::ZERO, 3, 100.0);
+
+        let count = |json: &str, feature: &str| {
+            let v: serde_json::Value = serde_json::from_str(json).unwrap();
+            assert_eq!(v["type"], "FeatureCollection");
+            v["features"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .filter(|f| f["properties"]["feature"] == feature)
+                .count()
+        };
+
+        let json = export_geojson(&map, false);
+        assert_eq!(count(&json, "intersection"), map.intersections().len());
+        assert_eq!(count(&json, "road"), map.roads().len());
+        assert_eq!(count(&json, "lane"), 0);
+
+        let json = export_geojson(&map, true);
+        assert_eq!(count(&json, "road"), map.roads().len());
+        assert_eq!(count(&json, "lane"), map.lanes().len());
+        assert!(map.lanes().len() > map.roads().len());
+    }
+}
@@ -0,0 +1,178 @@
+//! The lane connectivity of a map without its geometry, enough to route vehicles.
+//! It can be exported from a [`Map`] or written by hand to test routing on small graphs.
+use crate::map::{LaneID, LaneKind, Map};
+use ordered_float::OrderedFloat;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Directed graph where nodes are lanes and edges are the turns between them.
+/// The cost of an edge is the time in seconds to drive its destination lane.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LaneGraph {
+    /// Lane each node comes from, empty for hand-written graphs
+    lanes: Vec<LaneID>,
+    /// Outgoing edges of each node as (destination node, cost)
+    edges: Vec<Vec<(u32, f32)>>,
+}
+
+impl LaneGraph {
+    pub fn add_node(&mut self) -> u32 {
+        self.edges.push(vec![]);
+        self.edges.len() as u32 - 1
+    }
+
+    /// Adds a one way edge, both nodes must exist
+    pub fn add_edge(&mut self, from: u32, to: u32, cost: f32) {
+        assert!((to as usize) < self.edges.len(), "unknown node {}", to);
+        self.edges[from as usize].push((to, cost));
+    }
+
+    pub fn n_nodes(&self) -> usize {
+        self.edges.len()
+    }
+
+    pub fn edges(&self, node: u32) -> &[(u32, f32)] {
+        self.edges.get(node as usize).map_or(&[], |e| &e[..])
+    }
+
+    /// Lane the node was exported from
+    pub fn lane(&self, node: u32) -> Option<LaneID> {
+        self.lanes.get(node as usize).copied()
+    }
+
+    pub fn node_of(&self, lane: LaneID) -> Option<u32> {
+        self.lanes.iter().position(|&l| l == lane).map(|i| i as u32)
+    }
+
+    /// Cheapest path from `start` to `end` both included, with its cost
+    pub fn path(&self, start: u32, end: u32) -> Option<(Vec<u32>, f32)> {
+        if start as usize >= self.edges.len() {
+            return None;
+        }
+        let (path, cost) = pathfinding::directed::dijkstra::dijkstra(
+            &start,
+            |&n| {
+                self.edges(n)
+                    .iter()
+                    .map(|&(to, cost)| (to, OrderedFloat(cost)))
+            },
+            |&n| n == end,
+        )?;
+        Some((path, cost.0))
+    }
+}
+
+impl Map {
+    /// Graph of the lanes vehicles can drive on, with the same costs as vehicle routing
+    /// (see [`crate::map::Lane::travel_time`]) apart from its random tie breaking. Closed roads are left out.
+    pub fn export_lane_graph(&self) -> LaneGraph {
+        let mut g = LaneGraph::default();
+        let mut nodes = BTreeMap::new();
+        for (id, lane) in self.lanes.iter() {
+            if !matches!(lane.kind, LaneKind::Driving | LaneKind::Bus) || self.is_lane_closed(id) {
+                continue;
+            }
+            nodes.insert(id, g.add_node());
+            g.lanes.push(id);
+        }
+
+        for (&id, &node) in &nodes {
+            let Some(inter) = self.intersections.get(self.lanes[id].dst) else {
+                continue;
+            };
+            for (turn, _) in inter.turns_from(id) {
+                let (Some(&to), Some(dst)) = (nodes.get(&turn.dst), self.lanes.get(turn.dst))
+                else {
+                    continue;
+                };
+                g.add_edge(node, to, dst.travel_time());
+            }
+        }
+        g
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LaneGraph;
+    use crate::map::{LaneKind, LanePatternBuilder, MapBuilder};
+    use common::saveload::{Bincode, Encoder};
+    use geom::vec2;
+
+    #[test]
+    fn route_on_handwritten_graph() {
+        let mut g = LaneGraph::default();
+        let [a, b, c, d, dead_end] = [(); 5].map(|_| g.add_node());
+        g.add_edge(a, b, 1.0);
+        g.add_edge(a, c, 5.0);
+        g.add_edge(b, d, 10.0);
+        g.add_edge(c, d, 1.0);
+        g.add_edge(d, a, 2.0);
+        g.add_edge(dead_end, a, 1.0);
+
+        let g: LaneGraph = Bincode::decode(&Bincode::encode(&g).unwrap()).unwrap();
+
+        assert_eq!(g.path(a, d), Some((vec![a, c, d], 6.0)));
+        // edges are one way
+        assert_eq!(g.path(d, c), Some((vec![d, a, c], 7.0)));
+        assert_eq!(g.path(a, dead_end), None);
+        assert_eq!(g.path(dead_end, b), Some((vec![dead_end, a, b], 2.0)));
+        assert_eq!(g.path(a, a), Some((vec![a], 0.0)));
+    }
+
+    #[test]
+    fn exported_graph_keeps_turns_and_costs() {
+        let pat = LanePatternBuilder::new().parking(false).build();
+        let mut b = MapBuilder::new();
+        let center = b.add_inter(vec2(0.0, 0.0));
+        let west = b.add_inter(vec2(-100.0, 0.0));
+        let east = b.add_inter(vec2(100.0, 0.0));
+        let north = b.add_inter(vec2(0.0, 100.0));
+        b.connect(west, center, &pat).unwrap();
+        b.connect(center, east, &pat).unwrap();
+        b.connect(center, north, &pat).unwrap();
+        let map = b.build();
+
+        let g = map.export_lane_graph();
+        let driving = map
+            .lanes()
+            .values()
+            .filter(|l| l.kind == LaneKind::Driving)
+            .count();
+        assert_eq!(g.n_nodes(), driving);
+
+        let turns = map.intersections()[center]
+            .turns()
+            .filter(|t| map.lanes()[t.id.src].kind == LaneKind::Driving)
+            .count();
+        let center_edges = (0..g.n_nodes() as u32)
+            .filter(|&n| map.lanes()[g.lane(n).unwrap()].dst == center)
+            .map(|n| g.edges(n).len())
+            .sum::<usize>();
+        assert_eq!(center_edges, turns);
+
+        for n in 0..g.n_nodes() as u32 {
+            for &(to, cost) in g.edges(n) {
+                let dst = &map.lanes()[g.lane(to).unwrap()];
+                assert_eq!(cost, dst.points.length() / dst.speed_limit);
+                // edges follow the driving direction
+                assert_eq!(map.lanes()[g.lane(n).unwrap()].dst, dst.src);
+            }
+        }
+
+        let from = map
+            .lanes()
+            .values()
+            .find(|l| l.kind == LaneKind::Driving && l.src == west)
+            .unwrap();
+        let to = map
+            .lanes()
+            .values()
+            .find(|l| l.kind == LaneKind::Driving && l.dst == north)
+            .unwrap();
+        let (path, _) = g
+            .path(g.node_of(from.id).unwrap(), g.node_of(to.id).unwrap())
+            .unwrap();
+        assert_eq!(path.len(), 2);
+    }
+}
@@ -1,4 +1,7 @@
-use crate::map::{Intersection, LaneID, Lanes, Roads, TrafficControl, TrafficLightSchedule};
+use crate::map::{
+    Intersection, LaneID, Lanes, RoadID, Roads, TrafficBehavior, TrafficControl,
+    TrafficLightSchedule,
+};
 use egui_inspect::{egui, egui::Ui, Inspect, InspectArgs};
 use prototypes::SECONDS_PER_REALTIME_SECOND;
 use serde::{Deserialize, Serialize};
@@ -12,13 +15,66 @@ pub enum LightPolicy {
     Auto,
 }
 
+/// Durations of the phases of a signalized intersection, in game seconds.
+/// Each phase gives green then orange to a group of roads, followed by all lights red.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LightTiming {
+    pub green: u16,
+    pub orange: u16,
+    pub all_red: u16,
+}
+
+impl Default for LightTiming {
+    fn default() -> Self {
+        Self {
+            green: 10 * SECONDS_PER_REALTIME_SECOND as u16,
+            orange: 4 * SECONDS_PER_REALTIME_SECOND as u16,
+            all_red: 0,
+        }
+    }
+}
+
+impl LightTiming {
+    pub const MIN_GREEN: u16 = SECONDS_PER_REALTIME_SECOND as u16;
+    pub const MAX_GREEN: u16 = 120 * SECONDS_PER_REALTIME_SECOND as u16;
+    pub const MAX_ORANGE: u16 = 10 * SECONDS_PER_REALTIME_SECOND as u16;
+    pub const MAX_ALL_RED: u16 = 10 * SECONDS_PER_REALTIME_SECOND as u16;
+
+    /// Keeps the durations in their allowed ranges
+    pub fn clamped(self) -> Self {
+        Self {
+            green: self.green.clamp(Self::MIN_GREEN, Self::MAX_GREEN),
+            orange: self.orange.min(Self::MAX_ORANGE),
+            all_red: self.all_red.min(Self::MAX_ALL_RED),
+        }
+    }
+
+    /// Length of one phase
+    pub fn phase_length(&self) -> u16 {
+        self.green + self.orange + self.all_red
+    }
+}
+
+/// What the lights of a signalized intersection show at a given time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LightsState {
+    /// The phase currently giving way to its roads
+    pub phase: usize,
+    pub n_phases: usize,
+    /// Seconds before the next phase starts
+    pub remaining: u32,
+    /// Light of each incoming road, with the seconds before it changes
+    pub roads: Vec<(RoadID, TrafficBehavior, u32)>,
+}
+
 impl LightPolicy {
-    pub fn apply(self, inter: &Intersection, lanes: &mut Lanes, roads: &Roads) {
-        let in_road_lanes: Vec<Vec<LaneID>> = inter
+    /// Incoming lanes needing a light (or a stop sign), grouped by road
+    fn in_road_lanes(inter: &Intersection, roads: &Roads) -> Vec<(RoadID, Vec<LaneID>)> {
+        inter
             .roads
             .iter()
             .map(|&x| {
-                roads
+                let lanes = roads
                     .get(x)
                     .into_iter()
                     .flat_map(|r| {
@@ -27,12 +83,75 @@ impl LightPolicy {
                             .filter(|(_, kind)| kind.needs_light())
                             .map(|&(id, _)| id)
                     })
-                    .collect::<Vec<_>>()
+                    .collect::<Vec<_>>();
+                (x, lanes)
             })
-            .filter(|v| !v.is_empty())
-            .collect();
+            .filter(|(_, v)| !v.is_empty())
+            .collect()
+    }
+
+    fn n_phases(n_roads: usize) -> usize {
+        (n_roads + 1) / 2
+    }
+
+    /// Current state of the lights of the intersection, None if it has no traffic lights
+    pub fn lights_state(
+        inter: &Intersection,
+        lanes: &Lanes,
+        roads: &Roads,
+        seconds: u32,
+    ) -> Option<LightsState> {
+        let in_road_lanes = Self::in_road_lanes(inter, roads);
+        let n_phases = Self::n_phases(in_road_lanes.len());
+        let phase_length = inter.effective_light_timing(lanes, roads).phase_length();
+
+        let mut state = LightsState {
+            phase: 0,
+            n_phases,
+            remaining: 0,
+            roads: vec![],
+        };
+        for (i, (road, road_lanes)) in in_road_lanes.into_iter().enumerate() {
+            let Some(TrafficControl::Light(schedule)) = road_lanes
+                .first()
+                .and_then(|&l| lanes.get(l))
+                .map(|l| l.control)
+            else {
+                continue;
+            };
+            let pos = schedule.position(seconds);
+            if pos < phase_length {
+                state.phase = i % n_phases;
+                state.remaining = (phase_length - pos) as u32;
+            }
+            state.roads.push((
+                road,
+                TrafficControl::Light(schedule).get_behavior(seconds),
+                schedule.remaining(seconds),
+            ));
+        }
+
+        if state.roads.is_empty() {
+            return None;
+        }
+        Some(state)
+    }
+
+    /// Whether the intersection ends up with traffic lights under this policy
+    pub fn has_lights(self, inter: &Intersection, roads: &Roads) -> bool {
+        match self {
+            LightPolicy::Lights => true,
+            LightPolicy::Auto => {
+                inter.turn_policy.left_turns && Self::in_road_lanes(inter, roads).len() >= 4
+            }
+            LightPolicy::NoLights | LightPolicy::StopSigns => false,
+        }
+    }
+
+    pub fn apply(self, inter: &Intersection, lanes: &mut Lanes, roads: &Roads) {
+        let in_road_lanes = Self::in_road_lanes(inter, roads);
 
-        for incoming_lanes in &in_road_lanes {
+        for (_, incoming_lanes) in &in_road_lanes {
             for &lane in incoming_lanes {
                 unwrap_cont!(lanes.get_mut(lane)).control = TrafficControl::Always;
             }
@@ -41,24 +160,24 @@ impl LightPolicy {
         match self {
             LightPolicy::NoLights => {}
             LightPolicy::StopSigns => {
-                Self::stop_signs(in_road_lanes, lanes);
+                Self::stop_signs(in_road_lanes, lanes, roads);
             }
             LightPolicy::Lights => {
-                Self::lights(in_road_lanes, inter, lanes);
+                Self::lights(in_road_lanes, inter, lanes, roads);
             }
             LightPolicy::Auto => {
                 if in_road_lanes.len() <= 2 {
                     return;
                 }
                 if in_road_lanes.len() == 3 {
-                    Self::stop_signs(in_road_lanes, lanes);
+                    Self::stop_signs(in_road_lanes, lanes, roads);
                     return;
                 }
 
                 if inter.turn_policy.left_turns {
-                    Self::lights(in_road_lanes, inter, lanes);
+                    Self::lights(in_road_lanes, inter, lanes, roads);
                 } else {
-                    Self::stop_signs(in_road_lanes, lanes);
+                    Self::stop_signs(in_road_lanes, lanes, roads);
                 }
             }
         }
@@ -68,30 +187,68 @@ impl LightPolicy {
         matches!(self, LightPolicy::StopSigns)
     }
 
-    fn stop_signs(in_road_lanes: Vec<Vec<LaneID>>, lanes: &mut Lanes) {
-        for incoming_lanes in in_road_lanes {
+    /// The two roads with the most vehicle lanes, if they have more than all the others.
+    /// Traffic on them has the priority and doesn't stop.
+    fn major_roads(in_road_lanes: &[(RoadID, Vec<LaneID>)], roads: &Roads) -> Option<[RoadID; 2]> {
+        if in_road_lanes.len() < 3 {
+            return None;
+        }
+        let mut priorities: Vec<(usize, RoadID)> = in_road_lanes
+            .iter()
+            .map(|&(id, _)| {
+                let n = roads.get(id).map_or(0, |r| {
+                    r.lanes_iter().filter(|(_, kind)| kind.vehicles()).count()
+                });
+                (n, id)
+            })
+            .collect();
+        priorities.sort_by(|a, b| b.0.cmp(&a.0));
+        if priorities[1].0 <= priorities[2].0 {
+            return None;
+        }
+        Some([priorities[0].1, priorities[1].1])
+    }
+
+    /// The major roads of the intersection, None if no road is bigger than the others
+    pub(crate) fn priority_roads(inter: &Intersection, roads: &Roads) -> Option<[RoadID; 2]> {
+        Self::major_roads(&Self::in_road_lanes(inter, roads), roads)
+    }
+
+    /// Incoming lanes of the minor roads stop then yield, the major road flows freely.
+    /// Without a clear major road, every road stops.
+    fn stop_signs(in_road_lanes: Vec<(RoadID, Vec<LaneID>)>, lanes: &mut Lanes, roads: &Roads) {
+        let major = Self::major_roads(&in_road_lanes, roads);
+        for (road, incoming_lanes) in in_road_lanes {
+            if major.map_or(false, |m| m.contains(&road)) {
+                continue;
+            }
             for lane in incoming_lanes {
                 unwrap_cont!(lanes.get_mut(lane)).control = TrafficControl::StopSign;
             }
         }
     }
 
-    fn lights(in_road_lanes: Vec<Vec<LaneID>>, inter: &Intersection, lanes: &mut Lanes) {
-        let n_cycles = ((in_road_lanes.len() + 1) / 2) as u16;
-        let cycle_size = 14 * SECONDS_PER_REALTIME_SECOND as u16;
-        let orange_length = 4 * SECONDS_PER_REALTIME_SECOND as u16;
+    fn lights(
+        in_road_lanes: Vec<(RoadID, Vec<LaneID>)>,
+        inter: &Intersection,
+        lanes: &mut Lanes,
+        roads: &Roads,
+    ) {
+        let n_cycles = Self::n_phases(in_road_lanes.len()) as u16;
+        let timing = inter.effective_light_timing(lanes, roads);
+        let cycle_size = timing.phase_length();
 
         let total_length = cycle_size * n_cycles;
 
         let inter_offset =
             (common::rand::rand(inter.id.as_ffi() as f32) * total_length as f32) as u16;
 
-        for (i, incoming_lanes) in in_road_lanes.into_iter().enumerate() {
+        for (i, (_, incoming_lanes)) in in_road_lanes.into_iter().enumerate() {
             let i = i as u16;
             let light = TrafficControl::Light(TrafficLightSchedule::from_basic(
-                cycle_size - orange_length,
-                orange_length,
-                total_length - cycle_size,
+                timing.green,
+                timing.orange,
+                total_length - timing.green - timing.orange,
                 cycle_size * (i % n_cycles) + inter_offset,
             ));
 
@@ -146,3 +303,55 @@ impl Inspect<LightPolicy> for LightPolicy {
         changed
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::LightPolicy;
+    use crate::map::{IntersectionID, LanePatternBuilder, Map, MapBuilder, RoadID};
+    use geom::vec2;
+
+    /// A T junction, returns the map, its center and the side street
+    fn junction(main_lanes: u32) -> (Map, IntersectionID, RoadID) {
+        let main = LanePatternBuilder::new().n_lanes(main_lanes).build();
+        let side = LanePatternBuilder::new().build();
+        let mut b = MapBuilder::new();
+        let center = b.add_inter(vec2(200.0, 200.0));
+        let west = b.add_inter(vec2(0.0, 200.0));
+        let east = b.add_inter(vec2(400.0, 200.0));
+        let south = b.add_inter(vec2(200.0, 0.0));
+        b.connect(west, center, &main).unwrap();
+        b.connect(center, east, &main).unwrap();
+        let side = b.connect(south, center, &side).unwrap();
+        let mut map = b.build();
+        map.update_intersection(center, |i| i.light_policy = LightPolicy::StopSigns);
+        (map, center, side)
+    }
+
+    /// Whether the incoming lanes of each road at the intersection have a stop sign
+    fn stops(map: &Map, center: IntersectionID) -> Vec<(RoadID, bool)> {
+        map.intersections()[center]
+            .roads
+            .iter()
+            .map(|&r| {
+                let lanes = map.roads()[r].incoming_lanes_to(center);
+                let stop = lanes
+                    .iter()
+                    .filter(|(_, kind)| kind.needs_light())
+                    .all(|&(id, _)| map.lanes()[id].control.is_stop_sign());
+                (r, stop)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn minor_road_stops_for_the_major_one() {
+        let (map, center, side) = junction(2);
+        for (road, stop) in stops(&map, center) {
+            assert_eq!(stop, road == side, "{:?}", road);
+        }
+
+        // no road is bigger than the others, everyone stops
+        let (map, center, _) = junction(1);
+        assert!(stops(&map, center).iter().all(|&(_, stop)| stop));
+    }
+}
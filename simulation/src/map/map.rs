@@ -2,23 +2,32 @@ use crate::map::electricity_cache::ElectricityCache;
 use crate::map::height_override::find_overrides;
 use crate::map::serializing::SerializedMap;
 use crate::map::{
-    Building, BuildingID, BuildingKind, Environment, Intersection, IntersectionID, Lane, LaneID,
-    LaneKind, LanePattern, Lot, LotID, LotKind, MapSubscriber, MapSubscribers, ParkingSpotID,
-    ParkingSpots, ProjectFilter, ProjectKind, Road, RoadID, RoadSegmentKind, SpatialMap,
-    SubscriberChunkID, TerraformKind, UpdateType, Zone, ROAD_Z_OFFSET,
+    Building, BuildingID, BuildingKind, ConnectorKind, DeadEndStyle, Environment, ExposureCache,
+    Intersection, IntersectionID, Lane, LaneDirection, LaneID, LaneKind, LanePattern,
+    LanePatternBuilder, Lot, LotID, LotKind, MapChanges, MapSubscriber, MapSubscribers,
+    ParkingSpotID, ParkingSpots, ParkingStyle, ProjectFilter, ProjectKind, Road, RoadID,
+    RoadMaterial, RoadSegmentKind, RoundaboutPolicy, SpatialMap, SubscriberChunkID, TerraformKind,
+    TravelTimeCache, TurnRestriction, UpdateType, VerticalConnector, VerticalConnectorID, Zone,
+    MIN_CONNECTOR_HEIGHT, MIN_TURNING_RADIUS, ROAD_Z_OFFSET,
 };
-use geom::OBB;
-use geom::{Vec2, Vec3};
+use geom::{PolyLine3, Spline3, Transform, Vec2, Vec3};
+use geom::{AABB, OBB};
 use ordered_float::OrderedFloat;
 use prototypes::{BuildingGen, Tick};
 use serde::{Deserialize, Serialize};
 use slotmapd::HopSlotMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Mutex;
 
 pub type Roads = HopSlotMap<RoadID, Road>;
 pub type Lanes = HopSlotMap<LaneID, Lane>;
 pub type Intersections = HopSlotMap<IntersectionID, Intersection>;
 pub type Buildings = HopSlotMap<BuildingID, Building>;
 pub type Lots = HopSlotMap<LotID, Lot>;
+pub type VerticalConnectors = HopSlotMap<VerticalConnectorID, VerticalConnector>;
+
+/// How far from the entrance of a building a road can be to be its access
+const BUILDING_ACCESS_DIST: f32 = 50.0;
 
 #[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct MapProject {
@@ -26,12 +35,22 @@ pub struct MapProject {
     pub kind: ProjectKind,
 }
 
+/// What is needed to build a road again exactly as it was, see [`Map::restore_road`]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedRoad {
+    pub src: Vec3,
+    pub dst: Vec3,
+    pub points: PolyLine3,
+    pub pattern: LanePattern,
+}
+
 pub struct Map {
     pub(crate) roads: Roads,
     pub(crate) lanes: Lanes,
     pub(crate) intersections: Intersections,
     pub(crate) buildings: Buildings,
     pub(crate) lots: Lots,
+    pub(crate) vertical_connectors: VerticalConnectors,
     pub(crate) spatial_map: SpatialMap,
     pub(crate) external_train_stations: Vec<BuildingID>,
 
@@ -40,6 +59,11 @@ pub struct Map {
     pub parking: ParkingSpots,
     pub subscribers: MapSubscribers,
     pub(crate) override_subscriber: MapSubscriber,
+    pub(crate) travel_times: TravelTimeCache,
+    pub(crate) exposure: ExposureCache,
+    pub(crate) changes: Mutex<MapChanges>,
+    /// Bumped on every mutation, see [`Map::version`]
+    pub(crate) version: u64,
 }
 
 defer_serialize!(Map, SerializedMap);
@@ -61,15 +85,30 @@ impl Map {
             parking: ParkingSpots::default(),
             buildings: Buildings::default(),
             lots: Lots::default(),
+            vertical_connectors: VerticalConnectors::default(),
             environment: Environment::default(),
             spatial_map: SpatialMap::default(),
             external_train_stations: Default::default(),
             electricity: Default::default(),
             override_subscriber: subscribers.subscribe(UpdateType::Road | UpdateType::Building),
+            travel_times: TravelTimeCache::new(subscribers.subscribe(UpdateType::Road)),
+            exposure: ExposureCache::new(
+                subscribers
+                    .subscribe(UpdateType::Road | UpdateType::Building | UpdateType::Terrain),
+            ),
+            changes: Mutex::default(),
+            version: 0,
             subscribers,
         }
     }
 
+    /// Counter incremented every time the map is modified (roads, intersections, lanes, buildings...).
+    /// Systems caching data derived from the map can compare it to the version they computed it at
+    /// to know when to recompute. It is saved with the map, a loaded map keeps counting from there.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
     /// Perform cleanups potentially required every frame
     pub fn update(&mut self) {
         profiling::scope!("map::update");
@@ -90,6 +129,150 @@ impl Map {
         self.check_invariants()
     }
 
+    /// Turns the intersection into a roundabout of the given radius, None makes it a regular intersection again.
+    /// The radius is raised if needed so that the connected roads fit around the ring.
+    pub fn set_roundabout(&mut self, id: IntersectionID, radius: Option<f32>) {
+        let Some(inter) = self.intersections.get(id) else {
+            return;
+        };
+        let min = RoundaboutPolicy::min_radius(inter, &self.roads);
+        let policy = radius.map(|r| RoundaboutPolicy {
+            radius: r.min(RoundaboutPolicy::MAX_RADIUS).max(min),
+        });
+        self.update_intersection(id, move |i| i.turn_policy.roundabout = policy);
+    }
+
+    /// Replaces the turn restrictions of an intersection and regenerates its turns.
+    /// Restrictions referencing a road not connected to the intersection are ignored.
+    pub fn set_turn_restrictions(
+        &mut self,
+        id: IntersectionID,
+        restrictions: impl IntoIterator<Item = TurnRestriction>,
+    ) {
+        let Some(inter) = self.intersections.get_mut(id) else {
+            return;
+        };
+        inter.turn_restrictions = restrictions
+            .into_iter()
+            .filter(|r| {
+                let ok = inter.roads.contains(&r.from) && inter.roads.contains(&r.to);
+                if !ok {
+                    log::warn!(
+                        "ignoring turn restriction {:?} not on intersection {:?}",
+                        r,
+                        id
+                    );
+                }
+                ok
+            })
+            .collect();
+        self.invalidate(id);
+    }
+
+    /// Closes or reopens a road to traffic. New routes avoid closed roads,
+    /// vehicles already routed through it are left alone.
+    pub fn set_road_closed(&mut self, id: RoadID, closed: bool) {
+        info!("set_road_closed {:?} {}", id, closed);
+
+        let Some(road) = self.roads.get_mut(id) else {
+            return;
+        };
+        if road.closed == closed {
+            return;
+        }
+        road.closed = closed;
+        self.subscribers.dispatch(UpdateType::Road, &*road);
+        self.version += 1;
+    }
+
+    /// Adds a tick of traffic to every vehicle lane from the number of vehicles on it and the
+    /// sum of their speeds. Nothing is dispatched to the subscribers since no geometry changes.
+    pub fn update_traffic(&mut self, on_lanes: &BTreeMap<LaneID, (u32, f32)>) {
+        for (id, lane) in self.lanes.iter_mut() {
+            if !lane.kind.vehicles() {
+                continue;
+            }
+            let (vehicles, speed_sum) = on_lanes.get(&id).copied().unwrap_or_default();
+            let length = lane.points.length();
+            lane.traffic
+                .update(vehicles, speed_sum, length, lane.speed_limit);
+        }
+    }
+
+    /// Changes the surface of a road. Only the look and the speed of vehicles change,
+    /// the geometry and lanes are kept as is.
+    pub fn set_road_material(&mut self, id: RoadID, material: RoadMaterial) {
+        info!("set_road_material {:?} {:?}", id, material);
+
+        let Some(road) = self.roads.get_mut(id) else {
+            return;
+        };
+        if road.material == material {
+            return;
+        }
+        road.material = material;
+        self.subscribers.dispatch(UpdateType::Road, &*road);
+        self.version += 1;
+    }
+
+    /// Changes the speed limit of a single lane, in m/s
+    pub fn set_lane_speed_limit(&mut self, id: LaneID, limit: f32) {
+        info!("set_lane_speed_limit {:?} {}", id, limit);
+
+        let Some(lane) = self.lanes.get_mut(id) else {
+            return;
+        };
+        lane.speed_limit = limit.max(1.0);
+        self.version += 1;
+        let Some(road) = self.roads.get(lane.parent) else {
+            return;
+        };
+        self.subscribers.dispatch(UpdateType::Road, road);
+    }
+
+    /// Switches the direction of one of the [`Road::reversible_lanes`], the turns of both
+    /// intersections are generated again. Returns false if the lane can't go in that direction.
+    pub fn set_lane_direction(&mut self, id: LaneID, dir: LaneDirection) -> bool {
+        info!("set_lane_direction {:?} {:?}", id, dir);
+
+        let Some(lane) = self.lanes.get_mut(id) else {
+            return false;
+        };
+        let Some(road) = self.roads.get_mut(lane.parent) else {
+            return false;
+        };
+        if lane.direction(road) == dir || !road.reverse_lane(id) {
+            return false;
+        }
+        std::mem::swap(&mut lane.src, &mut lane.dst);
+        lane.reversed = !lane.reversed;
+        lane.gen_pos(road);
+        self.subscribers.dispatch(UpdateType::Road, &*road);
+
+        let (src, dst) = (road.src, road.dst);
+        self.invalidate(src);
+        self.invalidate(dst);
+        true
+    }
+
+    /// Speed limit of the lane, taking the material of its road into account
+    pub fn lane_speed_limit(&self, id: LaneID) -> Option<f32> {
+        let l = self.lanes.get(id)?;
+        let factor = self
+            .roads
+            .get(l.parent)
+            .map_or(1.0, |r| r.material.speed_factor());
+        Some(l.speed_limit * factor)
+    }
+
+    /// Is the lane part of a road closed to traffic
+    pub fn is_lane_closed(&self, id: LaneID) -> bool {
+        self.lanes
+            .get(id)
+            .and_then(|l| self.roads.get(l.parent))
+            .map_or(false, |r| r.closed)
+    }
+
     pub fn remove_intersection(&mut self, src: IntersectionID) {
         info!("remove_intersection {:?}", src);
         self.remove_intersection_inner(src);
@@ -97,9 +280,98 @@ impl Map {
         self.check_invariants()
     }
 
-    fn remove_intersection_inner(&mut self, src: IntersectionID) {
+    /// Merges b into a: the roads of b are reconnected to a and the roads between a and b are removed.
+    /// Returns None if one of the intersections doesn't exist or if nothing is left of a after the merge.
+    pub fn merge_intersections(
+        &mut self,
+        a: IntersectionID,
+        b: IntersectionID,
+    ) -> Option<IntersectionID> {
+        info!("merge_intersections {:?} {:?}", a, b);
+        if a == b {
+            return None;
+        }
+        let a_pos = self.intersections.get(a)?.pos;
+        let b_pos = self.intersections.get(b)?.pos;
+
+        let mut direct = vec![];
+        for road_id in self.intersections[b].roads.clone() {
+            let r = unwrap_cont!(self.roads.get(road_id));
+            if r.other_end(b) == Some(a) {
+                direct.push(road_id);
+                continue;
+            }
+
+            let pat = r.pattern(&self.lanes);
+            let r = unwrap_cont!(self.remove_raw_road(road_id));
+            self.subscribers.dispatch(UpdateType::Road, &r);
+
+            for (id, _) in r.lanes_iter() {
+                self.parking.remove_to_reuse(id);
+            }
+
+            let move_end = |p: Vec3| a_pos.xy().z(a_pos.z + p.z - b_pos.z);
+            let (src, dst) = (
+                if r.src == b { a } else { r.src },
+                if r.dst == b { a } else { r.dst },
+            );
+            let mut points = r.points.clone().into_vec();
+            if r.src == b {
+                points[0] = move_end(points[0]);
+            }
+            if r.dst == b {
+                let last = points.len() - 1;
+                points[last] = move_end(points[last]);
+            }
+
+            let new_r = unwrap_cont!(self.connect(
+                src,
+                dst,
+                &pat,
+                RoadSegmentKind::Arbitrary(PolyLine3::new(points))
+            ));
+
+            let new_r = &mut self.roads[new_r];
+            new_r.closed = r.closed;
+            new_r.material = r.material;
+
+            for &building_id in &r.connected_buildings {
+                let building = unwrap_cont!(self.buildings.get_mut(building_id));
+                building.connected_road = Some(new_r.id);
+                new_r.connected_buildings.push(building_id);
+
+                self.electricity.add_edge(building_id, new_r.id);
+            }
+
+            for lot in &mut self.lots.values_mut() {
+                if lot.parent == road_id {
+                    lot.parent = new_r.id;
+                }
+            }
+
+            let other = if src == a { dst } else { src };
+            self.invalidate(other);
+        }
+
+        self.parking.clean_reuse();
+
+        // the roads between a and b would have a length of zero
+        for road_id in direct {
+            self.remove_road_inner(road_id);
+        }
+
+        self.remove_intersection_inner(b);
+        self.invalidate(a);
+
+        self.check_invariants();
+
+        self.intersections.contains_key(a).then_some(a)
+    }
+
+    pub(crate) fn remove_intersection_inner(&mut self, src: IntersectionID) {
         let inter = unwrap_ret!(self.intersections.remove(src));
         self.subscribers.dispatch(UpdateType::Road, &inter);
+        self.changes_mut().intersection_removed(src);
 
         for road in inter.roads {
             let r = unwrap_cont!(self.remove_road_inner(road));
@@ -130,6 +402,7 @@ impl Map {
 
         let b = self.buildings.remove(b)?;
         self.subscribers.dispatch(UpdateType::Building, &b);
+        self.changes_mut().building_removed(b.id);
 
         if b.kind == BuildingKind::ExternalTrading {
             self.external_train_stations.retain(|id| *id != b.id);
@@ -214,6 +487,60 @@ impl Map {
         Some((to_id, r))
     }
 
+    /// Builds a grid of `nx` by `ny` intersections spaced by `spacing`, starting at `origin`,
+    /// each connected to its neighbours with the given pattern.
+    /// Grid points falling on unbuildable terrain are skipped, leaving gaps.
+    pub fn build_road_grid(
+        &mut self,
+        origin: Vec2,
+        nx: u32,
+        ny: u32,
+        spacing: f32,
+        pattern: &LanePattern,
+    ) {
+        info!("build_road_grid {:?} {}x{} {}", origin, nx, ny, spacing);
+
+        let (nx, ny) = (nx as usize, ny as usize);
+        let mut grid: Vec<Option<IntersectionID>> = Vec::with_capacity(nx * ny);
+        for y in 0..ny {
+            for x in 0..nx {
+                let pos = origin + Vec2::new(x as f32 * spacing, y as f32 * spacing);
+                if !self.environment.is_buildable(pos) {
+                    grid.push(None);
+                    continue;
+                }
+                let h = self.environment.height(pos).unwrap_or(0.0);
+                grid.push(Some(self.add_intersection(pos.z(h + ROAD_Z_OFFSET))));
+            }
+        }
+
+        let get = |x: usize, y: usize| {
+            if x >= nx || y >= ny {
+                return None;
+            }
+            grid.get(y * nx + x).copied().flatten()
+        };
+        for y in 0..ny {
+            for x in 0..nx {
+                let Some(src) = get(x, y) else {
+                    continue;
+                };
+                if let Some(dst) = get(x + 1, y) {
+                    self.connect(src, dst, pattern, RoadSegmentKind::Straight);
+                }
+                if let Some(dst) = get(x, y + 1) {
+                    self.connect(src, dst, pattern, RoadSegmentKind::Straight);
+                }
+            }
+        }
+
+        for id in grid.into_iter().flatten() {
+            self.invalidate(id);
+        }
+
+        self.check_invariants();
+    }
+
     pub fn update_zone(&mut self, id: BuildingID, f: impl FnOnce(&mut Zone)) {
         let Some(b) = self.buildings.get_mut(id) else {
             return;
@@ -223,6 +550,7 @@ impl Map {
         let Some(ref mut z) = b.zone else {
             return;
         };
+        self.version += 1;
         f(z);
 
         self.environment.remove_trees_near(&z.poly, |tree_chunk| {
@@ -249,6 +577,7 @@ impl Map {
         gen: BuildingGen,
         zone: Option<Zone>,
         connected_road: Option<RoadID>,
+        door_offset: u8,
     ) -> Option<BuildingID> {
         if self.building_overlaps(*obb) {
             log::warn!("did not build {:?}: building overlaps", kind);
@@ -281,6 +610,7 @@ impl Map {
             gen,
             zone,
             connected_road,
+            door_offset,
         ) else {
             self.check_invariants();
             return None;
@@ -288,6 +618,7 @@ impl Map {
 
         self.subscribers
             .dispatch(UpdateType::Building, &self.buildings[id]);
+        self.changes_mut().building_added(id);
 
         if kind == BuildingKind::ExternalTrading {
             self.external_train_stations.push(id);
@@ -315,6 +646,7 @@ impl Map {
             BuildingGen::House,
             None,
             Some(lot.parent),
+            0,
         ) else {
             self.check_invariants();
             return None;
@@ -322,6 +654,7 @@ impl Map {
 
         self.subscribers
             .dispatch(UpdateType::Building, &self.buildings[id]);
+        self.changes_mut().building_added(id);
         self.electricity.add_object(id);
 
         self.check_invariants();
@@ -336,10 +669,167 @@ impl Map {
         v
     }
 
+    /// Removes every road and intersection touching the box, then the intersections left without roads.
+    /// Objects are removed in id order so that every client ends up with the same map.
+    pub fn remove_area(&mut self, aabb: AABB) {
+        info!("remove_area {:?}", aabb);
+
+        let mut roads = vec![];
+        let mut inters = vec![];
+        for kind in self
+            .spatial_map
+            .query(aabb, ProjectFilter::ROAD | ProjectFilter::INTER)
+        {
+            match kind {
+                ProjectKind::Road(id) => roads.push(id),
+                ProjectKind::Intersection(id) => inters.push(id),
+                _ => {}
+            }
+        }
+        roads.sort_unstable();
+        inters.sort_unstable();
+
+        let mut touched = BTreeSet::new();
+        for id in roads {
+            if let Some(road) = self.remove_road_inner(id) {
+                touched.insert(road.src);
+                touched.insert(road.dst);
+            }
+        }
+        for id in inters {
+            self.remove_intersection_inner(id);
+        }
+        for id in touched {
+            if self
+                .intersections
+                .get(id)
+                .map_or(false, |i| i.roads.is_empty())
+            {
+                self.remove_intersection_inner(id);
+            }
+        }
+
+        self.check_invariants();
+    }
+
+    pub fn save_road(&self, id: RoadID) -> Option<SavedRoad> {
+        let road = self.roads.get(id)?;
+        Some(SavedRoad {
+            src: self.intersections.get(road.src)?.pos,
+            dst: self.intersections.get(road.dst)?.pos,
+            points: road.points.clone(),
+            pattern: road.pattern(&self.lanes),
+        })
+    }
+
+    /// Builds the road again with its exact geometry.
+    /// The intersections still standing at its ends are reused, the missing ones are created.
+    pub fn restore_road(&mut self, saved: &SavedRoad) -> Option<RoadID> {
+        info!("restore_road {:?} {:?}", saved.src, saved.dst);
+        let src = self.intersection_at(saved.src);
+        let dst = self.intersection_at(saved.dst);
+
+        let r = self.connect(
+            src,
+            dst,
+            &saved.pattern,
+            RoadSegmentKind::Arbitrary(saved.points.clone()),
+        );
+        self.invalidate(src);
+        self.invalidate(dst);
+        self.check_invariants();
+        r
+    }
+
+    /// Removes the road and the intersections it leaves without roads
+    pub fn unbuild_road(&mut self, id: RoadID) -> Option<SavedRoad> {
+        info!("unbuild_road {:?}", id);
+        let saved = self.save_road(id)?;
+        let road = self.remove_road_inner(id)?;
+        for inter in [road.src, road.dst] {
+            if self
+                .intersections
+                .get(inter)
+                .map_or(false, |i| i.roads.is_empty())
+            {
+                self.remove_intersection_inner(inter);
+            }
+        }
+        self.check_invariants();
+        Some(saved)
+    }
+
+    /// Builds stairs or a ramp for pedestrians between two walkways at different heights.
+    /// The ends reuse the intersections already at `bottom` and `top` so that they join the walkways there.
+    /// Returns None if the two ends are not far enough apart in height.
+    pub fn build_vertical_connector(
+        &mut self,
+        bottom: Vec3,
+        top: Vec3,
+        kind: ConnectorKind,
+    ) -> Option<VerticalConnectorID> {
+        info!("build_vertical_connector {:?} {:?} {:?}", bottom, top, kind);
+        if (top.z - bottom.z).abs() < MIN_CONNECTOR_HEIGHT || bottom.xy().distance(top.xy()) < 1.0 {
+            return None;
+        }
+
+        let src = self.intersection_at(bottom);
+        let dst = self.intersection_at(top);
+        let road = self.connect(
+            src,
+            dst,
+            &LanePatternBuilder::new().n_lanes(0).build(),
+            RoadSegmentKind::Arbitrary(PolyLine3::new(vec![bottom, top])),
+        )?;
+        let id = self
+            .vertical_connectors
+            .insert_with_key(|id| VerticalConnector {
+                id,
+                kind,
+                road,
+                bottom: src,
+                top: dst,
+            });
+        self.check_invariants();
+        Some(id)
+    }
+
+    /// The connector the road belongs to, if it is one
+    pub fn vertical_connector_of(&self, road: RoadID) -> Option<&VerticalConnector> {
+        self.vertical_connectors.values().find(|c| c.road == road)
+    }
+
+    /// The intersection exactly at pos, created if there is none
+    fn intersection_at(&mut self, pos: Vec3) -> IntersectionID {
+        let existing = self
+            .spatial_map
+            .query_around(pos.xy(), 0.1, ProjectFilter::INTER)
+            .find_map(|kind| match kind {
+                ProjectKind::Intersection(id) => self
+                    .intersections
+                    .get(id)
+                    .filter(|i| i.pos.xy().distance(pos.xy()) < 0.1)
+                    .map(|_| id),
+                _ => None,
+            });
+        existing.unwrap_or_else(|| self.add_intersection(pos))
+    }
+
     pub fn subscribe(&self, filter: UpdateType) -> MapSubscriber {
         self.subscribers.subscribe(filter)
     }
 
+    /// Returns the roads, intersections and buildings added/removed/changed since the last call.
+    /// Meant for external renderers that want to update incrementally.
+    pub fn take_changes(&self) -> MapChanges {
+        std::mem::take(&mut *self.changes.lock().unwrap())
+    }
+
+    fn changes_mut(&mut self) -> &mut MapChanges {
+        self.version += 1;
+        self.changes.get_mut().unwrap()
+    }
+
     fn clean_lots_inner(&mut self, to_clean: Vec<ProjectKind>) {
         for id in to_clean {
             if let ProjectKind::Lot(id) = id {
@@ -387,6 +877,7 @@ impl Map {
             Some(lot) => {
                 lot.kind = kind;
                 self.subscribers.dispatch(UpdateType::Road, lot);
+                self.version += 1;
             }
             None => log::warn!("trying to set kind of non-existing lot {:?}", lot),
         }
@@ -408,6 +899,7 @@ impl Map {
 
         for id in modified {
             self.subscribers.dispatch_chunk(UpdateType::Terrain, id);
+            self.version += 1;
         }
     }
 
@@ -418,14 +910,49 @@ impl Map {
         self.subscribers
             .dispatch(UpdateType::Building, &self.intersections[id]);
         self.electricity.add_object(id);
+        self.changes_mut().intersection_added(id);
         id
     }
 
-    fn invalidate(&mut self, id: IntersectionID) {
+    /// Radius of the cul-de-sac that fits at the intersection, None if it isn't a dead end with a
+    /// cul-de-sac style or if the other roads around leave no room for it
+    fn fit_turnaround(&self, id: IntersectionID) -> Option<f32> {
+        let inter = self.intersections.get(id)?;
+        let DeadEndStyle::CulDeSac(radius) = inter.dead_end else {
+            return None;
+        };
+        let [own_road] = *inter.roads else {
+            return None;
+        };
+        let radius = radius.max(MIN_TURNING_RADIUS);
+        let center = inter.pos;
+
+        let room = self
+            .spatial_map
+            .query_around(center.xy(), radius, ProjectFilter::ROAD)
+            .filter_map(|kind| match kind {
+                ProjectKind::Road(r) if r != own_road => self.roads.get(r),
+                _ => None,
+            })
+            .map(|r| r.points.project(center).xy().distance(center.xy()) - r.width * 0.5)
+            .fold(radius, f32::min);
+
+        if room < MIN_TURNING_RADIUS {
+            info!("no room for a cul-de-sac at {:?}", id);
+            return None;
+        }
+        Some(room)
+    }
+
+    pub(crate) fn invalidate(&mut self, id: IntersectionID) {
         info!("invalidate {:?}", id);
 
+        let turnaround = self.fit_turnaround(id);
         let inter = unwrap_ret!(self.intersections.get_mut(id));
+        inter.set_turnaround(turnaround);
         self.subscribers.dispatch(UpdateType::Road, inter);
+        self.changes.get_mut().unwrap().intersection_changed(id);
+        self.version += 1;
 
         if inter.roads.is_empty() {
             self.remove_intersection_inner(id);
@@ -465,6 +992,8 @@ impl Map {
     /// and potentially empty intersections.
     fn remove_raw_road(&mut self, road_id: RoadID) -> Option<Road> {
         let road = self.roads.remove(road_id)?;
+        self.changes_mut().road_removed(road_id);
+        self.vertical_connectors.retain(|_, c| c.road != road_id);
 
         self.spatial_map.remove(road_id);
         self.electricity.remove_object(road_id);
@@ -512,6 +1041,11 @@ impl Map {
         let r1 = self.connect(r.src, id, &pat, RoadSegmentKind::Arbitrary(before))?;
         let r2 = self.connect(id, r.dst, &pat, RoadSegmentKind::Arbitrary(after))?;
 
+        self.roads[r1].closed = r.closed;
+        self.roads[r2].closed = r.closed;
+        self.roads[r1].material = r.material;
+        self.roads[r2].material = r.material;
+
         self.invalidate(r.src);
         self.invalidate(r.dst);
         self.invalidate(id);
@@ -706,6 +1240,7 @@ impl Map {
 
         self.electricity.add_object(rid);
         self.electricity.add_edge(src_id, rid);
+        self.changes_mut().road_added(rid);
         self.electricity.add_edge(dst_id, rid);
 
         #[allow(clippy::indexing_slicing)]
@@ -797,6 +1332,10 @@ impl Map {
     pub fn buildings(&self) -> &Buildings {
         &self.buildings
     }
+    pub fn vertical_connectors(&self) -> &VerticalConnectors {
+        &self.vertical_connectors
+    }
+
     pub fn lots(&self) -> &Lots {
         &self.lots
     }
@@ -821,6 +1360,50 @@ impl Map {
         None
     }
 
+    /// Point of the sidewalk in front of the entrance of the building, see [`Map::building_access`].
+    /// None if the building isn't connected to a road.
+    pub fn building_access_point(&self, id: BuildingID) -> Option<Vec3> {
+        let b = self.buildings.get(id)?;
+        b.connected_road?;
+        let lane = self.lanes.get(self.building_access(id)?)?;
+        Some(lane.points.project(b.door_pos))
+    }
+
+    /// Sidewalk the entrance of the building leads to: the closest one in front of the door.
+    /// If the door faces no road, falls back to the closest sidewalk.
+    pub fn building_access(&self, id: BuildingID) -> Option<LaneID> {
+        let b = self.buildings.get(id)?;
+        let door = b.door_pos;
+        let outward = b.door_dir();
+
+        let facing = self
+            .spatial_map
+            .query_around(door.xy(), BUILDING_ACCESS_DIST, ProjectFilter::ROAD)
+            .filter_map(|kind| match kind {
+                ProjectKind::Road(r) => self.roads.get(r),
+                _ => None,
+            })
+            .flat_map(|road| road.lanes_iter())
+            .filter(|&(_, kind)| kind == LaneKind::Walking)
+            .filter_map(|(id, _)| self.lanes.get(id))
+            .filter(|lane| {
+                (lane.points.project(door) - door)
+                    .xy()
+                    .try_normalize()
+                    .map_or(false, |d| d.dot(outward) > 0.5)
+            })
+            .min_by_key(|lane| OrderedFloat(lane.points.project_dist2(door)));
+
+        if let Some(lane) = facing {
+            return Some(lane.id);
+        }
+        log::warn!(
+            "entrance of {:?} faces no road, using the nearest one instead",
+            id
+        );
+        self.nearest_lane(door, LaneKind::Walking, None)
+    }
+
     pub fn nearest_lane(&self, p: Vec3, kind: LaneKind, cutoff: Option<f32>) -> Option<LaneID> {
         let tryfind = |radius| {
             self.spatial_map()
@@ -858,6 +1441,24 @@ impl Map {
             .map(|(id, _)| id)
     }
 
+    /// Lane of any kind closest to `pos`, with the distance from `pos` to its center line.
+    /// None if no lane is within `max_dist`.
+    pub fn lane_at(&self, pos: Vec2, max_dist: f32) -> Option<(LaneID, f32)> {
+        self.spatial_map
+            .query_around(pos, max_dist, ProjectFilter::ROAD)
+            .filter_map(|x| match x {
+                ProjectKind::Road(id) => self.roads.get(id),
+                _ => None,
+            })
+            .flat_map(|road| road.lanes_iter())
+            .filter_map(|(id, _)| {
+                let lane = self.lanes.get(id)?;
+                Some((id, lane.points.project_2d(pos).xy().distance(pos)))
+            })
+            .filter(|&(_, dist)| dist <= max_dist)
+            .min_by_key(|&(_, dist)| OrderedFloat(dist))
+    }
+
     pub fn parking_to_drive(&self, spot: ParkingSpotID) -> Option<LaneID> {
         let spot = self.parking.get(spot)?;
         let park_lane = self.lanes.get(spot.parent)?;
@@ -886,6 +1487,23 @@ impl Map {
         Some(pos - dir * 4.0)
     }
 
+    /// Path backing out of an angled slot onto the driving lane.
+    /// None for parallel slots, which are left driving forward.
+    pub fn parking_exit_path(&self, spot: ParkingSpotID) -> Option<Spline3> {
+        let p = self.parking.get(spot)?;
+        let park_lane = self.lanes.get(p.parent)?;
+        if park_lane.parking_style.validated() == ParkingStyle::Parallel {
+            return None;
+        }
+        let lane = self.parking_to_drive(spot)?;
+        let (pos, _, dir) = self
+            .lanes()
+            .get(lane)?
+            .points
+            .project_segment_dir(p.trans.pos);
+        Some(p.exit_path(Transform::new_dir(pos - dir * 4.0, dir)))
+    }
+
     #[cfg(not(debug_assertions))]
     pub fn check_invariants(&self) {}
 
@@ -1107,3 +1725,168 @@ impl MapObj for LotID {
         map.lots.get(self)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::map::{
+        LaneDirection, LaneKind, LanePatternBuilder, Map, MapBuilder, ProjectFilter,
+        RoadSegmentKind,
+    };
+    use common::saveload::{Bincode, Encoder};
+    use geom::{vec2, vec3, AABB};
+
+    #[test]
+    fn lane_at_finds_nearest_lane() {
+        let pat = LanePatternBuilder::new().parking(false).build();
+        let mut b = MapBuilder::new();
+        let a = b.add_inter(vec2(0.0, 0.0));
+        let end = b.add_inter(vec2(200.0, 0.0));
+        let road = b.connect(a, end, &pat).unwrap();
+        let map = b.build();
+
+        let (lane_id, lane) = map.roads()[road]
+            .lanes_iter()
+            .map(|(id, _)| (id, &map.lanes()[id]))
+            .find(|(_, l)| l.kind == LaneKind::Driving)
+            .unwrap();
+        let center = lane.points.point_along(lane.points.length() * 0.5).xy();
+
+        let (found, dist) = map.lane_at(center + vec2(0.0, 0.3), 5.0).unwrap();
+        assert_eq!(found, lane_id);
+        assert!((dist - 0.3).abs() < 0.05, "{}", dist);
+
+        let (found, dist) = map.lane_at(center, 5.0).unwrap();
+        assert_eq!(found, lane_id);
+        assert!(dist < 0.05);
+
+        assert!(map.lane_at(vec2(100.0, 500.0), 20.0).is_none());
+    }
+
+    #[test]
+    fn remove_area_clears_the_box() {
+        let pat = LanePatternBuilder::new().parking(false).build();
+        let mut b = MapBuilder::new();
+        let line: Vec<_> = [0.0, 200.0, 400.0, 600.0]
+            .into_iter()
+            .map(|x| b.add_inter(vec2(x, 0.0)))
+            .collect();
+        for w in line.windows(2) {
+            b.connect(w[0], w[1], &pat).unwrap();
+        }
+        let branch = b.add_inter(vec2(0.0, 200.0));
+        let kept = b.connect(line[0], branch, &pat).unwrap();
+        let mut map = b.build();
+
+        map.remove_area(AABB::new_ll_ur(vec2(150.0, -50.0), vec2(450.0, 50.0)));
+
+        // the last intersection has no roads left, the first one still has its branch
+        assert_eq!(map.roads().len(), 1);
+        assert!(map.roads().contains_key(kept));
+        let mut left: Vec<_> = map.intersections().keys().collect();
+        left.sort_unstable();
+        let mut expected = vec![line[0], branch];
+        expected.sort_unstable();
+        assert_eq!(left, expected);
+        assert!(map.intersections()[line[0]].roads == vec![kept]);
+        assert!(map.validate().is_empty());
+    }
+
+    #[test]
+    fn version_bumps_on_mutation_only() {
+        let pat = LanePatternBuilder::new().parking(false).build();
+        let mut b = MapBuilder::new();
+        let a = b.add_inter(vec2(0.0, 0.0));
+        let end = b.add_inter(vec2(200.0, 0.0));
+        let road = b.connect(a, end, &pat).unwrap();
+        let mut map = b.build();
+
+        let mut v = map.version();
+        let mut bumped = |map: &Map| {
+            let bumped = map.version() > v;
+            v = map.version();
+            bumped
+        };
+
+        // read-only access
+        let _ = map.project(vec3(100.0, 0.0, 0.0), 5.0, ProjectFilter::ALL);
+        let _ = map.lane_at(vec2(100.0, 0.0), 5.0);
+        let _ = map.roads().len();
+        let _ = map.validate();
+        assert!(!bumped(&map));
+
+        let lane = map.roads()[road].lanes_iter().next().unwrap().0;
+        map.set_lane_speed_limit(lane, 5.0);
+        assert!(bumped(&map));
+
+        map.set_road_closed(road, true);
+        assert!(bumped(&map));
+
+        map.update_intersection(a, |_| {});
+        assert!(bumped(&map));
+
+        let other = map.add_intersection(vec3(0.0, 200.0, 0.3));
+        assert!(bumped(&map));
+        map.connect(a, other, &pat, RoadSegmentKind::Straight)
+            .unwrap();
+        assert!(bumped(&map));
+
+        map.remove_road(road);
+        assert!(bumped(&map));
+        assert!(!bumped(&map));
+
+        // caches saved with the map must not match a reloaded map that restarted counting
+        let loaded: Map = Bincode::decode(&Bincode::encode(&map).unwrap()).unwrap();
+        assert_eq!(loaded.version(), map.version());
+        assert!(loaded.version() > 0);
+    }
+
+    #[test]
+    fn reversed_lane_flips_its_turns() {
+        let pat = LanePatternBuilder::new().parking(false).build();
+        let mut b = MapBuilder::new();
+        let a = b.add_inter(vec2(0.0, 0.0));
+        let mid = b.add_inter(vec2(200.0, 0.0));
+        let c = b.add_inter(vec2(400.0, 0.0));
+        let road = b.connect(a, mid, &pat).unwrap();
+        b.connect(mid, c, &pat).unwrap();
+        let mut map = b.build();
+
+        let lane = map.roads()[road].forward_lanes()[0].0;
+        let sidewalk = map.roads()[road]
+            .lanes_iter()
+            .find(|(_, kind)| *kind == LaneKind::Walking)
+            .unwrap()
+            .0;
+        let turns_at_mid = |map: &Map| {
+            let inter = &map.intersections()[mid];
+            (
+                inter.turns().filter(|t| t.id.src == lane).count(),
+                inter.turns().filter(|t| t.id.dst == lane).count(),
+            )
+        };
+        let (from_lane, to_lane) = turns_at_mid(&map);
+        assert!(from_lane > 0);
+        assert_eq!(to_lane, 0);
+
+        assert!(!map.set_lane_direction(sidewalk, LaneDirection::Backward));
+        assert!(!map.set_lane_direction(lane, LaneDirection::Forward));
+        assert!(map.set_lane_direction(lane, LaneDirection::Backward));
+
+        let l = &map.lanes()[lane];
+        assert_eq!((l.src, l.dst), (mid, a));
+        assert!(l.reversed);
+        assert_eq!(
+            map.roads()[road].forward_lanes(),
+            [(sidewalk, LaneKind::Walking)]
+        );
+        assert_eq!(map.roads()[road].backward_lanes()[0].0, lane);
+        let (from_lane, to_lane) = turns_at_mid(&map);
+        assert_eq!(from_lane, 0);
+        assert!(to_lane > 0);
+        assert!(map.validate().is_empty());
+
+        assert!(map.set_lane_direction(lane, LaneDirection::Forward));
+        assert!(!map.lanes()[lane].reversed);
+        assert_eq!(map.roads()[road].forward_lanes()[0].0, lane);
+    }
+}
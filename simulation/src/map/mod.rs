@@ -6,6 +6,7 @@ mod objects {
     mod parking;
     mod road;
     mod turn;
+    mod vertical_connector;
 
     pub use building::*;
     pub use intersection::*;
@@ -14,6 +15,7 @@ mod objects {
     pub use parking::*;
     pub use road::*;
     pub use turn::*;
+    pub use vertical_connector::*;
 }
 
 pub use objects::*;
@@ -21,40 +23,57 @@ pub use objects::*;
 pub mod procgen {
     mod building;
     pub mod heightmap;
+    mod osm;
     mod presets;
 
     pub use building::*;
+    pub use osm::*;
     pub use presets::*;
 }
 
+#[cfg(any(test, feature = "test-utils"))]
+mod builder;
 mod change_detection;
 mod electricity_cache;
+mod geojson;
 mod height_override;
+mod lane_graph;
 mod light_policy;
 #[allow(clippy::module_inception)]
 mod map;
 mod pathfinding;
-mod serializing;
+mod repair;
+pub(crate) mod serializing;
 mod spatial_map;
 pub mod terrain;
 mod traffic_control;
+mod travel_time;
 mod traversable;
 mod turn_policy;
 
 // Use self or else it would be ambiguous with "pathfinding" crate
 pub use self::pathfinding::*;
+#[cfg(any(test, feature = "test-utils"))]
+pub use builder::*;
 pub use change_detection::*;
 pub use electricity_cache::*;
+pub use geojson::*;
+pub use lane_graph::*;
 pub use light_policy::*;
 pub use map::*;
+pub use repair::*;
 pub use spatial_map::*;
 pub use terrain::*;
 pub use traffic_control::*;
+pub use travel_time::*;
 pub use traversable::*;
 pub use turn_policy::*;
 
 pub use ::pathfinding as pathfinding_crate;
 
 pub const CROSSWALK_WIDTH: f32 = 2.0;
+/// Size of the island in the middle of two-stage crossings, across and along the road
+pub const REFUGE_WIDTH: f32 = 1.5;
+pub const REFUGE_LENGTH: f32 = 6.0;
 pub const ROAD_Z_OFFSET: f32 = 0.3;
 pub const MAX_SLOPE: f32 = 0.25; // 25% grade
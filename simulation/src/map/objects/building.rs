@@ -4,9 +4,13 @@ use crate::map::{
 };
 use egui_inspect::debug_inspect_impl;
 use geom::{Color, Polygon, Vec2, Vec3, OBB};
-use prototypes::{BuildingGen, FreightStationPrototypeID, GoodsCompanyID};
+use prototypes::{
+    BuildingGen, CompanyKind, DayTime, FreightStationPrototypeID, GoodsCompanyID, HOURS_PER_DAY,
+    SECONDS_PER_HOUR,
+};
 use serde::{Deserialize, Serialize};
 use slotmapd::new_key_type;
+use std::ops::Range;
 
 new_key_type! {
     pub struct BuildingID;
@@ -34,6 +38,89 @@ impl BuildingKind {
     pub fn is_cached_in_bkinds(&self) -> bool {
         matches!(self, BuildingKind::ExternalTrading)
     }
+
+    /// How souls trips to and from this kind of building are spread over the day.
+    /// Trips from houses follow the workplaces and shops the souls go to, so houses have no curve of their own.
+    pub fn activity_curve(&self) -> ActivityCurve {
+        match self {
+            BuildingKind::GoodsCompany(id) => {
+                ActivityCurve::company(prototypes::prototype(*id).kind)
+            }
+            BuildingKind::House
+            | BuildingKind::RailFreightStation(_)
+            | BuildingKind::TrainStation
+            | BuildingKind::ExternalTrading => ActivityCurve::FLAT,
+        }
+    }
+}
+
+/// Trip rate multiplier for each hour of the day.
+/// A curve averages to 1 over the day, so it moves trips around without changing how many there are.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ActivityCurve([f32; HOURS_PER_DAY as usize]);
+
+impl ActivityCurve {
+    pub const FLAT: ActivityCurve = ActivityCurve([1.0; HOURS_PER_DAY as usize]);
+
+    /// Normalizes the hourly weights so that they average to 1
+    pub fn new(weights: [f32; HOURS_PER_DAY as usize]) -> Self {
+        let mean = weights.iter().sum::<f32>() / weights.len() as f32;
+        if mean <= 0.0 {
+            return Self::FLAT;
+        }
+        Self(weights.map(|w| w.max(0.0) / mean))
+    }
+
+    /// Busiest around lunch time
+    #[rustfmt::skip]
+    pub fn shop() -> Self {
+        Self::new([
+            0.1, 0.1, 0.1, 0.1, 0.1, 0.1, 0.2, 0.4, 0.8, 1.2, 1.6, 2.2,
+            2.6, 2.4, 1.8, 1.5, 1.4, 1.4, 1.2, 0.8, 0.5, 0.3, 0.2, 0.1,
+        ])
+    }
+
+    /// Busiest at the morning and evening rush hours
+    #[rustfmt::skip]
+    pub fn office() -> Self {
+        Self::new([
+            0.1, 0.1, 0.1, 0.1, 0.1, 0.1, 0.6, 2.5, 3.0, 1.5, 0.8, 0.8,
+            1.2, 0.8, 0.8, 1.2, 2.2, 2.8, 1.6, 0.6, 0.2, 0.1, 0.1, 0.1,
+        ])
+    }
+
+    pub fn company(kind: CompanyKind) -> Self {
+        match kind {
+            CompanyKind::Store => Self::shop(),
+            CompanyKind::Factory => Self::office(),
+        }
+    }
+
+    /// Multiplier during the hour of the day `t` falls in
+    pub fn at(&self, t: &DayTime) -> f32 {
+        self.0[t.hour.rem_euclid(HOURS_PER_DAY) as usize]
+    }
+
+    /// Picks a time of the day, in seconds since midnight, following the curve.
+    /// `u` in [0; 1) is spread so that busier hours get proportionally more of it.
+    pub fn sample(&self, u: f32) -> i32 {
+        self.sample_within(u, 0..HOURS_PER_DAY)
+    }
+
+    /// Same as [`Self::sample`] but only picks a time between these hours of the day
+    pub fn sample_within(&self, u: f32, hours: Range<i32>) -> i32 {
+        let hours = hours.start.clamp(0, HOURS_PER_DAY)..hours.end.clamp(0, HOURS_PER_DAY);
+        let weights = &self.0[hours.start as usize..hours.end as usize];
+        let mut left = u.clamp(0.0, 1.0) * weights.iter().sum::<f32>();
+        for (hour, &w) in hours.clone().zip(weights) {
+            if left < w {
+                let into_hour = (left / w * SECONDS_PER_HOUR as f32) as i32;
+                return hour * SECONDS_PER_HOUR + into_hour.min(SECONDS_PER_HOUR - 1);
+            }
+            left -= w;
+        }
+        (hours.end * SECONDS_PER_HOUR - 1).max(0)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -77,9 +164,40 @@ pub struct Building {
     pub height: f32,
     pub zone: Option<Zone>,
     pub connected_road: Option<RoadID>,
+    /// Edge of the building the entrance is on, counted from the default one in the order of the corners
+    pub door_offset: u8,
+}
+
+/// Normal of an edge of the obb pointing outside
+fn edge_outward(obb: &OBB, edge: usize) -> Vec2 {
+    let seg = obb.segments()[edge % 4];
+    let n = seg.vec().perpendicular().try_normalize().unwrap_or(Vec2::X);
+    if n.dot(seg.middle() - obb.center()) < 0.0 {
+        return -n;
+    }
+    n
+}
+
+/// Moves a door placed on the first edge of the building to the same place on another edge
+fn door_on_edge(obb: &OBB, door: Vec3, door_offset: u8) -> Vec3 {
+    let edge = door_offset as usize % 4;
+    if edge == 0 {
+        return door;
+    }
+    let segs = obb.segments();
+    let t = segs[0].project_t(door.xy());
+    let inset = segs[0].project(door.xy()).distance(door.xy());
+
+    let seg = segs[edge];
+    (seg.src + seg.vec() * t - edge_outward(obb, edge) * inset).z(door.z)
 }
 
 impl Building {
+    /// Direction the entrance faces, towards the outside of the building
+    pub fn door_dir(&self) -> Vec2 {
+        edge_outward(&self.obb, self.door_offset as usize)
+    }
+
     pub fn make(
         buildings: &mut Buildings,
         spatial_map: &mut SpatialMap,
@@ -91,6 +209,7 @@ impl Building {
         gen: BuildingGen,
         zone: Option<Zone>,
         mut connected_road: Option<RoadID>,
+        door_offset: u8,
     ) -> Option<BuildingID> {
         let at = obb.center().z(env.height(obb.center()).unwrap_or(0.0));
         let axis = (obb.corners[1] - obb.corners[0]).normalize();
@@ -113,9 +232,10 @@ impl Building {
             }
         }
         let door_pos = door_pos.rotated_by(axis).z0() + at + Vec3::z(0.1);
+        let door_pos = door_on_edge(&obb, door_pos, door_offset);
 
         if let BuildingGen::House | BuildingGen::Farm | BuildingGen::CenteredDoor { .. } = gen {
-            let bot = obb.segments()[0];
+            let bot = obb.segments()[door_offset as usize % 4];
             let rpos = bot.project(door_pos.xy()).z(door_pos.z);
             let dir = bot.vec().normalize().z(0.0);
 
@@ -150,6 +270,7 @@ impl Building {
                 height: at.z,
                 zone,
                 connected_road,
+                door_offset,
             }
         });
 
@@ -158,3 +279,60 @@ impl Building {
         Some(b)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::map::{BuildingKind, LanePatternBuilder, Map, MapProject};
+    use geom::{vec2, vec3, OBB};
+    use prototypes::BuildingGen;
+
+    #[test]
+    fn door_offset_chooses_access_side() {
+        let mut map = Map::empty();
+        let pat = LanePatternBuilder::new().build();
+        for x in [0.0, 60.0] {
+            map.make_connection(
+                MapProject::ground(vec3(x, 0.0, 0.0)),
+                MapProject::ground(vec3(x, 200.0, 0.0)),
+                None,
+                &pat,
+            )
+            .unwrap();
+        }
+
+        // the default entrance is on the west side, facing the road at x = 0
+        let obb = OBB::new(vec2(30.0, 100.0), vec2(1.0, 0.0), 20.0, 20.0);
+        let build = |map: &mut Map, door_offset| {
+            let id = map
+                .build_special_building(
+                    &obb,
+                    BuildingKind::TrainStation,
+                    BuildingGen::CenteredDoor {
+                        vertical_factor: 1.0,
+                    },
+                    None,
+                    None,
+                    door_offset,
+                )
+                .unwrap();
+            let door = map.buildings()[id].door_pos;
+            let access = map.building_access(id).unwrap();
+            let access_x = map.lanes()[access].points.project(door).x;
+            map.remove_building(id);
+            (door, access_x)
+        };
+
+        let (door, access_x) = build(&mut map, 0);
+        assert!((door.x - 20.0).abs() < 0.5, "{:?}", door);
+        assert!(access_x < 20.0);
+
+        let (door, access_x) = build(&mut map, 2);
+        assert!((door.x - 40.0).abs() < 0.5, "{:?}", door);
+        assert!(access_x > 40.0);
+
+        // facing south where there is no road: falls back to the nearest sidewalk
+        let (door, access_x) = build(&mut map, 1);
+        assert!((door.y - 90.0).abs() < 0.5, "{:?}", door);
+        assert!(access_x < 20.0 || access_x > 40.0);
+    }
+}
@@ -1,14 +1,21 @@
+use crate::map::serializing::v0;
 use crate::map::{
-    Intersections, LaneID, LaneKind, Lanes, LightPolicy, Road, RoadID, Roads, SpatialMap,
-    TraverseDirection, Turn, TurnID, TurnPolicy,
+    Intersections, LaneID, LaneKind, Lanes, LightPolicy, LightTiming, Road, RoadID, Roads,
+    SpatialMap, TraverseDirection, Turn, TurnID, TurnPolicy, TurnRestriction,
 };
 use geom::{pseudo_angle, Circle, Ray};
 use geom::{Vec2, Vec3};
 use ordered_float::OrderedFloat;
+use prototypes::SECONDS_PER_REALTIME_SECOND;
 use serde::{Deserialize, Serialize};
 use slotmapd::new_key_type;
 use std::collections::BTreeSet;
 
+/// Length of the vehicle that must get out of the intersection during the all red, in meters
+const CLEARANCE_VEHICLE_LENGTH: f32 = 5.0;
+/// Even the tiniest intersection gets this much all red, in game seconds
+pub const MIN_CLEARANCE_TIME: f32 = SECONDS_PER_REALTIME_SECOND as f32;
+
 new_key_type! {
     pub struct IntersectionID;
 }
@@ -19,6 +26,19 @@ impl IntersectionID {
     }
 }
 
+/// Smallest radius of a cul-de-sac, so that every vehicle can turn around in it
+pub const MIN_TURNING_RADIUS: f32 = 8.0;
+
+/// What a dead end (intersection with a single road) looks like
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DeadEndStyle {
+    /// The road just stops, vehicles U-turn in place
+    #[default]
+    Cut,
+    /// A turnaround circle of the given radius
+    CulDeSac(f32),
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Intersection {
     pub id: IntersectionID,
@@ -32,6 +52,19 @@ pub struct Intersection {
 
     pub turn_policy: TurnPolicy,
     pub light_policy: LightPolicy,
+    /// Durations of the light phases set by the player, None uses [`Intersection::effective_light_timing`]
+    #[serde(default)]
+    pub light_timing: Option<LightTiming>,
+
+    /// Forbidden road to road movements, applied on top of the turn policy
+    #[serde(default)]
+    pub turn_restrictions: Vec<TurnRestriction>,
+
+    #[serde(default)]
+    pub dead_end: DeadEndStyle,
+    /// Radius of the cul-de-sac that was actually built, None if there is none or no room for it
+    #[serde(default)]
+    turnaround: Option<f32>,
 }
 
 impl Intersection {
@@ -44,6 +77,10 @@ impl Intersection {
             roads: Default::default(),
             turn_policy: Default::default(),
             light_policy: Default::default(),
+            light_timing: Default::default(),
+            turn_restrictions: Default::default(),
+            dead_end: Default::default(),
+            turnaround: None,
         });
         spatial.insert(&store[id]);
         id
@@ -60,6 +97,50 @@ impl Intersection {
         });
     }
 
+    /// All red duration in game seconds for a vehicle entering on the last moment of orange to cross
+    /// the intersection before the next phase starts. Bigger and slower intersections need longer.
+    pub fn min_clearance_time(&self, lanes: &Lanes, roads: &Roads) -> f32 {
+        let mut interfaces: Vec<f32> = self
+            .roads
+            .iter()
+            .filter_map(|&r| roads.get(r))
+            .map(|r| r.interface_from(self.id))
+            .collect();
+        interfaces.sort_by(|a, b| b.total_cmp(a));
+        let crossing = interfaces.iter().take(2).sum::<f32>() + CLEARANCE_VEHICLE_LENGTH;
+
+        let slowest_approach = self
+            .roads
+            .iter()
+            .filter_map(|&r| roads.get(r))
+            .filter_map(|r| {
+                let limit = r
+                    .incoming_lanes_to(self.id)
+                    .iter()
+                    .filter(|(_, kind)| kind.needs_light())
+                    .filter_map(|&(id, _)| lanes.get(id))
+                    .map(|l| l.speed_limit)
+                    .reduce(f32::max)?;
+                Some(limit * r.material.speed_factor())
+            })
+            .reduce(f32::min)
+            .unwrap_or(LaneKind::Driving.default_speed_limit());
+
+        let real_seconds = crossing / slowest_approach.max(1.0);
+        (real_seconds * SECONDS_PER_REALTIME_SECOND as f32).max(MIN_CLEARANCE_TIME)
+    }
+
+    /// Timing of the lights: the one set by the player, or the default one
+    /// with an all red long enough to clear the intersection
+    pub fn effective_light_timing(&self, lanes: &Lanes, roads: &Roads) -> LightTiming {
+        self.light_timing
+            .unwrap_or_else(|| LightTiming {
+                all_red: self.min_clearance_time(lanes, roads).ceil() as u16,
+                ..Default::default()
+            })
+            .clamped()
+    }
+
     pub fn bcircle(&self) -> Circle {
         Circle {
             center: self.pos.xy(),
@@ -67,7 +148,21 @@ impl Intersection {
         }
     }
 
+    /// Radius of the cul-de-sac turnaround circle, if this is a dead end with one
+    pub fn turnaround(&self) -> Option<f32> {
+        self.turnaround
+    }
+
+    /// Computed by the map since it depends on the roads around
+    pub(crate) fn set_turnaround(&mut self, turnaround: Option<f32>) {
+        self.turnaround = turnaround;
+    }
+
     fn update_radius(&mut self, roads: &Roads) {
+        if let Some(turnaround) = self.turnaround {
+            self.radius = turnaround;
+            return;
+        }
         self.radius = self
             .roads
             .iter()
@@ -86,30 +181,57 @@ impl Intersection {
 
     pub fn remove_road(&mut self, road_id: RoadID) {
         self.roads.retain(|x| *x != road_id);
+        self.turn_restrictions
+            .retain(|r| r.from != road_id && r.to != road_id);
     }
 
+    /// Regenerates the turns, reusing the points of the turns that were already there
+    /// and whose lanes didn't move
     pub fn update_turns(&mut self, lanes: &Lanes, roads: &Roads) {
-        self.turns = self
+        let mut old = std::mem::take(&mut self.turns);
+
+        let turns: Vec<Turn> = self
             .turn_policy
             .generate_turns(self, lanes, roads)
             .into_iter()
-            .map(|(id, kind)| Turn::new(id, kind))
+            .map(|(id, kind)| match old.take(&id) {
+                Some(t) if t.kind == kind => t,
+                _ => Turn::new(id, kind),
+            })
             .collect();
 
-        self.turns = std::mem::take(&mut self.turns)
+        self.turns = turns
             .into_iter()
             .map(|mut x| {
-                x.make_points(lanes, self);
+                x.update_points(lanes, self);
                 x
             })
             .collect();
     }
 
+    /// Keeps only the turns matching the predicate, returns the ids of the removed ones
+    pub(crate) fn retain_turns(&mut self, mut f: impl FnMut(&Turn) -> bool) -> Vec<TurnID> {
+        let mut removed = vec![];
+        self.turns.retain(|t| {
+            let keep = f(t);
+            if !keep {
+                removed.push(t.id);
+            }
+            keep
+        });
+        removed
+    }
+
     pub fn update_traffic_control(&self, lanes: &mut Lanes, roads: &Roads) {
         self.light_policy.apply(self, lanes, roads);
     }
 
     const MIN_INTERFACE: f32 = 9.0;
+    /// Under this angle two roads are almost parallel and are handled like a merge
+    const MERGE_ANGLE: f32 = 0.17453292; // 10°
+    /// Under this angle the interface from the sine formula grows too fast, the roads are staggered instead
+    const ACUTE_ANGLE: f32 = 0.34906584; // 20°
+
     pub fn update_interface_radius(&mut self, roads: &mut Roads) {
         let id = self.id;
 
@@ -117,7 +239,13 @@ impl Intersection {
             [] => return,
             [r1_id] => {
                 let r = &mut roads[r1_id];
-                r.set_interface(id, Self::empty_interface(r.width));
+                match self.turnaround {
+                    Some(turnaround) => {
+                        r.set_interface(id, turnaround);
+                        self.radius = turnaround;
+                    }
+                    None => r.set_interface(id, Self::empty_interface(r.width)),
+                }
                 return;
             }
             [r1_id, r2_id] => {
@@ -183,14 +311,19 @@ impl Intersection {
             let (r1, r2) = (&roads[r1_id], &roads[r2_id]);
             let (dir1, dir2) = (r1.dir_from(id), r2.dir_from(id));
 
-            let min_dist = if dir1.angle(dir2).abs() < 0.17453292 {
-                self.interface_calc_numerically(r1.width, r2.width, r1, r2)
+            let angle = dir1.angle(dir2).abs();
+            let (d1, d2) = if angle < Self::MERGE_ANGLE {
+                let d = self.interface_calc_numerically(r1.width, r2.width, r1, r2);
+                (d, d)
+            } else if angle < Self::ACUTE_ANGLE {
+                Self::interface_calc_staggered(r1.width, r2.width)
             } else {
-                Self::interface_calc_formula(r1.width, r2.width, dir1, dir2)
+                let d = Self::interface_calc_formula(r1.width, r2.width, dir1, dir2);
+                (d, d)
             };
 
-            roads[r1_id].max_interface(id, min_dist);
-            roads[r2_id].max_interface(id, min_dist);
+            roads[r1_id].max_interface(id, d1);
+            roads[r2_id].max_interface(id, d2);
         }
 
         self.update_radius(roads);
@@ -208,6 +341,19 @@ impl Intersection {
         (w * 1.1 / sin).min(50.0)
     }
 
+    /// Interfaces of two roads crossing at an acute angle (between MERGE_ANGLE and ACUTE_ANGLE).
+    /// Pushing both roads back until they stop overlapping makes a huge junction, so the ends
+    /// are staggered instead: the narrower road stops half its width further than the other one
+    /// and the intersection polygon covers the remaining overlap.
+    fn interface_calc_staggered(w1: f32, w2: f32) -> (f32, f32) {
+        let base = (w1 + w2) * 0.75;
+        if w1 < w2 {
+            (base + w1 * 0.5, base)
+        } else {
+            (base, base + w2 * 0.5)
+        }
+    }
+
     fn interface_calc_numerically(&self, w1: f32, w2: f32, r1: &Road, r2: &Road) -> f32 {
         let w: f32 = (w1 + w2) * 0.80;
 
@@ -276,7 +422,10 @@ impl Intersection {
         self.roads.iter().flat_map(move |&x| {
             let r = roads.get(x)?;
             r.outgoing_lanes_from(id).iter().find(|(_, kind)| {
-                matches!(kind, LaneKind::Driving | LaneKind::Rail | LaneKind::Bus)
+                matches!(
+                    kind,
+                    LaneKind::Driving | LaneKind::Rail | LaneKind::Bus | LaneKind::Tram
+                )
             })?;
             r.other_end(id)
         })
@@ -319,3 +468,236 @@ impl Intersection {
 }
 
 debug_inspect_impl!(IntersectionID);
+
+impl From<v0::Intersection> for Intersection {
+    fn from(i: v0::Intersection) -> Self {
+        Self {
+            id: i.id,
+            pos: i.pos,
+            radius: i.radius,
+            turns: i.turns,
+            roads: i.roads,
+            turn_policy: i.turn_policy.into(),
+            light_policy: i.light_policy,
+            light_timing: None,
+            turn_restrictions: Vec::new(),
+            dead_end: DeadEndStyle::default(),
+            turnaround: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DeadEndStyle, Intersection, MIN_TURNING_RADIUS};
+    use crate::map::objects::turn::MAKE_POINTS_COUNT;
+    use crate::map::{
+        LaneID, LaneKind, LanePatternBuilder, Map, PathKind, ProjectFilter, TurnID, TurnKind,
+    };
+    use crate::map_dynamic::Itinerary;
+    use common::FastMap;
+    use geom::{Vec2, Vec3};
+    use prototypes::Tick;
+
+    #[test]
+    fn acute_crossing_stays_compact() {
+        let mut map = Map::empty();
+        let pat = LanePatternBuilder::new().build();
+        let center = Vec3::new(500.0, 500.0, 0.0);
+
+        let angle = 15.0f32.to_radians();
+        let ends = [
+            Vec2::X,
+            Vec2::new(angle.cos(), angle.sin()),
+            Vec2::new(-1.0, 0.0),
+        ];
+        for dir in ends {
+            let a = map.project(center, 0.0, ProjectFilter::ALL);
+            let b = map.project(center + (dir * 200.0).z0(), 0.0, ProjectFilter::ALL);
+            map.make_connection(a, b, None, &pat).unwrap();
+        }
+
+        let inter = map
+            .intersections()
+            .values()
+            .find(|i| i.pos.xy().distance(center.xy()) < 1.0)
+            .unwrap();
+        assert_eq!(inter.roads.len(), 3);
+
+        let width = pat.width();
+        let formula_at_15 = Intersection::interface_calc_formula(
+            width,
+            width,
+            Vec2::X,
+            Vec2::new(angle.cos(), angle.sin()),
+        );
+        let bound = Intersection::interface_calc_staggered(width, width).1;
+
+        let interfaces = inter
+            .roads
+            .iter()
+            .map(|&r| map.roads()[r].interface_from(inter.id))
+            .collect::<Vec<_>>();
+        for &i in &interfaces {
+            assert!(i <= bound + 0.01, "{} > {}", i, bound);
+        }
+        let max = interfaces.iter().copied().fold(0.0, f32::max);
+        assert!(max < formula_at_15, "{} >= {}", max, formula_at_15);
+        assert!(max < width * 2.5, "{} for a road of width {}", max, width);
+        // the ends are staggered
+        assert!(interfaces.iter().any(|&i| (i - max).abs() > 1.0));
+    }
+
+    #[test]
+    fn only_new_turns_are_recomputed() {
+        let mut map = Map::empty();
+        let pat = LanePatternBuilder::new().build();
+        let center = Vec3::new(500.0, 500.0, 0.0);
+
+        let mut connect = |map: &mut Map, dir: Vec2| {
+            let a = map.project(center, 0.0, ProjectFilter::ALL);
+            let b = map.project(center + (dir * 200.0).z0(), 0.0, ProjectFilter::ALL);
+            map.make_connection(a, b, None, &pat).unwrap().1
+        };
+        for dir in [Vec2::X, Vec2::Y, -Vec2::X] {
+            connect(&mut map, dir);
+        }
+
+        let inter_id = map
+            .intersections()
+            .values()
+            .find(|i| i.pos.xy().distance(center.xy()) < 1.0)
+            .unwrap()
+            .id;
+        let before: FastMap<TurnID, (Vec3, Vec3)> = map.intersections()[inter_id]
+            .turns()
+            .map(|t| (t.id, (t.points.first(), t.points.last())))
+            .collect();
+
+        MAKE_POINTS_COUNT.with(|c| c.set(0));
+        let new_road = connect(&mut map, -Vec2::Y);
+        let recomputed = MAKE_POINTS_COUNT.with(|c| c.get());
+
+        let inter = &map.intersections()[inter_id];
+        assert_eq!(inter.roads.len(), 4);
+
+        // the turns of the other intersection of the new road are recomputed too
+        let other_end = map.roads()[new_road].other_end(inter_id).unwrap();
+        let n_other = map.intersections()[other_end].turns().count();
+
+        let changed = inter
+            .turns()
+            .filter(|t| before.get(&t.id) != Some(&(t.points.first(), t.points.last())))
+            .count();
+
+        assert!(changed > 0);
+        assert!(changed < inter.turns().count());
+        assert_eq!(recomputed, changed + n_other);
+    }
+
+    #[test]
+    fn cul_de_sac_turnaround() {
+        let mut map = Map::empty();
+        let pat = LanePatternBuilder::new().parking(false).build();
+        let a = map.project(Vec3::new(500.0, 500.0, 0.0), 0.0, ProjectFilter::ALL);
+        let b = map.project(Vec3::new(700.0, 500.0, 0.0), 0.0, ProjectFilter::ALL);
+        let (dead_end, road) = map.make_connection(a, b, None, &pat).unwrap();
+
+        let uturn_extent = |map: &Map| {
+            let inter = &map.intersections()[dead_end];
+            let turn = inter.turns().find(|t| t.kind == TurnKind::Driving).unwrap();
+            turn.points
+                .iter()
+                .map(|p| p.xy().distance(inter.pos.xy()))
+                .fold(0.0, f32::max)
+        };
+        let cut_extent = uturn_extent(&map);
+        assert!(map.intersections()[dead_end].turnaround().is_none());
+
+        // too small radii are enlarged
+        map.update_intersection(dead_end, |i| i.dead_end = DeadEndStyle::CulDeSac(2.0));
+        let inter = &map.intersections()[dead_end];
+        assert_eq!(inter.turnaround(), Some(MIN_TURNING_RADIUS));
+
+        map.update_intersection(dead_end, |i| i.dead_end = DeadEndStyle::CulDeSac(15.0));
+        let inter = &map.intersections()[dead_end];
+        assert_eq!(inter.turnaround(), Some(15.0));
+        assert_eq!(map.roads()[road].interface_from(dead_end), 15.0);
+        // the vehicles go around the circle
+        let extent = uturn_extent(&map);
+        assert!(extent > cut_extent);
+        assert!(extent <= 15.0 + 0.1, "{}", extent);
+
+        // the U-turn is routable
+        let lanes = &map.roads()[road];
+        let incoming = filter_driving(lanes.incoming_lanes_to(dead_end));
+        let outgoing = filter_driving(lanes.outgoing_lanes_from(dead_end));
+        let start = map.lanes()[incoming].points.point_along(20.0);
+        let end = map.lanes()[outgoing].points.point_along(100.0);
+        let it = Itinerary::route(Tick(0), start, end, &map, PathKind::Vehicle).unwrap();
+        assert!(!it.is_partial());
+
+        // a road passing right next to the dead end leaves no room
+        let c = map.project(Vec3::new(710.0, 400.0, 0.0), 0.0, ProjectFilter::ALL);
+        let d = map.project(Vec3::new(710.0, 600.0, 0.0), 0.0, ProjectFilter::ALL);
+        map.make_connection(c, d, None, &pat).unwrap();
+        map.update_intersection(dead_end, |_| {});
+        assert!(map.intersections()[dead_end].turnaround().is_none());
+    }
+
+    fn filter_driving(lanes: &[(LaneID, LaneKind)]) -> LaneID {
+        lanes
+            .iter()
+            .find(|(_, kind)| *kind == LaneKind::Driving)
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn bigger_intersections_need_longer_clearance() {
+        use super::MIN_CLEARANCE_TIME;
+
+        let clearance = |pat: &crate::map::LanePattern| {
+            let mut map = Map::empty();
+            let center = Vec3::new(500.0, 500.0, 0.0);
+            for dir in [Vec2::X, Vec2::Y, Vec2::new(-1.0, 0.0), Vec2::new(0.0, -1.0)] {
+                let a = map.project(center, 0.0, ProjectFilter::ALL);
+                let b = map.project(center + (dir * 200.0).z0(), 0.0, ProjectFilter::ALL);
+                map.make_connection(a, b, None, pat).unwrap();
+            }
+            let inter = map
+                .intersections()
+                .values()
+                .find(|i| i.pos.xy().distance(center.xy()) < 1.0)
+                .unwrap();
+            assert_eq!(inter.roads.len(), 4);
+            (
+                inter.min_clearance_time(map.lanes(), map.roads()),
+                inter
+                    .effective_light_timing(map.lanes(), map.roads())
+                    .all_red,
+            )
+        };
+
+        let (small, small_all_red) = clearance(
+            &LanePatternBuilder::new()
+                .parking(false)
+                .sidewalks(false)
+                .build(),
+        );
+        let (large, _) = clearance(&LanePatternBuilder::new().n_lanes(3).build());
+
+        assert!(large > small, "{} <= {}", large, small);
+
+        // the same intersection with slower approaches takes longer to clear
+        let (slow, _) = clearance(
+            &LanePatternBuilder::new()
+                .n_lanes(3)
+                .speed_limit(5.0)
+                .build(),
+        );
+        assert!(slow > large, "{} <= {}", slow, large);
+        assert!(small >= MIN_CLEARANCE_TIME);
+        assert_eq!(small_all_red, small.ceil() as u16);
+    }
+}
@@ -1,4 +1,6 @@
-use crate::map::{IntersectionID, Lanes, Road, RoadID, TrafficControl, TraverseDirection};
+use crate::map::{
+    IntersectionID, Lanes, ParkingStyle, Road, RoadID, TrafficControl, TraverseDirection,
+};
 use egui_inspect::Inspect;
 use geom::{PolyLine3, Vec2, Vec3};
 use serde::{Deserialize, Serialize};
@@ -17,24 +19,32 @@ pub enum LaneKind {
     Parking,
     Walking,
     Rail,
+    /// Tracks laid on the street, only trams drive on them
+    Tram,
 }
 
 impl LaneKind {
     #[inline]
     pub fn vehicles(self) -> bool {
-        matches!(self, LaneKind::Driving | LaneKind::Biking | LaneKind::Bus)
+        matches!(
+            self,
+            LaneKind::Driving | LaneKind::Biking | LaneKind::Bus | LaneKind::Tram
+        )
     }
 
     #[inline]
     pub fn needs_light(self) -> bool {
-        matches!(self, LaneKind::Driving | LaneKind::Biking | LaneKind::Bus)
+        matches!(
+            self,
+            LaneKind::Driving | LaneKind::Biking | LaneKind::Bus | LaneKind::Tram
+        )
     }
 
     #[inline]
     pub fn needs_arrows(self) -> bool {
         matches!(
             self,
-            LaneKind::Driving | LaneKind::Biking | LaneKind::Bus | LaneKind::Rail
+            LaneKind::Driving | LaneKind::Biking | LaneKind::Bus | LaneKind::Rail | LaneKind::Tram
         )
     }
 
@@ -43,6 +53,11 @@ impl LaneKind {
         matches!(self, LaneKind::Rail)
     }
 
+    #[inline]
+    pub fn is_tram(self) -> bool {
+        matches!(self, LaneKind::Tram)
+    }
+
     #[inline]
     pub const fn width(self) -> f32 {
         match self {
@@ -50,6 +65,21 @@ impl LaneKind {
             LaneKind::Parking => 2.5,
             LaneKind::Walking => 3.0,
             LaneKind::Rail => 5.3,
+            LaneKind::Tram => 8.0,
+        }
+    }
+
+    /// Speed limit in m/s of the lanes of this kind, driving lanes get the one of their road instead
+    #[inline]
+    pub const fn default_speed_limit(self) -> f32 {
+        match self {
+            LaneKind::Driving => 13.9,
+            LaneKind::Biking => 5.5,
+            LaneKind::Bus => 8.3,
+            LaneKind::Parking => 2.0,
+            LaneKind::Walking => 1.5,
+            LaneKind::Rail => 25.0,
+            LaneKind::Tram => 11.0,
         }
     }
 }
@@ -60,6 +90,15 @@ pub enum LaneDirection {
     Backward,
 }
 
+impl LaneDirection {
+    pub fn opposite(self) -> Self {
+        match self {
+            LaneDirection::Forward => LaneDirection::Backward,
+            LaneDirection::Backward => LaneDirection::Forward,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Lane {
     pub id: LaneID,
@@ -72,17 +111,60 @@ pub struct Lane {
     pub kind: LaneKind,
 
     pub control: TrafficControl,
+    /// In m/s
     pub speed_limit: f32,
+    /// Lanes of unversioned saves get the width of their kind, see [`crate::map::serializing::v0`]
+    pub width: f32,
+    /// Flows against the direction of the side of the road it was built on
+    pub reversed: bool,
+    pub traffic: LaneTraffic,
+    /// Only used by parking lanes
+    pub parking_style: ParkingStyle,
 
     /// Always from src to dst
     pub points: PolyLine3,
     pub dist_from_bottom: f32,
 }
 
+/// Vehicles on a lane averaged over the last second or so
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct LaneTraffic {
+    /// Vehicles per 100m
+    pub occupancy: f32,
+    /// Speed of the vehicles in m/s, the speed limit while the lane is empty
+    pub avg_speed: f32,
+}
+
+impl LaneTraffic {
+    /// Above this occupancy routes go around the lane if it is faster
+    pub const CONGESTION_OCCUPANCY: f32 = 5.0;
+    /// Jammed lanes are considered this fast so that their travel time stays finite, in m/s
+    pub const MIN_SPEED: f32 = 0.5;
+    /// Weight of the current tick in the averages, about a second at 50 ticks per second
+    const SMOOTHING: f32 = 0.02;
+
+    /// Adds a tick of traffic to the averages
+    pub fn update(&mut self, vehicles: u32, speed_sum: f32, length: f32, speed_limit: f32) {
+        let occupancy = vehicles as f32 * 100.0 / length.max(1.0);
+        let speed = if vehicles > 0 {
+            speed_sum / vehicles as f32
+        } else {
+            speed_limit
+        };
+        self.occupancy += (occupancy - self.occupancy) * Self::SMOOTHING;
+        self.avg_speed += (speed - self.avg_speed) * Self::SMOOTHING;
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct LanePattern {
     pub lanes_forward: Vec<(LaneKind, f32)>,
     pub lanes_backward: Vec<(LaneKind, f32)>,
+    /// Width of the driving lanes, None uses the width of the kind
+    #[serde(default)]
+    pub lane_width: Option<f32>,
+    #[serde(default)]
+    pub parking_style: ParkingStyle,
 }
 
 impl LanePattern {
@@ -98,12 +180,21 @@ impl LanePattern {
             )
     }
 
+    pub fn lane_width(&self, kind: LaneKind) -> f32 {
+        match (kind, self.lane_width) {
+            (LaneKind::Driving, Some(w)) => w,
+            (LaneKind::Parking, _) => self.parking_style.width(),
+            _ => kind.width(),
+        }
+    }
+
     pub fn width(&self) -> f32 {
-        self.lanes().map(|(kind, _, _)| kind.width()).sum()
+        self.lanes().map(|(kind, _, _)| self.lane_width(kind)).sum()
     }
 }
 
-#[derive(PartialEq, Copy, Clone, Inspect)]
+#[derive(PartialEq, Copy, Clone, Serialize, Deserialize, Inspect)]
+#[serde(default)]
 pub struct LanePatternBuilder {
     pub n_lanes: u32,
     #[inspect(name = "speed", step = 1.0, min_value = 4.0, max_value = 40.0)]
@@ -112,6 +203,13 @@ pub struct LanePatternBuilder {
     pub parking: bool,
     pub one_way: bool,
     pub rail: bool,
+    /// Overrides the width of the driving lanes, for narrow alleys or wide boulevards
+    pub lane_width: Option<f32>,
+    /// Adds a tram lane in each direction in the middle of the road
+    pub tram: bool,
+    /// Angled parking lanes are widened to fit the slots
+    #[inspect(skip)]
+    pub parking_style: ParkingStyle,
 }
 impl Eq for LanePatternBuilder {}
 
@@ -130,9 +228,86 @@ impl LanePatternBuilder {
             parking: true,
             one_way: false,
             rail: false,
+            lane_width: None,
+            tram: false,
+            parking_style: ParkingStyle::Parallel,
         }
     }
 
+    /// Built-in road types as (name, label, builder)
+    pub const PRESETS: &'static [(&'static str, &'static str, LanePatternBuilder)] = &[
+        ("street", "Street", LanePatternBuilder::new()),
+        (
+            "street_1way",
+            "Street one-way",
+            LanePatternBuilder::new().one_way(true),
+        ),
+        (
+            "avenue",
+            "Avenue",
+            LanePatternBuilder::new().n_lanes(2).speed_limit(13.0),
+        ),
+        (
+            "avenue_1way",
+            "Avenue one-way",
+            LanePatternBuilder::new()
+                .n_lanes(2)
+                .one_way(true)
+                .speed_limit(13.0),
+        ),
+        (
+            "drive",
+            "Drive",
+            LanePatternBuilder::new()
+                .parking(false)
+                .sidewalks(false)
+                .speed_limit(13.0),
+        ),
+        (
+            "drive_1way",
+            "Drive one-way",
+            LanePatternBuilder::new()
+                .parking(false)
+                .sidewalks(false)
+                .one_way(true)
+                .speed_limit(13.0),
+        ),
+        (
+            "highway",
+            "Highway",
+            LanePatternBuilder::new()
+                .n_lanes(3)
+                .speed_limit(25.0)
+                .parking(false)
+                .sidewalks(false),
+        ),
+        (
+            "highway_1way",
+            "Highway one-way",
+            LanePatternBuilder::new()
+                .n_lanes(3)
+                .speed_limit(25.0)
+                .parking(false)
+                .sidewalks(false)
+                .one_way(true),
+        ),
+        ("tram", "Tram street", LanePatternBuilder::new().tram(true)),
+        ("rail", "Rail", LanePatternBuilder::new().rail(true)),
+        (
+            "rail_1way",
+            "Rail one-way",
+            LanePatternBuilder::new().rail(true).one_way(true),
+        ),
+    ];
+
+    /// Returns the built-in preset with this name, None if there is none
+    pub fn preset(name: &str) -> Option<Self> {
+        Self::PRESETS
+            .iter()
+            .find(|(n, _, _)| *n == name)
+            .map(|&(_, _, b)| b)
+    }
+
     pub const fn n_lanes(mut self, n_lanes: u32) -> Self {
         self.n_lanes = if n_lanes > 10 { 10 } else { n_lanes };
         self
@@ -163,6 +338,21 @@ impl LanePatternBuilder {
         self
     }
 
+    pub const fn lane_width(mut self, lane_width: Option<f32>) -> Self {
+        self.lane_width = lane_width;
+        self
+    }
+
+    pub const fn tram(mut self, tram: bool) -> Self {
+        self.tram = tram;
+        self
+    }
+
+    pub const fn parking_style(mut self, parking_style: ParkingStyle) -> Self {
+        self.parking_style = parking_style;
+        self
+    }
+
     pub fn width(self) -> f32 {
         if self.rail {
             let wayf = if self.one_way { 1.0 } else { 2.0 };
@@ -175,9 +365,13 @@ impl LanePatternBuilder {
             w += LaneKind::Walking.width() * 2.0;
         }
         if self.parking {
-            w += LaneKind::Parking.width() * wayf;
+            w += self.parking_style.width() * wayf;
+        }
+        if self.tram {
+            w += LaneKind::Tram.width() * wayf;
         }
-        w += self.n_lanes as f32 * wayf * LaneKind::Driving.width();
+        let lane_width = self.lane_width.unwrap_or(LaneKind::Driving.width());
+        w += self.n_lanes as f32 * wayf * lane_width;
         w + 0.5
     }
 
@@ -195,6 +389,13 @@ impl LanePatternBuilder {
 
         let mut forward: Vec<_> = (0..self.n_lanes).map(|_| LaneKind::Driving).collect();
 
+        if self.tram {
+            if !self.one_way {
+                backward.insert(0, LaneKind::Tram);
+            }
+            forward.insert(0, LaneKind::Tram);
+        }
+
         if self.parking {
             if !self.one_way {
                 backward.push(LaneKind::Parking);
@@ -216,12 +417,16 @@ impl LanePatternBuilder {
             forward = (0..self.n_lanes).map(|_| LaneKind::Rail).collect();
         }
 
+        let with_limit = |kind: LaneKind| match kind {
+            LaneKind::Driving => (kind, self.speed_limit),
+            _ => (kind, kind.default_speed_limit()),
+        };
+
         LanePattern {
-            lanes_backward: backward
-                .into_iter()
-                .map(|x| (x, self.speed_limit))
-                .collect(),
-            lanes_forward: forward.into_iter().map(|x| (x, self.speed_limit)).collect(),
+            lanes_backward: backward.into_iter().map(with_limit).collect(),
+            lanes_forward: forward.into_iter().map(with_limit).collect(),
+            lane_width: self.lane_width.filter(|_| !self.rail),
+            parking_style: self.parking_style.validated(),
         }
     }
 }
@@ -232,6 +437,7 @@ impl Lane {
         store: &mut Lanes,
         kind: LaneKind,
         speed_limit: f32,
+        width: f32,
         direction: LaneDirection,
         dist_from_bottom: f32,
     ) -> LaneID {
@@ -250,9 +456,24 @@ impl Lane {
             dist_from_bottom,
             control: TrafficControl::Always,
             speed_limit,
+            width,
+            reversed: false,
+            traffic: LaneTraffic::default(),
+            parking_style: ParkingStyle::Parallel,
         })
     }
 
+    /// Time to drive along the lane in seconds, at the speed limit or at the speed of the traffic
+    /// once the lane is congested
+    pub fn travel_time(&self) -> f32 {
+        let speed = if self.traffic.occupancy < LaneTraffic::CONGESTION_OCCUPANCY {
+            self.speed_limit
+        } else {
+            self.traffic.avg_speed.min(self.speed_limit)
+        };
+        self.points.length() / speed.max(LaneTraffic::MIN_SPEED)
+    }
+
     pub fn get_inter_node_pos(&self, id: IntersectionID) -> Vec3 {
         match (id, self.points.as_slice()) {
             (x, [p, ..]) if x == self.src => *p,
@@ -263,7 +484,7 @@ impl Lane {
 
     pub fn gen_pos(&mut self, parent_road: &Road) {
         let dist_from_bottom = self.dist_from_bottom;
-        let lane_dist = self.kind.width() * 0.5 + dist_from_bottom - parent_road.width * 0.5;
+        let lane_dist = self.width * 0.5 + dist_from_bottom - parent_road.width * 0.5;
 
         let middle_points = parent_road.interfaced_points();
 
@@ -316,6 +537,15 @@ impl Lane {
         self.points.last()
     }
 
+    /// Direction of the lane relative to its road
+    pub fn direction(&self, parent_road: &Road) -> LaneDirection {
+        if self.src == parent_road.src {
+            LaneDirection::Forward
+        } else {
+            LaneDirection::Backward
+        }
+    }
+
     pub fn dir_from(&self, i: IntersectionID) -> TraverseDirection {
         if self.src == i {
             TraverseDirection::Forward
@@ -335,3 +565,100 @@ impl Lane {
 }
 
 debug_inspect_impl!(LaneID);
+
+#[cfg(test)]
+mod tests {
+    use super::{LaneKind, LanePatternBuilder};
+    use crate::map::MapBuilder;
+    use geom::vec2;
+
+    #[test]
+    fn lane_width_override() {
+        let builder = LanePatternBuilder::new()
+            .parking(false)
+            .lane_width(Some(3.0));
+        let pat = builder.build();
+        let expected = LaneKind::Walking.width() * 2.0 + 3.0 * 2.0;
+        assert!((pat.width() - expected).abs() < 0.01);
+        assert!((builder.width() - expected - 0.5).abs() < 0.01);
+
+        let mut b = MapBuilder::new();
+        let src = b.add_inter(vec2(0.0, 0.0));
+        let dst = b.add_inter(vec2(100.0, 0.0));
+        let road = b.connect(src, dst, &pat).unwrap();
+        let map = b.build();
+
+        let road = &map.roads()[road];
+        assert!((road.width - expected).abs() < 0.01);
+        assert_eq!(road.pattern(map.lanes()), pat);
+
+        let driving: Vec<_> = road
+            .lanes_iter()
+            .map(|(id, _)| &map.lanes()[id])
+            .filter(|l| l.kind == LaneKind::Driving)
+            .collect();
+        assert_eq!(driving.len(), 2);
+        assert!(driving.iter().all(|l| l.width == 3.0));
+        // the lanes are offset by their own width
+        let mid = vec2(50.0, 0.0).z0();
+        let gap = driving[0]
+            .points
+            .project(mid)
+            .distance(driving[1].points.project(mid));
+        assert!((gap - 3.0).abs() < 0.01, "{}", gap);
+    }
+
+    #[test]
+    fn presets_lanes() {
+        let count = |lanes: &[(LaneKind, f32)], kind: LaneKind| {
+            lanes.iter().filter(|(k, _)| *k == kind).count()
+        };
+
+        // name, driving lanes forward, driving lanes backward, parking, sidewalks
+        let expected = [
+            ("street", 1, 1, true, true),
+            ("street_1way", 1, 0, true, true),
+            ("avenue", 2, 2, true, true),
+            ("drive", 1, 1, false, false),
+            ("highway", 3, 3, false, false),
+            ("highway_1way", 3, 0, false, false),
+        ];
+        for (name, fwd, bwd, parking, sidewalks) in expected {
+            let builder = LanePatternBuilder::preset(name).unwrap();
+            let pat = builder.build();
+            assert_eq!(
+                count(&pat.lanes_forward, LaneKind::Driving),
+                fwd,
+                "{}",
+                name
+            );
+            assert_eq!(
+                count(&pat.lanes_backward, LaneKind::Driving),
+                bwd,
+                "{}",
+                name
+            );
+            assert_eq!(
+                count(&pat.lanes_forward, LaneKind::Parking) > 0,
+                parking,
+                "{}",
+                name
+            );
+            assert_eq!(
+                count(&pat.lanes_forward, LaneKind::Walking) > 0,
+                sidewalks,
+                "{}",
+                name
+            );
+
+            let width = pat.lanes().map(|(k, _, _)| k.width()).sum::<f32>() + 0.5;
+            assert!((builder.width() - width).abs() < 0.01, "{}", name);
+        }
+
+        let rail = LanePatternBuilder::preset("rail").unwrap().build();
+        assert_eq!(count(&rail.lanes_forward, LaneKind::Rail), 1);
+        assert_eq!(count(&rail.lanes_backward, LaneKind::Rail), 1);
+
+        assert!(LanePatternBuilder::preset("bike_boulevard").is_none());
+    }
+}
@@ -1,6 +1,6 @@
 use crate::map::{Lane, LaneID, LaneKind, CROSSWALK_WIDTH};
 use flat_spatial::Grid;
-use geom::{Transform, Vec2, Vec3};
+use geom::{Degrees, Spline3, Transform, Vec2, Vec3};
 use ordered_float::OrderedFloat;
 use serde::{Deserialize, Serialize};
 use slotmapd::{new_key_type, SecondaryMap, SlotMap};
@@ -10,6 +10,60 @@ new_key_type! {
 }
 
 pub const PARKING_SPOT_LENGTH: f32 = 6.0;
+/// Width of a slot for angled parking, measured perpendicular to the cars
+pub const PARKING_STALL_WIDTH: f32 = 2.5;
+/// Length of the cars an angled slot is drawn for
+const PARKED_CAR_LENGTH: f32 = 5.0;
+
+/// How the slots of a parking lane are laid out
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ParkingStyle {
+    /// Slots one after the other along the lane
+    #[default]
+    Parallel,
+    /// Slots at this angle in degrees from the lane, the cars go in forward towards the curb
+    Angled(f32),
+}
+
+debug_inspect_impl!(ParkingStyle);
+
+impl ParkingStyle {
+    /// Angles outside of 30..=90 degrees are clamped, an angle that isn't a number is parallel
+    pub fn validated(self) -> Self {
+        match self {
+            ParkingStyle::Angled(a) if a.is_finite() => ParkingStyle::Angled(a.clamp(30.0, 90.0)),
+            _ => ParkingStyle::Parallel,
+        }
+    }
+
+    /// Width the lane needs to fit the slots, angled slots are wider than parallel ones
+    pub fn width(self) -> f32 {
+        match self.validated() {
+            ParkingStyle::Parallel => LaneKind::Parking.width(),
+            ParkingStyle::Angled(a) => {
+                let (sin, cos) = Degrees(a).to_radians().0.sin_cos();
+                PARKED_CAR_LENGTH * sin + PARKING_STALL_WIDTH * cos
+            }
+        }
+    }
+
+    /// Length of lane taken by each slot
+    pub fn spacing(self) -> f32 {
+        match self.validated() {
+            ParkingStyle::Parallel => PARKING_SPOT_LENGTH,
+            ParkingStyle::Angled(a) => PARKING_STALL_WIDTH / Degrees(a).to_radians().0.sin(),
+        }
+    }
+
+    /// Orientation of a slot on a lane going towards `dir`.
+    /// The curb is on the right of the lane, so angled slots are turned clockwise.
+    pub fn slot_dir(self, dir: Vec3) -> Vec3 {
+        match self.validated() {
+            ParkingStyle::Parallel => dir,
+            ParkingStyle::Angled(a) => dir.xy().rotated_by_angle(-Degrees(a).to_radians()).z(dir.z),
+        }
+    }
+}
 
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct ParkingSpot {
@@ -17,6 +71,31 @@ pub struct ParkingSpot {
     pub trans: Transform,
 }
 
+impl ParkingSpot {
+    /// Path from `from` into the spot, arriving along the slot so angled slots are entered nose first
+    pub fn entry_path(&self, from: Transform) -> Spline3 {
+        let d = from.pos.distance(self.trans.pos);
+        Spline3 {
+            from: from.pos,
+            to: self.trans.pos,
+            from_derivative: from.dir * d,
+            to_derivative: self.trans.dir * d,
+        }
+    }
+
+    /// Path backing out of the spot along the slot to end at `to`.
+    /// The vehicle faces the opposite of the derivative since it is reversing.
+    pub fn exit_path(&self, to: Transform) -> Spline3 {
+        let d = self.trans.pos.distance(to.pos);
+        Spline3 {
+            from: self.trans.pos,
+            to: to.pos,
+            from_derivative: -self.trans.dir * d,
+            to_derivative: -to.dir * d,
+        }
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct ParkingSpots {
     pub(crate) spots: SlotMap<ParkingSpotID, ParkingSpot>,
@@ -85,7 +164,8 @@ impl ParkingSpots {
 
         let gap = CROSSWALK_WIDTH + 1.0;
         let l = lane.points.length() - gap * 2.0;
-        let n_spots = (l / PARKING_SPOT_LENGTH) as i32;
+        let style = lane.parking_style;
+        let n_spots = (l / style.spacing()) as i32;
         if n_spots <= 0 {
             self.lane_spots.insert(lane.id, vec![]);
             return;
@@ -99,6 +179,7 @@ impl ParkingSpots {
             .points
             .points_dirs_along((0..n_spots).map(|x| (x as f32 + 0.5) * step + gap))
             .map(move |(pos, dir)| {
+                let dir = style.slot_dir(dir);
                 let mut iter = reuse.query_around(pos.xy(), 3.0);
                 if let Some((h, _)) = iter.next() {
                     if let Some((_, spot_id)) = reuse.get(h) {
@@ -170,3 +251,54 @@ impl ParkingSpots {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::ParkingStyle;
+    use crate::map::{LaneKind, LanePatternBuilder, MapBuilder};
+    use geom::vec2;
+
+    #[test]
+    fn angled_parking_fits_more_cars() {
+        let count_spots = |style: ParkingStyle| {
+            let pat = LanePatternBuilder::new()
+                .one_way(true)
+                .parking_style(style)
+                .build();
+
+            let mut b = MapBuilder::new();
+            let src = b.add_inter(vec2(0.0, 0.0));
+            let dst = b.add_inter(vec2(200.0, 0.0));
+            let road = b.connect(src, dst, &pat).unwrap();
+            let map = b.build();
+
+            let road = &map.roads()[road];
+            let (lane, _) = road
+                .lanes_iter()
+                .find(|&(_, kind)| kind == LaneKind::Parking)
+                .unwrap();
+            let lane = &map.lanes()[lane];
+            assert_eq!(lane.parking_style, style);
+            assert!((lane.width - style.width()).abs() < 0.01);
+            assert_eq!(road.pattern(map.lanes()), pat);
+
+            map.parking.spots(lane.id).unwrap().count()
+        };
+
+        let parallel = count_spots(ParkingStyle::Parallel);
+        let angled = count_spots(ParkingStyle::Angled(45.0));
+        assert!(parallel > 0);
+        assert!(angled > parallel, "{} <= {}", angled, parallel);
+
+        // angled slots need a wider lane
+        assert!(ParkingStyle::Angled(45.0).width() > ParkingStyle::Parallel.width());
+        assert_eq!(
+            ParkingStyle::Angled(10.0).validated(),
+            ParkingStyle::Angled(30.0)
+        );
+        assert_eq!(
+            ParkingStyle::Angled(f32::NAN).validated(),
+            ParkingStyle::Parallel
+        );
+    }
+}
@@ -5,6 +5,7 @@ use geom::PolyLine;
 use geom::{BoldLine, Degrees, PolyLine3, Spline, Spline1};
 use geom::{Vec2, Vec3};
 
+use crate::map::serializing::v0;
 use crate::map::{
     BuildingID, Environment, Intersection, IntersectionID, Lane, LaneDirection, LaneID, LaneKind,
     LanePattern, Lanes, ParkingSpots, Roads, SpatialMap, MAX_SLOPE, ROAD_Z_OFFSET,
@@ -30,6 +31,38 @@ impl RoadSegmentKind {
     }
 }
 
+/// Surface of a road, changes how it looks and how fast vehicles drive on it
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RoadMaterial {
+    #[default]
+    Asphalt,
+    Cobblestone,
+    Dirt,
+    Gravel,
+}
+
+impl RoadMaterial {
+    pub const ALL: [RoadMaterial; 4] = [
+        RoadMaterial::Asphalt,
+        RoadMaterial::Cobblestone,
+        RoadMaterial::Dirt,
+        RoadMaterial::Gravel,
+    ];
+
+    /// Multiplier applied to the speed limit of the lanes
+    pub fn speed_factor(self) -> f32 {
+        match self {
+            RoadMaterial::Asphalt => 1.0,
+            RoadMaterial::Cobblestone => 0.8,
+            RoadMaterial::Gravel => 0.7,
+            RoadMaterial::Dirt => 0.6,
+        }
+    }
+}
+
+/// Minimum width of the carriageway for its crossings to be split by a refuge island
+pub const MIN_REFUGE_ROAD_WIDTH: f32 = 16.0;
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Road {
     pub id: RoadID,
@@ -44,6 +77,11 @@ pub struct Road {
 
     pub connected_buildings: Vec<BuildingID>,
 
+    /// Closed roads keep their geometry but are avoided by pathfinding
+    pub closed: bool,
+
+    pub material: RoadMaterial,
+
     src_interface: f32,
     dst_interface: f32,
 
@@ -56,6 +94,17 @@ pub struct LanePair {
     pub outgoing: Option<LaneID>,
 }
 
+/// Where a road meets one of its intersections
+#[derive(Copy, Clone, Debug)]
+pub struct RoadEndpoint {
+    /// Position of the interfaced end of the road
+    pub pos: Vec3,
+    /// Direction of the road going away from the intersection
+    pub dir: Vec2,
+    /// Distance between the intersection center and the start of the road
+    pub interface: f32,
+}
+
 pub struct PylonPosition {
     pub terrain_height: f32,
     pub pos: Vec3,
@@ -102,20 +151,28 @@ impl Road {
             interfaced_points: PolyLine3::new(vec![points.first()]),
             points,
             connected_buildings: vec![],
+            closed: false,
+            material: RoadMaterial::default(),
         });
         #[allow(clippy::indexing_slicing)]
         let road = &mut roads[id];
 
         let mut dist_from_bottom = 0.0;
         for (lane_k, dir, limit) in lane_pattern.lanes() {
-            let id = Lane::make(road, lanes, lane_k, limit, dir, dist_from_bottom);
+            let w = lane_pattern.lane_width(lane_k);
+            let id = Lane::make(road, lanes, lane_k, limit, w, dir, dist_from_bottom);
+            if lane_k == LaneKind::Parking {
+                if let Some(l) = lanes.get_mut(id) {
+                    l.parking_style = lane_pattern.parking_style;
+                }
+            }
 
             match dir {
                 LaneDirection::Forward => road.lanes_forward.insert(0, (id, lane_k)),
                 LaneDirection::Backward => road.lanes_backward.push((id, lane_k)),
             }
 
-            dist_from_bottom += lane_k.width();
+            dist_from_bottom += w;
         }
 
         road.update_lanes(lanes, parking, env);
@@ -132,6 +189,16 @@ impl Road {
         self.lanes_backward.len() + self.lanes_forward.len()
     }
 
+    /// Lanes going from src to dst, in the same order as the pattern's forward lanes
+    pub fn forward_lanes(&self) -> &[(LaneID, LaneKind)] {
+        &self.lanes_forward
+    }
+
+    /// Lanes going from dst to src, empty for a one-way road
+    pub fn backward_lanes(&self) -> &[(LaneID, LaneKind)] {
+        &self.lanes_backward
+    }
+
     /// Returns lanes in left to right order from the source
     pub fn lanes_iter(&self) -> impl DoubleEndedIterator<Item = (LaneID, LaneKind)> + Clone + '_ {
         self.lanes_forward
@@ -141,6 +208,33 @@ impl Road {
             .copied()
     }
 
+    /// Lanes whose direction can be switched: the vehicle lanes next to the middle of the road.
+    /// Sidewalks and parking are never reversible.
+    pub fn reversible_lanes(&self) -> impl Iterator<Item = LaneID> + '_ {
+        self.lanes_forward
+            .first()
+            .into_iter()
+            .chain(self.lanes_backward.first())
+            .filter(|(_, kind)| kind.vehicles())
+            .map(|&(id, _)| id)
+    }
+
+    /// Counts a reversible lane on the other side of the middle of the road.
+    /// The lane keeps its place, its points must be generated again afterwards.
+    pub(crate) fn reverse_lane(&mut self, lane: LaneID) -> bool {
+        if !self.reversible_lanes().any(|id| id == lane) {
+            return false;
+        }
+        let (from, to) = if self.lanes_forward.first().map(|&(id, _)| id) == Some(lane) {
+            (&mut self.lanes_forward, &mut self.lanes_backward)
+        } else {
+            (&mut self.lanes_backward, &mut self.lanes_forward)
+        };
+        let l = from.remove(0);
+        to.insert(0, l);
+        true
+    }
+
     pub fn sidewalks(&self, from: IntersectionID) -> LanePair {
         self.mk_pair(from, |lanes| {
             lanes
@@ -150,6 +244,39 @@ impl Road {
         })
     }
 
+    /// Whether the road is two-way and wide enough for pedestrians to cross it in two stages
+    pub fn has_refuge_room(&self, lanes: &Lanes) -> bool {
+        let vehicles = |lanes: &[(LaneID, LaneKind)]| lanes.iter().any(|(_, kind)| kind.vehicles());
+        let carriageway: f32 = self
+            .lanes_iter()
+            .filter(|(_, kind)| *kind != LaneKind::Walking)
+            .map(|(id, kind)| lanes.get(id).map_or(kind.width(), |l| l.width))
+            .sum();
+
+        vehicles(&self.lanes_forward)
+            && vehicles(&self.lanes_backward)
+            && carriageway >= MIN_REFUGE_ROAD_WIDTH
+    }
+
+    /// The oncoming lane a vehicle on `lane` can borrow to overtake,
+    /// only on two-way roads with a single driving lane each way
+    pub fn overtaking_lane(&self, lane: LaneID) -> Option<LaneID> {
+        let driving = |lanes: &[(LaneID, LaneKind)]| -> Option<LaneID> {
+            let mut it = lanes.iter().filter(|(_, kind)| *kind == LaneKind::Driving);
+            let (id, _) = it.next()?;
+            it.next().is_none().then_some(*id)
+        };
+        let forward = driving(&self.lanes_forward)?;
+        let backward = driving(&self.lanes_backward)?;
+        if lane == forward {
+            Some(backward)
+        } else if lane == backward {
+            Some(forward)
+        } else {
+            None
+        }
+    }
+
     pub fn has_sidewalks(&self) -> bool {
         self.lanes_forward
             .iter()
@@ -249,6 +376,18 @@ impl Road {
                     ))
                 })
                 .collect(),
+            lane_width: self
+                .lanes_iter()
+                .filter(|&(_, kind)| kind == LaneKind::Driving)
+                .find_map(|(id, _)| lanes.get(id))
+                .map(|l| l.width)
+                .filter(|&w| w != LaneKind::Driving.width()),
+            parking_style: self
+                .lanes_iter()
+                .filter(|&(_, kind)| kind == LaneKind::Parking)
+                .find_map(|(id, _)| lanes.get(id))
+                .map(|l| l.parking_style)
+                .unwrap_or_default(),
         }
     }
 
@@ -500,6 +639,19 @@ impl Road {
         Self::heightfinder(&p, from.z, to.z, MAX_SLOPE, env)
     }
 
+    /// Returns the endpoint of the road at the given intersection, or None if the intersection
+    /// is not one of the road's ends
+    pub fn endpoint(&self, id: IntersectionID) -> Option<RoadEndpoint> {
+        if id != self.src && id != self.dst {
+            return None;
+        }
+        Some(RoadEndpoint {
+            pos: self.interface_point(id),
+            dir: self.dir_from(id),
+            interface: self.interface_from(id),
+        })
+    }
+
     pub fn interface_point(&self, id: IntersectionID) -> Vec3 {
         if id == self.src {
             self.interfaced_points().first()
@@ -607,3 +759,100 @@ impl Road {
         None
     }
 }
+
+impl From<v0::Road> for Road {
+    fn from(r: v0::Road) -> Self {
+        Self {
+            id: r.id,
+            src: r.src,
+            dst: r.dst,
+            points: r.points,
+            interfaced_points: r.interfaced_points,
+            width: r.width,
+            connected_buildings: r.connected_buildings,
+            closed: false,
+            material: RoadMaterial::default(),
+            src_interface: r.src_interface,
+            dst_interface: r.dst_interface,
+            lanes_forward: r.lanes_forward,
+            lanes_backward: r.lanes_backward,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::map::{IntersectionID, LaneKind, LanePatternBuilder, Map, ProjectFilter};
+    use geom::Vec3;
+
+    #[test]
+    fn refuge_room_follows_lane_widths() {
+        let has_refuge = |pat: LanePatternBuilder| {
+            let mut map = Map::empty();
+            let a = map.project(Vec3::ZERO, 0.0, ProjectFilter::ALL);
+            let b = map.project(Vec3::new(100.0, 0.0, 0.0), 0.0, ProjectFilter::ALL);
+            let (_, r) = map.make_connection(a, b, None, &pat.build()).unwrap();
+            map.roads()[r].has_refuge_room(map.lanes())
+        };
+
+        assert!(!has_refuge(LanePatternBuilder::new()));
+        assert!(has_refuge(LanePatternBuilder::new().n_lanes(2)));
+        // wide lanes make room on a single lane each way, narrow ones take it away
+        assert!(has_refuge(LanePatternBuilder::new().lane_width(Some(6.0))));
+        assert!(!has_refuge(
+            LanePatternBuilder::new().n_lanes(2).lane_width(Some(2.5))
+        ));
+    }
+
+    #[test]
+    fn endpoint_matches_accessors() {
+        let mut map = Map::empty();
+        let a = map.project(Vec3::ZERO, 0.0, ProjectFilter::ALL);
+        let b = map.project(Vec3::new(100.0, 30.0, 0.0), 0.0, ProjectFilter::ALL);
+        let (_, r) = map
+            .make_connection(a, b, None, &LanePatternBuilder::default().build())
+            .unwrap();
+        let road = &map.roads()[r];
+
+        for id in [road.src, road.dst] {
+            let e = road.endpoint(id).unwrap();
+            assert_eq!(e.pos, road.interface_point(id));
+            assert_eq!(e.dir, road.dir_from(id));
+            assert_eq!(e.interface, road.interface_from(id));
+        }
+
+        assert!(road.endpoint(IntersectionID::default()).is_none());
+    }
+
+    #[test]
+    fn forward_backward_lanes_match_pattern() {
+        let mut map = Map::empty();
+        for (y, one_way) in [(0.0, false), (100.0, true)] {
+            let pat = LanePatternBuilder::new().one_way(one_way).build();
+            let a = map.project(Vec3::new(0.0, y, 0.0), 0.0, ProjectFilter::ALL);
+            let b = map.project(Vec3::new(100.0, y, 0.0), 0.0, ProjectFilter::ALL);
+            let (_, r) = map.make_connection(a, b, None, &pat).unwrap();
+            let road = &map.roads()[r];
+
+            let kinds = |v: &[(LaneKind, f32)]| v.iter().map(|x| x.0).collect::<Vec<_>>();
+            let road_kinds = |v: &[(_, LaneKind)]| v.iter().map(|x| x.1).collect::<Vec<_>>();
+            assert_eq!(road_kinds(road.forward_lanes()), kinds(&pat.lanes_forward));
+            assert_eq!(
+                road_kinds(road.backward_lanes()),
+                kinds(&pat.lanes_backward)
+            );
+
+            for &(id, _) in road.forward_lanes() {
+                let l = &map.lanes()[id];
+                assert_eq!((l.src, l.dst), (road.src, road.dst));
+            }
+            for &(id, _) in road.backward_lanes() {
+                let l = &map.lanes()[id];
+                assert_eq!((l.src, l.dst), (road.dst, road.src));
+            }
+
+            assert_eq!(road.backward_lanes().is_empty(), one_way);
+            assert!(!road.forward_lanes().is_empty());
+        }
+    }
+}
@@ -1,10 +1,10 @@
 use crate::map::{Intersection, IntersectionID, LaneID, Lanes};
-use geom::{Degrees, PolyLine3, Radians, Vec2};
+use geom::{Degrees, PolyLine3, Radians, Ray, Vec2};
 use geom::{Spline, Vec3};
 use serde::{Deserialize, Serialize};
 use std::borrow::Borrow;
 use std::cmp::Ordering;
-use std::f32::consts::TAU;
+use std::f32::consts::{PI, TAU};
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Hash)]
 pub struct TurnID {
@@ -31,11 +31,19 @@ pub enum TurnKind {
     WalkingCorner,
     Driving,
     Rail,
+    /// Crosswalk with a refuge island in the middle, pedestrians wait there to cross the second half
+    TwoStageCrosswalk,
+    /// Driving turn giving way to the traffic in the intersection and on the major road
+    Yield,
 }
 
 impl TurnKind {
     pub fn is_crosswalk(self) -> bool {
-        matches!(self, TurnKind::Crosswalk)
+        matches!(self, TurnKind::Crosswalk | TurnKind::TwoStageCrosswalk)
+    }
+
+    pub fn is_driving(self) -> bool {
+        matches!(self, TurnKind::Driving | TurnKind::Yield)
     }
 }
 
@@ -44,6 +52,28 @@ pub struct Turn {
     pub id: TurnID,
     pub points: PolyLine3,
     pub kind: TurnKind,
+    /// What the points were computed from, None if they were never computed
+    #[serde(skip)]
+    geometry: Option<TurnGeometry>,
+}
+
+/// Everything the points of a turn depend on, if it didn't change the points don't need to be recomputed
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct TurnGeometry {
+    pos_src: Vec3,
+    pos_dst: Vec3,
+    src_dir: Vec2,
+    dst_dir: Vec2,
+    center: Vec2,
+    roundabout_radius: Option<f32>,
+    turnaround_radius: Option<f32>,
+    walking_corner_radius: f32,
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Number of times turn points were computed
+    pub(crate) static MAKE_POINTS_COUNT: std::cell::Cell<usize> = std::cell::Cell::new(0);
 }
 
 impl Borrow<TurnID> for Turn {
@@ -76,6 +106,12 @@ const TURN_ANG_ADD: f32 = 0.29;
 const TURN_ANG_MUL: f32 = 0.36;
 const TURN_MUL: f32 = 0.46;
 const N_SPLINE: usize = 6;
+/// Distance between the edge of a cul-de-sac and the vehicles turning around in it
+const TURNAROUND_MARGIN: f32 = 3.0;
+/// Sidewalks this close to parallel or to a U-turn are joined with a spline instead of a rounded corner
+const CORNER_MIN_ANGLE: f32 = 0.087; // 5°
+/// Points per radian of the arc of rounded corners
+const CORNER_ARC_PRECISION: f32 = 10.0;
 
 impl Turn {
     pub fn new(id: TurnID, kind: TurnKind) -> Self {
@@ -83,31 +119,86 @@ impl Turn {
             id,
             points: PolyLine3::new(vec![Vec3::ZERO; N_SPLINE + 2]),
             kind,
+            geometry: None,
         }
     }
 
-    pub fn make_points(&mut self, lanes: &Lanes, parent: &Intersection) {
-        let Some(src_lane) = lanes.get(self.id.src) else {
+    fn geometry(&self, lanes: &Lanes, parent: &Intersection) -> Option<TurnGeometry> {
+        let src_lane = lanes.get(self.id.src)?;
+        let dst_lane = lanes.get(self.id.dst)?;
+
+        Some(TurnGeometry {
+            pos_src: src_lane.get_inter_node_pos(self.id.parent),
+            pos_dst: dst_lane.get_inter_node_pos(self.id.parent),
+            src_dir: -src_lane.orientation_from(self.id.parent),
+            dst_dir: dst_lane.orientation_from(self.id.parent),
+            center: parent.pos.xy(),
+            roundabout_radius: parent
+                .turn_policy
+                .roundabout
+                .filter(|_| parent.is_roundabout())
+                .map(|rp| rp.radius),
+            turnaround_radius: parent.turnaround(),
+            walking_corner_radius: parent.turn_policy.walking_corner_radius,
+        })
+    }
+
+    /// Recomputes the points only if the lanes or the intersection changed since the last time
+    pub fn update_points(&mut self, lanes: &Lanes, parent: &Intersection) {
+        let geometry = self.geometry(lanes, parent);
+        if geometry.is_some() && geometry == self.geometry {
             return;
-        };
-        let Some(dst_lane) = lanes.get(self.id.dst) else {
+        }
+        self.make_points(lanes, parent);
+    }
+
+    pub fn make_points(&mut self, lanes: &Lanes, parent: &Intersection) {
+        let Some(geometry) = self.geometry(lanes, parent) else {
             return;
         };
+        self.geometry = Some(geometry);
 
-        let pos_src = src_lane.get_inter_node_pos(self.id.parent);
-        let pos_dst = dst_lane.get_inter_node_pos(self.id.parent);
+        #[cfg(test)]
+        MAKE_POINTS_COUNT.with(|c| c.set(c.get() + 1));
+
+        let TurnGeometry {
+            pos_src,
+            pos_dst,
+            src_dir,
+            dst_dir,
+            center,
+            turnaround_radius,
+            walking_corner_radius,
+            ..
+        } = geometry;
 
         self.points.clear_push(pos_src);
 
         if self.kind.is_crosswalk() {
+            if self.kind == TurnKind::TwoStageCrosswalk {
+                self.points.push((pos_src + pos_dst) * 0.5);
+            }
             self.points.push(pos_dst);
             return;
         }
 
-        let src_dir = -src_lane.orientation_from(self.id.parent);
-        let dst_dir = dst_lane.orientation_from(self.id.parent);
+        if let (Some(radius), true) = (turnaround_radius, self.kind.is_driving()) {
+            self.points.extend(
+                Self::gen_roundabout(
+                    pos_src,
+                    pos_dst,
+                    src_dir,
+                    dst_dir,
+                    radius - TURNAROUND_MARGIN,
+                    center,
+                )
+                .skip(1)
+                .map(|x| x.z(pos_src.z)),
+            );
+            return;
+        }
 
-        if matches!(self.kind, TurnKind::Driving | TurnKind::WalkingCorner)
+        if (self.kind.is_driving() || self.kind == TurnKind::WalkingCorner)
             && parent.is_roundabout()
         {
             if let Some(rp) = parent.turn_policy.roundabout {
@@ -151,6 +242,20 @@ impl Turn {
             }
         }
 
+        if self.kind == TurnKind::WalkingCorner {
+            if let Some(corner) = Self::rounded_corner(
+                pos_src.xy(),
+                pos_dst.xy(),
+                src_dir,
+                dst_dir,
+                walking_corner_radius,
+            ) {
+                self.points
+                    .extend(corner.into_iter().map(|x| x.z(pos_src.z)));
+                return;
+            }
+        }
+
         let spline = Self::spline(pos_src.xy(), pos_dst.xy(), src_dir, dst_dir);
 
         self.points.extend(
@@ -161,6 +266,14 @@ impl Turn {
         );
     }
 
+    /// Where pedestrians wait in the middle of a two-stage crossing
+    pub fn refuge(&self) -> Option<Vec3> {
+        if self.kind != TurnKind::TwoStageCrosswalk {
+            return None;
+        }
+        self.points.as_slice().get(1).copied()
+    }
+
     pub fn gen_roundabout(
         pos_src: Vec3,
         pos_dst: Vec3,
@@ -210,6 +323,63 @@ impl Turn {
             .chain(sp3.into_smart_points(0.3, 0.0, 1.0).skip(1))
     }
 
+    /// Points after `from` of a path going straight along both sidewalks until their corner,
+    /// which is cut by an arc of the given radius tangent to both.
+    /// The arc is on the inner side of the corner, away from the road, and the radius is shrunk
+    /// so that it starts and ends between the ends of the sidewalks and the corner.
+    /// None if the sidewalks don't meet in front of both ends, for example when they are parallel.
+    pub fn rounded_corner(
+        from: Vec2,
+        to: Vec2,
+        from_dir: Vec2,
+        to_dir: Vec2,
+        radius: f32,
+    ) -> Option<Vec<Vec2>> {
+        let turn_angle = from_dir.angle(to_dir);
+        if turn_angle.abs() < CORNER_MIN_ANGLE || turn_angle.abs() > PI - CORNER_MIN_ANGLE {
+            return None;
+        }
+        let (dist_from, dist_to) =
+            Ray::new(from, from_dir).both_dist_to_inter(&Ray::new(to, -to_dir))?;
+        let corner = from + from_dir * dist_from;
+
+        let half_tan = (turn_angle.abs() * 0.5).tan();
+        let tangent_dist = (radius.max(0.0) * half_tan).min(dist_from).min(dist_to);
+        let radius = tangent_dist / half_tan;
+
+        let start = corner - from_dir * tangent_dist;
+        let end = corner + to_dir * tangent_dist;
+
+        let mut points = Vec::with_capacity(16);
+        if radius > 0.01 {
+            if start.distance(from) > 0.01 {
+                points.push(start);
+            }
+            // the center is on the side the path turns to
+            let normal = if turn_angle > 0.0 {
+                -from_dir.perpendicular()
+            } else {
+                from_dir.perpendicular()
+            };
+            let center = start + normal * radius;
+            let ang_start = (start - center).angle_cossin().0;
+
+            let n = (turn_angle.abs() * CORNER_ARC_PRECISION).ceil() as usize;
+            points.extend((1..n).map(|i| {
+                let ang = ang_start + turn_angle * i as f32 / n as f32;
+                center + Vec2::from_angle(Radians(ang)) * radius
+            }));
+            points.push(end);
+        } else {
+            points.push(corner);
+        }
+        match points.last_mut() {
+            Some(last) if last.distance(to) <= 0.01 => *last = to,
+            _ => points.push(to),
+        }
+        Some(points)
+    }
+
     /// Return points of a circular arc in counter-clockwise order from ang_a to ang_b, assuming ang_a < ang_b
     pub fn circular_arc(
         center: Vec2,
@@ -0,0 +1,59 @@
+use crate::map::{IntersectionID, LaneKind, Map, RoadID};
+use serde::{Deserialize, Serialize};
+use slotmapd::new_key_type;
+
+new_key_type! {
+    pub struct VerticalConnectorID;
+}
+
+/// Connectors between walkways closer in height than this are just sloped walkways
+pub const MIN_CONNECTOR_HEIGHT: f32 = 1.0;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConnectorKind {
+    Stairs,
+    Ramp,
+}
+
+impl ConnectorKind {
+    /// Extra distance in meters a pedestrian is willing to walk to avoid climbing one meter
+    pub fn climb_cost(self) -> f32 {
+        match self {
+            ConnectorKind::Stairs => 10.0,
+            ConnectorKind::Ramp => 4.0,
+        }
+    }
+}
+
+/// Stairs or a ramp between walkways at different heights, like a footbridge and the street below.
+/// Pedestrians walk it through a road made only of walking lanes.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct VerticalConnector {
+    pub id: VerticalConnectorID,
+    pub kind: ConnectorKind,
+    pub road: RoadID,
+    pub bottom: IntersectionID,
+    pub top: IntersectionID,
+}
+
+impl VerticalConnector {
+    /// Height climbed from the bottom to the top
+    pub fn height(&self, map: &Map) -> f32 {
+        let z = |id| map.intersections.get(id).map_or(0.0, |i| i.pos.z);
+        (z(self.top) - z(self.bottom)).abs()
+    }
+
+    /// A connector can only be used if a walkway other than itself leaves both its ends
+    pub fn is_usable(&self, map: &Map) -> bool {
+        [self.bottom, self.top].into_iter().all(|end| {
+            map.intersections.get(end).map_or(false, |inter| {
+                inter.roads.iter().any(|&r| {
+                    r != self.road
+                        && map.roads.get(r).map_or(false, |road| {
+                            road.lanes_iter().any(|(_, kind)| kind == LaneKind::Walking)
+                        })
+                })
+            })
+        })
+    }
+}
@@ -1,5 +1,6 @@
 use crate::map::{
-    LaneID, LaneKind, LanePatternBuilder, Map, Traversable, TraverseDirection, TraverseKind, TurnID,
+    Lane, LaneID, LaneKind, LanePatternBuilder, Map, MapSubscriber, ProjectFilter, Traversable,
+    TraverseDirection, TraverseKind, TurnID,
 };
 use common::hash_u64;
 use geom::{PolyLine3, Vec3};
@@ -7,6 +8,25 @@ use ordered_float::OrderedFloat;
 use prototypes::Tick;
 use serde::{Deserialize, Serialize};
 use slotmapd::Key;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct PathfindOptions {
+    /// If the destination is unreachable, return a path to the reachable lane closest to it
+    /// instead of failing. Only supported for vehicles and trains.
+    pub allow_partial: bool,
+    /// How much pedestrians avoid walking in the open, an exposed lane costs `1 + comfort_weight` times
+    /// its length. 0 means the plain shortest path. Only supported for pedestrians.
+    pub comfort_weight: f32,
+}
+
+#[derive(Debug)]
+pub struct PathResult {
+    pub path: Vec<Traversable>,
+    /// The path doesn't end on the requested lane but on the closest one reachable
+    pub partial: bool,
+}
 
 pub trait Pathfinder {
     fn path(
@@ -16,6 +36,19 @@ pub trait Pathfinder {
         start: Traversable,
         end: LaneID,
     ) -> Option<Vec<Traversable>>;
+    fn path_with_options(
+        &self,
+        map: &Map,
+        tick: Tick,
+        start: Traversable,
+        end: LaneID,
+        _options: PathfindOptions,
+    ) -> Option<PathResult> {
+        Some(PathResult {
+            path: self.path(map, tick, start, end)?,
+            partial: false,
+        })
+    }
     fn nearest_lane(&self, map: &Map, pos: Vec3) -> Option<LaneID>;
     fn local_route(&self, map: &Map, lane: LaneID, start: Vec3, end: Vec3) -> Option<PolyLine3>;
     fn authorized_lane(&self, kind: LaneKind) -> bool;
@@ -43,6 +76,23 @@ impl Pathfinder for PathKind {
         }
     }
 
+    fn path_with_options(
+        &self,
+        map: &Map,
+        tick: Tick,
+        start: Traversable,
+        end: LaneID,
+        options: PathfindOptions,
+    ) -> Option<PathResult> {
+        match self {
+            PathKind::Pedestrian => {
+                PedestrianPath.path_with_options(map, tick, start, end, options)
+            }
+            PathKind::Vehicle => CarPath.path_with_options(map, tick, start, end, options),
+            PathKind::Rail => RailPath.path_with_options(map, tick, start, end, options),
+        }
+    }
+
     fn nearest_lane(&self, map: &Map, pos: Vec3) -> Option<LaneID> {
         match self {
             PathKind::Pedestrian => PedestrianPath.nearest_lane(map, pos),
@@ -68,16 +118,94 @@ impl Pathfinder for PathKind {
     }
 }
 
+/// Distance between the points where the shade of a walking lane is sampled
+const SHADE_SAMPLE_DIST: f32 = 8.0;
+/// A point is covered if a tree is closer than this
+const TREE_SHADE_RADIUS: f32 = 6.0;
+/// A point is covered if a building is closer than this
+const BUILDING_SHADE_RADIUS: f32 = 10.0;
+
+/// Fraction of the lane that is neither under trees nor along buildings, in [0; 1]
+pub fn lane_exposure(map: &Map, lane: &Lane) -> f32 {
+    let length = lane.points.length();
+    let n_samples = ((length / SHADE_SAMPLE_DIST) as usize).max(1);
+
+    let exposed = (0..n_samples)
+        .filter(|&i| {
+            let d = (i as f32 + 0.5) * length / n_samples as f32;
+            let pos = lane.points.point_along(d).xy();
+
+            let under_tree = map
+                .environment
+                .trees
+                .query_around(pos, TREE_SHADE_RADIUS)
+                .next()
+                .is_some();
+            let near_building = map
+                .spatial_map
+                .query_around(pos, BUILDING_SHADE_RADIUS, ProjectFilter::BUILDING)
+                .next()
+                .is_some();
+
+            !under_tree && !near_building
+        })
+        .count();
+
+    exposed as f32 / n_samples as f32
+}
+
+/// Computes the exposure of a lane the first time it is asked for, and forgets them all once the
+/// roads, buildings or trees changed
+pub(crate) struct ExposureCache {
+    sub: MapSubscriber,
+    lanes: Mutex<BTreeMap<LaneID, f32>>,
+}
+
+impl ExposureCache {
+    pub fn new(sub: MapSubscriber) -> Self {
+        Self {
+            sub,
+            lanes: Mutex::new(BTreeMap::new()),
+        }
+    }
+}
+
+impl Map {
+    /// Same as [`lane_exposure`], recomputed only if the map changed since it was last asked for
+    pub fn exposure(&self, lane: &Lane) -> f32 {
+        let mut cache = self.exposure.lanes.lock().unwrap();
+        if self.exposure.sub.take_changed() {
+            cache.clear();
+        }
+        *cache
+            .entry(lane.id)
+            .or_insert_with(|| lane_exposure(self, lane))
+    }
+}
+
 struct PedestrianPath;
 
 impl Pathfinder for PedestrianPath {
     fn path(
         &self,
         map: &Map,
-        _tick: Tick,
+        tick: Tick,
         start: Traversable,
         end: LaneID,
     ) -> Option<Vec<Traversable>> {
+        self.path_with_options(map, tick, start, end, PathfindOptions::default())
+            .map(|r| r.path)
+    }
+
+    fn path_with_options(
+        &self,
+        map: &Map,
+        _tick: Tick,
+        start: Traversable,
+        end: LaneID,
+        options: PathfindOptions,
+    ) -> Option<PathResult> {
+        let comfort_weight = options.comfort_weight.max(0.0);
         let inters = &map.intersections;
         let lanes = &map.lanes;
 
@@ -103,14 +231,24 @@ impl Pathfinder for PedestrianPath {
             let lane_from_id = t.destination_lane();
             let lane_from = lanes.get(lane_from_id);
 
-            let lane_travers = inter.zip(lane_from).map(|(inter, lane_from)| {
-                (
+            let lane_travers = inter.zip(lane_from).and_then(|(inter, lane_from)| {
+                let mut cost = lane_from.points.length();
+                if comfort_weight > 0.0 {
+                    cost *= 1.0 + comfort_weight * map.exposure(lane_from);
+                }
+                if let Some(connector) = map.vertical_connector_of(lane_from.parent) {
+                    if !connector.is_usable(map) {
+                        return None;
+                    }
+                    cost += connector.kind.climb_cost() * connector.height(map);
+                }
+                Some((
                     Traversable::new(
                         TraverseKind::Lane(lane_from_id),
                         lane_from.dir_from(inter.id),
                     ),
-                    OrderedFloat(lane_from.points.length()),
-                )
+                    OrderedFloat(cost),
+                ))
             });
 
             inter
@@ -131,8 +269,12 @@ impl Pathfinder for PedestrianPath {
             TraverseKind::Turn(_) => false,
         };
 
-        pathfinding::directed::astar::astar(&start, successors, heuristic, has_arrived)
-            .map(|(v, _)| v)
+        pathfinding::directed::astar::astar(&start, successors, heuristic, has_arrived).map(
+            |(path, _)| PathResult {
+                path,
+                partial: false,
+            },
+        )
     }
 
     fn nearest_lane(&self, map: &Map, pos: Vec3) -> Option<LaneID> {
@@ -173,6 +315,17 @@ impl Pathfinder for RailPath {
         CarPath.path(map, tick, start, end)
     }
 
+    fn path_with_options(
+        &self,
+        map: &Map,
+        tick: Tick,
+        start: Traversable,
+        end: LaneID,
+        options: PathfindOptions,
+    ) -> Option<PathResult> {
+        CarPath.path_with_options(map, tick, start, end, options)
+    }
+
     fn nearest_lane(&self, map: &Map, pos: Vec3) -> Option<LaneID> {
         map.nearest_lane(pos, LaneKind::Rail, None)
     }
@@ -230,16 +383,20 @@ impl Pathfinder for CarPath {
             l.and_then(move |x| inters.get(x.dst))
                 .into_iter()
                 .flat_map(move |inter| {
-                    inter.turns_from(p).map(move |(x, _)| {
-                        let mut cost = f32::INFINITY;
-
-                        if let Some(l) = lanes.get(x.dst) {
-                            cost = l.points.length() / l.speed_limit;
-                            cost += common::rand::randu(l.dist_from_bottom.to_bits() ^ base_random);
-                        }
-
-                        (x.dst, OrderedFloat(cost))
-                    })
+                    inter
+                        .turns_from(p)
+                        .filter(move |(x, _)| !map.is_lane_closed(x.dst))
+                        .map(move |(x, _)| {
+                            let mut cost = f32::INFINITY;
+
+                            if let Some(l) = lanes.get(x.dst) {
+                                cost = l.travel_time();
+                                cost +=
+                                    common::rand::randu(l.dist_from_bottom.to_bits() ^ base_random);
+                            }
+
+                            (x.dst, OrderedFloat(cost))
+                        })
                 })
         };
 
@@ -268,6 +425,51 @@ impl Pathfinder for CarPath {
         Some(path)
     }
 
+    fn path_with_options(
+        &self,
+        map: &Map,
+        tick: Tick,
+        start: Traversable,
+        end: LaneID,
+        options: PathfindOptions,
+    ) -> Option<PathResult> {
+        if let Some(path) = self.path(map, tick, start, end) {
+            return Some(PathResult {
+                path,
+                partial: false,
+            });
+        }
+        if !options.allow_partial {
+            return None;
+        }
+
+        let inters = &map.intersections;
+        let lanes = &map.lanes;
+        let end_pos = lanes.get(end)?.points.last();
+
+        let reachable = pathfinding::directed::bfs::bfs_reach(start.destination_lane(), |&l| {
+            lanes
+                .get(l)
+                .and_then(|x| inters.get(x.dst))
+                .into_iter()
+                .flat_map(move |inter| inter.turns_from(l).map(|(x, _)| x.dst))
+                .filter(move |&dst| !map.is_lane_closed(dst))
+        });
+
+        let closest = reachable
+            .filter_map(|l| {
+                let d = lanes.get(l)?.points.project(end_pos).distance2(end_pos);
+                Some((l, d))
+            })
+            .min_by_key(|&(l, d)| (OrderedFloat(d), l))?
+            .0;
+
+        Some(PathResult {
+            path: self.path(map, tick, start, closest)?,
+            partial: true,
+        })
+    }
+
     fn nearest_lane(&self, map: &Map, pos: Vec3) -> Option<LaneID> {
         map.nearest_lane(pos, LaneKind::Driving, None)
     }
@@ -297,3 +499,159 @@ impl Pathfinder for CarPath {
         matches!(kind, LaneKind::Driving | LaneKind::Bus)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{lane_exposure, PathKind, PathfindOptions, Pathfinder};
+    use crate::map::terrain::Tree;
+    use crate::map::{
+        ConnectorKind, LaneKind, LanePatternBuilder, Map, ProjectFilter, RoadSegmentKind,
+        Traversable, TraverseDirection, TraverseKind, UpdateType,
+    };
+    use geom::{vec2, vec3, PolyLine3, Vec3, AABB};
+    use prototypes::Tick;
+
+    #[test]
+    fn pedestrians_take_the_footbridge_stairs() {
+        let mut map = Map::empty();
+        let pat = LanePatternBuilder::new().n_lanes(0).build();
+
+        let walkway = |map: &mut Map, from: Vec3, to: Vec3| {
+            let a = map.add_intersection(from);
+            let b = map.add_intersection(to);
+            let segment = RoadSegmentKind::Arbitrary(PolyLine3::new(vec![from, to]));
+            map.connect(a, b, &pat, segment).unwrap()
+        };
+        // two banks of a river only linked by the footbridge
+        walkway(&mut map, vec3(-200.0, 0.0, 0.0), vec3(-50.0, 0.0, 0.0));
+        walkway(&mut map, vec3(50.0, 0.0, 0.0), vec3(200.0, 0.0, 0.0));
+        let bridge = walkway(&mut map, vec3(-30.0, 0.0, 6.0), vec3(30.0, 0.0, 6.0));
+
+        let up = map
+            .build_vertical_connector(
+                vec3(-50.0, 0.0, 0.0),
+                vec3(-30.0, 0.0, 6.0),
+                ConnectorKind::Stairs,
+            )
+            .unwrap();
+        let down = map
+            .build_vertical_connector(
+                vec3(50.0, 0.0, 0.0),
+                vec3(30.0, 0.0, 6.0),
+                ConnectorKind::Stairs,
+            )
+            .unwrap();
+        // leads nowhere at the top
+        let dangling = map
+            .build_vertical_connector(
+                vec3(-100.0, 0.0, 0.0),
+                vec3(-100.0, 30.0, 6.0),
+                ConnectorKind::Ramp,
+            )
+            .unwrap();
+
+        let connectors = map.vertical_connectors();
+        assert!(connectors[up].is_usable(&map));
+        assert!(connectors[down].is_usable(&map));
+        assert!(!connectors[dangling].is_usable(&map));
+        let roads = [connectors[up].road, bridge, connectors[down].road];
+
+        let kind = PathKind::Pedestrian;
+        let start = kind.nearest_lane(&map, vec3(-150.0, 0.0, 0.0)).unwrap();
+        let end = kind.nearest_lane(&map, vec3(150.0, 0.0, 0.0)).unwrap();
+        let start = Traversable::new(TraverseKind::Lane(start), TraverseDirection::Forward);
+        let path = kind.path(&map, Tick(0), start, end).unwrap();
+
+        // up the stairs, across, and down, in that order
+        let walked: Vec<_> = path
+            .iter()
+            .filter_map(|t| match t.kind {
+                TraverseKind::Lane(l) => Some(map.lanes()[l].parent),
+                TraverseKind::Turn(_) => None,
+            })
+            .filter(|r| roads.contains(r))
+            .collect();
+        assert_eq!(walked, roads);
+        assert!(!path.iter().any(|t| match t.kind {
+            TraverseKind::Lane(l) => map.lanes()[l].parent == connectors[dangling].road,
+            TraverseKind::Turn(_) => false,
+        }));
+    }
+
+    #[test]
+    fn pedestrians_prefer_shade() {
+        let mut map = Map::empty();
+        let pat = LanePatternBuilder::new().parking(false).build();
+
+        let mut road = |from: Vec3, to: Vec3| {
+            let a = map.project(from, 0.0, ProjectFilter::ALL);
+            let b = map.project(to, 0.0, ProjectFilter::ALL);
+            map.make_connection(a, b, None, &pat).unwrap();
+        };
+        let (a, d) = (vec3(0.0, 0.0, 0.0), vec3(200.0, 0.0, 0.0));
+        road(vec3(-100.0, 0.0, 0.0), a);
+        // exposed and shorter
+        road(a, vec3(100.0, 40.0, 0.0));
+        road(vec3(100.0, 40.0, 0.0), d);
+        // shaded and longer
+        road(a, vec3(100.0, -60.0, 0.0));
+        road(vec3(100.0, -60.0, 0.0), d);
+        road(d, vec3(300.0, 0.0, 0.0));
+
+        for x in (0..=50).map(|x| x as f32 * 4.0) {
+            for y in (2..=18).map(|y| y as f32 * -4.0) {
+                let pos = vec2(x, y);
+                map.environment.trees.insert(pos, Tree::new(pos));
+            }
+        }
+
+        let kind = PathKind::Pedestrian;
+        let start = kind.nearest_lane(&map, vec3(-50.0, 0.0, 0.0)).unwrap();
+        let end = kind.nearest_lane(&map, vec3(250.0, 0.0, 0.0)).unwrap();
+        let start = Traversable::new(TraverseKind::Lane(start), TraverseDirection::Forward);
+
+        let path = |comfort_weight: f32| {
+            kind.path_with_options(
+                &map,
+                Tick(0),
+                start,
+                end,
+                PathfindOptions {
+                    comfort_weight,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .path
+        };
+        let is_shaded = |path: &[Traversable]| {
+            path.iter().any(|t| match t.kind {
+                TraverseKind::Lane(l) => map.lanes()[l].points.first().y < -20.0,
+                TraverseKind::Turn(_) => false,
+            })
+        };
+
+        let plain = path(0.0);
+        assert_eq!(plain, kind.path(&map, Tick(0), start, end).unwrap());
+        assert!(!is_shaded(&plain));
+
+        assert!(is_shaded(&path(2.0)));
+
+        // the exposure is kept until the trees are cut
+        let shaded = map
+            .lanes()
+            .values()
+            .find(|l| l.kind == LaneKind::Walking && l.points.first().y < -20.0)
+            .unwrap()
+            .id;
+        let exposure = map.exposure(&map.lanes()[shaded]);
+        assert_eq!(exposure, lane_exposure(&map, &map.lanes()[shaded]));
+        assert!(exposure < 0.5);
+
+        map.environment.remove_trees_near(
+            AABB::new_ll_ur(vec2(-10.0, -80.0), vec2(210.0, 0.0)),
+            |chunk| map.subscribers.dispatch_chunk(UpdateType::Terrain, chunk),
+        );
+        assert_eq!(map.exposure(&map.lanes()[shaded]), 1.0);
+    }
+}
@@ -0,0 +1,970 @@
+use crate::map::{IntersectionID, LanePatternBuilder, Map, RoadSegmentKind};
+use flat_spatial::Grid;
+use geom::{vec2, PolyLine3, Vec2, Vec3};
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io::Read;
+
+/// Earth radius in meters, for the projection of lat/lon to local coordinates
+const EARTH_RADIUS: f64 = 6_371_000.0;
+/// The south-west corner of the extract is placed this far from the origin
+const IMPORT_MARGIN: f32 = 100.0;
+/// Nodes closer than this are merged into the same intersection
+const MERGE_DIST: f32 = 5.0;
+/// Road segments shorter than this are too short to hold an intersection on both ends
+const MIN_SEGMENT_LENGTH: f32 = 10.0;
+/// Height of the imported intersections and roads
+const INTER_HEIGHT: f32 = 0.3;
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct ImportStats {
+    /// Nodes read from the extract
+    pub nodes: usize,
+    /// Ways with a supported highway tag that were imported
+    pub ways: usize,
+    pub intersections: usize,
+    pub roads: usize,
+    /// Ways that are not roads, such as footways or buildings
+    pub ignored_ways: usize,
+    /// Ways or parts of ways whose geometry can't be built: missing nodes, segments too short,
+    /// overlapping roads...
+    pub skipped: usize,
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io(std::io::Error),
+    /// Neither an OSM XML nor an OSM PBF extract, or a PBF extract using features that are not
+    /// supported (history, compressions other than zlib...)
+    UnsupportedFormat,
+    /// The extract could not be parsed
+    Malformed(String),
+    /// The extract contains no road that could be imported
+    NoRoads,
+}
+
+impl Display for ImportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::Io(err) => write!(f, "could not read extract: {err}"),
+            ImportError::UnsupportedFormat => {
+                write!(
+                    f,
+                    "unsupported extract format, only OSM XML and PBF are supported"
+                )
+            }
+            ImportError::Malformed(why) => write!(f, "malformed extract: {why}"),
+            ImportError::NoRoads => write!(f, "the extract contains no road"),
+        }
+    }
+}
+
+impl Error for ImportError {}
+
+/// The lane pattern used for a road with the given OSM highway tag, None if it's not a road cars drive on
+pub fn osm_pattern(highway: &str) -> Option<LanePatternBuilder> {
+    Some(match highway {
+        "motorway" | "trunk" => LanePatternBuilder::new()
+            .n_lanes(3)
+            .speed_limit(25.0)
+            .parking(false)
+            .sidewalks(false),
+        "motorway_link" | "trunk_link" => LanePatternBuilder::new()
+            .speed_limit(13.0)
+            .parking(false)
+            .sidewalks(false),
+        "primary" | "secondary" => LanePatternBuilder::new().n_lanes(2).speed_limit(13.0),
+        "primary_link" | "secondary_link" | "tertiary" | "tertiary_link" | "unclassified" => {
+            LanePatternBuilder::new().speed_limit(11.0)
+        }
+        "residential" | "living_street" => LanePatternBuilder::new(),
+        "service" => LanePatternBuilder::new().parking(false).speed_limit(6.0),
+        _ => return None,
+    })
+}
+
+struct Way {
+    nodes: Vec<i64>,
+    pattern: LanePatternBuilder,
+}
+
+/// Nodes merged into the same intersection, which is only added to the map once a road reaches it
+struct Cluster {
+    pos: Vec2,
+    inter: Option<IntersectionID>,
+}
+
+struct Clusters {
+    clusters: Vec<Cluster>,
+    of_node: BTreeMap<i64, usize>,
+    grid: Grid<usize, Vec2>,
+}
+
+impl Clusters {
+    fn new() -> Self {
+        Self {
+            clusters: vec![],
+            of_node: BTreeMap::new(),
+            grid: Grid::new(50),
+        }
+    }
+
+    fn of(&mut self, n: i64, pos: Vec2) -> usize {
+        if let Some(&c) = self.of_node.get(&n) {
+            return c;
+        }
+        let c = match self.grid.query_around(pos, MERGE_DIST).next() {
+            Some((h, _)) => *self.grid.get(h).unwrap().1,
+            None => {
+                self.clusters.push(Cluster { pos, inter: None });
+                self.grid.insert(pos, self.clusters.len() - 1);
+                self.clusters.len() - 1
+            }
+        };
+        self.of_node.insert(n, c);
+        c
+    }
+
+    fn pos(&self, c: usize) -> Vec2 {
+        self.clusters[c].pos
+    }
+
+    fn inter(&mut self, map: &mut Map, c: usize) -> IntersectionID {
+        let cluster = &mut self.clusters[c];
+        let pos = cluster.pos;
+        *cluster
+            .inter
+            .get_or_insert_with(|| map.add_intersection(pos.z(INTER_HEIGHT)))
+    }
+}
+
+/// Builds the roads of an OSM extract, either XML or PBF.
+/// Intersections are made at the nodes shared by several roads and at the end of the roads,
+/// the nodes in between give the shape of the road connecting them.
+/// Only intersections that end up with a road are added to the map.
+pub fn import_osm(map: &mut Map, mut reader: impl Read) -> Result<ImportStats, ImportError> {
+    let mut data = vec![];
+    reader.read_to_end(&mut data).map_err(ImportError::Io)?;
+
+    let mut stats = ImportStats::default();
+    let mut nodes: BTreeMap<i64, (f64, f64)> = BTreeMap::new();
+    let mut ways: Vec<Way> = vec![];
+
+    if data.iter().find(|c| !c.is_ascii_whitespace()) == Some(&b'<') {
+        let data = std::str::from_utf8(&data).map_err(|e| ImportError::Malformed(e.to_string()))?;
+        read_xml(data, &mut nodes, &mut ways, &mut stats)?;
+    } else {
+        read_pbf(&data, &mut nodes, &mut ways, &mut stats)?;
+    }
+
+    // a way referencing a node that is not in the extract can't be placed
+    ways.retain(|w| {
+        let complete = w.nodes.iter().all(|n| nodes.contains_key(n));
+        if !complete {
+            stats.skipped += 1;
+        }
+        complete
+    });
+    if ways.is_empty() {
+        return Err(ImportError::NoRoads);
+    }
+    stats.ways = ways.len();
+
+    let project = projection(ways.iter().flat_map(|w| &w.nodes).map(|n| nodes[n]));
+
+    let mut uses: BTreeMap<i64, usize> = BTreeMap::new();
+    for w in &ways {
+        for &n in &w.nodes {
+            *uses.entry(n).or_default() += 1;
+        }
+    }
+
+    let node_pos = |n: i64| {
+        let (lat, lon) = nodes[&n];
+        project(lat, lon)
+    };
+
+    let mut clusters = Clusters::new();
+
+    let mut connected = BTreeSet::new();
+    for w in &ways {
+        let last = w.nodes.len() - 1;
+        let mut src = clusters.of(w.nodes[0], node_pos(w.nodes[0]));
+        // the nodes the road goes through since src
+        let mut shape = vec![];
+        for (i, &n) in w.nodes.iter().enumerate().skip(1) {
+            if i != last && uses[&n] < 2 {
+                shape.push(node_pos(n));
+                continue;
+            }
+            let dst = clusters.of(n, node_pos(n));
+
+            // too short, the segment is merged with the next one if there is one
+            let short =
+                src == dst || clusters.pos(src).distance(clusters.pos(dst)) < MIN_SEGMENT_LENGTH;
+            if short && i != last {
+                shape.push(node_pos(n));
+                continue;
+            }
+
+            if short || !connected.insert((src.min(dst), src.max(dst))) {
+                stats.skipped += 1;
+            } else {
+                let src_id = clusters.inter(map, src);
+                let dst_id = clusters.inter(map, dst);
+                let segment = if shape.is_empty() {
+                    RoadSegmentKind::Straight
+                } else {
+                    let points = std::iter::once(clusters.pos(src))
+                        .chain(shape.iter().copied())
+                        .chain(std::iter::once(clusters.pos(dst)))
+                        .map(|p| p.z(INTER_HEIGHT))
+                        .collect::<Vec<Vec3>>();
+                    RoadSegmentKind::Arbitrary(PolyLine3::new(points))
+                };
+                map.connect(src_id, dst_id, &w.pattern.build(), segment);
+                stats.roads += 1;
+            }
+            src = dst;
+            shape.clear();
+        }
+    }
+
+    // the turns, interfaces and traffic control are made once all the roads are there
+    for c in &clusters.clusters {
+        if let Some(id) = c.inter {
+            map.invalidate(id);
+        }
+    }
+
+    stats.intersections = clusters
+        .clusters
+        .iter()
+        .filter(|c| c.inter.is_some())
+        .count();
+
+    map.check_invariants();
+
+    Ok(stats)
+}
+
+/// The road to build along a way, None if it's not a road cars drive on
+fn road_way(
+    mut way_nodes: Vec<i64>,
+    tags: &BTreeMap<&str, &str>,
+    stats: &mut ImportStats,
+) -> Option<Way> {
+    let Some(mut pattern) = tags.get("highway").and_then(|h| osm_pattern(h)) else {
+        stats.ignored_ways += 1;
+        return None;
+    };
+    if way_nodes.len() < 2 {
+        stats.skipped += 1;
+        return None;
+    }
+
+    // motorways are one way unless said otherwise
+    let oneway =
+        tags.get("oneway")
+            .copied()
+            .unwrap_or(if tags.get("highway") == Some(&"motorway") {
+                "yes"
+            } else {
+                "no"
+            });
+    match oneway {
+        "yes" | "true" | "1" => pattern = pattern.one_way(true),
+        "-1" => {
+            pattern = pattern.one_way(true);
+            way_nodes.reverse();
+        }
+        _ => {}
+    }
+    if let Some(lanes) = tags.get("lanes").and_then(|l| l.parse::<u32>().ok()) {
+        let per_side = if pattern.one_way { lanes } else { lanes / 2 };
+        pattern = pattern.n_lanes(per_side.clamp(1, 4));
+    }
+
+    Some(Way {
+        nodes: way_nodes,
+        pattern,
+    })
+}
+
+fn read_xml(
+    data: &str,
+    nodes: &mut BTreeMap<i64, (f64, f64)>,
+    ways: &mut Vec<Way>,
+    stats: &mut ImportStats,
+) -> Result<(), ImportError> {
+    // state of the way being parsed
+    let mut way_nodes: Option<Vec<i64>> = None;
+    let mut tags: BTreeMap<&str, &str> = BTreeMap::new();
+
+    for tag in XmlTags(data) {
+        let tag = tag?;
+        match (tag.name, tag.closing) {
+            ("node", false) => {
+                let id = tag.parse("id")?;
+                let lat = tag.parse("lat")?;
+                let lon = tag.parse("lon")?;
+                nodes.insert(id, (lat, lon));
+                stats.nodes += 1;
+            }
+            ("way", false) => {
+                way_nodes = Some(vec![]);
+                tags.clear();
+            }
+            ("nd", false) => {
+                if let Some(ref mut way_nodes) = way_nodes {
+                    way_nodes.push(tag.parse("ref")?);
+                }
+            }
+            ("tag", false) => {
+                if way_nodes.is_some() {
+                    tags.insert(tag.attr("k")?, tag.attr("v")?);
+                }
+            }
+            ("way", true) => {
+                let Some(way_nodes) = way_nodes.take() else {
+                    continue;
+                };
+                ways.extend(road_way(way_nodes, &tags, stats));
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Reads a PBF extract: a sequence of blobs, each one preceded by its header and the length of
+/// the header. The first blob is the header of the file, the others hold the nodes and ways.
+/// See <https://wiki.openstreetmap.org/wiki/PBF_Format>
+fn read_pbf(
+    mut data: &[u8],
+    nodes: &mut BTreeMap<i64, (f64, f64)>,
+    ways: &mut Vec<Way>,
+    stats: &mut ImportStats,
+) -> Result<(), ImportError> {
+    let mut first = true;
+    while !data.is_empty() {
+        let Some((len, rest)) = data.split_first_chunk::<4>() else {
+            return Err(truncated());
+        };
+        let len = u32::from_be_bytes(*len) as usize;
+        let header = rest.get(..len).ok_or_else(truncated)?;
+        data = &rest[len..];
+
+        let mut kind = "";
+        let mut blob_len = 0;
+        for field in Proto(header) {
+            match field? {
+                (1, Field::Bytes(b)) => kind = std::str::from_utf8(b).unwrap_or_default(),
+                (3, Field::Int(n)) => blob_len = n as usize,
+                _ => {}
+            }
+        }
+        // anything but a PBF extract stops here, as it doesn't start with the file header
+        if first && kind != "OSMHeader" {
+            return Err(ImportError::UnsupportedFormat);
+        }
+        first = false;
+
+        let blob = data.get(..blob_len).ok_or_else(truncated)?;
+        data = &data[blob_len..];
+
+        match kind {
+            "OSMHeader" => read_pbf_header(&blob_data(blob)?)?,
+            "OSMData" => read_pbf_block(&blob_data(blob)?, nodes, ways, stats)?,
+            // unknown blobs are to be skipped
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// The uncompressed content of a blob
+fn blob_data(blob: &[u8]) -> Result<Vec<u8>, ImportError> {
+    for field in Proto(blob) {
+        match field? {
+            (1, Field::Bytes(raw)) => return Ok(raw.to_vec()),
+            (3, Field::Bytes(zlib)) => {
+                return miniz_oxide::inflate::decompress_to_vec_zlib(zlib)
+                    .map_err(|e| ImportError::Malformed(format!("bad zlib blob: {e:?}")))
+            }
+            // other compressions
+            (4..=7, _) => return Err(ImportError::UnsupportedFormat),
+            _ => {}
+        }
+    }
+    Err(ImportError::Malformed("empty blob".to_string()))
+}
+
+/// Refuses the extracts that need features this reader doesn't have
+fn read_pbf_header(header: &[u8]) -> Result<(), ImportError> {
+    for field in Proto(header) {
+        if let (4, Field::Bytes(feature)) = field? {
+            match feature {
+                b"OsmSchema-V0.6" | b"DenseNodes" => {}
+                _ => return Err(ImportError::UnsupportedFormat),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_pbf_block(
+    block: &[u8],
+    nodes: &mut BTreeMap<i64, (f64, f64)>,
+    ways: &mut Vec<Way>,
+    stats: &mut ImportStats,
+) -> Result<(), ImportError> {
+    let mut strings: Vec<&str> = vec![];
+    let mut groups = vec![];
+    let mut granularity = 100;
+    let (mut lat_offset, mut lon_offset) = (0, 0);
+    for field in Proto(block) {
+        match field? {
+            (1, Field::Bytes(table)) => {
+                for s in Proto(table) {
+                    if let (1, Field::Bytes(s)) = s? {
+                        strings.push(std::str::from_utf8(s).unwrap_or_default());
+                    }
+                }
+            }
+            (2, Field::Bytes(group)) => groups.push(group),
+            (17, Field::Int(g)) => granularity = g as i64,
+            (19, Field::Int(o)) => lat_offset = o as i64,
+            (20, Field::Int(o)) => lon_offset = o as i64,
+            _ => {}
+        }
+    }
+    let degrees = |offset: i64, v: i64| (offset + granularity * v) as f64 * 1e-9;
+    let string = |i: u64| {
+        strings
+            .get(i as usize)
+            .copied()
+            .ok_or_else(|| ImportError::Malformed(format!("unknown string {i}")))
+    };
+
+    for group in groups {
+        for field in Proto(group) {
+            match field? {
+                (1, Field::Bytes(node)) => {
+                    let (mut id, mut lat, mut lon) = (0, 0, 0);
+                    for field in Proto(node) {
+                        match field? {
+                            (1, Field::Int(v)) => id = zigzag(v),
+                            (8, Field::Int(v)) => lat = zigzag(v),
+                            (9, Field::Int(v)) => lon = zigzag(v),
+                            _ => {}
+                        }
+                    }
+                    nodes.insert(id, (degrees(lat_offset, lat), degrees(lon_offset, lon)));
+                    stats.nodes += 1;
+                }
+                (2, Field::Bytes(dense)) => {
+                    let (mut ids, mut lats, mut lons) = (vec![], vec![], vec![]);
+                    for field in Proto(dense) {
+                        match field? {
+                            (1, v) => ids = v.packed()?,
+                            (8, v) => lats = v.packed()?,
+                            (9, v) => lons = v.packed()?,
+                            _ => {}
+                        }
+                    }
+                    if ids.len() != lats.len() || ids.len() != lons.len() {
+                        return Err(ImportError::Malformed("incomplete dense nodes".to_string()));
+                    }
+                    let (mut id, mut lat, mut lon) = (0, 0, 0);
+                    for ((did, dlat), dlon) in ids.into_iter().zip(lats).zip(lons) {
+                        id += zigzag(did);
+                        lat += zigzag(dlat);
+                        lon += zigzag(dlon);
+                        nodes.insert(id, (degrees(lat_offset, lat), degrees(lon_offset, lon)));
+                        stats.nodes += 1;
+                    }
+                }
+                (3, Field::Bytes(way)) => {
+                    let (mut keys, mut vals, mut refs) = (vec![], vec![], vec![]);
+                    for field in Proto(way) {
+                        match field? {
+                            (2, v) => keys.extend(v.packed()?),
+                            (3, v) => vals.extend(v.packed()?),
+                            (8, v) => refs.extend(v.packed()?),
+                            _ => {}
+                        }
+                    }
+                    let mut tags = BTreeMap::new();
+                    for (&k, &v) in keys.iter().zip(&vals) {
+                        tags.insert(string(k)?, string(v)?);
+                    }
+                    let mut node = 0;
+                    let way_nodes = refs
+                        .into_iter()
+                        .map(|delta| {
+                            node += zigzag(delta);
+                            node
+                        })
+                        .collect();
+                    ways.extend(road_way(way_nodes, &tags, stats));
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+fn truncated() -> ImportError {
+    ImportError::Malformed("truncated pbf".to_string())
+}
+
+fn zigzag(v: u64) -> i64 {
+    (v >> 1) as i64 ^ -((v & 1) as i64)
+}
+
+/// A field of a protobuf message, the integers are kept as encoded
+enum Field<'a> {
+    Int(u64),
+    Bytes(&'a [u8]),
+}
+
+impl Field<'_> {
+    /// The values of a repeated integer field, packed or not
+    fn packed(self) -> Result<Vec<u64>, ImportError> {
+        match self {
+            Field::Int(v) => Ok(vec![v]),
+            Field::Bytes(mut b) => {
+                let mut values = vec![];
+                while !b.is_empty() {
+                    values.push(varint(&mut b)?);
+                }
+                Ok(values)
+            }
+        }
+    }
+}
+
+fn varint(data: &mut &[u8]) -> Result<u64, ImportError> {
+    let mut v = 0;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = data.split_first().ok_or_else(truncated)?;
+        *data = rest;
+        v |= ((byte & 0x7F) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(v);
+        }
+    }
+    Err(ImportError::Malformed("varint too long".to_string()))
+}
+
+/// Iterates over the fields of a protobuf message as (field number, value)
+struct Proto<'a>(&'a [u8]);
+
+impl<'a> Proto<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], ImportError> {
+        if n > self.0.len() {
+            return Err(truncated());
+        }
+        let (taken, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn field(&mut self) -> Result<(u32, Field<'a>), ImportError> {
+        let key = varint(&mut self.0)?;
+        let value = match key & 7 {
+            0 => Field::Int(varint(&mut self.0)?),
+            1 => Field::Int(u64::from_le_bytes(self.take(8)?.try_into().unwrap())),
+            2 => {
+                let len = varint(&mut self.0)? as usize;
+                Field::Bytes(self.take(len)?)
+            }
+            5 => Field::Int(u32::from_le_bytes(self.take(4)?.try_into().unwrap()) as u64),
+            wire => {
+                return Err(ImportError::Malformed(format!(
+                    "unknown protobuf wire type {wire}"
+                )))
+            }
+        };
+        Ok(((key >> 3) as u32, value))
+    }
+}
+
+impl<'a> Iterator for Proto<'a> {
+    type Item = Result<(u32, Field<'a>), ImportError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let field = self.field();
+        if field.is_err() {
+            // nothing can be read after an error
+            self.0 = &[];
+        }
+        Some(field)
+    }
+}
+
+/// Equirectangular projection around the middle of the nodes, which is precise enough at the
+/// scale of a city. x points east and y north, the south-west corner is at [`IMPORT_MARGIN`].
+fn projection(nodes: impl Iterator<Item = (f64, f64)>) -> impl Fn(f64, f64) -> Vec2 {
+    let (mut min_lat, mut min_lon) = (f64::MAX, f64::MAX);
+    let (mut max_lat, mut max_lon) = (f64::MIN, f64::MIN);
+    for (lat, lon) in nodes {
+        min_lat = min_lat.min(lat);
+        min_lon = min_lon.min(lon);
+        max_lat = max_lat.max(lat);
+        max_lon = max_lon.max(lon);
+    }
+    let meters_per_deg = EARTH_RADIUS * std::f64::consts::PI / 180.0;
+    let lon_scale = ((min_lat + max_lat) * 0.5).to_radians().cos();
+
+    move |lat, lon| {
+        vec2(
+            ((lon - min_lon) * lon_scale * meters_per_deg) as f32,
+            ((lat - min_lat) * meters_per_deg) as f32,
+        ) + Vec2::splat(IMPORT_MARGIN)
+    }
+}
+
+struct XmlTag<'a> {
+    name: &'a str,
+    /// `</way>`, self closing tags like `<nd ref="1"/>` are not closing
+    closing: bool,
+    attrs: &'a str,
+}
+
+impl<'a> XmlTag<'a> {
+    fn attr(&self, key: &str) -> Result<&'a str, ImportError> {
+        let mut rest = self.attrs;
+        while let Some(eq) = rest.find('=') {
+            let k = rest[..eq].trim();
+            let after = rest[eq + 1..].trim_start();
+            let quote = after.chars().next().filter(|&c| c == '"' || c == '\'');
+            let Some(quote) = quote else {
+                break;
+            };
+            let Some(end) = after[1..].find(quote) else {
+                break;
+            };
+            if k == key {
+                return Ok(&after[1..end + 1]);
+            }
+            rest = &after[end + 2..];
+        }
+        Err(ImportError::Malformed(format!(
+            "<{}> without a valid {} attribute",
+            self.name, key
+        )))
+    }
+
+    fn parse<T: std::str::FromStr>(&self, key: &str) -> Result<T, ImportError> {
+        let v = self.attr(key)?;
+        v.parse().map_err(|_| {
+            ImportError::Malformed(format!("<{}> has an invalid {}: {}", self.name, key, v))
+        })
+    }
+}
+
+/// Minimal XML tokenizer yielding the tags, which is all OSM extracts are made of.
+/// Comments, processing instructions and text are skipped.
+struct XmlTags<'a>(&'a str);
+
+impl<'a> Iterator for XmlTags<'a> {
+    type Item = Result<XmlTag<'a>, ImportError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let start = self.0.find('<')?;
+            let rest = &self.0[start + 1..];
+
+            if let Some(comment) = rest.strip_prefix("!--") {
+                let Some(end) = comment.find("-->") else {
+                    return Some(Err(ImportError::Malformed("unclosed comment".to_string())));
+                };
+                self.0 = &comment[end + 3..];
+                continue;
+            }
+
+            let Some(end) = rest.find('>') else {
+                return Some(Err(ImportError::Malformed("unclosed tag".to_string())));
+            };
+            let inner = &rest[..end];
+            self.0 = &rest[end + 1..];
+
+            if inner.starts_with('?') || inner.starts_with('!') {
+                continue;
+            }
+
+            let closing = inner.starts_with('/');
+            let inner = inner.trim_start_matches('/').trim_end_matches('/');
+            let name_end = inner
+                .find(|c: char| c.is_ascii_whitespace())
+                .unwrap_or(inner.len());
+
+            return Some(Ok(XmlTag {
+                name: &inner[..name_end],
+                closing,
+                attrs: &inner[name_end..],
+            }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{import_osm, ImportError, ImportStats};
+    use crate::map::Map;
+
+    static EXTRACT: &str = include_str!("test_extract.osm");
+
+    #[test]
+    fn import_small_extract() {
+        let mut map = Map::empty();
+        let stats = import_osm(&mut map, EXTRACT.as_bytes()).unwrap();
+
+        assert_eq!(
+            stats,
+            ImportStats {
+                nodes: 8,
+                ways: 3,
+                intersections: 6,
+                roads: 5,
+                ignored_ways: 1,
+                skipped: 1,
+            }
+        );
+        assert_eq!(map.intersections().len(), 6);
+        assert_eq!(map.roads().len(), 5);
+
+        // the intersections are generated, so traffic can go through them
+        for inter in map.intersections().values() {
+            if inter.roads.len() >= 2 {
+                assert_ne!(inter.turns().len(), 0, "{:?} has no turns", inter.id);
+            }
+        }
+
+        // the one way street only has lanes going one way
+        let one_way = map
+            .roads()
+            .values()
+            .filter(|r| {
+                r.lanes_iter()
+                    .filter(|(_, kind)| kind.vehicles())
+                    .all(|(l, _)| map.lanes()[l].dst == r.dst)
+            })
+            .count();
+        assert_eq!(one_way, 1);
+    }
+
+    #[test]
+    fn bends_are_kept_and_lone_short_ways_leave_nothing() {
+        let extract = r#"<?xml version="1.0" encoding="UTF-8"?>
+<osm version="0.6">
+  <node id="1" lat="48.8500" lon="2.3500"/>
+  <node id="2" lat="48.8510" lon="2.3510"/>
+  <node id="3" lat="48.8500" lon="2.3520"/>
+  <node id="10" lat="48.8400" lon="2.3500"/>
+  <node id="11" lat="48.84002" lon="2.3500"/>
+  <way id="100">
+    <nd ref="1"/>
+    <nd ref="2"/>
+    <nd ref="3"/>
+    <tag k="highway" v="residential"/>
+  </way>
+  <way id="101">
+    <nd ref="10"/>
+    <nd ref="11"/>
+    <tag k="highway" v="residential"/>
+  </way>
+</osm>"#;
+        let mut map = Map::empty();
+        let stats = import_osm(&mut map, extract.as_bytes()).unwrap();
+
+        assert_eq!(stats.roads, 1);
+        assert_eq!(stats.skipped, 1);
+        assert_eq!(stats.intersections, 2);
+        assert_eq!(map.intersections().len(), 2);
+
+        // the road goes through the bend instead of taking the chord
+        let road = map.roads().values().next().unwrap();
+        let chord = map.intersections()[road.src]
+            .pos
+            .xy()
+            .distance(map.intersections()[road.dst].pos.xy());
+        assert!(road.length() > 1.5 * chord, "{} {}", road.length(), chord);
+    }
+
+    fn varint(out: &mut Vec<u8>, mut v: u64) {
+        while v >= 0x80 {
+            out.push(v as u8 | 0x80);
+            v >>= 7;
+        }
+        out.push(v as u8);
+    }
+
+    fn int_field(out: &mut Vec<u8>, field: u64, v: u64) {
+        varint(out, field << 3);
+        varint(out, v);
+    }
+
+    fn bytes_field(out: &mut Vec<u8>, field: u64, b: &[u8]) {
+        varint(out, field << 3 | 2);
+        varint(out, b.len() as u64);
+        out.extend_from_slice(b);
+    }
+
+    fn packed_field(out: &mut Vec<u8>, field: u64, values: impl IntoIterator<Item = u64>) {
+        let mut b = vec![];
+        for v in values {
+            varint(&mut b, v);
+        }
+        bytes_field(out, field, &b);
+    }
+
+    fn zigzag(v: i64) -> u64 {
+        ((v << 1) ^ (v >> 63)) as u64
+    }
+
+    fn blob(out: &mut Vec<u8>, kind: &str, blob: &[u8]) {
+        let mut header = vec![];
+        bytes_field(&mut header, 1, kind.as_bytes());
+        int_field(&mut header, 3, blob.len() as u64);
+        out.extend_from_slice(&(header.len() as u32).to_be_bytes());
+        out.extend_from_slice(&header);
+        out.extend_from_slice(blob);
+    }
+
+    /// A T junction: a street going through 1, 2 and 3 and a one way street from 4 to 2
+    fn small_pbf() -> Vec<u8> {
+        let mut header = vec![];
+        bytes_field(&mut header, 4, b"OsmSchema-V0.6");
+        bytes_field(&mut header, 4, b"DenseNodes");
+        let mut header_blob = vec![];
+        bytes_field(&mut header_blob, 1, &header);
+
+        let mut strings = vec![];
+        for s in ["", "highway", "residential", "oneway", "yes"] {
+            bytes_field(&mut strings, 1, s.as_bytes());
+        }
+
+        // in units of 100 nanodegrees
+        let nodes: [(i64, i64, i64); 4] = [
+            (1, 488500000, 23500000),
+            (2, 488500000, 23520000),
+            (3, 488500000, 23540000),
+            (4, 488520000, 23520000),
+        ];
+        let deltas = |f: fn(&(i64, i64, i64)) -> i64| {
+            let mut last = 0;
+            nodes
+                .iter()
+                .map(move |n| {
+                    let delta = f(n) - last;
+                    last = f(n);
+                    zigzag(delta)
+                })
+                .collect::<Vec<_>>()
+        };
+        let mut dense = vec![];
+        packed_field(&mut dense, 1, deltas(|n| n.0));
+        packed_field(&mut dense, 8, deltas(|n| n.1));
+        packed_field(&mut dense, 9, deltas(|n| n.2));
+
+        let mut through = vec![];
+        int_field(&mut through, 1, 100);
+        packed_field(&mut through, 2, [1]);
+        packed_field(&mut through, 3, [2]);
+        packed_field(&mut through, 8, [1, 1, 1].map(zigzag));
+
+        let mut oneway = vec![];
+        int_field(&mut oneway, 1, 101);
+        packed_field(&mut oneway, 2, [1, 3]);
+        packed_field(&mut oneway, 3, [2, 4]);
+        packed_field(&mut oneway, 8, [4, -2].map(zigzag));
+
+        let mut group = vec![];
+        bytes_field(&mut group, 2, &dense);
+        bytes_field(&mut group, 3, &through);
+        bytes_field(&mut group, 3, &oneway);
+
+        let mut block = vec![];
+        bytes_field(&mut block, 1, &strings);
+        bytes_field(&mut block, 2, &group);
+        let mut data_blob = vec![];
+        bytes_field(
+            &mut data_blob,
+            3,
+            &miniz_oxide::deflate::compress_to_vec_zlib(&block, 6),
+        );
+
+        let mut pbf = vec![];
+        blob(&mut pbf, "OSMHeader", &header_blob);
+        blob(&mut pbf, "OSMData", &data_blob);
+        pbf
+    }
+
+    #[test]
+    fn import_pbf() {
+        let mut map = Map::empty();
+        let stats = import_osm(&mut map, &*small_pbf()).unwrap();
+
+        assert_eq!(
+            stats,
+            ImportStats {
+                nodes: 4,
+                ways: 2,
+                intersections: 4,
+                roads: 3,
+                ignored_ways: 0,
+                skipped: 0,
+            }
+        );
+
+        let junction = map
+            .intersections()
+            .values()
+            .find(|i| i.roads.len() == 3)
+            .unwrap();
+        assert_ne!(junction.turns().len(), 0);
+
+        // the one way street only goes toward the junction
+        let oneway = map
+            .roads()
+            .values()
+            .find(|r| {
+                r.lanes_iter()
+                    .filter(|(_, kind)| kind.vehicles())
+                    .all(|(l, _)| map.lanes()[l].dst == r.dst)
+            })
+            .unwrap();
+        assert_eq!(oneway.dst, junction.id);
+    }
+
+    #[test]
+    fn truncated_pbf_is_malformed() {
+        let mut map = Map::empty();
+        let pbf = small_pbf();
+        assert!(matches!(
+            import_osm(&mut map, &pbf[..pbf.len() - 10]),
+            Err(ImportError::Malformed(_))
+        ));
+        assert!(map.roads().is_empty());
+    }
+
+    #[test]
+    fn unknown_format_is_rejected() {
+        let mut map = Map::empty();
+        let mut not_osm = vec![];
+        blob(&mut not_osm, "NotOSM", b"");
+        assert!(matches!(
+            import_osm(&mut map, &*not_osm),
+            Err(ImportError::UnsupportedFormat)
+        ));
+        assert!(map.roads().is_empty());
+    }
+}
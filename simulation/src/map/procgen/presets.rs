@@ -1,9 +1,10 @@
 #![allow(clippy::indexing_slicing)]
 
-use crate::map::{IntersectionID, LanePatternBuilder, Map, RoadSegmentKind};
+use crate::map::{IntersectionID, LanePatternBuilder, Map, RoadSegmentKind, TurnRestriction};
 use common::FastMap;
 use flat_spatial::Grid;
 use geom::{vec2, vec3, Vec2};
+use std::collections::BTreeMap;
 use std::io::{BufRead, Cursor};
 
 struct Scanner<T> {
@@ -22,12 +23,19 @@ impl<T> Scanner<T> {
 
 impl<R: BufRead> Scanner<R> {
     fn next<T: std::str::FromStr>(&mut self) -> T {
+        self.try_next().expect("Failed read")
+    }
+
+    /// None once the end of the input is reached
+    fn try_next<T: std::str::FromStr>(&mut self) -> Option<T> {
         loop {
             if let Some(token) = self.buffer.pop() {
-                return token.parse().ok().expect("Failed parse");
+                return Some(token.parse().ok().expect("Failed parse"));
             }
             let mut input = String::new();
-            self.reader.read_line(&mut input).expect("Failed read");
+            if self.reader.read_line(&mut input).expect("Failed read") == 0 {
+                return None;
+            }
             self.buffer = input.split_whitespace().rev().map(String::from).collect();
         }
     }
@@ -35,7 +43,36 @@ impl<R: BufRead> Scanner<R> {
 
 static PARISMAP_STR: &str = include_str!("../../../../assets/paris_54000.txt");
 
+/// A turn restriction expressed with the node indices of the source data:
+/// vehicles coming from `from` through `via` cannot continue to `to`.
+#[derive(Debug, Copy, Clone)]
+pub struct SourceTurnRestriction {
+    pub from: usize,
+    pub via: usize,
+    pub to: usize,
+}
+
+/// Reads the optional section of turn restrictions following the roads: their count then
+/// `from via to` for each of them
+fn read_source_restrictions(scanner: &mut Scanner<impl BufRead>) -> Vec<SourceTurnRestriction> {
+    let Some(n_restrictions) = scanner.try_next::<usize>() else {
+        return vec![];
+    };
+    (0..n_restrictions)
+        .map(|_| SourceTurnRestriction {
+            from: scanner.next(),
+            via: scanner.next(),
+            to: scanner.next(),
+        })
+        .collect()
+}
+
 pub fn load_parismap(map: &mut Map) {
+    load_parismap_with_restrictions(map, &[]);
+}
+
+/// Loads the map with the turn restrictions of the source data and the `extra` ones
+pub fn load_parismap_with_restrictions(map: &mut Map, extra: &[SourceTurnRestriction]) {
     let time = std::time::Instant::now();
 
     let mut scanner = Scanner::new(Cursor::new(PARISMAP_STR));
@@ -126,6 +163,10 @@ pub fn load_parismap(map: &mut Map) {
         .unwrap();
     }
 
+    let mut restrictions = read_source_restrictions(&mut scanner);
+    restrictions.extend_from_slice(extra);
+    apply_source_restrictions(map, &ids, &restrictions);
+
     info!(
         "loading parismap took {}ms",
         time.elapsed().as_secs_f32() * 1000.0
@@ -136,6 +177,35 @@ pub fn load_parismap(map: &mut Map) {
     print_stats(map);
 }
 
+fn apply_source_restrictions(
+    map: &mut Map,
+    ids: &[IntersectionID],
+    restrictions: &[SourceTurnRestriction],
+) {
+    let mut per_inter: BTreeMap<IntersectionID, Vec<TurnRestriction>> = BTreeMap::new();
+
+    for r in restrictions {
+        let (Some(&from), Some(&via), Some(&to)) = (ids.get(r.from), ids.get(r.via), ids.get(r.to))
+        else {
+            log::warn!("ignoring turn restriction {:?}: unknown node", r);
+            continue;
+        };
+        let road_between = |a, b| map.find_road(a, b).or_else(|| map.find_road(b, a));
+        let (Some(from), Some(to)) = (road_between(from, via), road_between(via, to)) else {
+            log::warn!("ignoring turn restriction {:?}: no such road", r);
+            continue;
+        };
+        per_inter
+            .entry(via)
+            .or_default()
+            .push(TurnRestriction { from, to });
+    }
+
+    for (inter, restrictions) in per_inter {
+        map.set_turn_restrictions(inter, restrictions);
+    }
+}
+
 pub fn add_doublecircle(pos: Vec2, m: &mut Map) {
     let mut first_circle = vec![];
     let mut second_circle = vec![];
@@ -259,4 +329,14 @@ mod tests {
         load_parismap(&mut m);
         m.check_invariants();
     }
+
+    #[test]
+    fn source_restrictions_are_optional() {
+        let read = |s: &str| read_source_restrictions(&mut Scanner::new(Cursor::new(s)));
+
+        assert!(read("").is_empty());
+        let r = read("2\n1 2 3\n4 5 6\n");
+        assert_eq!(r.len(), 2);
+        assert_eq!((r[1].from, r[1].via, r[1].to), (4, 5, 6));
+    }
 }
@@ -0,0 +1,157 @@
+use crate::map::{IntersectionID, LaneID, Map, RoadID, TurnID};
+
+/// An inconsistency between the map's objects, as found by [`Map::validate`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MapIssue {
+    /// The lane's parent road doesn't exist
+    DanglingLane(LaneID),
+    /// The turn goes from or to a lane that doesn't exist
+    DanglingTurn(TurnID),
+    /// The intersection lists a road that doesn't exist
+    MissingRoad(IntersectionID, RoadID),
+    /// The intersection has no roads
+    EmptyIntersection(IntersectionID),
+}
+
+/// What was fixed by [`Map::repair`]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RepairReport {
+    pub removed_lanes: Vec<LaneID>,
+    pub removed_turns: Vec<TurnID>,
+    pub pruned_roads: Vec<(IntersectionID, RoadID)>,
+    pub removed_intersections: Vec<IntersectionID>,
+}
+
+impl RepairReport {
+    pub fn is_empty(&self) -> bool {
+        self.removed_lanes.is_empty()
+            && self.removed_turns.is_empty()
+            && self.pruned_roads.is_empty()
+            && self.removed_intersections.is_empty()
+    }
+}
+
+impl Map {
+    /// Lists the inconsistencies of the map without panicking, unlike `check_invariants`
+    pub fn validate(&self) -> Vec<MapIssue> {
+        let mut issues = vec![];
+
+        for lane in self.lanes.values() {
+            if !self.roads.contains_key(lane.parent) {
+                issues.push(MapIssue::DanglingLane(lane.id));
+            }
+        }
+
+        for inter in self.intersections.values() {
+            for &road in &inter.roads {
+                if !self.roads.contains_key(road) {
+                    issues.push(MapIssue::MissingRoad(inter.id, road));
+                }
+            }
+
+            for turn in inter.turns() {
+                if !self.lanes.contains_key(turn.id.src) || !self.lanes.contains_key(turn.id.dst) {
+                    issues.push(MapIssue::DanglingTurn(turn.id));
+                }
+            }
+
+            if inter.roads.is_empty() {
+                issues.push(MapIssue::EmptyIntersection(inter.id));
+            }
+        }
+
+        issues
+    }
+
+    /// Fixes the issues found by [`Map::validate`]. Running it again on a repaired map does nothing.
+    pub fn repair(&mut self) -> RepairReport {
+        let mut report = RepairReport::default();
+
+        let roads = &self.roads;
+        self.lanes.retain(|id, lane| {
+            let keep = roads.contains_key(lane.parent);
+            if !keep {
+                report.removed_lanes.push(id);
+            }
+            keep
+        });
+        for &lane in &report.removed_lanes {
+            self.parking.remove_spots(lane);
+        }
+
+        let lanes = &self.lanes;
+        for inter in self.intersections.values_mut() {
+            let id = inter.id;
+            inter.roads.retain(|&road| {
+                let keep = roads.contains_key(road);
+                if !keep {
+                    report.pruned_roads.push((id, road));
+                }
+                keep
+            });
+
+            report
+                .removed_turns
+                .extend(inter.retain_turns(|t| {
+                    lanes.contains_key(t.id.src) && lanes.contains_key(t.id.dst)
+                }));
+        }
+
+        let empty: Vec<_> = self
+            .intersections
+            .values()
+            .filter(|inter| inter.roads.is_empty())
+            .map(|inter| inter.id)
+            .collect();
+        for id in empty {
+            self.remove_intersection_inner(id);
+            report.removed_intersections.push(id);
+        }
+
+        if !report.is_empty() {
+            self.version += 1;
+            log::info!("repaired map: {:?}", report);
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::map::{LanePatternBuilder, Map, RoadSegmentKind};
+    use geom::vec3;
+
+    #[test]
+    fn repair_corrupted_map() {
+        let mut map = Map::empty();
+        let pat = LanePatternBuilder::new().build();
+
+        let a = map.add_intersection(vec3(0.0, 0.0, 0.3));
+        let b = map.add_intersection(vec3(100.0, 0.0, 0.3));
+        let c = map.add_intersection(vec3(100.0, 100.0, 0.3));
+
+        let ab = map.connect(a, b, &pat, RoadSegmentKind::Straight).unwrap();
+        map.connect(b, c, &pat, RoadSegmentKind::Straight).unwrap();
+
+        assert!(map.validate().is_empty());
+
+        // remove the road without cleaning up anything that references it
+        let n_lanes = map.roads[ab].lanes_iter().count();
+        map.roads.remove(ab);
+
+        assert!(!map.validate().is_empty());
+
+        let report = map.repair();
+        assert_eq!(report.removed_lanes.len(), n_lanes);
+        assert!(!report.removed_turns.is_empty());
+        assert!(report.pruned_roads.contains(&(a, ab)));
+        assert!(report.pruned_roads.contains(&(b, ab)));
+        assert_eq!(report.removed_intersections, vec![a]);
+
+        assert!(map.validate().is_empty());
+        assert!(map.intersections.contains_key(b));
+
+        assert!(map.repair().is_empty());
+    }
+}
@@ -1,9 +1,14 @@
-use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use slotmapd::{HopSlotMap, Key};
+use std::marker::PhantomData;
 
 use crate::map::{
     BuildingID, Buildings, ElectricityCache, Environment, Intersections, Lanes, Lots, Map,
-    ParkingSpots, Roads, SpatialMap,
+    ParkingSpots, ProjectKind, Roads, SpatialMap, SpatialMapObject, VerticalConnectors,
 };
+use common::saveload::{Bincode, Encoder};
+use geom::ShapeEnum;
 
 #[derive(Default, Serialize, Deserialize)]
 pub(crate) struct SerializedMap {
@@ -15,6 +20,11 @@ pub(crate) struct SerializedMap {
     pub lots: Lots,
     pub environment: Environment,
     pub external_train_stations: Vec<BuildingID>,
+    #[serde(default)]
+    pub vertical_connectors: VerticalConnectors,
+    /// Saved so that caches saved along the map still match it once loaded
+    #[serde(default)]
+    pub version: u64,
 }
 
 impl From<&Map> for SerializedMap {
@@ -28,6 +38,8 @@ impl From<&Map> for SerializedMap {
             lots: m.lots.clone(),
             environment: m.environment.clone(),
             external_train_stations: m.external_train_stations.clone(),
+            vertical_connectors: m.vertical_connectors.clone(),
+            version: m.version,
         }
     }
 }
@@ -45,26 +57,292 @@ impl From<SerializedMap> for Map {
             parking: sel.parking,
             environment: sel.environment,
             external_train_stations: sel.external_train_stations,
+            vertical_connectors: sel.vertical_connectors,
+            version: sel.version,
             ..Self::empty()
         };
         m.electricity = ElectricityCache::build(&m);
+        rebuild_bkinds_cache(&mut m);
         m
     }
 }
 
-fn mk_spatial_map(m: &SerializedMap) -> SpatialMap {
-    let mut sm = SpatialMap::default();
-    for b in m.buildings.values() {
-        sm.insert(b);
+/// The per-kind building lists are derived from the buildings themselves, make sure they agree
+/// with what was loaded while keeping the saved order.
+fn rebuild_bkinds_cache(m: &mut Map) {
+    let buildings = &m.buildings;
+    m.external_train_stations.retain(|&id| {
+        buildings
+            .get(id)
+            .map_or(false, |b| b.kind.is_cached_in_bkinds())
+    });
+    for (id, b) in buildings.iter() {
+        if b.kind.is_cached_in_bkinds() && !m.external_train_stations.contains(&id) {
+            m.external_train_stations.push(id);
+        }
+    }
+}
+
+/// Converts the values of a slotmap keeping their keys. A key can't be picked on insert so this
+/// goes through the encoded slotmap, which is laid out the same whatever the values are.
+fn convert_slots<K, A, B>(slots: &HopSlotMap<K, A>) -> HopSlotMap<K, B>
+where
+    K: Key,
+    A: Clone + Serialize + DeserializeOwned,
+    B: From<A> + Serialize + DeserializeOwned,
+{
+    /// Read as `A` and written as `B`
+    struct Convert<A, B>(A, PhantomData<B>);
+
+    impl<'de, A: Deserialize<'de>, B> Deserialize<'de> for Convert<A, B> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            A::deserialize(deserializer).map(|a| Convert(a, PhantomData))
+        }
+    }
+
+    impl<A: Clone, B: From<A> + Serialize> Serialize for Convert<A, B> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            B::from(self.0.clone()).serialize(serializer)
+        }
+    }
+
+    let converted: HopSlotMap<K, Convert<A, B>> =
+        Bincode::decode(&Bincode::encode(slots).expect("could not encode slots"))
+            .expect("could not decode the slots just encoded");
+    Bincode::decode(&Bincode::encode(&converted).expect("could not encode converted slots"))
+        .expect("could not decode the slots just converted")
+}
+
+/// The map as saved before saves were versioned, read from the layout of the objects back then.
+/// Bincode is positional so every object that gained a field since needs its old layout here.
+pub(crate) mod v0 {
+    use super::convert_slots;
+    use crate::map::procgen::ColoredMesh;
+    use crate::map::{
+        BuildingID, BuildingKind, Environment, IntersectionID, LaneID, LaneKind, LightPolicy, Lots,
+        ParkingSpots, RoadID, RoundaboutPolicy, TrafficControl, Turn, Zone,
+    };
+    use geom::{PolyLine3, Vec3, OBB};
+    use serde::{Deserialize, Serialize};
+    use slotmapd::HopSlotMap;
+    use std::collections::BTreeSet;
+
+    /// Unknown fields are denied so that json maps already in the current layout aren't read as this
+    #[derive(Serialize, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub(crate) struct SerializedMap {
+        pub roads: HopSlotMap<RoadID, Road>,
+        pub intersections: HopSlotMap<IntersectionID, Intersection>,
+        pub buildings: HopSlotMap<BuildingID, Building>,
+        pub lanes: HopSlotMap<LaneID, Lane>,
+        pub parking: ParkingSpots,
+        pub lots: Lots,
+        pub environment: Environment,
+        pub external_train_stations: Vec<BuildingID>,
+    }
+
+    #[derive(Clone, Serialize, Deserialize)]
+    pub(crate) struct Road {
+        pub id: RoadID,
+        pub src: IntersectionID,
+        pub dst: IntersectionID,
+        pub points: PolyLine3,
+        pub interfaced_points: PolyLine3,
+        pub width: f32,
+        pub connected_buildings: Vec<BuildingID>,
+        pub src_interface: f32,
+        pub dst_interface: f32,
+        pub lanes_forward: Vec<(LaneID, LaneKind)>,
+        pub lanes_backward: Vec<(LaneID, LaneKind)>,
+    }
+
+    #[derive(Clone, Serialize, Deserialize)]
+    pub(crate) struct Intersection {
+        pub id: IntersectionID,
+        pub pos: Vec3,
+        pub radius: f32,
+        pub turns: BTreeSet<Turn>,
+        pub roads: Vec<RoadID>,
+        pub turn_policy: TurnPolicy,
+        pub light_policy: LightPolicy,
+    }
+
+    #[derive(Copy, Clone, Serialize, Deserialize)]
+    pub(crate) struct TurnPolicy {
+        pub back_turns: bool,
+        pub left_turns: bool,
+        pub crosswalks: bool,
+        pub roundabout: Option<RoundaboutPolicy>,
+    }
+
+    #[derive(Clone, Serialize, Deserialize)]
+    pub(crate) struct Lane {
+        pub id: LaneID,
+        pub parent: RoadID,
+        pub src: IntersectionID,
+        pub dst: IntersectionID,
+        pub kind: LaneKind,
+        pub control: TrafficControl,
+        #[serde(default)]
+        pub speed_limit: f32,
+        pub points: PolyLine3,
+        pub dist_from_bottom: f32,
+    }
+
+    #[derive(Clone, Serialize, Deserialize)]
+    pub(crate) struct Building {
+        pub id: BuildingID,
+        pub door_pos: Vec3,
+        pub kind: BuildingKind,
+        pub mesh: ColoredMesh,
+        pub obb: OBB,
+        pub height: f32,
+        pub zone: Option<Zone>,
+        pub connected_road: Option<RoadID>,
     }
-    for r in m.roads.values() {
-        sm.insert(r);
+
+    impl From<SerializedMap> for super::SerializedMap {
+        fn from(m: SerializedMap) -> Self {
+            Self {
+                roads: convert_slots(&m.roads),
+                intersections: convert_slots(&m.intersections),
+                buildings: convert_slots(&m.buildings),
+                lanes: convert_slots(&m.lanes),
+                parking: m.parking,
+                lots: m.lots,
+                environment: m.environment,
+                external_train_stations: m.external_train_stations,
+                vertical_connectors: Default::default(),
+                version: 0,
+            }
+        }
     }
-    for i in m.intersections.values() {
-        sm.insert(i);
+
+    impl From<TurnPolicy> for crate::map::TurnPolicy {
+        fn from(p: TurnPolicy) -> Self {
+            Self {
+                back_turns: p.back_turns,
+                left_turns: p.left_turns,
+                protected_left_only: false,
+                crosswalks: p.crosswalks,
+                roundabout: p.roundabout,
+                walking_corner_radius: Self::DEFAULT_WALKING_CORNER_RADIUS,
+            }
+        }
+    }
+
+    impl From<Lane> for crate::map::Lane {
+        fn from(l: Lane) -> Self {
+            Self {
+                id: l.id,
+                parent: l.parent,
+                src: l.src,
+                dst: l.dst,
+                kind: l.kind,
+                control: l.control,
+                // missing from saves from before lanes had one
+                speed_limit: match l.speed_limit {
+                    limit if limit > 0.0 => limit,
+                    _ => l.kind.default_speed_limit(),
+                },
+                width: l.kind.width(),
+                reversed: false,
+                traffic: Default::default(),
+                parking_style: Default::default(),
+                points: l.points,
+                dist_from_bottom: l.dist_from_bottom,
+            }
+        }
     }
-    for l in m.lots.values() {
-        sm.insert(l);
+
+    impl From<Building> for crate::map::Building {
+        fn from(b: Building) -> Self {
+            Self {
+                id: b.id,
+                door_pos: b.door_pos,
+                kind: b.kind,
+                mesh: b.mesh,
+                obb: b.obb,
+                height: b.height,
+                zone: b.zone,
+                connected_road: b.connected_road,
+                door_offset: 0,
+            }
+        }
+    }
+}
+
+fn mk_spatial_map(m: &SerializedMap) -> SpatialMap {
+    fn item(obj: &impl SpatialMapObject) -> (ProjectKind, ShapeEnum) {
+        (obj.kind(), obj.shape())
+    }
+
+    SpatialMap::from_items(
+        m.buildings
+            .values()
+            .map(item)
+            .chain(m.roads.values().map(item))
+            .chain(m.intersections.values().map(item))
+            .chain(m.lots.values().map(item)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::v0;
+    use crate::map::{Lane, LaneKind, LanePatternBuilder, MapBuilder};
+    use geom::vec2;
+
+    #[test]
+    fn old_lanes_get_default_speed_limit() {
+        let pat = LanePatternBuilder::new().speed_limit(20.0).build();
+        let mut b = MapBuilder::new();
+        let a = b.add_inter(vec2(0.0, 0.0));
+        let end = b.add_inter(vec2(100.0, 0.0));
+        b.connect(a, end, &pat).unwrap();
+        let map = b.build();
+
+        // only the driving lanes take the limit of the road
+        for lane in map.lanes().values() {
+            match lane.kind {
+                LaneKind::Driving => assert_eq!(lane.speed_limit, 20.0),
+                kind => assert_eq!(lane.speed_limit, kind.default_speed_limit()),
+            }
+        }
+
+        let old_lanes: Vec<serde_json::Value> = map
+            .lanes()
+            .values()
+            .map(|l| {
+                serde_json::to_value(v0::Lane {
+                    id: l.id,
+                    parent: l.parent,
+                    src: l.src,
+                    dst: l.dst,
+                    kind: l.kind,
+                    control: l.control,
+                    speed_limit: l.speed_limit,
+                    points: l.points.clone(),
+                    dist_from_bottom: l.dist_from_bottom,
+                })
+                .unwrap()
+            })
+            .collect();
+        let migrate =
+            |v: serde_json::Value| Lane::from(serde_json::from_value::<v0::Lane>(v).unwrap());
+
+        // saved limits are kept
+        for old in &old_lanes {
+            let migrated = migrate(old.clone());
+            assert_eq!(migrated.speed_limit, map.lanes()[migrated.id].speed_limit);
+            assert_eq!(migrated.width, migrated.kind.width());
+        }
+
+        // lanes saved without one get the default of their kind
+        for mut old in old_lanes {
+            old.as_object_mut().unwrap().remove("speed_limit");
+            let migrated = migrate(old);
+            assert_eq!(migrated.speed_limit, migrated.kind.default_speed_limit());
+        }
     }
-    sm
 }
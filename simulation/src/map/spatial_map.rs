@@ -77,6 +77,9 @@ pub trait SpatialMapObject {
     fn shape(&self) -> ShapeEnum;
 }
 
+/// Side of a cell of the broad phase grid, in meters
+pub const SPATIAL_CELL_SIZE: i32 = 50;
+
 pub struct SpatialMap {
     broad: AABBGrid<ProjectKind, AABB>,
     near: BTreeMap<ProjectKind, ShapeEnum>,
@@ -86,7 +89,7 @@ pub struct SpatialMap {
 impl Default for SpatialMap {
     fn default() -> Self {
         Self {
-            broad: AABBGrid::new(50),
+            broad: AABBGrid::new(SPATIAL_CELL_SIZE),
             near: Default::default(),
             ids: Default::default(),
         }
@@ -94,6 +97,20 @@ impl Default for SpatialMap {
 }
 
 impl SpatialMap {
+    /// Builds the index in a single pass from a batch of (kind, shape) items.
+    /// If a kind appears multiple times, the last shape wins, same as repeated [`Self::insert`].
+    pub fn from_items(items: impl IntoIterator<Item = (ProjectKind, ShapeEnum)>) -> Self {
+        let near: BTreeMap<ProjectKind, ShapeEnum> = items.into_iter().collect();
+
+        let mut broad = AABBGrid::new(SPATIAL_CELL_SIZE);
+        let ids = near
+            .iter()
+            .map(|(&kind, shape)| (kind, broad.insert(shape.bbox(), kind)))
+            .collect();
+
+        Self { broad, near, ids }
+    }
+
     pub fn insert(&mut self, obj: &impl SpatialMapObject) {
         let kind = obj.kind();
         let shape = obj.shape();
@@ -158,6 +175,27 @@ impl SpatialMap {
             .map(|obj| obj.aabb)
     }
 
+    /// Every non-empty cell of the grid with the number of objects overlapping it
+    pub fn cell_occupancy(&self) -> impl Iterator<Item = (AABB, usize)> {
+        let cell = SPATIAL_CELL_SIZE as f32;
+        let mut counts: BTreeMap<(i32, i32), usize> = BTreeMap::new();
+        for aabb in self.debug_grid() {
+            let x0 = (aabb.ll.x / cell).floor() as i32;
+            let y0 = (aabb.ll.y / cell).floor() as i32;
+            let x1 = (aabb.ur.x / cell).floor() as i32;
+            let y1 = (aabb.ur.y / cell).floor() as i32;
+            for x in x0..=x1 {
+                for y in y0..=y1 {
+                    *counts.entry((x, y)).or_default() += 1;
+                }
+            }
+        }
+        counts.into_iter().map(move |((x, y), n)| {
+            let ll = Vec2::new(x as f32 * cell, y as f32 * cell);
+            (AABB::new_ll_size(ll, Vec2::splat(cell)), n)
+        })
+    }
+
     pub fn contains<T: Into<ProjectKind>>(&self, p: T) -> bool {
         let kind = p.into();
 
@@ -260,3 +298,98 @@ impl Neg for ProjectFilter {
         Self(!self.0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use slotmapd::SlotMap;
+
+    struct TestObj(ProjectKind, Circle);
+
+    impl SpatialMapObject for TestObj {
+        fn kind(&self) -> ProjectKind {
+            self.0
+        }
+
+        fn shape(&self) -> ShapeEnum {
+            self.1.into()
+        }
+    }
+
+    #[test]
+    fn from_items_matches_incremental() {
+        let mut keys = SlotMap::<IntersectionID, ()>::with_key();
+        let mut objs = vec![];
+        for i in 0..200 {
+            let kind = ProjectKind::Intersection(keys.insert(()));
+            let center = Vec2::new((i * 37 % 500) as f32, (i * 91 % 500) as f32);
+            objs.push(TestObj(kind, Circle::new(center, 5.0 + (i % 7) as f32)));
+        }
+        // same kind inserted twice, the last one should win
+        let dup = objs[0].0;
+        objs.push(TestObj(dup, Circle::new(Vec2::new(1000.0, 1000.0), 3.0)));
+
+        let batched = SpatialMap::from_items(objs.iter().map(|o| (o.kind(), o.shape())));
+
+        let mut incremental = SpatialMap::default();
+        for o in &objs {
+            incremental.insert(o);
+        }
+
+        let mut a = batched.objects().copied().collect::<Vec<_>>();
+        let mut b = incremental.objects().copied().collect::<Vec<_>>();
+        a.sort();
+        b.sort();
+        assert_eq!(a, b);
+
+        for x in (0..1100).step_by(50) {
+            for y in (0..1100).step_by(50) {
+                let center = Vec2::new(x as f32, y as f32);
+                let mut a = batched
+                    .query_around(center, 30.0, ProjectFilter::ALL)
+                    .collect::<Vec<_>>();
+                let mut b = incremental
+                    .query_around(center, 30.0, ProjectFilter::ALL)
+                    .collect::<Vec<_>>();
+                a.sort();
+                b.sort();
+                assert_eq!(a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn cell_occupancy_finds_clusters() {
+        assert_eq!(SpatialMap::default().cell_occupancy().count(), 0);
+
+        let mut keys = SlotMap::<IntersectionID, ()>::with_key();
+        let mut map = SpatialMap::default();
+        // a dense cluster in a single cell
+        for i in 0..20 {
+            let center = Vec2::new(120.0 + (i % 5) as f32, 120.0 + (i / 5) as f32);
+            map.insert(&TestObj(
+                ProjectKind::Intersection(keys.insert(())),
+                Circle::new(center, 1.0),
+            ));
+        }
+        // sparse objects elsewhere
+        for i in 0..5 {
+            let center = Vec2::new(500.0 + i as f32 * 100.0, 500.0);
+            map.insert(&TestObj(
+                ProjectKind::Intersection(keys.insert(())),
+                Circle::new(center + Vec2::splat(25.0), 1.0),
+            ));
+        }
+
+        let occupancy = map.cell_occupancy().collect::<Vec<_>>();
+        assert_eq!(occupancy.len(), 6);
+        let (hottest, n) = occupancy.iter().max_by_key(|(_, n)| *n).unwrap();
+        assert_eq!(*n, 20);
+        assert!(hottest.contains(Vec2::new(122.0, 122.0)));
+        for (aabb, n) in &occupancy {
+            if aabb != hottest {
+                assert_eq!(*n, 1);
+            }
+        }
+    }
+}
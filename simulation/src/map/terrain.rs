@@ -21,6 +21,9 @@ pub(super) const CELL_SIZE: f32 = TerrainChunkID::SIZE_F32 / TERRAIN_CHUNK_RESOL
 
 const TREE_GRID_SIZE: usize = 256;
 
+/// Steepest slope (height difference over horizontal distance) on which things can be built
+pub const MAX_BUILDABLE_SLOPE: f32 = 0.3;
+
 pub type Chunk = geom::HeightmapChunk<TERRAIN_CHUNK_RESOLUTION, { TerrainChunkID::SIZE }>;
 pub type Heightmap = geom::Heightmap<TERRAIN_CHUNK_RESOLUTION, { TerrainChunkID::SIZE }>;
 
@@ -88,6 +91,39 @@ impl Environment {
         self.heightmap.height(pos)
     }
 
+    /// Returns the normal of the terrain at the given position, from the heights of the neighboring cells.
+    /// Returns None if the position is too close to the edge of the terrain to have all its neighbors.
+    pub fn normal(&self, pos: Vec2) -> Option<Vec3> {
+        let bounds = self.bounds();
+        let sample = |off: Vec2| {
+            let p = pos + off;
+            if !bounds.contains(p) {
+                return None;
+            }
+            self.true_height(p)
+        };
+
+        let left = sample(Vec2::x(-CELL_SIZE))?;
+        let right = sample(Vec2::x(CELL_SIZE))?;
+        let down = sample(Vec2::y(-CELL_SIZE))?;
+        let up = sample(Vec2::y(CELL_SIZE))?;
+
+        let dx = (right - left) / (2.0 * CELL_SIZE);
+        let dy = (up - down) / (2.0 * CELL_SIZE);
+
+        Some(Vec3::new(-dx, -dy, 1.0).normalize())
+    }
+
+    /// Whether roads can be built at the given position, positions under water or on steep slopes are not buildable.
+    /// Positions outside of the terrain are considered buildable like everywhere else in the map.
+    pub fn is_buildable(&self, pos: Vec2) -> bool {
+        if self.true_height(pos).map_or(false, |h| h < 0.0) {
+            return false;
+        }
+        self.normal(pos)
+            .map_or(true, |n| n.xy().mag() <= MAX_BUILDABLE_SLOPE * n.z)
+    }
+
     pub fn remove_trees_near(
         &mut self,
         obj: impl Intersect<Vec2>,
@@ -398,3 +434,38 @@ impl From<&Environment> for SerializedEnvironment {
         t
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Environment, Heightmap, TREE_GRID_SIZE};
+    use flat_spatial::Grid;
+    use geom::{vec2, Vec3};
+
+    fn flat_env() -> Environment {
+        Environment {
+            heightmap: Heightmap::new(1, 1),
+            trees: Grid::new(TREE_GRID_SIZE as i32),
+        }
+    }
+
+    #[test]
+    fn normal_follows_slope() {
+        let mut env = flat_env();
+
+        let n = env.normal(vec2(256.0, 256.0)).unwrap();
+        assert!(n.distance(Vec3::Z) < 1e-4, "{:?}", n);
+        assert!(env.is_buildable(vec2(256.0, 256.0)));
+
+        // edges don't have all their neighbors
+        assert!(env.normal(vec2(1.0, 256.0)).is_none());
+        assert!(env.normal(vec2(256.0, 511.0)).is_none());
+
+        // rises towards +x
+        env.terrain_apply(env.bounds(), |p| p.x * 0.5);
+
+        let n = env.normal(vec2(256.0, 256.0)).unwrap();
+        let expected = Vec3::new(-0.5, 0.0, 1.0).normalize();
+        assert!(n.distance(expected) < 1e-3, "{:?}", n);
+        assert!(!env.is_buildable(vec2(256.0, 256.0)));
+    }
+}
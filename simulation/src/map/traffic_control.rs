@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TrafficBehavior {
     RED,
     ORANGE,
@@ -33,6 +33,24 @@ impl TrafficLightSchedule {
             offset,
         }
     }
+
+    /// Where the light is in its period, the period starting with green
+    pub(crate) fn position(&self, seconds: u32) -> u16 {
+        ((seconds % self.period as u32) as u16 + self.offset) % self.period
+    }
+
+    /// Seconds before the light changes color
+    pub fn remaining(&self, seconds: u32) -> u32 {
+        let pos = self.position(seconds);
+        let next_change = if pos < self.green {
+            self.green
+        } else if pos < self.green + self.orange {
+            self.green + self.orange
+        } else {
+            self.period
+        };
+        (next_change - pos) as u32
+    }
 }
 
 #[derive(Copy, Clone, Debug, Serialize, Deserialize)]
@@ -59,8 +77,7 @@ impl TrafficControl {
         match self {
             TrafficControl::Always => TrafficBehavior::GREEN,
             TrafficControl::Light(schedule) => {
-                let remainder =
-                    ((seconds % schedule.period as u32) as u16 + schedule.offset) % schedule.period;
+                let remainder = schedule.position(seconds);
                 if remainder < schedule.green {
                     TrafficBehavior::GREEN
                 } else if remainder < schedule.green + schedule.orange {
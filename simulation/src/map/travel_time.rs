@@ -0,0 +1,192 @@
+use crate::map::{IntersectionID, LaneKind, Map, MapSubscriber};
+use ordered_float::OrderedFloat;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// Driving travel times (in seconds) from one intersection to all the ones reachable from it.
+/// Used for logistics queries that would otherwise run an A* each time.
+/// It is an intersection-level approximation: turn restrictions are ignored.
+pub struct TravelTimes {
+    times: BTreeMap<IntersectionID, f32>,
+}
+
+impl TravelTimes {
+    pub fn new(map: &Map, from: IntersectionID) -> Self {
+        profiling::scope!("map::TravelTimes::new");
+        let successors = |&id: &IntersectionID| {
+            map.intersections
+                .get(id)
+                .into_iter()
+                .flat_map(|inter| inter.roads.iter())
+                .filter_map(|&r| map.roads.get(r))
+                .filter(|r| !r.closed)
+                .flat_map(|r| r.lanes_iter())
+                .filter(|&(_, kind)| kind == LaneKind::Driving)
+                .filter_map(|(l, _)| map.lanes.get(l))
+                .filter(move |l| l.src == id)
+                .map(|l| (l.dst, OrderedFloat(l.points.length() / l.speed_limit)))
+                .collect::<Vec<_>>()
+        };
+
+        let mut times: BTreeMap<IntersectionID, f32> =
+            pathfinding::directed::dijkstra::dijkstra_all(&from, successors)
+                .into_iter()
+                .map(|(to, (_, cost))| (to, cost.0))
+                .collect();
+        if map.intersections.contains_key(from) {
+            times.insert(from, 0.0);
+        }
+
+        Self { times }
+    }
+
+    /// Travel time in seconds, None if `to` is not reachable
+    pub fn to(&self, to: IntersectionID) -> Option<f32> {
+        self.times.get(&to).copied()
+    }
+}
+
+/// Computes the travel times from an intersection on the first query from it,
+/// and forgets them all once the roads changed
+pub(crate) struct TravelTimeCache {
+    sub: MapSubscriber,
+    from: Mutex<BTreeMap<IntersectionID, Arc<TravelTimes>>>,
+}
+
+impl TravelTimeCache {
+    pub fn new(sub: MapSubscriber) -> Self {
+        Self {
+            sub,
+            from: Mutex::new(BTreeMap::new()),
+        }
+    }
+}
+
+impl Map {
+    /// Returns the travel times from this intersection, recomputed only if the roads changed since
+    /// they were last asked for
+    pub fn travel_times_from(&self, from: IntersectionID) -> Arc<TravelTimes> {
+        let mut cache = self.travel_times.from.lock().unwrap();
+        if self.travel_times.sub.take_changed() {
+            cache.clear();
+        }
+        cache
+            .entry(from)
+            .or_insert_with(|| Arc::new(TravelTimes::new(self, from)))
+            .clone()
+    }
+
+    /// Driving travel time in seconds between two intersections, None if `to` is not reachable
+    pub fn travel_time(&self, from: IntersectionID, to: IntersectionID) -> Option<f32> {
+        self.travel_times_from(from).to(to)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::map::{
+        IntersectionID, LaneKind, LanePatternBuilder, Map, PathKind, Pathfinder, ProjectFilter,
+        Traversable, TraverseDirection, TraverseKind,
+    };
+    use geom::{vec3, Vec3};
+    use prototypes::Tick;
+
+    #[test]
+    fn travel_times_match_astar() {
+        let mut map = Map::empty();
+        let pat = LanePatternBuilder::new().parking(false).build();
+
+        let mut road = |from: Vec3, to: Vec3| {
+            let a = map.project(from, 0.0, ProjectFilter::ALL);
+            let b = map.project(to, 0.0, ProjectFilter::ALL);
+            map.make_connection(a, b, None, &pat).unwrap();
+        };
+        for y in 0..3 {
+            for x in 0..2 {
+                let y = y as f32 * 100.0;
+                let x = x as f32 * 100.0;
+                road(vec3(x, y, 0.0), vec3(x + 100.0, y, 0.0));
+                road(vec3(y, x, 0.0), vec3(y, x + 100.0, 0.0));
+            }
+        }
+        // disconnected from the grid
+        road(vec3(1000.0, 0.0, 0.0), vec3(1100.0, 0.0, 0.0));
+
+        let inter = |x: f32, y: f32| -> IntersectionID {
+            map.intersections()
+                .values()
+                .find(|i| i.pos.xy().distance(vec3(x, y, 0.0).xy()) < 1.0)
+                .unwrap()
+                .id
+        };
+        let lane = |from: IntersectionID, to: IntersectionID| {
+            map.lanes()
+                .values()
+                .find(|l| l.kind == LaneKind::Driving && l.src == from && l.dst == to)
+                .unwrap()
+                .id
+        };
+
+        // (start lane), (end lane) so that the A* path goes from the first to the last intersection
+        let pairs = [
+            ((0.0, 0.0), (100.0, 0.0), (100.0, 0.0), (200.0, 0.0)),
+            ((0.0, 0.0), (0.0, 100.0), (0.0, 100.0), (0.0, 200.0)),
+            ((0.0, 0.0), (100.0, 0.0), (200.0, 100.0), (200.0, 200.0)),
+            ((200.0, 200.0), (100.0, 200.0), (0.0, 100.0), (0.0, 0.0)),
+        ];
+
+        for (a, b, c, d) in pairs {
+            let start = lane(inter(a.0, a.1), inter(b.0, b.1));
+            let end = lane(inter(c.0, c.1), inter(d.0, d.1));
+
+            let path = PathKind::Vehicle
+                .path(
+                    &map,
+                    Tick(0),
+                    Traversable::new(TraverseKind::Lane(start), TraverseDirection::Forward),
+                    end,
+                )
+                .unwrap();
+            let astar: f32 = path
+                .iter()
+                .filter_map(|t| match t.kind {
+                    TraverseKind::Lane(l) => Some(&map.lanes()[l]),
+                    TraverseKind::Turn(_) => None,
+                })
+                .map(|l| l.points.length() / l.speed_limit)
+                .sum();
+
+            let cached = map.travel_time(inter(a.0, a.1), inter(d.0, d.1)).unwrap();
+            // A* adds a bit of noise to its costs so it might not take the exact shortest path
+            assert!(
+                (astar - cached).abs() <= cached * 0.02,
+                "{:?} -> {:?}: astar {} cached {}",
+                a,
+                d,
+                astar,
+                cached
+            );
+        }
+
+        assert_eq!(map.travel_time(inter(0.0, 0.0), inter(0.0, 0.0)), Some(0.0));
+        assert_eq!(map.travel_time(inter(0.0, 0.0), inter(1100.0, 0.0)), None);
+
+        // only the intersections queried from are computed
+        assert_eq!(map.travel_times.from.lock().unwrap().len(), 2);
+
+        // cached until the roads change
+        let from_origin = map.travel_times_from(inter(0.0, 0.0));
+        assert!(std::sync::Arc::ptr_eq(
+            &from_origin,
+            &map.travel_times_from(inter(0.0, 0.0))
+        ));
+        let r = map
+            .find_road(inter(1000.0, 0.0), inter(1100.0, 0.0))
+            .unwrap();
+        map.set_road_closed(r, true);
+        assert!(!std::sync::Arc::ptr_eq(
+            &from_origin,
+            &map.travel_times_from(inter(0.0, 0.0))
+        ));
+    }
+}
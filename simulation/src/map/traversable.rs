@@ -1,6 +1,6 @@
 use crate::map::{IntersectionID, Intersections, LaneID, Lanes, Map, TurnID};
 use egui_inspect::Inspect;
-use geom::PolyLine3;
+use geom::{PolyLine3, Vec3};
 use serde::{Deserialize, Serialize};
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Hash, Inspect)]
@@ -64,6 +64,25 @@ impl Traversable {
         }
     }
 
+    /// Whether a pedestrian waiting on the refuge of a two-stage crossing can cross the half
+    /// towards `to`: none of the lights of the lanes coming into the intersection on that side are green.
+    pub fn can_leave_refuge(&self, refuge: Vec3, to: Vec3, time: u32, m: &Map) -> bool {
+        let TraverseKind::Turn(id) = self.kind else {
+            return true;
+        };
+        let Some(road) = m.lanes.get(id.src).and_then(|l| m.roads.get(l.parent)) else {
+            return true;
+        };
+        let side = to - refuge;
+
+        road.incoming_lanes_to(id.parent)
+            .iter()
+            .filter(|(_, kind)| kind.vehicles())
+            .filter_map(|&(lane, _)| m.lanes.get(lane))
+            .filter(|l| (l.get_inter_node_pos(id.parent) - refuge).dot(side) > 0.0)
+            .all(|l| !l.control.is_light() || l.control.get_behavior(time).is_red())
+    }
+
     pub fn destination_intersection(&self, lanes: &Lanes) -> Option<IntersectionID> {
         Some(match self.kind {
             TraverseKind::Lane(p) => match self.dir {
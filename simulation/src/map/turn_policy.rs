@@ -1,7 +1,11 @@
-use crate::map::{Intersection, IntersectionID, LaneID, LaneKind, Lanes, Roads, TurnID, TurnKind};
+use crate::map::{
+    Intersection, IntersectionID, LaneID, LaneKind, Lanes, LightPolicy, RoadID, Roads, TurnID,
+    TurnKind,
+};
 use egui_inspect::{Inspect, OptionDefault};
 use geom::{vec2, Vec2};
 use serde::{Deserialize, Serialize};
+use std::f32::consts::TAU;
 use std::iter::{Extend, Iterator};
 
 #[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize, Inspect)]
@@ -16,13 +20,42 @@ impl Default for RoundaboutPolicy {
     }
 }
 
+impl RoundaboutPolicy {
+    pub const MIN_RADIUS: f32 = 10.0;
+    pub const MAX_RADIUS: f32 = 50.0;
+    /// Room kept around the ring between two consecutive roads, in meters
+    const ROAD_SPACING: f32 = 4.0;
+
+    /// Smallest radius at which the connected roads fit around the ring without their lanes overlapping
+    pub fn min_radius(inter: &Intersection, roads: &Roads) -> f32 {
+        let around: f32 = inter
+            .roads
+            .iter()
+            .filter_map(|&r| roads.get(r))
+            .map(|r| r.width + Self::ROAD_SPACING)
+            .sum();
+        (around / TAU).max(Self::MIN_RADIUS)
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize, Inspect)]
 pub struct TurnPolicy {
     pub back_turns: bool,
     pub left_turns: bool,
+    /// Forbids left turns across opposing traffic unless a traffic light protects them
+    #[serde(default)]
+    pub protected_left_only: bool,
     pub crosswalks: bool,
     #[inspect(proxy_type = "OptionDefault")]
     pub roundabout: Option<RoundaboutPolicy>,
+    /// Radius of the arc pedestrians follow around the corners, shrunk if the sidewalks are too short
+    #[serde(default = "default_walking_corner_radius")]
+    #[inspect(min_value = 0.0, max_value = 10.0)]
+    pub walking_corner_radius: f32,
+}
+
+fn default_walking_corner_radius() -> f32 {
+    TurnPolicy::DEFAULT_WALKING_CORNER_RADIUS
 }
 
 impl Default for TurnPolicy {
@@ -30,15 +63,35 @@ impl Default for TurnPolicy {
         Self {
             back_turns: false,
             left_turns: true,
+            protected_left_only: false,
             crosswalks: true,
             roundabout: None,
+            walking_corner_radius: Self::DEFAULT_WALKING_CORNER_RADIUS,
         }
     }
 }
 
-fn filter_vehicles(x: &[(LaneID, LaneKind)]) -> Vec<LaneID> {
+/// Forbids vehicles coming from the `from` road to go into the `to` road at an intersection,
+/// for example to represent a "no left turn" sign
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TurnRestriction {
+    pub from: RoadID,
+    pub to: RoadID,
+}
+
+impl TurnRestriction {
+    pub fn forbids(&self, lanes: &Lanes, turn: TurnID) -> bool {
+        let (Some(src), Some(dst)) = (lanes.get(turn.src), lanes.get(turn.dst)) else {
+            return false;
+        };
+        src.parent == self.from && dst.parent == self.to
+    }
+}
+
+/// Trams only turn onto tram lanes and the other vehicles never do
+fn filter_vehicles(x: &[(LaneID, LaneKind)], tram: bool) -> Vec<LaneID> {
     x.iter()
-        .filter(|(_, kind)| kind.vehicles())
+        .filter(|(_, kind)| kind.vehicles() && kind.is_tram() == tram)
         .map(|(id, _)| id)
         .copied()
         .collect::<Vec<_>>()
@@ -53,6 +106,8 @@ fn filter_rail(x: &[(LaneID, LaneKind)]) -> Vec<LaneID> {
 }
 
 impl TurnPolicy {
+    pub const DEFAULT_WALKING_CORNER_RADIUS: f32 = 2.0;
+
     fn zip(
         inter_id: IntersectionID,
         incoming: &[LaneID],
@@ -103,14 +158,27 @@ impl TurnPolicy {
         lanes: &Lanes,
         roads: &Roads,
         turns: &mut Vec<(TurnID, TurnKind)>,
+    ) {
+        for tram in [false, true] {
+            self.generate_vehicle_turns_inner(inter, lanes, roads, tram, turns);
+        }
+    }
+
+    fn generate_vehicle_turns_inner(
+        self,
+        inter: &Intersection,
+        lanes: &Lanes,
+        roads: &Roads,
+        tram: bool,
+        turns: &mut Vec<(TurnID, TurnKind)>,
     ) {
         match inter.roads.as_slice() {
             [road_id] => {
                 let road = unwrap_ret!(roads.get(*road_id));
                 turns.extend(Self::zip_on_same_length(
                     inter.id,
-                    &filter_vehicles(road.incoming_lanes_to(inter.id)),
-                    &filter_vehicles(road.outgoing_lanes_from(inter.id)),
+                    &filter_vehicles(road.incoming_lanes_to(inter.id), tram),
+                    &filter_vehicles(road.outgoing_lanes_from(inter.id), tram),
                     TurnKind::Driving,
                 ));
                 return;
@@ -119,11 +187,11 @@ impl TurnPolicy {
                 let road1 = unwrap_ret!(roads.get(*road1));
                 let road2 = unwrap_ret!(roads.get(*road2));
 
-                let incoming_road1 = filter_vehicles(road1.incoming_lanes_to(inter.id));
-                let incoming_road2 = filter_vehicles(road2.incoming_lanes_to(inter.id));
+                let incoming_road1 = filter_vehicles(road1.incoming_lanes_to(inter.id), tram);
+                let incoming_road2 = filter_vehicles(road2.incoming_lanes_to(inter.id), tram);
 
-                let outgoing_road1 = filter_vehicles(road1.outgoing_lanes_from(inter.id));
-                let outgoing_road2 = filter_vehicles(road2.outgoing_lanes_from(inter.id));
+                let outgoing_road1 = filter_vehicles(road1.outgoing_lanes_from(inter.id), tram);
+                let outgoing_road2 = filter_vehicles(road2.outgoing_lanes_from(inter.id), tram);
 
                 turns.extend(Self::zip_on_same_length(
                     inter.id,
@@ -161,6 +229,8 @@ impl TurnPolicy {
         }
 
         let n_roads = inter.roads.len();
+        let left_turns = self.left_turns
+            && (!self.protected_left_only || inter.light_policy.has_lights(inter, roads));
 
         for (i1, road1) in inter.roads.iter().enumerate() {
             for (i2, road2) in inter.roads.iter().enumerate() {
@@ -172,7 +242,11 @@ impl TurnPolicy {
                 let r2 = unwrap_cont!(roads.get(*road2));
                 for (incoming, incoming_kind) in r1.incoming_lanes_to(inter.id) {
                     for (outgoing, outgoing_kind) in r2.outgoing_lanes_from(inter.id) {
-                        if !incoming_kind.vehicles() || !outgoing_kind.vehicles() {
+                        if !incoming_kind.vehicles()
+                            || !outgoing_kind.vehicles()
+                            || incoming_kind.is_tram() != tram
+                            || outgoing_kind.is_tram() != tram
+                        {
                             continue;
                         }
 
@@ -185,7 +259,7 @@ impl TurnPolicy {
                         let incoming_right = vec2(incoming_dir.y, -incoming_dir.x);
                         let id = TurnID::new(inter.id, incoming.id, outgoing.id, false);
 
-                        if self.left_turns
+                        if left_turns
                             || incoming_right.dot(outgoing_dir) <= 0.1
                             || i2 == (i1 + 1) % n_roads
                         {
@@ -197,9 +271,44 @@ impl TurnPolicy {
         }
     }
 
+    /// Entering a roundabout, or going from a minor road of an intersection without lights
+    /// or stop signs, means giving way to the traffic with the priority
+    fn mark_yield_turns(
+        inter: &Intersection,
+        lanes: &Lanes,
+        roads: &Roads,
+        turns: &mut [(TurnID, TurnKind)],
+    ) {
+        let major = if inter.is_roundabout() {
+            None
+        } else if inter.light_policy == LightPolicy::NoLights {
+            let Some(major) = LightPolicy::priority_roads(inter, roads) else {
+                return;
+            };
+            Some(major)
+        } else {
+            return;
+        };
+
+        for (id, kind) in turns {
+            if *kind != TurnKind::Driving {
+                continue;
+            }
+            let minor = major.map_or(true, |major| {
+                lanes
+                    .get(id.src)
+                    .map_or(false, |l| !major.contains(&l.parent))
+            });
+            if minor {
+                *kind = TurnKind::Yield;
+            }
+        }
+    }
+
     pub fn generate_walking_turns(
         self,
         inter: &Intersection,
+        lanes: &Lanes,
         roads: &Roads,
         turns: &mut Vec<(TurnID, TurnKind)>,
     ) {
@@ -209,11 +318,14 @@ impl TurnPolicy {
             .roads
             .iter()
             .chain(inter.roads.iter().take(1))
-            .flat_map(|x| Some(roads.get(*x)?.sidewalks(inter.id)))
+            .flat_map(|x| roads.get(*x))
             .collect::<Vec<_>>()
             .windows(2)
         {
-            if let [a, b] = *w {
+            if let [road_a, road_b] = *w {
+                let a = road_a.sidewalks(inter.id);
+                let b = road_b.sidewalks(inter.id);
+
                 if let (Some(incoming), Some(outgoing)) = (a.incoming, b.outgoing) {
                     turns.push((
                         TurnID::new(inter.id, incoming, outgoing, true),
@@ -223,10 +335,12 @@ impl TurnPolicy {
 
                 if self.crosswalks && n_roads > 2 {
                     if let (Some(incoming), Some(outgoing_in)) = (a.incoming, a.outgoing) {
-                        turns.push((
-                            TurnID::new(inter.id, incoming, outgoing_in, true),
-                            TurnKind::Crosswalk,
-                        ));
+                        let kind = if road_a.has_refuge_room(lanes) {
+                            TurnKind::TwoStageCrosswalk
+                        } else {
+                            TurnKind::Crosswalk
+                        };
+                        turns.push((TurnID::new(inter.id, incoming, outgoing_in, true), kind));
                     }
                 }
             }
@@ -323,10 +437,294 @@ impl TurnPolicy {
         let mut turns = vec![];
 
         self.generate_vehicle_turns(inter, lanes, roads, &mut turns);
+        if !inter.turn_restrictions.is_empty() {
+            turns.retain(|(id, _)| {
+                !inter
+                    .turn_restrictions
+                    .iter()
+                    .any(|r| r.forbids(lanes, *id))
+            });
+        }
+        Self::mark_yield_turns(inter, lanes, roads, &mut turns);
         self.generate_rail_turns(inter, lanes, roads, &mut turns);
 
-        self.generate_walking_turns(inter, roads, &mut turns);
+        self.generate_walking_turns(inter, lanes, roads, &mut turns);
 
         turns
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::map::{
+        LanePatternBuilder, LightPolicy, Map, MapBuilder, RoadSegmentKind, RoundaboutPolicy,
+        TurnKind, TurnRestriction,
+    };
+    use geom::{vec2, vec3, Radians, Vec2};
+
+    #[test]
+    fn trams_stay_on_tram_lanes() {
+        let tram = LanePatternBuilder::new().tram(true).build();
+        let street = LanePatternBuilder::new().build();
+
+        let mut b = MapBuilder::new();
+        let center = b.add_inter(vec2(0.0, 0.0));
+        let south = b.add_inter(vec2(0.0, -100.0));
+        let north = b.add_inter(vec2(0.0, 100.0));
+        let east = b.add_inter(vec2(100.0, 0.0));
+
+        b.connect(south, center, &tram).unwrap();
+        b.connect(center, north, &tram).unwrap();
+        b.connect(center, east, &street).unwrap();
+        let map = b.build();
+
+        let mut n_tram_turns = 0;
+        for turn in map.intersections[center].turns() {
+            if !turn.kind.is_driving() {
+                continue;
+            }
+            let src = map.lanes[turn.id.src].kind;
+            let dst = map.lanes[turn.id.dst].kind;
+            assert_eq!(src.is_tram(), dst.is_tram(), "{:?} -> {:?}", src, dst);
+            if src.is_tram() {
+                n_tram_turns += 1;
+            }
+        }
+        // straight through in both directions, the street has no tracks to turn onto
+        assert_eq!(n_tram_turns, 2);
+    }
+
+    #[test]
+    fn no_left_turn_restriction() {
+        let pat = LanePatternBuilder::new().build();
+
+        let mut b = MapBuilder::new();
+        let center = b.add_inter(vec2(0.0, 0.0));
+        let south = b.add_inter(vec2(0.0, -100.0));
+        let west = b.add_inter(vec2(-100.0, 0.0));
+        let east = b.add_inter(vec2(100.0, 0.0));
+        let far = b.add_inter(vec2(500.0, 500.0));
+        let far2 = b.add_inter(vec2(600.0, 500.0));
+
+        let from_south = b.connect(south, center, &pat).unwrap();
+        let to_west = b.connect(center, west, &pat).unwrap();
+        let to_east = b.connect(center, east, &pat).unwrap();
+        let unrelated = b.connect(far, far2, &pat).unwrap();
+        let mut map = b.build();
+
+        let has_turn = |map: &Map, from, to| {
+            map.intersections[center].turns().any(|t| {
+                !t.id.bidirectional
+                    && map.lanes[t.id.src].parent == from
+                    && map.lanes[t.id.dst].parent == to
+            })
+        };
+
+        assert!(has_turn(&map, from_south, to_west));
+
+        map.set_turn_restrictions(
+            center,
+            [
+                TurnRestriction {
+                    from: from_south,
+                    to: to_west,
+                },
+                TurnRestriction {
+                    from: unrelated,
+                    to: to_east,
+                },
+            ],
+        );
+
+        assert_eq!(map.intersections[center].turn_restrictions.len(), 1);
+        assert!(!has_turn(&map, from_south, to_west));
+        assert!(has_turn(&map, from_south, to_east));
+        assert!(has_turn(&map, to_west, from_south));
+    }
+
+    #[test]
+    fn protected_left_only_needs_lights() {
+        let mut map = Map::empty();
+        let pat = LanePatternBuilder::new().build();
+
+        let center = map.add_intersection(vec3(0.0, 0.0, 0.3));
+        let south = map.add_intersection(vec3(0.0, -100.0, 0.3));
+        let north = map.add_intersection(vec3(0.0, 100.0, 0.3));
+        let west = map.add_intersection(vec3(-100.0, 0.0, 0.3));
+        let east = map.add_intersection(vec3(100.0, 0.0, 0.3));
+
+        let from_south = map
+            .connect(south, center, &pat, RoadSegmentKind::Straight)
+            .unwrap();
+        let to_north = map
+            .connect(center, north, &pat, RoadSegmentKind::Straight)
+            .unwrap();
+        let to_west = map
+            .connect(center, west, &pat, RoadSegmentKind::Straight)
+            .unwrap();
+        let to_east = map
+            .connect(center, east, &pat, RoadSegmentKind::Straight)
+            .unwrap();
+
+        let has_turn = |map: &Map, from, to| {
+            map.intersections[center].turns().any(|t| {
+                !t.id.bidirectional
+                    && map.lanes[t.id.src].parent == from
+                    && map.lanes[t.id.dst].parent == to
+            })
+        };
+
+        let set_policy = |map: &mut Map, protected: bool, light: LightPolicy| {
+            map.update_intersection(center, |i| {
+                i.turn_policy.protected_left_only = protected;
+                i.light_policy = light;
+            });
+        };
+
+        set_policy(&mut map, false, LightPolicy::NoLights);
+        assert!(has_turn(&map, from_south, to_west));
+
+        // unsignalized: no more left turns, the rest is untouched
+        set_policy(&mut map, true, LightPolicy::NoLights);
+        assert!(!has_turn(&map, from_south, to_west));
+        assert!(!has_turn(&map, to_west, to_north));
+        assert!(has_turn(&map, from_south, to_east));
+        assert!(has_turn(&map, from_south, to_north));
+
+        set_policy(&mut map, true, LightPolicy::StopSigns);
+        assert!(!has_turn(&map, from_south, to_west));
+
+        // lights protect the left turns
+        set_policy(&mut map, true, LightPolicy::Lights);
+        assert!(has_turn(&map, from_south, to_west));
+
+        // a 4-way with the auto policy gets lights
+        set_policy(&mut map, true, LightPolicy::Auto);
+        assert!(has_turn(&map, from_south, to_west));
+    }
+
+    #[test]
+    fn minor_road_yields_without_lights() {
+        let main = LanePatternBuilder::new().n_lanes(2).build();
+        let side = LanePatternBuilder::new().build();
+        let mut b = MapBuilder::new();
+        let center = b.add_inter(vec2(200.0, 200.0));
+        let west = b.add_inter(vec2(0.0, 200.0));
+        let east = b.add_inter(vec2(400.0, 200.0));
+        let south = b.add_inter(vec2(200.0, 0.0));
+        b.connect(west, center, &main).unwrap();
+        b.connect(center, east, &main).unwrap();
+        let side = b.connect(south, center, &side).unwrap();
+        let mut map = b.build();
+
+        let yields = |map: &Map| {
+            map.intersections[center]
+                .turns()
+                .filter(|t| t.kind.is_driving())
+                .map(|t| (map.lanes[t.id.src].parent, t.kind == TurnKind::Yield))
+                .collect::<Vec<_>>()
+        };
+
+        map.update_intersection(center, |i| i.light_policy = LightPolicy::NoLights);
+        let turns = yields(&map);
+        assert!(!turns.is_empty());
+        for (road, yields) in turns {
+            assert_eq!(yields, road == side, "{:?}", road);
+        }
+
+        // stop signs already tell the minor road what to do
+        map.update_intersection(center, |i| i.light_policy = LightPolicy::StopSigns);
+        assert!(yields(&map).iter().all(|&(_, y)| !y));
+    }
+
+    /// Radius of the circle going through the three points
+    fn circumradius(a: Vec2, b: Vec2, c: Vec2) -> f32 {
+        let area = (b - a).cross(c - a).abs() * 0.5;
+        a.distance(b) * b.distance(c) * c.distance(a) / (4.0 * area)
+    }
+
+    #[test]
+    fn walking_corners_are_rounded() {
+        let mut map = Map::empty();
+        let pat = LanePatternBuilder::new().parking(false).build();
+
+        let center = map.add_intersection(vec3(0.0, 0.0, 0.3));
+        for (x, y) in [(100.0, 0.0), (0.0, 100.0), (-100.0, 0.0), (0.0, -100.0)] {
+            let end = map.add_intersection(vec3(x, y, 0.3));
+            map.connect(center, end, &pat, RoadSegmentKind::Straight)
+                .unwrap();
+        }
+
+        // the sidewalks are centered 5.5m from the middle of the roads, after the 4m driving lanes
+        let check = |map: &Map, radius: f32| {
+            let corners: Vec<_> = map.intersections[center]
+                .turns()
+                .filter(|t| t.kind == TurnKind::WalkingCorner)
+                .collect();
+            assert_eq!(corners.len(), 4);
+
+            for turn in corners {
+                let points: Vec<Vec2> = turn.points.iter().map(|p| p.xy()).collect();
+                for p in &points {
+                    assert!(
+                        p.x.abs() > 5.49 && p.y.abs() > 5.49,
+                        "{:?} is on the road",
+                        p
+                    );
+                }
+                let mid = points.len() / 2;
+                let r = circumradius(points[mid - 1], points[mid], points[mid + 1]);
+                assert!(
+                    (r - radius).abs() < 0.1,
+                    "radius is {} instead of {}",
+                    r,
+                    radius
+                );
+            }
+        };
+
+        map.update_intersection(center, |i| i.turn_policy.walking_corner_radius = 3.0);
+        check(&map, 3.0);
+
+        // too big for the sidewalks, the arc is shrunk to fit between their ends
+        let sidewalk_end = map.roads()[map.intersections[center].roads[0]].interface_from(center);
+        map.update_intersection(center, |i| i.turn_policy.walking_corner_radius = 30.0);
+        check(&map, sidewalk_end - 5.5);
+    }
+
+    #[test]
+    fn roundabout_radius_fits_roads() {
+        let mut map = Map::empty();
+        let pat = LanePatternBuilder::new().n_lanes(3).build();
+        let center = map.add_intersection(vec3(0.0, 0.0, 0.3));
+        for i in 0..6 {
+            let dir = Vec2::from_angle(Radians(i as f32 * std::f32::consts::TAU / 6.0));
+            let end = map.add_intersection((dir * 200.0).z(0.3));
+            map.connect(center, end, &pat, RoadSegmentKind::Straight)
+                .unwrap();
+        }
+        let radius = |map: &Map| {
+            map.intersections[center]
+                .turn_policy
+                .roundabout
+                .map(|rb| rb.radius)
+        };
+        let min = RoundaboutPolicy::min_radius(&map.intersections[center], map.roads());
+        assert!(min > RoundaboutPolicy::MIN_RADIUS);
+        assert!(min < 45.0);
+
+        // too small for six big roads
+        map.set_roundabout(center, Some(RoundaboutPolicy::MIN_RADIUS));
+        assert_eq!(radius(&map), Some(min));
+        assert!(map.intersections[center].is_roundabout());
+
+        map.set_roundabout(center, Some(45.0));
+        assert_eq!(radius(&map), Some(45.0));
+
+        map.set_roundabout(center, Some(1000.0));
+        assert_eq!(radius(&map), Some(RoundaboutPolicy::MAX_RADIUS));
+
+        map.set_roundabout(center, None);
+        assert!(!map.intersections[center].is_roundabout());
+    }
+}
@@ -40,6 +40,18 @@ impl BuildingInfos {
         self.owners.insert(soul, building);
     }
 
+    /// The soul no longer owns any building
+    pub fn clear_owner(&mut self, soul: SoulID) {
+        let Some(building) = self.owners.remove(&soul) else {
+            return;
+        };
+        if let Some(x) = self.get_mut(building) {
+            if x.owner == Some(soul) {
+                x.owner = None;
+            }
+        }
+    }
+
     pub fn owner(&self, building: BuildingID) -> Option<SoulID> {
         self.assignment.get(building).and_then(|x| x.owner)
     }
@@ -1,4 +1,9 @@
-use crate::map::{Map, PathKind, Pathfinder, Traversable, TraverseDirection, TraverseKind};
+use crate::map::{
+    LaneID, Map, PathKind, PathfindOptions, Pathfinder, Traversable, TraverseDirection,
+    TraverseKind,
+};
+use crate::transportation::road::{move_vehicle, PhysicsSettings};
+use crate::transportation::{TransportGrid, WalkingComfort};
 use crate::utils::resources::Resources;
 use crate::world::TrainID;
 use crate::World;
@@ -22,13 +27,13 @@ pub struct ItineraryLeader {
     pub past: Polyline3Queue,
 }
 
-#[derive(Default, Debug, Serialize, Deserialize, Inspect)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize, Inspect)]
 pub struct Itinerary {
     kind: ItineraryKind,
     reversed_local_path: Vec<Vec3>,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub enum ItineraryKind {
     #[default]
     None,
@@ -42,16 +47,35 @@ pub enum ItineraryKind {
     },
 }
 
-#[derive(Debug, Serialize, Deserialize, Inspect)]
+#[derive(Debug, Clone, Serialize, Deserialize, Inspect)]
 pub struct Route {
     /// Route is reversed, allows for efficient popping
     pub reversed_route: Vec<Traversable>,
     pub end_pos: Vec3,
     pub cur: Traversable,
+    /// The destination was unreachable, `end_pos` is the closest reachable point instead
+    #[serde(default)]
+    pub partial: bool,
 }
 
 pub const OBJECTIVE_OK_DIST: f32 = 3.0;
 
+impl Route {
+    /// Points to go through on the current traversable, ending at `end_pos` on the last one
+    fn cur_points(&self, map: &Map, pathkind: PathKind, position: Vec3) -> Option<Vec<Vec3>> {
+        let points = self.cur.points(map)?;
+        if !self.reversed_route.is_empty() {
+            return Some(points.into_vec());
+        }
+        Some(
+            pathkind
+                .local_route(map, self.cur.destination_lane(), position, self.end_pos)
+                .unwrap_or(points)
+                .into_vec(),
+        )
+    }
+}
+
 impl Itinerary {
     pub const NONE: Self = Self {
         kind: ItineraryKind::None,
@@ -98,6 +122,17 @@ impl Itinerary {
         end: Vec3,
         map: &Map,
         pathkind: PathKind,
+    ) -> Option<Itinerary> {
+        Self::route_with_options(tick, start, end, map, pathkind, PathfindOptions::default())
+    }
+
+    pub fn route_with_options(
+        tick: Tick,
+        start: Vec3,
+        mut end: Vec3,
+        map: &Map,
+        pathkind: PathKind,
+        options: PathfindOptions,
     ) -> Option<Itinerary> {
         let start_lane = pathkind.nearest_lane(map, start)?;
         let end_lane = pathkind.nearest_lane(map, end)?;
@@ -113,6 +148,7 @@ impl Itinerary {
                             reversed_route: vec![],
                             end_pos: end,
                             cur,
+                            partial: false,
                         },
                         pathkind,
                     ),
@@ -121,11 +157,13 @@ impl Itinerary {
             }
         }
 
-        let mut reversed_route: Vec<Traversable> = pathkind
-            .path(map, tick, cur, end_lane)?
-            .into_iter()
-            .rev()
-            .collect();
+        let result = pathkind.path_with_options(map, tick, cur, end_lane, options)?;
+        if result.partial {
+            let last_lane = result.path.last()?.destination_lane();
+            end = map.lanes().get(last_lane)?.points.project(end);
+        }
+
+        let mut reversed_route: Vec<Traversable> = result.path.into_iter().rev().collect();
 
         reversed_route.pop(); // Remove start
 
@@ -145,6 +183,7 @@ impl Itinerary {
                 reversed_route,
                 end_pos: end,
                 cur,
+                partial: result.partial,
             },
             pathkind,
         );
@@ -175,28 +214,45 @@ impl Itinerary {
             if let ItineraryKind::Route(ref mut r, pathkind) = self.kind {
                 r.cur = r.reversed_route.pop()?;
 
-                let points = match r.cur.points(map) {
+                let points = match r.cur_points(map, pathkind, position) {
                     Some(x) => x,
                     None => {
                         *self = Self::wait_for_reroute(pathkind, r.end_pos);
                         return None;
                     }
                 };
-
-                if r.reversed_route.is_empty() {
-                    self.reversed_local_path = pathkind
-                        .local_route(map, r.cur.destination_lane(), position, r.end_pos)
-                        .unwrap_or(points)
-                        .into_vec();
-                } else {
-                    self.reversed_local_path = points.into_vec();
-                }
+                self.reversed_local_path = points;
                 self.reversed_local_path.reverse();
             }
         }
         v
     }
 
+    /// Heads straight for the furthest upcoming point for which `inside` is true, skipping the
+    /// traversables that are entirely inside. Pedestrians use it to walk freely across a shared space.
+    pub fn cut_through(&mut self, map: &Map, position: Vec3, inside: impl Fn(Vec3) -> bool) {
+        if let ItineraryKind::Route(ref mut r, pathkind) = self.kind {
+            while self.reversed_local_path.iter().all(|&p| inside(p)) {
+                let Some(next) = r.reversed_route.pop() else {
+                    break;
+                };
+                let prev = std::mem::replace(&mut r.cur, next);
+                match r.cur_points(map, pathkind, position) {
+                    Some(points) if points.iter().all(|&p| inside(p)) => {
+                        self.reversed_local_path = points;
+                        self.reversed_local_path.reverse();
+                    }
+                    _ => {
+                        r.cur = prev;
+                        r.reversed_route.push(next);
+                        break;
+                    }
+                }
+            }
+        }
+        self.detour(&[], inside);
+    }
+
     pub fn update(
         &mut self,
         mut position: Vec3,
@@ -204,6 +260,7 @@ impl Itinerary {
         tick: Tick,
         time: u32,
         map: &Map,
+        options: PathfindOptions,
     ) -> Vec3 {
         while let Some(p) = self.get_point() {
             let dist = position.distance(p);
@@ -216,6 +273,9 @@ impl Itinerary {
                 }
 
                 if self.remaining_points() > 1 {
+                    if self.waits_on_refuge(map, time) {
+                        return p;
+                    }
                     self.advance(map, position);
                     continue;
                 }
@@ -245,15 +305,42 @@ impl Itinerary {
                 *wait_ticks -= 1;
                 return position;
             }
-            *self = unwrap_or!(Self::route(tick, position, dest, map, kind), {
-                *wait_ticks = 200;
-                return position;
-            });
+            *self = unwrap_or!(
+                Self::route_with_options(tick, position, dest, map, kind, options),
+                {
+                    *wait_ticks = 200;
+                    return position;
+                }
+            );
         }
 
         position
     }
 
+    /// On a two-stage crossing, pedestrians stop on the refuge until the second half can be crossed
+    fn waits_on_refuge(&self, map: &Map, time: u32) -> bool {
+        let [to, refuge] = self.reversed_local_path[..] else {
+            return false;
+        };
+        let Some(t) = self.get_travers() else {
+            return false;
+        };
+        let TraverseKind::Turn(id) = t.kind else {
+            return false;
+        };
+        let Some(turn) = map
+            .intersections
+            .get(id.parent)
+            .and_then(|i| i.find_turn(id))
+        else {
+            return false;
+        };
+        if !turn.refuge().map_or(false, |r| r.distance(refuge) < 0.1) {
+            return false;
+        }
+        !t.can_leave_refuge(refuge, to, time, map)
+    }
+
     pub fn random_route(
         rng: u64,
         position: Vec3,
@@ -326,6 +413,33 @@ impl Itinerary {
         }
     }
 
+    /// The lane or turn followed after the current one
+    pub fn next_travers(&self) -> Option<&Traversable> {
+        self.get_route()?.reversed_route.last()
+    }
+
+    /// Whether the lane or turn currently being followed still exists in the map
+    pub fn cur_traversable_exists(&self, map: &Map) -> bool {
+        match self.get_travers() {
+            Some(t) => t.raw_points(map).is_some(),
+            None => true,
+        }
+    }
+
+    /// Whether the route stops short of the requested destination because it is unreachable
+    pub fn is_partial(&self) -> bool {
+        self.get_route().map_or(false, |r| r.partial)
+    }
+
+    /// What the route was computed for, None if it isn't a route
+    pub fn path_kind(&self) -> Option<PathKind> {
+        match self.kind {
+            ItineraryKind::Route(_, kind) => Some(kind),
+            ItineraryKind::WaitForReroute { kind, .. } => Some(kind),
+            _ => None,
+        }
+    }
+
     pub fn get_route(&self) -> Option<&Route> {
         match &self.kind {
             ItineraryKind::Route(r, _) => Some(r),
@@ -333,10 +447,44 @@ impl Itinerary {
         }
     }
 
+    /// Whether what is left of the route goes on the lane or takes a turn to or from it
+    pub fn goes_through(&self, lane: LaneID) -> bool {
+        let Some(r) = self.get_route() else {
+            return false;
+        };
+        std::iter::once(&r.cur)
+            .chain(&r.reversed_route)
+            .any(|t| match t.kind {
+                TraverseKind::Lane(id) => id == lane,
+                TraverseKind::Turn(id) => id.src == lane || id.dst == lane,
+            })
+    }
+
     pub fn local_path(&self) -> &[Vec3] {
         &self.reversed_local_path
     }
 
+    /// Points the itinerary goes through in order, starting with the current objective.
+    /// Continues through the rest of the route as long as its traversables still exist.
+    pub fn upcoming_points<'a>(&'a self, map: &'a Map) -> impl Iterator<Item = Vec3> + 'a {
+        let rest = self
+            .get_route()
+            .into_iter()
+            .flat_map(|r| r.reversed_route.iter().rev())
+            .map_while(|t| t.points(map))
+            .flat_map(|p| p.into_vec());
+        self.reversed_local_path.iter().rev().copied().chain(rest)
+    }
+
+    /// Goes through `points` before resuming the current traversable.
+    /// Upcoming points for which `skip` is true are dropped, except the last one which ends the traversable.
+    pub fn detour(&mut self, points: &[Vec3], skip: impl Fn(Vec3) -> bool) {
+        while self.reversed_local_path.len() > 1 && self.get_point().map_or(false, &skip) {
+            self.reversed_local_path.pop();
+        }
+        self.reversed_local_path.extend(points.iter().rev());
+    }
+
     pub fn prepend_local_path(&mut self, points: impl IntoIterator<Item = Vec3>) {
         self.reversed_local_path.extend(points);
     }
@@ -417,13 +565,26 @@ pub fn itinerary_update(world: &mut World, resources: &mut Resources) {
     let time = &*resources.read::<GameTime>();
     let map = &*resources.read::<Map>();
     let tick = resources.read::<GameTime>().tick;
+    let comfort = resources.read::<WalkingComfort>();
+    let options = PathfindOptions {
+        comfort_weight: comfort.weight(time.season()),
+        ..Default::default()
+    };
 
     world.query_it_trans_speed().for_each(
         |(it, trans, speed): (&mut Itinerary, &mut Transform, f32)| {
-            trans.pos = it.update(trans.pos, speed * DELTA, tick, time.seconds, map);
+            trans.pos = it.update(trans.pos, speed * DELTA, tick, time.seconds, map, options);
         },
     );
 
+    let grid = &*resources.read::<TransportGrid>();
+    let physics = &*resources.read::<PhysicsSettings>();
+    world.vehicles.values_mut().for_each(|v| {
+        move_vehicle(v, grid, physics, |it, pos, dist| {
+            it.update(pos, dist, tick, time.seconds, map, options)
+        });
+    });
+
     world.trains.values_mut().for_each(|train| {
         train.leader.past.push(train.trans.pos);
     });
@@ -436,3 +597,270 @@ pub fn itinerary_update(world: &mut World, resources: &mut Resources) {
         wagon.trans.dir = (dir + dir2).try_normalize().unwrap_or(dir);
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Itinerary;
+    use crate::map::{
+        LanePatternBuilder, Map, PathKind, PathfindOptions, ProjectFilter, TraverseKind,
+    };
+    use geom::{vec3, Vec3};
+    use prototypes::Tick;
+
+    #[test]
+    fn partial_route_to_disconnected_destination() {
+        let mut map = Map::empty();
+        let pat = LanePatternBuilder::new().parking(false).build();
+
+        let mut road = |from: Vec3, to: Vec3| {
+            let a = map.project(from, 0.0, ProjectFilter::ALL);
+            let b = map.project(to, 0.0, ProjectFilter::ALL);
+            map.make_connection(a, b, None, &pat).unwrap();
+        };
+        road(vec3(0.0, 0.0, 0.0), vec3(100.0, 0.0, 0.0));
+        road(vec3(300.0, 0.0, 0.0), vec3(400.0, 0.0, 0.0));
+
+        let start = vec3(10.0, 0.0, 0.0);
+        let end = vec3(350.0, 0.0, 0.0);
+
+        assert!(Itinerary::route(Tick(0), start, end, &map, PathKind::Vehicle).is_none());
+
+        let it = Itinerary::route_with_options(
+            Tick(0),
+            start,
+            end,
+            &map,
+            PathKind::Vehicle,
+            PathfindOptions {
+                allow_partial: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert!(it.is_partial());
+        let end_pos = it.get_route().unwrap().end_pos;
+        assert!(
+            end_pos.xy().distance(vec3(100.0, 0.0, 0.0).xy()) < 15.0,
+            "{:?} should be close to the end of the first road",
+            end_pos
+        );
+
+        let full = Itinerary::route_with_options(
+            Tick(0),
+            start,
+            vec3(90.0, 0.0, 0.0),
+            &map,
+            PathKind::Vehicle,
+            PathfindOptions {
+                allow_partial: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(!full.is_partial());
+    }
+
+    #[test]
+    fn closed_road_is_avoided() {
+        let mut map = Map::empty();
+        let pat = LanePatternBuilder::new().parking(false).build();
+
+        let mut road = |from: Vec3, to: Vec3| {
+            let a = map.project(from, 0.0, ProjectFilter::ALL);
+            let b = map.project(to, 0.0, ProjectFilter::ALL);
+            map.make_connection(a, b, None, &pat).unwrap().1
+        };
+        road(vec3(-100.0, 0.0, 0.0), vec3(0.0, 0.0, 0.0));
+        let short = road(vec3(0.0, 0.0, 0.0), vec3(100.0, 0.0, 0.0));
+        road(vec3(100.0, 0.0, 0.0), vec3(200.0, 0.0, 0.0));
+        road(vec3(0.0, 0.0, 0.0), vec3(100.0, 100.0, 0.0));
+        road(vec3(100.0, 100.0, 0.0), vec3(200.0, 0.0, 0.0));
+        road(vec3(200.0, 0.0, 0.0), vec3(300.0, 0.0, 0.0));
+
+        let start = vec3(-90.0, 0.0, 0.0);
+        let end = vec3(290.0, 0.0, 0.0);
+
+        let uses_short = |it: &Itinerary, map: &Map| {
+            let r = it.get_route().unwrap();
+            r.reversed_route
+                .iter()
+                .chain(std::iter::once(&r.cur))
+                .any(|t| match t.kind {
+                    TraverseKind::Lane(id) => map.lanes()[id].parent == short,
+                    TraverseKind::Turn(_) => false,
+                })
+        };
+
+        let mut in_progress =
+            Itinerary::route(Tick(0), start, end, &map, PathKind::Vehicle).unwrap();
+        assert!(uses_short(&in_progress, &map));
+
+        map.set_road_closed(short, true);
+
+        let rerouted = Itinerary::route(Tick(0), start, end, &map, PathKind::Vehicle).unwrap();
+        assert!(!uses_short(&rerouted, &map));
+
+        let mut pos = start;
+        for time in 0..10000 {
+            if in_progress.is_terminal() {
+                break;
+            }
+            pos = in_progress.update(pos, 2.0, Tick(0), time, &map, PathfindOptions::default());
+        }
+        assert!(in_progress.is_terminal());
+        assert!(pos.xy().distance(end.xy()) < 5.0, "{:?}", pos);
+
+        map.set_road_closed(short, false);
+        let reopened = Itinerary::route(Tick(0), start, end, &map, PathKind::Vehicle).unwrap();
+        assert!(uses_short(&reopened, &map));
+    }
+
+    #[test]
+    fn congested_road_is_avoided() {
+        let mut map = Map::empty();
+        let pat = LanePatternBuilder::new().parking(false).build();
+
+        let mut road = |from: Vec3, to: Vec3| {
+            let a = map.project(from, 0.0, ProjectFilter::ALL);
+            let b = map.project(to, 0.0, ProjectFilter::ALL);
+            map.make_connection(a, b, None, &pat).unwrap().1
+        };
+        road(vec3(-100.0, 0.0, 0.0), vec3(0.0, 0.0, 0.0));
+        let short = road(vec3(0.0, 0.0, 0.0), vec3(100.0, 0.0, 0.0));
+        road(vec3(100.0, 0.0, 0.0), vec3(200.0, 0.0, 0.0));
+        road(vec3(0.0, 0.0, 0.0), vec3(100.0, 100.0, 0.0));
+        road(vec3(100.0, 100.0, 0.0), vec3(200.0, 0.0, 0.0));
+        road(vec3(200.0, 0.0, 0.0), vec3(300.0, 0.0, 0.0));
+
+        let start = vec3(-90.0, 0.0, 0.0);
+        let end = vec3(290.0, 0.0, 0.0);
+
+        let uses_short = |map: &Map| {
+            let it = Itinerary::route(Tick(0), start, end, map, PathKind::Vehicle).unwrap();
+            let r = it.get_route().unwrap();
+            r.reversed_route
+                .iter()
+                .chain(std::iter::once(&r.cur))
+                .any(|t| match t.kind {
+                    TraverseKind::Lane(id) => map.lanes()[id].parent == short,
+                    TraverseKind::Turn(_) => false,
+                })
+        };
+        let short_lanes: Vec<_> = map.roads()[short]
+            .lanes_iter()
+            .filter(|(_, kind)| kind.vehicles())
+            .map(|(id, _)| id)
+            .collect();
+
+        // a few seconds of moving traffic doesn't change anything
+        let moving = short_lanes.iter().map(|&id| (id, (3, 27.0))).collect();
+        for _ in 0..200 {
+            map.update_traffic(&moving);
+        }
+        assert!(uses_short(&map));
+
+        // a jam builds up on the short road, new routes take the detour
+        let jammed = short_lanes.iter().map(|&id| (id, (12, 0.0))).collect();
+        for _ in 0..150 {
+            map.update_traffic(&jammed);
+        }
+        assert!(!uses_short(&map));
+
+        // it takes a while for the jam to be forgotten once it's gone
+        let empty = Default::default();
+        map.update_traffic(&empty);
+        assert!(!uses_short(&map));
+        for _ in 0..300 {
+            map.update_traffic(&empty);
+        }
+        assert!(uses_short(&map));
+    }
+
+    #[test]
+    fn pedestrians_wait_on_refuge() {
+        use super::{ItineraryKind, Route};
+        use crate::map::{LightPolicy, RoadSegmentKind, Traversable, TraverseDirection, TurnKind};
+
+        let mut map = Map::empty();
+        let narrow = LanePatternBuilder::new().build();
+        let wide = LanePatternBuilder::new().n_lanes(2).build();
+
+        let center = map.add_intersection(vec3(0.0, 0.0, 0.3));
+        let mut arm = |pos: Vec3, pat| {
+            let i = map.add_intersection(pos);
+            map.connect(center, i, pat, RoadSegmentKind::Straight)
+                .unwrap()
+        };
+        let east = arm(vec3(100.0, 0.0, 0.3), &wide);
+        let north = arm(vec3(0.0, 100.0, 0.3), &narrow);
+        arm(vec3(-100.0, 0.0, 0.3), &wide);
+        arm(vec3(0.0, -100.0, 0.3), &narrow);
+        map.update_intersection(center, |i| i.light_policy = LightPolicy::Lights);
+
+        let crosswalk = |map: &Map, road| {
+            map.intersections[center]
+                .turns()
+                .find(|t| t.kind.is_crosswalk() && map.lanes[t.id.src].parent == road)
+                .unwrap()
+                .clone()
+        };
+        // narrow roads are crossed in one go
+        assert_eq!(crosswalk(&map, north).kind, TurnKind::Crosswalk);
+        let turn = crosswalk(&map, east);
+        assert_eq!(turn.kind, TurnKind::TwoStageCrosswalk);
+        let refuge = turn.refuge().unwrap();
+
+        let control = map.roads[east]
+            .incoming_lanes_to(center)
+            .iter()
+            .find(|(_, kind)| kind.vehicles())
+            .map(|&(id, _)| map.lanes[id].control)
+            .unwrap();
+        let green = (0..1000)
+            .find(|&t| !control.get_behavior(t).is_red())
+            .unwrap();
+        let red = (0..1000)
+            .find(|&t| control.get_behavior(t).is_red())
+            .unwrap();
+
+        let walk = |dir, time| {
+            let t = Traversable::new(TraverseKind::Turn(turn.id), dir);
+            let mut points = t.points(&map).unwrap().into_vec();
+            let end = *points.last().unwrap();
+            points.reverse();
+            let mut it = Itinerary {
+                kind: ItineraryKind::Route(
+                    Route {
+                        reversed_route: vec![],
+                        end_pos: end,
+                        cur: t,
+                        partial: false,
+                    },
+                    PathKind::Pedestrian,
+                ),
+                reversed_local_path: points,
+            };
+            let mut pos = it.get_point().unwrap();
+            for _ in 0..100 {
+                pos = it.update(pos, 1.0, Tick(0), time, &map, PathfindOptions::default());
+            }
+            (pos, end)
+        };
+
+        // only the half crossing the lanes coming into the intersection is signalized
+        let mut stopped = 0;
+        for dir in [TraverseDirection::Forward, TraverseDirection::Backward] {
+            let (pos, end) = walk(dir, green);
+            if pos.distance(refuge) < 0.1 {
+                stopped += 1;
+            } else {
+                assert!(pos.distance(end) < 0.1);
+            }
+
+            let (pos, end) = walk(dir, red);
+            assert!(pos.distance(end) < 0.1, "{} should have crossed", pos);
+        }
+        assert_eq!(stopped, 1);
+    }
+}
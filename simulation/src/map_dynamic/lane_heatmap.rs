@@ -0,0 +1,174 @@
+use crate::map::{LaneID, TraverseKind};
+use crate::Simulation;
+use common::FastMap;
+use geom::Color;
+
+/// Color used for lanes without any data
+pub const HEATMAP_NO_DATA: Color = Color::new(0.5, 0.5, 0.5, 0.5);
+
+/// Gives a scalar per lane so that debug overlays can color lanes by it.
+/// Lanes missing from the returned map have no data.
+pub trait LaneHeatmap {
+    fn name(&self) -> &'static str;
+
+    fn values(&self, sim: &Simulation) -> FastMap<LaneID, f32>;
+
+    /// Values are normalized from this range to [0; 1] before going through the color ramp
+    fn range(&self) -> (f32, f32);
+
+    fn color(&self, value: Option<f32>) -> Color {
+        let Some(v) = value else {
+            return HEATMAP_NO_DATA;
+        };
+        let (min, max) = self.range();
+        heatmap_color(Some((v - min) / (max - min)))
+    }
+}
+
+/// Maps a normalized value to a green -> yellow -> red ramp.
+/// Values outside of [0; 1] are clamped, None gives the neutral gray.
+pub fn heatmap_color(normalized: Option<f32>) -> Color {
+    const LOW: Color = Color::new(0.1, 0.8, 0.2, 0.8);
+    const MID: Color = Color::new(0.95, 0.85, 0.1, 0.8);
+    const HIGH: Color = Color::new(0.9, 0.1, 0.1, 0.8);
+
+    let Some(v) = normalized.filter(|v| v.is_finite()) else {
+        return HEATMAP_NO_DATA;
+    };
+    let v = v.clamp(0.0, 1.0);
+
+    let (a, b, t) = if v < 0.5 {
+        (LOW, MID, v * 2.0)
+    } else {
+        (MID, HIGH, v * 2.0 - 1.0)
+    };
+
+    Color::new(
+        a.r + (b.r - a.r) * t,
+        a.g + (b.g - a.g) * t,
+        a.b + (b.b - a.b) * t,
+        a.a + (b.a - a.a) * t,
+    )
+}
+
+/// Color of a lane of a route from its [`LaneCongestion`] value.
+/// Lanes without data are shown as free flowing.
+pub fn congestion_color(congestion: Option<f32>) -> Color {
+    let (min, max) = LaneCongestion.range();
+    let v = congestion.filter(|v| v.is_finite()).unwrap_or(min);
+    heatmap_color(Some((v - min) / (max - min)))
+}
+
+/// Number of vehicles per 100m on every vehicle lane
+pub struct LaneCongestion;
+
+impl LaneHeatmap for LaneCongestion {
+    fn name(&self) -> &'static str {
+        "congestion"
+    }
+
+    fn values(&self, sim: &Simulation) -> FastMap<LaneID, f32> {
+        let map = sim.map();
+        let mut counts: FastMap<LaneID, u32> = map
+            .lanes()
+            .iter()
+            .filter(|(_, l)| l.kind.vehicles())
+            .map(|(id, _)| (id, 0))
+            .collect();
+
+        for v in sim.world().vehicles.values() {
+            let Some(TraverseKind::Lane(id)) = v.it.get_travers().map(|t| t.kind) else {
+                continue;
+            };
+            if let Some(c) = counts.get_mut(&id) {
+                *c += 1;
+            }
+        }
+
+        counts
+            .into_iter()
+            .filter_map(|(id, c)| {
+                let len = map.lanes().get(id)?.points.length();
+                Some((id, c as f32 * 100.0 / len.max(1.0)))
+            })
+            .collect()
+    }
+
+    fn range(&self) -> (f32, f32) {
+        (0.0, 10.0)
+    }
+}
+
+/// Average speed of the vehicles on a lane relative to its speed limit, reversed so that slow is red.
+/// Lanes without vehicles have no data.
+pub struct LaneSlowdown;
+
+impl LaneHeatmap for LaneSlowdown {
+    fn name(&self) -> &'static str {
+        "slowdown"
+    }
+
+    fn values(&self, sim: &Simulation) -> FastMap<LaneID, f32> {
+        let map = sim.map();
+        let mut sums: FastMap<LaneID, (f32, u32)> = FastMap::default();
+
+        for v in sim.world().vehicles.values() {
+            let Some(TraverseKind::Lane(id)) = v.it.get_travers().map(|t| t.kind) else {
+                continue;
+            };
+            let Some(lane) = map.lanes().get(id) else {
+                continue;
+            };
+            let e = sums.entry(id).or_default();
+            e.0 += v.speed.0 / lane.speed_limit.max(0.1);
+            e.1 += 1;
+        }
+
+        sums.into_iter()
+            .map(|(id, (sum, n))| (id, 1.0 - sum / n as f32))
+            .collect()
+    }
+
+    fn range(&self) -> (f32, f32) {
+        (0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{congestion_color, heatmap_color, HEATMAP_NO_DATA};
+
+    #[test]
+    fn color_ramp() {
+        let low = heatmap_color(Some(0.0));
+        let mid = heatmap_color(Some(0.5));
+        let high = heatmap_color(Some(1.0));
+
+        assert!(low.g > low.r, "low values are green");
+        assert!(mid.r > 0.9 && mid.g > 0.8, "middle values are yellow");
+        assert!(high.r > high.g, "high values are red");
+
+        let quarter = heatmap_color(Some(0.25));
+        assert!(quarter.r > low.r && quarter.r < mid.r);
+
+        assert_eq!(heatmap_color(Some(-3.0)), low);
+        assert_eq!(heatmap_color(Some(7.0)), high);
+        assert_eq!(heatmap_color(None), HEATMAP_NO_DATA);
+        assert_eq!(heatmap_color(Some(f32::NAN)), HEATMAP_NO_DATA);
+    }
+
+    #[test]
+    fn route_congestion_colors() {
+        let free = congestion_color(Some(0.0));
+        assert!(free.g > free.r);
+        assert_eq!(congestion_color(None), free);
+        assert_eq!(congestion_color(Some(f32::NAN)), free);
+
+        let busy = congestion_color(Some(5.0));
+        assert!(busy.r > 0.9 && busy.g > 0.8);
+
+        let jammed = congestion_color(Some(10.0));
+        assert!(jammed.r > jammed.g);
+        assert_eq!(congestion_color(Some(50.0)), jammed);
+    }
+}
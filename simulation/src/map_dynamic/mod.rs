@@ -2,12 +2,18 @@ mod binfos;
 mod dispatch;
 mod electricity;
 mod itinerary;
+mod lane_heatmap;
 mod parking;
+mod pollution;
 mod router;
+mod traffic;
 
 pub use binfos::*;
 pub use dispatch::*;
 pub use electricity::*;
 pub use itinerary::*;
+pub use lane_heatmap::*;
 pub use parking::*;
+pub use pollution::*;
 pub use router::*;
+pub use traffic::*;
@@ -1,6 +1,6 @@
 use crate::map::{Lane, LaneKind, Map, ParkingSpot, ParkingSpotID, ParkingSpots};
 use common::AccessCmp;
-use geom::Vec3;
+use geom::{Spline3, Vec3};
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeSet;
 use std::option::Option::None;
@@ -123,4 +123,8 @@ impl SpotReservation {
     pub fn park_pos(&self, map: &Map) -> Option<Vec3> {
         map.parking_to_drive_pos(self.0)
     }
+
+    pub fn exit_path(&self, map: &Map) -> Option<Spline3> {
+        map.parking_exit_path(self.0)
+    }
 }
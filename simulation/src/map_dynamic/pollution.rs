@@ -0,0 +1,181 @@
+use crate::map::{LaneID, Map, TraverseKind};
+use crate::map_dynamic::LaneHeatmap;
+use crate::utils::resources::Resources;
+use crate::world::VehicleID;
+use crate::{Simulation, World};
+use common::FastMap;
+use prototypes::DELTA;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Pollution emitted per second by a vehicle standing still with its engine running
+pub const EMISSION_IDLE: f32 = 0.2;
+/// Pollution emitted per second for each m/s of speed
+pub const EMISSION_PER_SPEED: f32 = 0.05;
+/// Pollution emitted per second for each m/s² of acceleration, braking emits nothing more
+pub const EMISSION_PER_ACCEL: f32 = 0.5;
+/// Time for the pollution of a lane to halve without traffic, in seconds
+pub const POLLUTION_HALF_LIFE: f32 = 60.0;
+/// Levels below this are considered clean and forgotten
+const POLLUTION_EPSILON: f32 = 0.001;
+
+/// Pollution accumulated on each lane by the vehicles driving on it, decaying over time.
+/// Since it decays exponentially, constant traffic converges to a bounded level.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Pollution {
+    levels: BTreeMap<LaneID, f32>,
+    /// Speed of each vehicle at the previous update, to know how much it accelerated
+    last_speed: BTreeMap<VehicleID, f32>,
+}
+
+impl Pollution {
+    /// Current pollution of a lane, 0 for clean lanes
+    pub fn level(&self, lane: LaneID) -> f32 {
+        self.levels.get(&lane).copied().unwrap_or(0.0)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (LaneID, f32)> + '_ {
+        self.levels.iter().map(|(&id, &v)| (id, v))
+    }
+
+    /// Pollution emitted during dt by a vehicle going at speed with the given acceleration
+    pub fn emission(speed: f32, accel: f32, dt: f32) -> f32 {
+        (EMISSION_IDLE + EMISSION_PER_SPEED * speed.abs() + EMISSION_PER_ACCEL * accel.max(0.0))
+            * dt
+    }
+
+    pub fn emit(&mut self, lane: LaneID, amount: f32) {
+        *self.levels.entry(lane).or_default() += amount;
+    }
+
+    /// Decays every lane by dt seconds, lanes that became clean are removed
+    pub fn decay(&mut self, dt: f32) {
+        let factor = 0.5f32.powf(dt / POLLUTION_HALF_LIFE);
+        self.levels.retain(|_, v| {
+            *v *= factor;
+            *v > POLLUTION_EPSILON
+        });
+    }
+}
+
+pub fn pollution_system(world: &mut World, resources: &mut Resources) {
+    profiling::scope!("map_dynamic::pollution_system");
+    let pollution: &mut Pollution = &mut resources.write();
+    let map: &Map = &resources.read();
+
+    pollution.decay(DELTA);
+
+    let mut last_speed = BTreeMap::new();
+    for (id, v) in world.vehicles.iter() {
+        if !v.vehicle.state.is_on_road() {
+            continue;
+        }
+        let speed = v.speed.0;
+        last_speed.insert(id, speed);
+
+        let Some(TraverseKind::Lane(lane)) = v.it.get_travers().map(|t| t.kind) else {
+            continue;
+        };
+        if !map.lanes().contains_key(lane) {
+            continue;
+        }
+        let prev = pollution.last_speed.get(&id).copied().unwrap_or(speed);
+        let accel = (speed - prev) / DELTA;
+        pollution.emit(lane, Pollution::emission(speed, accel, DELTA));
+    }
+    pollution.last_speed = last_speed;
+
+    pollution
+        .levels
+        .retain(|&id, _| map.lanes().contains_key(id));
+}
+
+/// Pollution level of every lane, clean lanes have no data
+pub struct LanePollution;
+
+impl LaneHeatmap for LanePollution {
+    fn name(&self) -> &'static str {
+        "pollution"
+    }
+
+    fn values(&self, sim: &Simulation) -> FastMap<LaneID, f32> {
+        sim.read::<Pollution>().iter().collect()
+    }
+
+    fn range(&self) -> (f32, f32) {
+        (0.0, 200.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Pollution, POLLUTION_HALF_LIFE};
+    use crate::map::{LaneKind, PathKind};
+    use crate::map_dynamic::Itinerary;
+    use crate::tests::TestCtx;
+    use crate::transportation::{make_vehicle_entity, Vehicle, VehicleKind};
+    use crate::utils::rand_provider::RandProvider;
+    use crate::world::VehicleEnt;
+    use crate::ParCommandBuffer;
+    use geom::{vec3, Color, Transform};
+    use prototypes::Tick;
+
+    #[test]
+    fn pollution_rises_with_traffic_and_decays() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(300.0, 0.0, 0.0)]);
+
+        let map = test.g.map();
+        let lane = map
+            .lanes()
+            .values()
+            .find(|l| l.kind == LaneKind::Driving && l.points.first_dir().unwrap().x > 0.9)
+            .unwrap();
+        let lane_id = lane.id;
+        let starts = [20.0, 40.0, 60.0].map(|d| lane.points.point_dir_along(d));
+        let end = lane.points.point_along(lane.points.length() - 5.0);
+        let its = starts
+            .map(|(pos, _)| Itinerary::route(Tick(0), pos, end, &map, PathKind::Vehicle).unwrap());
+        drop(map);
+
+        for ((pos, dir), it) in starts.into_iter().zip(its) {
+            let vehicle =
+                Vehicle::new_driving(VehicleKind::Car, Color::WHITE, &mut RandProvider::new(1));
+            make_vehicle_entity(&mut test.g, Transform::new_dir(pos, dir), vehicle, it, true);
+        }
+
+        let mut prev = 0.0;
+        for _ in 0..50 {
+            test.tick();
+            let level = test.g.read::<Pollution>().level(lane_id);
+            assert!(level > prev, "pollution should rise with traffic");
+            prev = level;
+        }
+
+        // constant traffic converges instead of growing forever
+        let steady = 3.0 * Pollution::emission(20.0, 0.0, 1.0) * POLLUTION_HALF_LIFE
+            / std::f32::consts::LN_2;
+        assert!(prev < steady);
+
+        let vehicles: Vec<_> = test.g.world.vehicles.keys().collect();
+        test.g
+            .read::<ParCommandBuffer<VehicleEnt>>()
+            .kill_all(&vehicles);
+        test.tick();
+        let after_traffic = test.g.read::<Pollution>().level(lane_id);
+        assert!(after_traffic > 0.0 && after_traffic < prev);
+
+        let mut pollution = test.g.write::<Pollution>();
+        for _ in 0..10 {
+            pollution.decay(POLLUTION_HALF_LIFE);
+        }
+        let level = pollution.level(lane_id);
+        assert!(level < after_traffic / 1000.0, "{}", level);
+
+        for _ in 0..10 {
+            pollution.decay(POLLUTION_HALF_LIFE);
+        }
+        assert_eq!(pollution.level(lane_id), 0.0);
+        assert_eq!(pollution.iter().count(), 0);
+    }
+}
@@ -1,12 +1,15 @@
-use crate::map::{BuildingID, Map, PathKind};
+use crate::map::{
+    BuildingID, Map, PathKind, Pathfinder, Traversable, TraverseDirection, TraverseKind,
+};
 use crate::map_dynamic::{Itinerary, ParkingManagement, ParkingReserveError, SpotReservation};
 use crate::transportation::TransportGrid;
 use crate::transportation::{put_pedestrian_in_transport_grid, unpark, Location, VehicleState};
 use crate::utils::resources::Resources;
 use crate::world::{HumanEnt, HumanID, VehicleEnt, VehicleID};
-use crate::{ParCommandBuffer, World};
+use crate::{ParCommandBuffer, Simulation, SoulID, World};
 use egui_inspect::Inspect;
-use geom::{Spline3, Transform, Vec3};
+use geom::{Transform, Vec3};
+use prototypes::{GameDuration, Tick};
 use serde::{Deserialize, Serialize};
 use slotmapd::HopSlotMap;
 
@@ -19,6 +22,76 @@ pub struct Router {
     vehicle: Option<VehicleID>,
     pub personal_car: Option<VehicleID>,
     pub last_error: Option<RouterError>,
+    /// How often (in ticks) the driven vehicle re-evaluates its route.
+    /// None means the route is only recomputed when it becomes invalid.
+    pub repath_interval: Option<u32>,
+    last_repath: Tick,
+    /// Most recent completed trips, oldest first. Only filled if enabled in [`TripHistorySettings`]
+    #[inspect(skip)]
+    trips: Vec<TripRecord>,
+    #[inspect(skip)]
+    cur_trip: Option<TripRecord>,
+}
+
+/// Whether souls record their recent trips, off by default as it is only used for analytics
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct TripHistorySettings {
+    pub enabled: bool,
+    /// Maximum number of trips kept per soul, the oldest ones are dropped
+    pub capacity: usize,
+}
+
+impl Default for TripHistorySettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            capacity: 16,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TripMode {
+    Walk,
+    Drive,
+}
+
+/// Destinations whose route is longer than the maximum trip distance of a mode are unreachable
+/// in that mode. Those already farther as the crow flies are rejected without looking for a route,
+/// sparing the pathfinding of absurdly long routes.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct TripDistanceSettings {
+    pub max_walk: f32,
+    pub max_drive: f32,
+}
+
+impl Default for TripDistanceSettings {
+    fn default() -> Self {
+        Self {
+            max_walk: 5000.0,
+            max_drive: 200000.0,
+        }
+    }
+}
+
+impl TripDistanceSettings {
+    pub fn max_trip_distance(&self, mode: TripMode) -> f32 {
+        match mode {
+            TripMode::Walk => self.max_walk,
+            TripMode::Drive => self.max_drive,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct TripRecord {
+    /// None if the trip started outside
+    pub origin: Option<BuildingID>,
+    /// None if the destination was outside
+    pub destination: Option<BuildingID>,
+    pub start: Tick,
+    pub duration: GameDuration,
+    pub mode: TripMode,
 }
 
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
@@ -26,6 +99,8 @@ pub enum RouterError {
     ReservingParkingSpot(ParkingReserveError),
     TranslatingParkingSpotToDrivePos,
     LocatingVehicle,
+    /// The destination is beyond the maximum trip distance of every available mode
+    TooFar(TripMode),
 }
 
 debug_inspect_impl!(RouterError);
@@ -56,19 +131,37 @@ pub fn routing_changed_system(world: &mut World, resources: &mut Resources) {
     profiling::scope!("map_dynamic::routing_changed_system");
     let map: &Map = &resources.read();
     let parking: &mut ParkingManagement = &mut resources.write();
+    let trip_settings: &TripHistorySettings = &resources.read();
+    let distances: &TripDistanceSettings = &resources.read();
+    let tick = resources.tick();
 
     world.humans.values_mut().for_each(|h| {
         let router = &mut h.router;
         let loc = &h.location;
+        let start = match *loc {
+            Location::Outside => h.trans.pos,
+            Location::Vehicle(id) => world.vehicles.get(id).map_or(h.trans.pos, |v| v.trans.pos),
+            Location::Building(id) => map.buildings.get(id).map_or(h.trans.pos, |b| b.door_pos),
+        };
         if router.cur_dest == router.target_dest {
             return;
         }
         let dest = unwrap_ret!(router.target_dest);
 
         router.clear_steps(parking);
+        router.cur_trip = None;
         match dest {
             Destination::Outside(pos) => {
-                router.steps = match router.steps_to(pos, parking, map, loc, &world.vehicles) {
+                router.steps = match router.steps_to(
+                    start,
+                    pos,
+                    parking,
+                    map,
+                    loc,
+                    &world.vehicles,
+                    distances,
+                    tick,
+                ) {
                     Ok(x) => x,
                     Err(e) => {
                         router.last_error = Some(e);
@@ -92,7 +185,16 @@ pub fn routing_changed_system(world: &mut World, resources: &mut Resources) {
                     }
                 };
                 let door_pos = bobj.door_pos;
-                router.steps = match router.steps_to(door_pos, parking, map, loc, &world.vehicles) {
+                router.steps = match router.steps_to(
+                    start,
+                    door_pos,
+                    parking,
+                    map,
+                    loc,
+                    &world.vehicles,
+                    distances,
+                    tick,
+                ) {
                     Ok(x) => x,
                     Err(e) => {
                         router.last_error = Some(e);
@@ -105,6 +207,29 @@ pub fn routing_changed_system(world: &mut World, resources: &mut Resources) {
 
         router.cur_dest = router.target_dest;
 
+        if trip_settings.enabled {
+            router.cur_trip = Some(TripRecord {
+                origin: match *loc {
+                    Location::Building(b) => Some(b),
+                    _ => None,
+                },
+                destination: match dest {
+                    Destination::Building(b) => Some(b),
+                    Destination::Outside(_) => None,
+                },
+                start: tick,
+                duration: GameDuration(Tick(0)),
+                mode: match router
+                    .steps
+                    .iter()
+                    .any(|s| matches!(s, RoutingStep::DriveTo(..)))
+                {
+                    true => TripMode::Drive,
+                    false => TripMode::Walk,
+                },
+            });
+        }
+
         router.steps.reverse();
     });
 }
@@ -114,12 +239,23 @@ pub fn routing_update_system(world: &mut World, resources: &mut Resources) {
     let map: &Map = &resources.read();
     let cbuf_human: &ParCommandBuffer<HumanEnt> = &resources.read();
     let cbuf_vehicle: &ParCommandBuffer<VehicleEnt> = &resources.read();
+    let trip_settings: &TripHistorySettings = &resources.read();
+    let tick = resources.tick();
 
     world.humans.iter_mut().for_each(|(body, h)| {
         if h.router.cur_step.is_none() && h.router.steps.is_empty() {
             return;
         }
 
+        if let Some(RoutingStep::DriveTo(vehicle, dest)) = h.router.cur_step {
+            if let Some(v) = world.vehicles.get_mut(vehicle) {
+                if h.router.should_repath(tick, &v.it, map) {
+                    v.it = Itinerary::wait_for_reroute(PathKind::Vehicle, dest);
+                    h.router.last_repath = tick;
+                }
+            }
+        }
+
         let trans: &Transform = &h.trans;
         let itin: &Itinerary = &h.it;
 
@@ -172,7 +308,9 @@ pub fn routing_update_system(world: &mut World, resources: &mut Resources) {
                 RoutingStep::GetInVehicle(vehicle) => world
                     .vehicles
                     .get(vehicle)
-                    .map(|v| v.trans.pos.is_close(pos, 3.0))
+                    .map(|v| {
+                        v.trans.pos.is_close(pos, 3.0) && v.vehicle.can_board(SoulID::Human(body))
+                    })
                     .unwrap_or(true),
                 RoutingStep::GetOutVehicle(_) => true,
                 RoutingStep::GetInBuilding(build) => map
@@ -190,6 +328,13 @@ pub fn routing_update_system(world: &mut World, resources: &mut Resources) {
 
         h.router.cur_step = h.router.steps.pop();
 
+        if h.router.cur_step.is_none() {
+            if let Some(mut trip) = h.router.cur_trip.take() {
+                trip.duration = GameDuration(Tick(tick.0 - trip.start.0));
+                h.router.record_trip(trip, trip_settings.capacity);
+            }
+        }
+
         if let Some(ref mut next_step) = h.router.cur_step {
             match *next_step {
                 RoutingStep::WalkTo(obj) => {
@@ -200,6 +345,7 @@ pub fn routing_update_system(world: &mut World, resources: &mut Resources) {
                     if let Some(x) = world.vehicles.get_mut(vehicle) {
                         x.it = route
                     }
+                    h.router.last_repath = tick;
                 }
                 RoutingStep::Park(vehicle, ref mut spot) => {
                     if let Some(spot_resa) = spot.take() {
@@ -217,9 +363,15 @@ pub fn routing_update_system(world: &mut World, resources: &mut Resources) {
                     cbuf_vehicle.exec_ent(vehicle, move |sim| unpark(sim, vehicle));
                 }
                 RoutingStep::GetInVehicle(vehicle) => {
-                    if !world.vehicles.contains_key(vehicle) {
+                    let Some(v) = world.vehicles.get_mut(vehicle) else {
                         h.router.reset_dest();
                         return;
+                    };
+                    if !v.vehicle.board(SoulID::Human(body)) {
+                        // full, wait for a seat to free up
+                        h.router.steps.push(RoutingStep::GetInVehicle(vehicle));
+                        h.router.cur_step = None;
+                        return;
                     }
                     h.location = Location::Vehicle(vehicle);
                     walk_inside(body, h, cbuf_human);
@@ -227,8 +379,11 @@ pub fn routing_update_system(world: &mut World, resources: &mut Resources) {
                 RoutingStep::GetOutVehicle(vehicle) => {
                     let pos = world
                         .vehicles
-                        .get(vehicle)
-                        .map(|v| v.trans)
+                        .get_mut(vehicle)
+                        .map(|v| {
+                            v.vehicle.alight(SoulID::Human(body));
+                            v.trans
+                        })
                         .map(|vtrans| vtrans.pos + vtrans.dir.cross(Vec3::Z) * 2.0)
                         .unwrap_or(pos);
                     walk_outside(body, pos, cbuf_human, &mut h.location);
@@ -264,14 +419,33 @@ fn walk_inside(body: HumanID, h: &mut HumanEnt, cbuf: &ParCommandBuffer<HumanEnt
 fn walk_outside(body: HumanID, pos: Vec3, cbuf: &ParCommandBuffer<HumanEnt>, loc: &mut Location) {
     *loc = Location::Outside;
     cbuf.exec_ent(body, move |sim| {
-        let coll = put_pedestrian_in_transport_grid(&mut sim.write::<TransportGrid>(), pos);
+        let coll = put_pedestrian_in_transport_grid(&mut sim.write::<TransportGrid>(), pos, body);
         let h = unwrap_ret!(sim.world.humans.get_mut(body));
         h.trans.pos = pos;
         h.collider = Some(coll);
     });
 }
 
-fn park(map: &Map, vehicle: &mut VehicleEnt, spot_resa: SpotReservation) {
+/// Puts a human that was inside a vehicle back outside at the given position,
+/// for example when the vehicle is removed. Its current route is dropped.
+pub(crate) fn eject_from_vehicle(sim: &mut Simulation, human: HumanID, pos: Vec3) {
+    let h = unwrap_ret!(sim.world.humans.get_mut(human));
+    if !matches!(h.location, Location::Vehicle(_)) {
+        return;
+    }
+    h.location = Location::Outside;
+    h.trans.pos = pos;
+    h.router
+        .clear_steps(&mut sim.resources.write::<ParkingManagement>());
+    h.router.reset_dest();
+    h.collider = Some(put_pedestrian_in_transport_grid(
+        &mut sim.resources.write::<TransportGrid>(),
+        pos,
+        human,
+    ));
+}
+
+pub(crate) fn park(map: &Map, vehicle: &mut VehicleEnt, spot_resa: SpotReservation) {
     let trans = vehicle.trans;
     let spot = match spot_resa.get(&map.parking) {
         Some(x) => x,
@@ -281,18 +455,20 @@ fn park(map: &Map, vehicle: &mut VehicleEnt, spot_resa: SpotReservation) {
         }
     };
 
-    let s = Spline3 {
-        from: trans.pos,
-        to: spot.trans.pos,
-        from_derivative: trans.dir * 2.0,
-        to_derivative: spot.trans.dir * 2.0,
-    };
+    let s = spot.entry_path(trans);
 
-    vehicle.vehicle.state = VehicleState::RoadToPark(s, 0.0, spot_resa);
+    vehicle
+        .vehicle
+        .set_state(VehicleState::RoadToPark(s, 0.0, spot_resa));
     vehicle.speed.0 = 0.0;
 }
 
 impl Router {
+    /// The router has nothing left to do, the human is waiting for its next desire
+    pub fn is_idle(&self) -> bool {
+        self.cur_step.is_none() && self.steps.is_empty()
+    }
+
     pub fn new(personal_car: Option<VehicleID>) -> Self {
         Self {
             steps: vec![],
@@ -302,6 +478,40 @@ impl Router {
             vehicle: personal_car,
             cur_dest: None,
             last_error: None,
+            repath_interval: None,
+            last_repath: Tick::default(),
+            trips: vec![],
+            cur_trip: None,
+        }
+    }
+
+    /// Most recent completed trips, oldest first
+    pub fn trips(&self) -> &[TripRecord] {
+        &self.trips
+    }
+
+    fn record_trip(&mut self, trip: TripRecord, capacity: usize) {
+        if capacity == 0 {
+            return;
+        }
+        if self.trips.len() >= capacity {
+            self.trips.drain(..=self.trips.len() - capacity);
+        }
+        self.trips.push(trip);
+    }
+
+    /// Whether the route followed by the driven vehicle should be recomputed.
+    /// A route going through a removed lane or turn is always recomputed, regardless of the interval.
+    pub fn should_repath(&self, tick: Tick, it: &Itinerary, map: &Map) -> bool {
+        if it.get_route().is_none() {
+            return false;
+        }
+        if !it.cur_traversable_exists(map) {
+            return true;
+        }
+        match self.repath_interval {
+            Some(interval) if interval > 0 => tick.0 >= self.last_repath.0 + interval as u64,
+            _ => false,
         }
     }
 
@@ -332,20 +542,46 @@ impl Router {
         false
     }
 
+    /// The destination is reached by driving if the soul uses a vehicle, or if it is too far to walk
+    /// and the soul has a car of its own
+    #[allow(clippy::too_many_arguments)]
     fn steps_to(
         &mut self,
+        start: Vec3,
         obj: Vec3,
         parking: &mut ParkingManagement,
         map: &Map,
         loc: &Location,
         cars: &HopSlotMap<VehicleID, VehicleEnt>,
+        distances: &TripDistanceSettings,
+        tick: Tick,
     ) -> Result<Vec<RoutingStep>, RouterError> {
+        let too_far = |from: Vec3, mode: TripMode, kind: PathKind| {
+            let max = distances.max_trip_distance(mode);
+            from.xy().distance(obj.xy()) > max
+                || route_distance(map, tick, from, obj, kind).map_or(false, |d| d > max)
+        };
+
+        let mut vehicle = self.vehicle;
+        if vehicle.is_none() && too_far(start, TripMode::Walk, PathKind::Pedestrian) {
+            vehicle = self.personal_car;
+            if vehicle.is_none() {
+                return Err(RouterError::TooFar(TripMode::Walk));
+            }
+        }
+        if let Some(car) = vehicle {
+            let from = cars.get(car).map_or(start, |v| v.trans.pos);
+            if too_far(from, TripMode::Drive, PathKind::Vehicle) {
+                return Err(RouterError::TooFar(TripMode::Drive));
+            }
+        }
+
         let mut steps = vec![];
         if let Location::Building(cur_build) = loc {
             steps.push(RoutingStep::GetOutBuilding(*cur_build));
         }
 
-        if let Some(car) = self.vehicle {
+        if let Some(car) = vehicle {
             let spot_resa = parking
                 .reserve_near(obj, map)
                 .map_err(RouterError::ReservingParkingSpot)?;
@@ -378,3 +614,197 @@ impl Router {
         Ok(steps)
     }
 }
+
+/// Length of the shortest route between the two points, None if there is none
+fn route_distance(map: &Map, tick: Tick, start: Vec3, end: Vec3, kind: PathKind) -> Option<f32> {
+    let start_lane = kind.nearest_lane(map, start)?;
+    let end_lane = kind.nearest_lane(map, end)?;
+    let cur = Traversable::new(TraverseKind::Lane(start_lane), TraverseDirection::Forward);
+    let path = kind.path(map, tick, cur, end_lane)?;
+    Some(
+        path.iter()
+            .filter_map(|t| t.points(map))
+            .map(|p| p.length())
+            .sum(),
+    )
+}
+
+impl Simulation {
+    /// Recent trips of the soul, empty if it doesn't move around or trip history is disabled
+    pub fn soul_trips(&self, id: SoulID) -> &[TripRecord] {
+        match id {
+            SoulID::Human(h) => self.world.humans.get(h).map_or(&[], |h| h.router.trips()),
+            _ => &[],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::{LanePatternBuilder, ProjectFilter};
+    use crate::souls::human::{spawn_human, spawn_resident, HumanDecisionKind};
+    use crate::tests::TestCtx;
+    use geom::{vec2, vec3};
+
+    #[test]
+    fn repath_on_interval_and_lane_removal() {
+        let mut map = Map::empty();
+        let pat = LanePatternBuilder::new().build();
+        for (a, b) in [
+            (vec3(0.0, 0.0, 0.0), vec3(100.0, 0.0, 0.0)),
+            (vec3(100.0, 0.0, 0.0), vec3(100.0, 100.0, 0.0)),
+        ] {
+            let a = map.project(a, 0.0, ProjectFilter::ALL);
+            let b = map.project(b, 0.0, ProjectFilter::ALL);
+            map.make_connection(a, b, None, &pat);
+        }
+
+        let it = Itinerary::route(
+            Tick(0),
+            vec3(10.0, -2.0, 0.0),
+            vec3(98.0, 90.0, 0.0),
+            &map,
+            PathKind::Vehicle,
+        )
+        .unwrap();
+
+        let mut router = Router::new(None);
+        assert!(!router.should_repath(Tick(1000), &it, &map));
+
+        router.repath_interval = Some(10);
+        assert!(!router.should_repath(Tick(5), &it, &map));
+        assert!(router.should_repath(Tick(10), &it, &map));
+
+        router.last_repath = Tick(10);
+        assert!(!router.should_repath(Tick(15), &it, &map));
+
+        let lane = it.get_travers().unwrap().destination_lane();
+        let road = map.lanes()[lane].parent;
+        map.remove_road(road);
+
+        assert!(router.should_repath(Tick(15), &it, &map));
+        router.repath_interval = None;
+        assert!(router.should_repath(Tick(15), &it, &map));
+    }
+
+    #[test]
+    fn trips_are_recorded() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(100.0, 0.0, 0.0)]);
+        let b1 = test.build_house_near(vec2(10.0, 10.0));
+        let b2 = test.build_house_near(vec2(90.0, 10.0));
+        let human = spawn_human(&mut test.g, b1).unwrap();
+        test.g.write::<TripHistorySettings>().enabled = true;
+
+        let go_to = |test: &mut TestCtx, dest: BuildingID, n_trips: usize| {
+            test.g.world_mut_unchecked().humans[human].decision.kind =
+                HumanDecisionKind::GoTo(Destination::Building(dest));
+            for _ in 0..3000 {
+                test.tick();
+                if test.g.soul_trips(SoulID::Human(human)).len() == n_trips {
+                    return;
+                }
+            }
+            panic!("trip {} was not recorded", n_trips);
+        };
+
+        go_to(&mut test, b2, 1);
+        go_to(&mut test, b1, 2);
+
+        let trips = test.g.soul_trips(SoulID::Human(human));
+        assert_eq!(trips[0].origin, Some(b1));
+        assert_eq!(trips[0].destination, Some(b2));
+        assert_eq!(trips[1].origin, Some(b2));
+        assert_eq!(trips[1].destination, Some(b1));
+        assert!(trips[0].duration.0 .0 > 0);
+        assert!(trips[1].start.0 >= trips[0].start.0 + trips[0].duration.0 .0);
+
+        // bounded per soul, the oldest trip is dropped
+        let first = trips[0];
+        test.g.world_mut_unchecked().humans[human]
+            .router
+            .record_trip(first, 2);
+        let trips = test.g.soul_trips(SoulID::Human(human));
+        assert_eq!(trips.len(), 2);
+        assert_eq!(trips[0].origin, Some(b2));
+    }
+
+    #[test]
+    fn too_far_to_walk_drives_instead() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(400.0, 0.0, 0.0)]);
+        let home = test.build_house_near(vec2(10.0, 10.0));
+        let far = test.build_house_near(vec2(390.0, 10.0));
+        test.g.write::<TripDistanceSettings>().max_walk = 100.0;
+
+        let walker = spawn_human(&mut test.g, home).unwrap();
+        let owner = spawn_resident(&mut test.g, home).unwrap();
+        {
+            let humans = &mut test.g.world_mut_unchecked().humans;
+            humans[walker].router = Router::new(None);
+            // prefers walking but has a car
+            humans[owner].router.use_vehicle(None);
+            assert!(humans[owner].router.personal_car.is_some());
+            for h in [walker, owner] {
+                humans[h].decision.kind = HumanDecisionKind::GoTo(Destination::Building(far));
+            }
+        }
+        for _ in 0..10 {
+            test.tick();
+        }
+
+        let humans = &test.g.world.humans;
+        let walker = &humans[walker];
+        assert!(matches!(
+            walker.router.last_error,
+            Some(RouterError::TooFar(TripMode::Walk))
+        ));
+        assert!(walker.router.is_idle());
+        assert_eq!(walker.location, Location::Building(home));
+
+        let drives = |s: &RoutingStep| matches!(s, RoutingStep::DriveTo(..));
+        let owner = &humans[owner].router;
+        assert!(owner.last_error.is_none());
+        assert!(owner.steps.iter().chain(&owner.cur_step).any(drives));
+    }
+
+    #[test]
+    fn walking_cap_follows_the_route() {
+        let mut test = TestCtx::new();
+        // the two houses face each other across a gap, but the way around is long
+        test.build_roads(&[
+            vec3(0.0, 0.0, 0.0),
+            vec3(0.0, 300.0, 0.0),
+            vec3(100.0, 300.0, 0.0),
+            vec3(100.0, 0.0, 0.0),
+        ]);
+        let home = test.build_house_near(vec2(20.0, 20.0));
+        let far = test.build_house_near(vec2(80.0, 20.0));
+        let map = test.g.map();
+        let (a, b) = (
+            map.buildings()[home].door_pos,
+            map.buildings()[far].door_pos,
+        );
+        let route = route_distance(&map, Tick(0), a, b, PathKind::Pedestrian).unwrap();
+        drop(map);
+        assert!(a.xy().distance(b.xy()) < 150.0);
+        assert!(route > 400.0, "{}", route);
+        test.g.write::<TripDistanceSettings>().max_walk = 300.0;
+
+        let walker = spawn_human(&mut test.g, home).unwrap();
+        {
+            let h = &mut test.g.world_mut_unchecked().humans[walker];
+            h.router = Router::new(None);
+            h.decision.kind = HumanDecisionKind::GoTo(Destination::Building(far));
+        }
+        for _ in 0..10 {
+            test.tick();
+        }
+
+        assert!(matches!(
+            test.g.world.humans[walker].router.last_error,
+            Some(RouterError::TooFar(TripMode::Walk))
+        ));
+    }
+}
@@ -0,0 +1,149 @@
+use crate::map::{LaneID, Map, TraverseKind};
+use crate::utils::resources::Resources;
+use crate::World;
+use std::collections::BTreeMap;
+
+/// Updates the traffic of every lane from the vehicles on it, so that new routes avoid the
+/// congested lanes. Runs before any system routing vehicles so that they all see the same traffic.
+pub fn lane_traffic_system(world: &mut World, resources: &mut Resources) {
+    profiling::scope!("map_dynamic::lane_traffic_system");
+    let map: &mut Map = &mut resources.write();
+
+    let mut on_lanes: BTreeMap<LaneID, (u32, f32)> = BTreeMap::new();
+    for v in world.vehicles.values() {
+        if !v.vehicle.state.is_on_road() {
+            continue;
+        }
+        let Some(TraverseKind::Lane(lane)) = v.it.get_travers().map(|t| t.kind) else {
+            continue;
+        };
+        let e = on_lanes.entry(lane).or_default();
+        e.0 += 1;
+        e.1 += v.speed.0;
+    }
+
+    map.update_traffic(&on_lanes);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::map::{
+        LaneKind, LanePatternBuilder, Map, PathKind, ProjectFilter, RoadID, TraverseKind,
+    };
+    use crate::map_dynamic::Itinerary;
+    use crate::tests::TestCtx;
+    use crate::transportation::{spawn_driving_vehicle, Vehicle, VehicleKind, VehicleState};
+    use crate::RandProvider;
+    use geom::{vec3, Color, Transform, Vec3};
+    use prototypes::{Tick, DELTA};
+
+    fn goes_through(it: &Itinerary, map: &Map, road: RoadID) -> bool {
+        let r = it.get_route().unwrap();
+        r.reversed_route
+            .iter()
+            .chain(std::iter::once(&r.cur))
+            .any(|t| match t.kind {
+                TraverseKind::Lane(id) => map.lanes()[id].parent == road,
+                TraverseKind::Turn(_) => false,
+            })
+    }
+
+    /// Position on the eastbound driving lane of the road closest to (x, y)
+    fn eastbound(map: &Map, x: f32, y: f32) -> Vec3 {
+        let p = vec3(x, y, 0.0);
+        map.lanes()
+            .values()
+            .filter(|l| {
+                l.kind == LaneKind::Driving && l.points.first_dir().map_or(false, |d| d.x > 0.5)
+            })
+            .map(|l| l.points.project(p))
+            .min_by(|a, b| a.distance(p).total_cmp(&b.distance(p)))
+            .unwrap()
+    }
+
+    #[test]
+    fn traffic_spreads_around_a_bottleneck() {
+        let mut test = TestCtx::new();
+        let pat = LanePatternBuilder::new().parking(false).build();
+        let (short, detour) = {
+            let mut map = test.g.map_mut();
+            let mut road = |from: Vec3, to: Vec3| {
+                let a = map.project(from, 0.0, ProjectFilter::ALL);
+                let b = map.project(to, 0.0, ProjectFilter::ALL);
+                map.make_connection(a, b, None, &pat).unwrap().1
+            };
+            road(vec3(0.0, 0.0, 0.0), vec3(100.0, 0.0, 0.0));
+            let short = road(vec3(100.0, 0.0, 0.0), vec3(200.0, 0.0, 0.0));
+            road(vec3(200.0, 0.0, 0.0), vec3(300.0, 0.0, 0.0));
+            let detour = road(vec3(100.0, 0.0, 0.0), vec3(200.0, 100.0, 0.0));
+            road(vec3(200.0, 100.0, 0.0), vec3(300.0, 0.0, 0.0));
+            road(vec3(300.0, 0.0, 0.0), vec3(400.0, 0.0, 0.0));
+            (short, detour)
+        };
+
+        let spawn = |test: &mut TestCtx, from: Vec3| {
+            let map = test.g.map();
+            let to = eastbound(&map, 390.0, 0.0);
+            let it = Itinerary::route(Tick(0), from, to, &map, PathKind::Vehicle).unwrap();
+            let on_detour = goes_through(&it, &map, detour);
+            drop(map);
+            let vehicle = Vehicle::new_driving(
+                VehicleKind::Car,
+                Color::WHITE,
+                &mut test.g.write::<RandProvider>(),
+            );
+            let id =
+                spawn_driving_vehicle(&mut test.g, Transform::new_dir(from, Vec3::X), vehicle, it);
+            (id, on_detour)
+        };
+
+        // a car broken down at the end of the short road
+        let broken_pos = eastbound(&test.g.map(), 190.0, 0.0);
+        let (broken, _) = spawn(&mut test, broken_pos);
+        let broken = broken.unwrap();
+        test.tick();
+        {
+            let v = &mut test.g.world_mut_unchecked().vehicles[broken];
+            v.vehicle.wait_time = f32::MAX;
+            v.vehicle.state = VehicleState::Yielding;
+        }
+
+        let start = eastbound(&test.g.map(), 10.0, 0.0);
+        let mut routes = vec![];
+        let spawn_every = (2.0 / DELTA) as usize;
+        for t in 0..(55.0 / DELTA) as usize {
+            if t % spawn_every == 0 && t < (40.0 / DELTA) as usize {
+                let (_, on_detour) = spawn(&mut test, start);
+                routes.push(on_detour);
+            }
+            test.tick();
+        }
+
+        // the first cars take the shortest road and queue behind the broken down car,
+        // then the jam sends the next ones on the parallel road
+        assert!(!routes[0]);
+        let n_detour = routes.iter().filter(|&&d| d).count();
+        assert!(n_detour >= 3, "{:?}", routes);
+        assert!(*routes.last().unwrap(), "{:?}", routes);
+
+        let map = test.g.map();
+        let short_cars = test
+            .g
+            .world
+            .vehicles
+            .values()
+            .filter(|v| {
+                matches!(v.it.get_travers().map(|t| t.kind), Some(TraverseKind::Lane(l)) if map.lanes()[l].parent == short)
+            })
+            .count();
+        let detour_cars = test
+            .g
+            .world
+            .vehicles
+            .values()
+            .filter(|v| v.trans.pos.y > 20.0)
+            .count();
+        assert!(short_cars > 1);
+        assert!(detour_cars > 0);
+    }
+}
@@ -3,6 +3,17 @@ use serde::{Deserialize, Serialize};
 
 pub mod chat;
 
+/// Identifies a player, the same on every client of a multiplayer game.
+/// Commands not sent through the network are from [`PlayerID::LOCAL`].
+#[derive(
+    Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub struct PlayerID(pub u32);
+
+impl PlayerID {
+    pub const LOCAL: PlayerID = PlayerID(0);
+}
+
 #[derive(Default, Serialize, Deserialize)]
 pub struct MultiplayerState {
     pub chat: Chat,
@@ -0,0 +1,186 @@
+//! Scenarios describe an initial map, some spawns and commands to run at tick 0.
+//! They are meant for reproducible demos and regression tests and are stored as JSON files.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use common::saveload::Encoder;
+use geom::{Vec2, Vec3};
+
+use crate::expectation::Expectation;
+use crate::map::{LanePatternBuilder, ProjectFilter};
+use crate::transportation::{spawn_parked_vehicle, VehicleKind};
+use crate::world_command::WorldCommand;
+use crate::{Simulation, SimulationOptions};
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Scenario {
+    pub options: ScenarioOptions,
+    /// Lane patterns that can be referenced by name from roads, on top of the builtin ones
+    pub patterns: BTreeMap<String, LanePatternBuilder>,
+    pub roads: Vec<ScenarioRoad>,
+    pub spawns: Vec<ScenarioSpawn>,
+    /// Commands applied after the roads and spawns
+    pub commands: Vec<WorldCommand>,
+    /// Checked by [`crate::expectation::run_expectations`] in headless runs
+    pub expectations: Vec<Expectation>,
+}
+
+#[derive(Debug, Default, Copy, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScenarioOptions {
+    /// No terrain is generated by default so that scenarios load quickly
+    pub terrain_size: u16,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScenarioRoad {
+    pub from: Vec3,
+    pub to: Vec3,
+    #[serde(default)]
+    pub interpoint: Option<Vec2>,
+    pub pattern: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScenarioSpawn {
+    pub kind: VehicleKind,
+    /// The vehicle is parked in the closest free spot
+    pub near: Vec3,
+}
+
+#[derive(Debug)]
+pub enum ScenarioError {
+    /// The file could not be read or parsed
+    Io(std::io::Error),
+    /// The extension of the file isn't one of a supported format, only JSON is
+    UnsupportedFormat(String),
+    /// A road references a lane pattern that is neither builtin nor defined in the scenario
+    UnknownPattern(String),
+    /// A road could not be built (for example because it overlaps another one)
+    RoadFailed(usize),
+    /// No parking spot was found to spawn the vehicle
+    SpawnFailed(usize),
+}
+
+impl Display for ScenarioError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScenarioError::Io(err) => write!(f, "could not load scenario: {err}"),
+            ScenarioError::UnsupportedFormat(ext) => {
+                write!(
+                    f,
+                    "unsupported scenario format '{ext}', only .json is supported"
+                )
+            }
+            ScenarioError::UnknownPattern(name) => write!(f, "unknown lane pattern: {name}"),
+            ScenarioError::RoadFailed(i) => write!(f, "could not build road #{i}"),
+            ScenarioError::SpawnFailed(i) => {
+                write!(f, "could not find a parking spot for spawn #{i}")
+            }
+        }
+    }
+}
+
+impl Error for ScenarioError {}
+
+impl Scenario {
+    pub fn builtin_pattern(name: &str) -> Option<LanePatternBuilder> {
+        Some(match name {
+            "default" => LanePatternBuilder::new(),
+            "one_way" => LanePatternBuilder::new().one_way(true),
+            "rail" => LanePatternBuilder::new().rail(true),
+            "tram" => LanePatternBuilder::new().tram(true),
+            _ => return None,
+        })
+    }
+
+    pub fn pattern(&self, name: &str) -> Result<LanePatternBuilder, ScenarioError> {
+        self.patterns
+            .get(name)
+            .copied()
+            .or_else(|| Self::builtin_pattern(name))
+            .ok_or_else(|| ScenarioError::UnknownPattern(name.to_string()))
+    }
+
+    /// Checks that everything referenced by the scenario exists, so nothing gets applied
+    /// if the scenario is invalid
+    pub fn validate(&self) -> Result<(), ScenarioError> {
+        for road in &self.roads {
+            self.pattern(&road.pattern)?;
+        }
+        Ok(())
+    }
+
+    /// Applies the scenario to a freshly created simulation
+    pub fn apply(&self, sim: &mut Simulation) -> Result<(), ScenarioError> {
+        self.validate()?;
+
+        for (i, road) in self.roads.iter().enumerate() {
+            let pat = self.pattern(&road.pattern)?.build();
+            let mut map = sim.map_mut();
+            let from = map.project(road.from, 0.0, ProjectFilter::ALL);
+            let to = map.project(road.to, 0.0, ProjectFilter::ALL);
+            map.make_connection(from, to, road.interpoint, &pat)
+                .ok_or(ScenarioError::RoadFailed(i))?;
+        }
+
+        for (i, spawn) in self.spawns.iter().enumerate() {
+            spawn_parked_vehicle(sim, spawn.kind, spawn.near)
+                .ok_or(ScenarioError::SpawnFailed(i))?;
+        }
+
+        for command in &self.commands {
+            command.apply(sim);
+        }
+
+        Ok(())
+    }
+
+    /// Reads the scenario file at the given path, the format is chosen from its extension.
+    /// Only JSON scenarios are supported: RON would need a parser the game doesn't depend on,
+    /// so `.ron` files are rejected with [`ScenarioError::UnsupportedFormat`]
+    pub fn load(path: impl AsRef<Path>) -> Result<Scenario, ScenarioError> {
+        let path = path.as_ref();
+        let ext = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if ext != "json" {
+            return Err(ScenarioError::UnsupportedFormat(ext));
+        }
+
+        let data = common::saveload::load_raw(path).map_err(ScenarioError::Io)?;
+        let scenario: Scenario =
+            common::saveload::JSON::decode(&data).map_err(ScenarioError::Io)?;
+        scenario.validate()?;
+        Ok(scenario)
+    }
+
+    /// Creates a new simulation and applies the scenario to it
+    pub fn create_sim(&self) -> Result<Simulation, ScenarioError> {
+        self.validate()?;
+
+        let mut sim = Simulation::new_with_options(SimulationOptions {
+            terrain_size: self.options.terrain_size,
+            save_replay: false,
+            ..Default::default()
+        });
+        self.apply(&mut sim)?;
+
+        Ok(sim)
+    }
+}
+
+impl Simulation {
+    /// Creates a new simulation from the scenario file at the given path
+    pub fn load_scenario(path: impl AsRef<Path>) -> Result<Simulation, ScenarioError> {
+        Scenario::load(path)?.create_sim()
+    }
+}
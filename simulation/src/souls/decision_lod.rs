@@ -0,0 +1,104 @@
+use geom::Vec3;
+use prototypes::Tick;
+use serde::{Deserialize, Serialize};
+
+/// Level of detail for soul decisions: souls far from the focus point (the camera in singleplayer)
+/// pick what to do next only every `decision_interval` ticks.
+/// Following the route once it is chosen is still done every tick.
+///
+/// The focus is part of the simulation: it only changes through [`WorldCommand::SetDecisionFocus`]
+/// so replays and other clients make the same decisions.
+///
+/// [`WorldCommand::SetDecisionFocus`]: crate::world_command::WorldCommand::SetDecisionFocus
+#[derive(Serialize, Deserialize)]
+pub struct DecisionLod {
+    /// None disables the LOD, all souls are updated every tick
+    pub focus: Option<Vec3>,
+    /// Souls within this distance of the focus are updated every tick
+    pub near_dist: f32,
+    /// Number of ticks between two updates of a far soul
+    pub decision_interval: u32,
+}
+
+impl Default for DecisionLod {
+    fn default() -> Self {
+        Self {
+            focus: None,
+            near_dist: 500.0,
+            decision_interval: 8,
+        }
+    }
+}
+
+impl DecisionLod {
+    /// Number of ticks between two decision updates for a soul at the given position
+    pub fn decision_interval(&self, pos: Vec3) -> u32 {
+        let Some(focus) = self.focus else {
+            return 1;
+        };
+        if focus.is_close(pos, self.near_dist) {
+            return 1;
+        }
+        self.decision_interval.max(1)
+    }
+
+    /// Whether the soul should be updated this tick.
+    /// `stagger` spreads the updates of far souls over the interval.
+    /// As the interval is computed from the current position, a soul getting near
+    /// is updated on the very next tick.
+    pub fn should_update(&self, tick: Tick, stagger: u64, pos: Vec3) -> bool {
+        let interval = self.decision_interval(pos) as u64;
+        (tick.0 + stagger) % interval == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geom::vec3;
+
+    #[test]
+    fn far_souls_update_less_often() {
+        let lod = DecisionLod {
+            focus: Some(Vec3::ZERO),
+            near_dist: 100.0,
+            decision_interval: 10,
+        };
+
+        let positions = [
+            vec3(0.0, 0.0, 0.0),
+            vec3(50.0, 50.0, 0.0),
+            vec3(300.0, 0.0, 0.0),
+            vec3(1000.0, 1000.0, 0.0),
+        ];
+        let mut updates = [0; 4];
+        for tick in 0..100 {
+            for (i, &pos) in positions.iter().enumerate() {
+                if lod.should_update(Tick(tick), i as u64, pos) {
+                    updates[i] += 1;
+                }
+            }
+        }
+        assert_eq!(updates, [100, 100, 10, 10]);
+
+        let disabled = DecisionLod::default();
+        assert_eq!(disabled.decision_interval(vec3(1000.0, 1000.0, 0.0)), 1);
+    }
+
+    #[test]
+    fn far_to_near_resumes_immediately() {
+        let lod = DecisionLod {
+            focus: Some(Vec3::ZERO),
+            near_dist: 100.0,
+            decision_interval: 10,
+        };
+        let far = vec3(300.0, 0.0, 0.0);
+        let near = vec3(10.0, 0.0, 0.0);
+
+        assert!(lod.should_update(Tick(0), 0, far));
+        assert!(!lod.should_update(Tick(1), 0, far));
+        // the soul got near, it is updated every tick from now on
+        assert!(lod.should_update(Tick(2), 0, near));
+        assert!(lod.should_update(Tick(3), 0, near));
+    }
+}
@@ -0,0 +1,343 @@
+use crate::economy::{find_trade_place, Market, Trade};
+use crate::map::{BuildingID, Map, PathKind};
+use crate::map_dynamic::{park, BuildingInfos, Itinerary, ParkingManagement};
+use crate::souls::desire::WorkKind;
+use crate::transportation::{unpark, Location, VehicleState};
+use crate::utils::resources::Resources;
+use crate::world::{HumanEnt, VehicleEnt, VehicleID};
+use crate::{ParCommandBuffer, SoulID, World};
+use prototypes::ItemID;
+use serde::{Deserialize, Serialize};
+
+/// Distance to the company door under which a returning truck parks
+const PARK_DIST: f32 = 50.0;
+
+/// Goods sold by a company that one of its trucks needs to carry to the buyer.
+/// The buyer only gets the goods once the truck arrives.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeliveryJob {
+    pub buyer: SoulID,
+    pub destination: BuildingID,
+    pub kind: ItemID,
+    pub qty: i32,
+}
+
+impl DeliveryJob {
+    pub fn from_trade(trade: &Trade, binfos: &BuildingInfos) -> Option<Self> {
+        Some(Self {
+            buyer: trade.buyer.0,
+            destination: find_trade_place(trade.buyer, binfos)?,
+            kind: trade.kind,
+            qty: trade.qty,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TruckState {
+    Delivering(DeliveryJob),
+    /// Going back to the company to park
+    Returning,
+}
+
+/// Sets the delivery of the driver, who rides the truck while it is set
+fn set_deliver_order(h: &mut HumanEnt, order: Option<BuildingID>) {
+    if let Some(WorkKind::Driver {
+        ref mut deliver_order,
+        ..
+    }) = h.work.as_mut().map(|w| &mut w.kind)
+    {
+        *deliver_order = order;
+    }
+}
+
+/// The truck of the driver if it is at its workplace and free to deliver
+fn available_truck(h: &HumanEnt) -> Option<VehicleID> {
+    let w = h.work.as_ref()?;
+    let WorkKind::Driver {
+        deliver_order: None,
+        truck,
+    } = w.kind
+    else {
+        return None;
+    };
+    (h.location == Location::Building(w.workplace) && h.router.is_idle()).then_some(truck)
+}
+
+/// Turns the trades sold by companies into delivery jobs, and sends the company's driver in a parked
+/// truck to deliver them. Jobs stay queued until the driver and its truck are available.
+pub fn delivery_system(world: &mut World, res: &mut Resources) {
+    profiling::scope!("souls::delivery_system");
+    let cbuf: &ParCommandBuffer<VehicleEnt> = &res.read();
+    let binfos: &BuildingInfos = &res.read();
+    let map: &Map = &res.read();
+    let market: &mut Market = &mut res.write();
+    let pm: &mut ParkingManagement = &mut res.write();
+    let tick = res.tick();
+
+    for (_, c) in world.companies.iter_mut() {
+        if c.comp.trucks.is_empty() {
+            continue;
+        }
+        if c.comp
+            .driver
+            .map_or(false, |d| !world.humans.contains_key(d))
+        {
+            c.comp.driver = None;
+        }
+        let Some(b) = map.buildings().get(c.comp.building) else {
+            continue;
+        };
+        let door = b.door_pos;
+
+        for trade in c.sold.0.drain(..) {
+            let Some(mut job) = DeliveryJob::from_trade(&trade, binfos) else {
+                log::warn!("can't find the place to deliver for {:?}", &trade);
+                continue;
+            };
+            // the goods are in transit until the truck arrives, the buyer may already have used some
+            job.qty = job.qty.min(market.capital(job.buyer, job.kind));
+            if job.qty <= 0 {
+                continue;
+            }
+            market.produce(job.buyer, job.kind, -job.qty);
+            c.comp.deliveries.push_back(job);
+        }
+
+        let workplace = c.comp.building;
+        let driver = c.comp.driver;
+        let humans = &mut world.humans;
+        // the driver gets out of the truck once it's back, or if it disappeared
+        let mut back_at_work = |truck: VehicleID| {
+            let Some(h) = driver.and_then(|d| humans.get_mut(d)) else {
+                return;
+            };
+            if h.location == Location::Vehicle(truck) {
+                h.location = Location::Building(workplace);
+            }
+            set_deliver_order(h, None);
+        };
+
+        c.comp.truck_states.retain(|&truck, state| {
+            let Some(v) = world.vehicles.get_mut(truck) else {
+                if let TruckState::Delivering(job) = state {
+                    c.comp.deliveries.push_front(job.clone());
+                }
+                back_at_work(truck);
+                return false;
+            };
+
+            match state {
+                TruckState::Delivering(job) => {
+                    if !v.it.has_ended(0.0) {
+                        return true;
+                    }
+                    market.produce(job.buyer, job.kind, job.qty);
+                    if let SoulID::FreightStation(f) = job.buyer {
+                        if let Some(f) = world.freight_stations.get_mut(f) {
+                            f.f.waiting_cargo += 1;
+                        }
+                    }
+                    v.it = Itinerary::route(tick, v.trans.pos, door, map, PathKind::Vehicle)
+                        .unwrap_or(Itinerary::NONE);
+                    *state = TruckState::Returning;
+                    true
+                }
+                TruckState::Returning => {
+                    if matches!(v.vehicle.state, VehicleState::Parked(_)) {
+                        if let Some(d) = driver {
+                            v.vehicle.alight(SoulID::Human(d));
+                        }
+                        back_at_work(truck);
+                        return false;
+                    }
+                    if !v.vehicle.state.is_on_road() || !v.it.has_ended(0.0) {
+                        return true;
+                    }
+                    if !v.trans.pos.is_close(door, PARK_DIST) {
+                        if let Some(it) =
+                            Itinerary::route(tick, v.trans.pos, door, map, PathKind::Vehicle)
+                        {
+                            v.it = it;
+                        }
+                        return true;
+                    }
+                    if let Ok(spot) = pm.reserve_near(door, map) {
+                        park(map, v, spot);
+                    }
+                    true
+                }
+            }
+        });
+
+        while let Some(job) = c.comp.deliveries.front() {
+            let Some(dest) = map.buildings().get(job.destination) else {
+                // nowhere to carry the goods anymore, give them back to the buyer
+                let job = c.comp.deliveries.pop_front().unwrap();
+                market.produce(job.buyer, job.kind, job.qty);
+                continue;
+            };
+
+            let Some(driver) = driver else {
+                break;
+            };
+            let Some(h) = world.humans.get_mut(driver) else {
+                break;
+            };
+            let Some(truck) = available_truck(h).filter(|t| {
+                !c.comp.truck_states.contains_key(t)
+                    && world.vehicles.get(*t).map_or(false, |v| {
+                        matches!(v.vehicle.state, VehicleState::Parked(_))
+                    })
+            }) else {
+                break;
+            };
+
+            let v = world.vehicles.get_mut(truck).unwrap();
+            let Some(it) =
+                Itinerary::route(tick, v.trans.pos, dest.door_pos, map, PathKind::Vehicle)
+            else {
+                break;
+            };
+            if !v.vehicle.board(SoulID::Human(driver)) {
+                break;
+            }
+            v.it = it;
+            cbuf.exec_ent(truck, move |sim| unpark(sim, truck));
+
+            h.location = Location::Vehicle(truck);
+            set_deliver_order(h, Some(job.destination));
+
+            let job = c.comp.deliveries.pop_front().unwrap();
+            c.comp
+                .truck_states
+                .insert(truck, TruckState::Delivering(job));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use geom::{vec2, vec3, OBB};
+    use prototypes::{BuildingGen, GoodsCompanyID, ItemID, Money};
+
+    use crate::economy::{Market, Trade, TradeTarget};
+    use crate::map::ActivityCurve;
+    use crate::map_dynamic::BuildingInfos;
+    use crate::souls::delivery::TruckState;
+    use crate::souls::desire::{Work, WorkKind};
+    use crate::souls::human::spawn_human;
+    use crate::tests::TestCtx;
+    use crate::transportation::{Location, VehicleState};
+    use crate::{BuildingKind, SoulID, WorldCommand};
+
+    #[test]
+    fn goods_are_delivered_by_truck() {
+        let mut test = TestCtx::new();
+
+        test.build_roads(&[vec3(0., 0., 0.), vec3(200., 0., 0.)]);
+
+        let company = |door: f32| WorldCommand::MapBuildSpecialBuilding {
+            pos: OBB::new(vec2(door, 30.0), vec2(1.0, 0.0), 10.0, 10.0),
+            kind: BuildingKind::GoodsCompany(GoodsCompanyID::new("cereal-farm")),
+            gen: BuildingGen::NoWalkway {
+                door_pos: vec2(door, 20.0),
+            },
+            zone: None,
+            connected_road: None,
+            door_offset: 0,
+        };
+        test.apply(&[company(40.0), company(160.0)]);
+        test.tick();
+        // the game starts at 8am so the driver is at work
+        let house = test.build_house_near(vec2(100.0, 30.0));
+
+        let binfos = test.g.read::<BuildingInfos>();
+        let mut souls = test
+            .g
+            .map()
+            .buildings()
+            .iter()
+            .filter(|(_, b)| matches!(b.kind, BuildingKind::GoodsCompany(_)))
+            .map(|(id, b)| (b.door_pos.x, binfos.owner(id).unwrap()))
+            .collect::<Vec<_>>();
+        drop(binfos);
+        souls.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let (SoulID::GoodsCompany(producer), consumer) = (souls[0].1, souls[1].1) else {
+            panic!()
+        };
+
+        let item = ItemID::new("flour");
+        test.g.write::<Market>().produce(consumer, item, 5);
+
+        let driver = spawn_human(&mut test.g, house).unwrap();
+        let world = test.g.world_mut_unchecked();
+        let comp = world.companies.get_mut(producer).unwrap();
+        let truck = comp.comp.trucks[0];
+        let workplace = comp.comp.building;
+        comp.comp.driver = Some(driver);
+        let h = world.humans.get_mut(driver).unwrap();
+        h.location = Location::Building(workplace);
+        h.work = Some(Work::new(
+            workplace,
+            WorkKind::Driver {
+                deliver_order: None,
+                truck,
+            },
+            &ActivityCurve::FLAT,
+            0.0,
+        ));
+        let comp = world.companies.get_mut(producer).unwrap();
+        comp.sold.0.push(Trade {
+            buyer: TradeTarget(consumer),
+            seller: TradeTarget(SoulID::GoodsCompany(producer)),
+            qty: 1,
+            kind: item,
+            money_delta: Money::ZERO,
+        });
+
+        let mut dispatched = false;
+        for _ in 0..3000 {
+            test.tick();
+
+            let comp = &test.g.get(producer).unwrap().comp;
+            let capital = test.g.read::<Market>().capital(consumer, item);
+            match comp.truck_states.get(&truck) {
+                Some(TruckState::Delivering(_)) => {
+                    dispatched = true;
+                    assert_eq!(capital, 4, "goods should be in transit");
+                    let v = test.g.get(truck).unwrap();
+                    assert!(v.vehicle.passengers.contains(&SoulID::Human(driver)));
+                    assert_eq!(
+                        test.g.get(driver).unwrap().location,
+                        Location::Vehicle(truck)
+                    );
+                }
+                Some(TruckState::Returning) => {
+                    assert!(dispatched);
+                    assert_eq!(capital, 5);
+                    assert!(comp.deliveries.is_empty());
+                }
+                None => {
+                    if dispatched {
+                        let v = test.g.get(truck).unwrap();
+                        assert!(matches!(v.vehicle.state, VehicleState::Parked(_)));
+                        assert!(v.vehicle.passengers.is_empty());
+                        let h = test.g.get(driver).unwrap();
+                        assert_eq!(h.location, Location::Building(workplace));
+                        assert!(matches!(
+                            h.work.as_ref().unwrap().kind,
+                            WorkKind::Driver {
+                                deliver_order: None,
+                                ..
+                            }
+                        ));
+                        return;
+                    }
+                }
+            }
+        }
+
+        panic!("the truck should have delivered the goods and come back");
+    }
+}
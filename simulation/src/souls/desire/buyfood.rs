@@ -2,7 +2,7 @@ use serde::{Deserialize, Serialize};
 
 use egui_inspect::Inspect;
 use geom::Transform;
-use prototypes::{GameInstant, GameTime, ItemID};
+use prototypes::{GameInstant, GameTime, ItemID, SECONDS_PER_HOUR};
 
 use crate::economy::{find_trade_place, Bought, Market};
 use crate::map::BuildingID;
@@ -37,7 +37,11 @@ impl BuyFood {
         }
     }
 
-    pub fn score(&self, time: &GameTime, loc: &Location, bought: &Bought) -> f32 {
+    /// How much the soul wants to eat. A new meal starts during the hour after `meal_time`,
+    /// in seconds since midnight, once half a day has passed since the last one.
+    /// Past that hour the soul waits for the next day's meal time, unless it becomes starving
+    /// (a day and a half without eating) and goes to buy food right away.
+    pub fn score(&self, time: &GameTime, loc: &Location, bought: &Bought, meal_time: i32) -> f32 {
         if matches!(self.state, BuyFoodState::WaitingForTrade)
             && bought
                 .0
@@ -52,7 +56,16 @@ impl BuyFood {
                 return 1.0;
             }
         }
-        self.last_ate.elapsed(time).seconds() as f32 / GameTime::DAY as f32 - 1.0
+        let hunger = self.last_ate.elapsed(time).seconds() as f32 / GameTime::DAY as f32;
+        if !matches!(self.state, BuyFoodState::Empty) {
+            // the meal is underway, see it through
+            return (hunger - 1.0).max(0.6);
+        }
+        let since_meal_time = (time.daytime.daysec() - meal_time).rem_euclid(GameTime::DAY);
+        if hunger > 0.5 && since_meal_time < SECONDS_PER_HOUR {
+            return 0.6;
+        }
+        hunger - 1.5
     }
 
     pub fn apply(
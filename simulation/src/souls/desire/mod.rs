@@ -5,3 +5,108 @@ mod work;
 pub use buyfood::*;
 pub use home::*;
 pub use work::*;
+
+#[cfg(test)]
+mod tests {
+    use super::{BuyFood, Work, WorkKind};
+    use crate::economy::Bought;
+    use crate::map::{ActivityCurve, BuildingID};
+    use crate::transportation::Location;
+    use prototypes::{
+        CompanyKind, DayTime, GameDuration, GameTime, Tick, HOURS_PER_DAY, SECONDS_PER_HOUR,
+    };
+
+    /// Average hour of the day at which the shifts start
+    fn mean_shift_start(curve: &ActivityCurve) -> f32 {
+        let n = 500;
+        (0..n)
+            .map(|i| {
+                let u = (i as f32 + 0.5) / n as f32;
+                let work = Work::new(BuildingID::default(), WorkKind::Worker, curve, u);
+                work.work_inter.dist_start(&DayTime::new(0)) as f32 / SECONDS_PER_HOUR as f32
+            })
+            .sum::<f32>()
+            / n as f32
+    }
+
+    #[test]
+    fn shifts_follow_the_workplace_curve() {
+        let flat = mean_shift_start(&ActivityCurve::FLAT);
+        let factory = mean_shift_start(&ActivityCurve::company(CompanyKind::Factory));
+        let store = mean_shift_start(&ActivityCurve::company(CompanyKind::Store));
+        // a flat curve spreads the shifts over the whole morning
+        assert!((5.5..=6.5).contains(&flat), "{}", flat);
+        // factories start at the morning rush hour, stores later to be open at lunch
+        assert!((7.0..=9.0).contains(&factory), "{}", factory);
+        assert!(store > factory + 0.5, "{} {}", store, factory);
+    }
+
+    #[test]
+    fn shops_peak_midday_offices_at_rush_hour() {
+        for curve in [ActivityCurve::shop(), ActivityCurve::office()] {
+            let mean = (0..HOURS_PER_DAY)
+                .map(|h| curve.at(&DayTime::new(h * SECONDS_PER_HOUR)))
+                .sum::<f32>()
+                / HOURS_PER_DAY as f32;
+            assert!((mean - 1.0).abs() < 1e-4, "{}", mean);
+        }
+
+        let n_souls = 500;
+        let start = GameTime::new(Tick(0));
+        let shops = ActivityCurve::company(CompanyKind::Store);
+        let offices = ActivityCurve::company(CompanyKind::Factory);
+        let souls: Vec<(i32, Work)> = (0..n_souls)
+            .map(|i| {
+                let u = (i as f32 + 0.5) / n_souls as f32;
+                let work = Work::new(BuildingID::default(), WorkKind::Worker, &offices, u);
+                (shops.sample(u), work)
+            })
+            .collect();
+        let mut food: Vec<BuyFood> = (0..n_souls)
+            .map(|_| BuyFood::new(start.instant()))
+            .collect();
+        let mut at_work = vec![false; n_souls];
+
+        let mut shop_inbound = [0u32; HOURS_PER_DAY as usize];
+        let mut office_inbound = [0u32; HOURS_PER_DAY as usize];
+        let bought = Bought::default();
+        let loc = Location::Outside;
+
+        // the first day is skipped while the meals settle, the next two are counted
+        let n_minutes = 3 * 24 * 60;
+        for minute in 0..n_minutes {
+            let time = start + GameDuration::from_minutes(minute);
+            let hour = time.daytime.hour as usize;
+            let counted = minute >= n_minutes / 3;
+
+            for ((meal_time, work), (f, at_work)) in
+                souls.iter().zip(food.iter_mut().zip(&mut at_work))
+            {
+                // beats staying home
+                if f.score(&time, &loc, &bought, *meal_time) > 0.2 {
+                    f.last_ate = time.instant();
+                    if counted {
+                        shop_inbound[hour] += 1;
+                    }
+                }
+
+                let working = work.score(&time) > 0.0;
+                if working && !*at_work && counted {
+                    office_inbound[hour] += 1;
+                }
+                *at_work = working;
+            }
+        }
+
+        let peak = |counts: &[u32]| (0..counts.len()).max_by_key(|&h| counts[h]).unwrap();
+        let shop_peak = peak(&shop_inbound);
+        let office_peak = peak(&office_inbound);
+        assert!((11..=14).contains(&shop_peak), "{:?}", shop_inbound);
+        assert!((7..=9).contains(&office_peak), "{:?}", office_inbound);
+        assert!(shop_inbound[shop_peak] > 10 * shop_inbound[3].max(1));
+
+        // trips are moved around the day, not added: one meal per soul per day
+        let meals = shop_inbound.iter().sum::<u32>() as f32 / (2 * n_souls) as f32;
+        assert!((0.95..=1.05).contains(&meals), "{}", meals);
+    }
+}
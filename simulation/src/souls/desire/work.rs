@@ -1,14 +1,15 @@
-use crate::map::BuildingID;
+use crate::map::{ActivityCurve, BuildingID};
 use crate::map_dynamic::{Destination, Router};
 use crate::souls::human::HumanDecisionKind;
 use crate::transportation::Location;
 use crate::world::VehicleID;
 use egui_inspect::Inspect;
-use prototypes::{GameTime, RecTimeInterval, MINUTES_PER_HOUR};
+use prototypes::{GameTime, RecTimeInterval, SECONDS_PER_HOUR};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub enum WorkKind {
+    /// Drives the company's truck, the delivery system sets the building it is delivering to
     Driver {
         deliver_order: Option<BuildingID>,
         truck: VehicleID,
@@ -25,14 +26,17 @@ pub struct Work {
     pub last_score: f32,
 }
 
+/// How long a shift lasts
+const SHIFT_HOURS: i32 = 10;
+
 impl Work {
-    pub fn new(workplace: BuildingID, kind: WorkKind, offset: f32) -> Self {
+    /// The shift starts in the morning half of the day, following the workplace's activity curve.
+    /// `u` in [0; 1) picks where in the curve this worker falls.
+    pub fn new(workplace: BuildingID, kind: WorkKind, curve: &ActivityCurve, u: f32) -> Self {
+        let start = curve.sample_within(u, 0..12);
         Work {
             workplace,
-            work_inter: RecTimeInterval::new(
-                (8, (offset * MINUTES_PER_HOUR as f32) as i32),
-                (18, (offset * MINUTES_PER_HOUR as f32) as i32),
-            ),
+            work_inter: RecTimeInterval::new_daysec(start, start + SHIFT_HOURS * SECONDS_PER_HOUR),
             kind,
             last_score: 0.0,
         }
@@ -42,23 +46,15 @@ impl Work {
         use HumanDecisionKind::*;
         match self.kind {
             WorkKind::Worker => GoTo(Destination::Building(self.workplace)),
-            WorkKind::Driver {
-                deliver_order,
-                truck,
-            } => {
-                if &Location::Building(self.workplace) != loc {
+            WorkKind::Driver { deliver_order, .. } => {
+                if deliver_order.is_some() {
+                    // in the truck, which is driven by the delivery system
+                    Yield
+                } else if &Location::Building(self.workplace) != loc {
                     MultiStack(vec![
                         GoTo(Destination::Building(self.workplace)),
                         SetVehicle(router.personal_car),
                     ])
-                } else if let Some(b) = deliver_order {
-                    MultiStack(vec![
-                        SetVehicle(router.personal_car),
-                        GoTo(Destination::Building(self.workplace)),
-                        DeliverAtBuilding(b),
-                        GoTo(Destination::Building(b)),
-                        SetVehicle(Some(truck)),
-                    ])
                 } else {
                     Yield
                 }
@@ -67,6 +63,14 @@ impl Work {
     }
 
     pub fn score(&self, time: &GameTime) -> f32 {
+        if let WorkKind::Driver {
+            deliver_order: Some(_),
+            ..
+        } = self.kind
+        {
+            // a delivery is not left halfway
+            return 1.0;
+        }
         if self.work_inter.dist_start(&time.daytime) == 0 {
             0.5
         } else {
@@ -189,6 +189,7 @@ mod tests {
             },
             zone: None,
             connected_road: None,
+            door_offset: 0,
         }]);
         test.tick();
 
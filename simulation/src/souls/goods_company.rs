@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
 
 use egui_inspect::Inspect;
 use geom::{Transform, Vec2};
@@ -9,6 +10,7 @@ use prototypes::{
 use crate::economy::{find_trade_place, Market};
 use crate::map::{Building, BuildingID, Map, Zone, MAX_ZONE_AREA};
 use crate::map_dynamic::{BuildingInfos, ElectricityFlow};
+use crate::souls::delivery::{DeliveryJob, TruckState};
 use crate::souls::desire::WorkKind;
 use crate::transportation::{spawn_parked_vehicle, VehicleKind};
 use crate::utils::resources::Resources;
@@ -64,8 +66,14 @@ pub struct GoodsCompanyState {
     pub max_workers: u32,
     /// In [0; 1] range, to show how much has been made until new product
     pub progress: f32,
+    /// Drives the trucks, no truck leaves without it
     pub driver: Option<HumanID>,
     pub trucks: Vec<VehicleID>,
+    /// Sold goods waiting for a truck
+    #[inspect(skip)]
+    pub deliveries: VecDeque<DeliveryJob>,
+    #[inspect(skip)]
+    pub truck_states: BTreeMap<VehicleID, TruckState>,
 }
 
 impl CompanyEnt {
@@ -139,6 +147,8 @@ pub fn company_soul(
         progress: 0.0,
         driver: None,
         trucks,
+        deliveries: Default::default(),
+        truck_states: Default::default(),
     };
 
     let id = sim.world.insert(CompanyEnt {
@@ -226,43 +236,6 @@ pub fn company_system(world: &mut World, res: &mut Resources) {
             }
         }
 
-        (|| {
-            let Some(driver) = c.comp.driver else {
-                return;
-            };
-            let Some(w) = world.humans.get(driver).and_then(|h| h.work.as_ref()) else {
-                return;
-            };
-            if !matches!(
-                w.kind,
-                WorkKind::Driver {
-                    deliver_order: None,
-                    ..
-                }
-            ) {
-                return;
-            }
-            let Some(trade) = c.sold.0.pop() else {
-                return;
-            };
-            let Some(owner_build) = find_trade_place(trade.buyer, binfos) else {
-                log::warn!("driver can't find the place to deliver for {:?}", &trade);
-                return;
-            };
-            cbuf.exec_ent(me, move |sim| {
-                let Some(h) = sim.world.humans.get_mut(driver) else {
-                    return;
-                };
-                let Some(w) = h.work.as_mut() else {
-                    return;
-                };
-                let WorkKind::Driver { deliver_order, .. } = &mut w.kind else {
-                    return;
-                };
-                *deliver_order = Some(owner_build)
-            });
-        })();
-
         for &worker in c.workers.0.iter() {
             let Some(w) = world.humans.get(worker) else {
                 continue;
@@ -282,14 +255,14 @@ pub fn company_system(world: &mut World, res: &mut Resources) {
                     }
                 }
 
-                let offset = common::rand::randu(common::hash_u64(worker) as u32);
+                let u = common::rand::randu(common::hash_u64(worker) as u32);
+                let work = Work::new(b.id, kind, &b.kind.activity_curve(), u);
 
-                let b = c.comp.building;
                 cbuf_human.exec_ent(worker, move |sim| {
                     let Some(w) = sim.world.humans.get_mut(worker) else {
                         return;
                     };
-                    w.work = Some(Work::new(b, kind, offset));
+                    w.work = Some(work);
                 });
             }
         }
@@ -1,10 +1,12 @@
 use crate::economy::{Bought, Market};
-use crate::map::BuildingID;
+use crate::map::{ActivityCurve, BuildingID};
 use crate::map_dynamic::{BuildingInfos, Destination, Itinerary, Router};
+use crate::souls::decision_lod::DecisionLod;
 use crate::souls::desire::{BuyFood, Home, Work};
 use crate::transportation::Speed;
 use crate::transportation::{
-    random_pedestrian_shirt_color, spawn_parked_vehicle, Location, Pedestrian, VehicleKind,
+    put_pedestrian_in_transport_grid, random_pedestrian_shirt_color, spawn_parked_vehicle,
+    Location, Pedestrian, TransportGrid, VehicleKind, WalkingSpeedDistribution,
 };
 use crate::utils::rand_provider::RandProvider;
 use crate::utils::resources::Resources;
@@ -14,8 +16,9 @@ use crate::{BuildingKind, Map, ParCommandBuffer, Simulation, SoulID};
 use egui_inspect::Inspect;
 use geom::Transform;
 use lazy_static::lazy_static;
-use prototypes::{GameTime, ItemID};
+use prototypes::{CompanyKind, GameTime, ItemID};
 use serde::{Deserialize, Serialize};
+use slotmapd::Key;
 
 #[derive(Inspect, Serialize, Deserialize, Default)]
 pub struct HumanDecision {
@@ -139,8 +142,13 @@ pub fn update_decision_system(world: &mut World, resources: &mut Resources) {
     let rc = &*resources.read();
     let rd = &*resources.read();
     let re = &*resources.read();
+    let lod: &DecisionLod = &resources.read();
+    let tick = resources.tick();
 
     world.humans.iter_mut().for_each(|(ent, h)| {
+        if !lod.should_update(tick, ent.data().as_ffi(), h.trans.pos) {
+            return;
+        }
         update_decision(
             ra,
             rb,
@@ -211,7 +219,10 @@ pub fn update_decision(
     }
 
     if let Some(food) = food {
-        let score = food.score(time, loc, bought);
+        // food is bought in stores, so meals are taken when they are busy, each soul having its usual time
+        let meal_time =
+            ActivityCurve::company(CompanyKind::Store).sample(common::rand::randhash(me));
+        let score = food.score(time, loc, bought, meal_time);
         food.last_score = score;
 
         #[allow(unused_assignments)]
@@ -232,7 +243,36 @@ pub fn update_decision(
 }
 
 pub fn spawn_human(sim: &mut Simulation, house: BuildingID) -> Option<HumanID> {
-    profiling::scope!("spawn_human");
+    let id = spawn_resident(sim, house)?;
+    sim.write::<BuildingInfos>()
+        .set_owner(house, SoulID::Human(id));
+    Some(id)
+}
+
+/// Spawns a human living in the building, standing outside on the sidewalk in front of its entrance.
+/// If the building isn't connected to a road, it stands at the door and loiters around it.
+pub fn spawn_pedestrian_at_building(sim: &mut Simulation, building: BuildingID) -> Option<HumanID> {
+    let map = sim.map();
+    let pos = map
+        .building_access_point(building)
+        .or_else(|| Some(map.buildings().get(building)?.door_pos))?;
+    drop(map);
+
+    let id = spawn_resident(sim, building)?;
+    sim.write::<BuildingInfos>()
+        .get_out(building, SoulID::Human(id));
+    let coll = put_pedestrian_in_transport_grid(&mut sim.write::<TransportGrid>(), pos, id);
+
+    let h = &mut sim.world.humans[id];
+    h.location = Location::Outside;
+    h.trans.pos = pos;
+    h.collider = Some(coll);
+    Some(id)
+}
+
+/// Spawns a human living in `house` without making it the owner of the house
+pub fn spawn_resident(sim: &mut Simulation, house: BuildingID) -> Option<HumanID> {
+    profiling::scope!("spawn_resident");
     let map = sim.map();
     let housepos = map.buildings().get(house)?.door_pos;
     drop(map);
@@ -240,7 +280,8 @@ pub fn spawn_human(sim: &mut Simulation, house: BuildingID) -> Option<HumanID> {
     let _color = random_pedestrian_shirt_color(&mut sim.write::<RandProvider>());
 
     let hpos = sim.map().buildings().get(house)?.door_pos;
-    let p = Pedestrian::new(&mut sim.write::<RandProvider>());
+    let speeds = *sim.read::<WalkingSpeedDistribution>();
+    let p = Pedestrian::new(&mut sim.write::<RandProvider>(), &speeds);
 
     let time = sim.read::<GameTime>().instant();
 
@@ -269,7 +310,6 @@ pub fn spawn_human(sim: &mut Simulation, house: BuildingID) -> Option<HumanID> {
     m.buy(soul, housepos.xy(), ItemID::new("job-opening"), 1);
 
     sim.write::<BuildingInfos>().get_in(house, soul);
-    sim.write::<BuildingInfos>().set_owner(house, soul);
 
     Some(id)
 }
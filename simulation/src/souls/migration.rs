@@ -0,0 +1,333 @@
+use crate::economy::{employable, EconomyStats};
+use crate::map::{BuildingID, BuildingKind, Map};
+use crate::map_dynamic::{BuildingInfos, Destination};
+use crate::souls::human::{spawn_human, spawn_resident, HumanDecisionKind};
+use crate::transportation::{EdgePortals, Location};
+use crate::utils::par_command_buffer::ParCommandBuffer;
+use crate::world::{HumanEnt, HumanID, VehicleEnt};
+use crate::{Simulation, SoulID};
+use geom::Vec3;
+use prototypes::{GameDuration, GameInstant, GameTime};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// At most this many souls arrive, and this many souls leave, each tick
+pub const MAX_MIGRATIONS_PER_TICK: usize = 1;
+
+/// Maximum number of souls living in the same house
+pub const MAX_RESIDENTS_PER_HOUSE: usize = 4;
+
+/// Distance to the edge portal at which a leaving soul is removed
+const PORTAL_REACHED_DIST: f32 = 10.0;
+
+/// How long an unemployed or unhoused soul puts up with it before leaving the city
+pub fn emigration_delay() -> GameDuration {
+    GameDuration::from_minutes(6 * 60)
+}
+
+/// Souls stuck on their way out are removed anyway after this long
+fn leaving_timeout() -> GameDuration {
+    GameDuration::from_minutes(60)
+}
+
+/// Population dynamics: souls leave when they can't find a job or a home,
+/// and newcomers settle when companies are short on workers.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Migration {
+    /// When each unemployed or unhoused soul started to be so
+    struggling_since: BTreeMap<HumanID, GameInstant>,
+    /// Souls walking to an edge portal to leave the city
+    leaving: BTreeMap<HumanID, GameInstant>,
+    /// Houses left empty by emigrants, only given to immigrants
+    vacated: BTreeSet<BuildingID>,
+    pub immigrated: u32,
+    pub emigrated: u32,
+}
+
+impl Migration {
+    pub fn is_leaving(&self, soul: HumanID) -> bool {
+        self.leaving.contains_key(&soul)
+    }
+
+    /// Empty houses that are not filled automatically
+    pub fn is_vacated(&self, house: BuildingID) -> bool {
+        self.vacated.contains(&house)
+    }
+}
+
+/// Nearest edge portal, the city has no way out if there are none
+fn nearest_portal(map: &Map, portals: &EdgePortals, pos: Vec3) -> Option<Vec3> {
+    portals
+        .iter()
+        .filter_map(|(id, _)| map.intersections().get(id))
+        .map(|i| i.pos)
+        .min_by(|a, b| a.distance2(pos).total_cmp(&b.distance2(pos)))
+}
+
+pub fn migration_system(sim: &mut Simulation) {
+    profiling::scope!("souls::migration_system");
+    let stats = *sim.read::<EconomyStats>();
+    let time = *sim.read::<GameTime>();
+
+    emigration(sim, stats, &time);
+    immigration(sim, stats);
+}
+
+fn emigration(sim: &mut Simulation, stats: EconomyStats, time: &GameTime) {
+    let now = time.instant();
+    let mut to_leave = vec![];
+    let mut to_remove = vec![];
+    {
+        let map = sim.map();
+        let mut migration = sim.write::<Migration>();
+        let migration = &mut *migration;
+        let humans = &sim.world.humans;
+
+        migration
+            .vacated
+            .retain(|&b| map.buildings().contains_key(b));
+
+        // who is struggling and for how long
+        migration
+            .struggling_since
+            .retain(|id, _| humans.contains_key(*id));
+        for (id, h) in humans.iter() {
+            let unemployed = h.work.is_none() && employable(&h.personal_info);
+            let unhoused = !map.buildings().contains_key(h.home.house);
+            if unemployed || unhoused {
+                migration.struggling_since.entry(id).or_insert(now);
+            } else {
+                migration.struggling_since.remove(&id);
+            }
+        }
+
+        // people that arrived at the city's edge
+        migration.leaving.retain(|&id, &mut since| {
+            let Some(h) = humans.get(id) else {
+                return false;
+            };
+            let arrived = match h.decision.kind {
+                HumanDecisionKind::GoTo(Destination::Outside(p)) => {
+                    p.distance2(h.trans.pos) < PORTAL_REACHED_DIST * PORTAL_REACHED_DIST
+                }
+                _ => true,
+            };
+            if arrived || since.elapsed(time) >= leaving_timeout() {
+                to_remove.push(id);
+                return false;
+            }
+            true
+        });
+
+        // people only leave if staying is hopeless
+        let job_shortage = stats.unemployed > stats.open_jobs;
+        for (&id, &since) in migration.struggling_since.iter() {
+            if to_leave.len() >= MAX_MIGRATIONS_PER_TICK {
+                break;
+            }
+            if migration.leaving.contains_key(&id) || since.elapsed(time) < emigration_delay() {
+                continue;
+            }
+            let h = &humans[id];
+            let unhoused = !map.buildings().contains_key(h.home.house);
+            if !unhoused && !job_shortage {
+                continue;
+            }
+            to_leave.push(id);
+        }
+    }
+
+    for id in to_leave {
+        let portal = {
+            let h = &sim.world.humans[id];
+            nearest_portal(&sim.map(), &sim.read::<EdgePortals>(), h.trans.pos)
+        };
+        let mut migration = sim.write::<Migration>();
+        migration.struggling_since.remove(&id);
+        migration.emigrated += 1;
+        drop(migration);
+
+        leave_home(sim, id);
+        match portal {
+            Some(p) => {
+                sim.write::<Migration>().leaving.insert(id, now);
+                if let Some(h) = sim.world.humans.get_mut(id) {
+                    h.decision.kind = HumanDecisionKind::GoTo(Destination::Outside(p));
+                    h.decision.wait = 0;
+                }
+            }
+            None => to_remove.push(id),
+        }
+    }
+
+    for id in to_remove {
+        let Some(h) = sim.world.humans.get(id) else {
+            continue;
+        };
+        if let Location::Building(b) = h.location {
+            sim.write::<BuildingInfos>().get_out(b, SoulID::Human(id));
+        }
+        if let Some(car) = h.router.personal_car {
+            sim.read::<ParCommandBuffer<VehicleEnt>>().kill(car);
+        }
+        sim.read::<ParCommandBuffer<HumanEnt>>().kill(id);
+    }
+}
+
+/// The soul gives up its home, handing the house over to a roommate if any
+fn leave_home(sim: &mut Simulation, id: HumanID) {
+    let soul = SoulID::Human(id);
+    let house = sim.world.humans[id].home.house;
+    let migration = sim.read::<Migration>();
+    let roommate = sim
+        .world
+        .humans
+        .iter()
+        .find(|&(other, h)| other != id && h.home.house == house && !migration.is_leaving(other))
+        .map(|(other, _)| other);
+    drop(migration);
+
+    let mut binfos = sim.write::<BuildingInfos>();
+    if binfos.owner(house) != Some(soul) {
+        return;
+    }
+    binfos.clear_owner(soul);
+    match roommate {
+        Some(r) => binfos.set_owner(house, SoulID::Human(r)),
+        None => {
+            drop(binfos);
+            sim.write::<Migration>().vacated.insert(house);
+        }
+    }
+}
+
+fn immigration(sim: &mut Simulation, stats: EconomyStats) {
+    // newcomers only come for jobs nobody here can take
+    if stats.open_jobs <= stats.unemployed {
+        return;
+    }
+
+    let mut houses = vec![];
+    {
+        let map = sim.map();
+        let binfos = sim.read::<BuildingInfos>();
+        let mut migration = sim.write::<Migration>();
+
+        // vacated houses first, then room in the inhabited ones
+        while houses.len() < MAX_MIGRATIONS_PER_TICK {
+            let Some(house) = migration.vacated.pop_first() else {
+                break;
+            };
+            houses.push((house, true));
+        }
+
+        let mut residents: BTreeMap<BuildingID, usize> = BTreeMap::new();
+        for h in sim.world.humans.values() {
+            *residents.entry(h.home.house).or_default() += 1;
+        }
+        for (id, b) in map.buildings() {
+            if houses.len() >= MAX_MIGRATIONS_PER_TICK {
+                break;
+            }
+            if b.kind != BuildingKind::House || binfos.owner(id).is_none() {
+                continue;
+            }
+            let n = residents.get(&id).copied().unwrap_or(0);
+            if n < MAX_RESIDENTS_PER_HOUSE {
+                houses.push((id, false));
+            }
+        }
+    }
+
+    for (house, vacated) in houses {
+        let spawned = if vacated {
+            spawn_human(sim, house)
+        } else {
+            spawn_resident(sim, house)
+        };
+        if spawned.is_some() {
+            sim.write::<Migration>().immigrated += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{emigration_delay, Migration, MAX_MIGRATIONS_PER_TICK};
+    use crate::map::BuildingKind;
+    use crate::map_dynamic::BuildingInfos;
+    use crate::tests::TestCtx;
+    use crate::WorldCommand;
+    use geom::{vec2, vec3, OBB};
+    use prototypes::{BuildingGen, GameTime, GoodsCompanyID, Tick};
+
+    fn skip_time(test: &mut TestCtx, ticks: u64) {
+        let tick = test.g.read::<GameTime>().tick;
+        test.apply(&[WorldCommand::SetGameTime(GameTime::new(Tick(
+            tick.0 + ticks,
+        )))]);
+    }
+
+    #[test]
+    fn unemployment_makes_souls_leave() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(300.0, 0.0, 0.0)]);
+        let houses: Vec<_> = [20.0, 80.0, 140.0, 200.0]
+            .into_iter()
+            .map(|x| test.build_house_near(vec2(x, 20.0)))
+            .collect();
+        test.tick();
+        test.tick();
+        assert_eq!(test.g.world().humans.len(), houses.len());
+
+        skip_time(&mut test, emigration_delay().0 .0 + 1);
+        test.tick();
+        assert_eq!(
+            test.g.world().humans.len(),
+            houses.len() - MAX_MIGRATIONS_PER_TICK
+        );
+
+        for _ in 0..houses.len() {
+            test.tick();
+        }
+        assert_eq!(test.g.world().humans.len(), 0);
+        assert_eq!(test.g.read::<Migration>().emigrated, houses.len() as u32);
+
+        // left houses are not filled back without jobs to offer
+        let binfos = test.g.read::<BuildingInfos>();
+        for &h in &houses {
+            assert!(binfos.owner(h).is_none());
+            assert!(test.g.read::<Migration>().is_vacated(h));
+        }
+    }
+
+    #[test]
+    fn open_jobs_attract_souls() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(300.0, 0.0, 0.0)]);
+        test.build_house_near(vec2(20.0, 20.0));
+        test.apply(&[WorldCommand::MapBuildSpecialBuilding {
+            pos: OBB::new(vec2(150.0, 30.0), vec2(1.0, 0.0), 10.0, 10.0),
+            kind: BuildingKind::GoodsCompany(GoodsCompanyID::new("cereal-farm")),
+            gen: BuildingGen::NoWalkway {
+                door_pos: vec2(150.0, 20.0),
+            },
+            zone: None,
+            connected_road: None,
+            door_offset: 0,
+        }]);
+
+        let mut max_humans = 0;
+        for _ in 0..100 {
+            let before = test.g.world().humans.len();
+            test.tick();
+            let after = test.g.world().humans.len();
+            assert!(after <= before + MAX_MIGRATIONS_PER_TICK + 1);
+            max_humans = max_humans.max(after);
+        }
+
+        assert!(test.g.read::<Migration>().immigrated > 0);
+        assert!(max_humans > 1);
+        assert_eq!(test.g.read::<Migration>().emigrated, 0);
+    }
+}
@@ -3,29 +3,41 @@ use crate::map_dynamic::BuildingInfos;
 use crate::souls::freight_station::freight_station_soul;
 use crate::souls::goods_company::company_soul;
 use crate::souls::human::spawn_human;
+use crate::souls::migration::Migration;
 use crate::Simulation;
 
 #[macro_use]
 pub mod desire;
 
+pub mod decision_lod;
+pub mod delivery;
 pub mod freight_station;
 pub mod goods_company;
 pub mod human;
+pub mod migration;
 
-/// Adds souls to empty buildings
+/// Adds souls to empty buildings.
+/// Buildings are filled in id order, the order of the slotmap, and the souls draw from the saved
+/// [`RandProvider`](crate::utils::rand_provider::RandProvider) so that the same save always gets the same souls.
 pub(crate) fn add_souls_to_empty_buildings(sim: &mut Simulation) {
     profiling::scope!("souls::add_souls_to_empty_buildings");
     let map = sim.map();
     let infos = sim.read::<BuildingInfos>();
+    let migration = sim.read::<Migration>();
     let mut empty_buildings = Vec::with_capacity(16);
 
     for (id, building) in map.buildings() {
         if unwrap_cont!(infos.get(id)).owner.is_some() {
             continue;
         }
+        // houses left by emigrants wait for immigrants
+        if migration.is_vacated(id) {
+            continue;
+        }
 
         empty_buildings.push((building.kind, id));
     }
+    drop(migration);
     drop(infos);
     drop(map);
 
@@ -54,3 +66,44 @@ pub(crate) fn add_souls_to_empty_buildings(sim: &mut Simulation) {
         log::info!("{} souls added", n_souls_added);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::add_souls_to_empty_buildings;
+    use crate::map::BuildingID;
+    use crate::tests::TestCtx;
+    use crate::world::HumanID;
+    use crate::Simulation;
+    use common::saveload::{Bincode, Encoder};
+    use geom::{vec2, vec3};
+
+    fn assignment(sim: &Simulation) -> Vec<(HumanID, BuildingID, String)> {
+        sim.world()
+            .humans
+            .iter()
+            .map(|(id, h)| (id, h.home.house, h.personal_info.name.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn soul_assignment_is_deterministic() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(300.0, 0.0, 0.0)]);
+        for x in [20.0, 80.0, 140.0, 200.0] {
+            test.build_house_near(vec2(x, 20.0));
+            test.build_house_near(vec2(x, -20.0));
+        }
+        // the random draws of the first run must not leak into the second one
+        let mut other: Simulation = Bincode::decode(&Bincode::encode(&test.g).unwrap()).unwrap();
+
+        add_souls_to_empty_buildings(&mut test.g);
+        add_souls_to_empty_buildings(&mut other);
+
+        let first = assignment(&test.g);
+        assert_eq!(first.len(), 8);
+        assert_eq!(first, assignment(&other));
+
+        // names are drawn from the saved rng, so they differ between souls
+        assert!(first.iter().any(|(_, _, name)| *name != first[0].2));
+    }
+}
@@ -10,8 +10,10 @@ use common::logger::MyLog;
 use common::saveload::Encoder;
 use geom::{Vec2, Vec3};
 
+mod scenario;
 mod test_iso;
 mod vehicles;
+mod world_command;
 
 pub(crate) struct TestCtx {
     pub g: Simulation,
@@ -20,18 +22,33 @@ pub(crate) struct TestCtx {
 
 impl TestCtx {
     pub(crate) fn new() -> Self {
+        Self::with_options(SimulationOptions {
+            terrain_size: 1,
+            save_replay: false,
+            ..Default::default()
+        })
+    }
+
+    pub(crate) fn with_options(opts: SimulationOptions) -> Self {
         MyLog::init();
         crate::init::init();
 
-        let g = Simulation::new_with_options(SimulationOptions {
-            terrain_size: 1,
-            save_replay: false,
-        });
+        let g = Simulation::new_with_options(opts);
         let sched = Simulation::schedule();
 
         Self { g, sched }
     }
 
+    pub(crate) fn from_seed(seed: u64) -> Self {
+        MyLog::init();
+        crate::init::init();
+
+        Self {
+            g: Simulation::from_seed(seed),
+            sched: Simulation::schedule(),
+        }
+    }
+
     pub(crate) fn build_roads(&self, v: &[Vec3]) {
         let mut m = self.g.map_mut();
         for w in v.windows(2) {
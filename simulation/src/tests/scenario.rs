@@ -0,0 +1,121 @@
+use crate::expectation::{run_expectations, Condition, Expectation};
+use crate::map::BuildingID;
+use crate::scenario::{Scenario, ScenarioError, ScenarioRoad};
+use crate::world::VehicleID;
+use crate::{Simulation, SimulationOptions};
+use geom::Vec3;
+
+const SMALL_SCENARIO: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/tests/scenario_small.json");
+
+#[test]
+fn load_small_scenario() {
+    common::logger::MyLog::init();
+    crate::init::init();
+
+    let base = Simulation::new_with_options(SimulationOptions {
+        terrain_size: 0,
+        save_replay: false,
+        ..Default::default()
+    });
+    let sim = Simulation::load_scenario(SMALL_SCENARIO).unwrap();
+
+    let map = sim.map();
+    let base_map = base.map();
+    assert_eq!(map.roads().len(), base_map.roads().len() + 3);
+    assert_eq!(
+        map.intersections().len(),
+        base_map.intersections().len() + 4
+    );
+    assert_eq!(sim.world.vehicles.len(), base.world.vehicles.len() + 2);
+}
+
+#[test]
+fn unknown_pattern_is_reported() {
+    let scenario = Scenario {
+        roads: vec![ScenarioRoad {
+            from: Vec3::ZERO,
+            to: Vec3::x(100.0),
+            interpoint: None,
+            pattern: "highway_to_nowhere".to_string(),
+        }],
+        ..Default::default()
+    };
+
+    let err = scenario.validate().unwrap_err();
+    assert!(matches!(err, ScenarioError::UnknownPattern(ref name) if name == "highway_to_nowhere"));
+    assert!(err.to_string().contains("highway_to_nowhere"));
+}
+
+#[test]
+fn ron_scenarios_are_rejected() {
+    let ron = SMALL_SCENARIO.replace(".json", ".ron");
+    let err = Scenario::load(ron).unwrap_err();
+    assert!(matches!(err, ScenarioError::UnsupportedFormat(ref ext) if ext == "ron"));
+    assert!(err.to_string().contains("ron"));
+}
+
+#[test]
+fn small_scenario_meets_expectations() {
+    common::logger::MyLog::init();
+    crate::init::init();
+
+    let scenario = Scenario::load(SMALL_SCENARIO).unwrap();
+    assert_eq!(scenario.expectations.len(), 1);
+    let mut sim = scenario.create_sim().unwrap();
+
+    run_expectations(&mut sim, &scenario.expectations).unwrap();
+}
+
+#[test]
+fn failed_expectations_are_reported() {
+    common::logger::MyLog::init();
+    crate::init::init();
+
+    let scenario = Scenario {
+        expectations: vec![
+            Expectation {
+                by_tick: 10,
+                condition: Condition::MinVehicles(0),
+            },
+            Expectation {
+                by_tick: 20,
+                condition: Condition::MinVehicles(100_000),
+            },
+            Expectation {
+                by_tick: 20,
+                condition: Condition::BuildingOccupancy {
+                    building: BuildingID::default(),
+                    min: 1,
+                },
+            },
+            Expectation {
+                by_tick: 20,
+                condition: Condition::VehicleNear {
+                    vehicle: VehicleID::default(),
+                    pos: Vec3::ZERO,
+                    dist: 10.0,
+                },
+            },
+        ],
+        ..Default::default()
+    };
+    let mut sim = scenario.create_sim().unwrap();
+    let start = sim.get_tick();
+
+    let failures = run_expectations(&mut sim, &scenario.expectations).unwrap_err();
+    assert_eq!(
+        failures.iter().map(|f| f.index).collect::<Vec<_>>(),
+        vec![1, 2, 3]
+    );
+
+    // unmet conditions fail at their deadline
+    assert_eq!(failures[0].tick, 20);
+    assert!(failures[0].to_string().contains("MinVehicles"));
+
+    // missing entities fail right away, saying what is missing
+    assert_eq!(failures[1].tick, start);
+    assert!(failures[1].reason.contains("building"));
+    assert!(failures[1].reason.contains("doesn't exist"));
+    assert_eq!(failures[2].tick, start);
+    assert!(failures[2].reason.contains("vehicle"));
+}
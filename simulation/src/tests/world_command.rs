@@ -0,0 +1,610 @@
+use super::TestCtx;
+use crate::map::LanePatternBuilder;
+use crate::world_command::WorldCommand;
+use crate::world_command::WorldCommands;
+use crate::{Simulation, SimulationOptions};
+use common::saveload::{Bincode, Encoder};
+use geom::{vec3, Vec2};
+
+#[test]
+fn batch_road_grid() {
+    // no terrain, so nothing is under water and no grid point is skipped
+    let mut test = TestCtx::with_options(SimulationOptions {
+        terrain_size: 0,
+        save_replay: false,
+        ..Default::default()
+    });
+
+    test.apply(&[WorldCommand::BatchRoadGrid {
+        origin: Vec2::new(100.0, 100.0),
+        nx: 3,
+        ny: 3,
+        spacing: 100.0,
+        pattern: LanePatternBuilder::new().build(),
+    }]);
+
+    let map = test.g.map();
+    assert_eq!(map.intersections().len(), 9);
+    assert_eq!(map.roads().len(), 12);
+    for inter in map.intersections().values() {
+        assert!(!inter.turns().is_empty());
+    }
+}
+
+#[test]
+fn light_timing_goes_back_to_automatic() {
+    use crate::map::LightTiming;
+
+    let mut test = TestCtx::new();
+    test.build_roads(&[
+        vec3(0.0, 0.0, 0.0),
+        vec3(100.0, 0.0, 0.0),
+        vec3(200.0, 0.0, 0.0),
+    ]);
+    let inter = test.g.map().intersections().keys().next().unwrap();
+
+    let custom = LightTiming {
+        green: 300,
+        orange: 60,
+        all_red: 30,
+    };
+    test.apply(&[WorldCommand::SetLightTiming {
+        inter,
+        timing: Some(custom),
+    }]);
+    assert_eq!(
+        test.g.map().intersections()[inter].light_timing,
+        Some(custom)
+    );
+
+    test.apply(&[WorldCommand::SetLightTiming {
+        inter,
+        timing: None,
+    }]);
+    let map = test.g.map();
+    let i = &map.intersections()[inter];
+    assert_eq!(i.light_timing, None);
+    assert_eq!(
+        i.effective_light_timing(map.lanes(), map.roads()).all_red,
+        i.min_clearance_time(map.lanes(), map.roads()).ceil() as u16
+    );
+}
+
+#[test]
+fn paused_commands_apply_on_step() {
+    let mut test = TestCtx::new();
+    test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(300.0, 0.0, 0.0)]);
+    test.tick();
+
+    let tick = test.g.get_tick();
+    test.g.set_paused(true);
+
+    let spawn = [WorldCommand::SpawnRandomCars { n_cars: 3 }];
+    test.g.tick(&mut test.sched, &spawn);
+    test.g
+        .tick(&mut test.sched, WorldCommands::default().as_ref());
+    assert_eq!(test.g.get_tick(), tick);
+    assert!(test.g.world.vehicles.is_empty());
+
+    test.g
+        .step(&mut test.sched, WorldCommands::default().as_ref());
+    assert_eq!(test.g.get_tick(), tick + 1);
+    assert_eq!(test.g.world.vehicles.len(), 3);
+
+    // queued commands are only applied once
+    test.g
+        .step(&mut test.sched, WorldCommands::default().as_ref());
+    assert_eq!(test.g.get_tick(), tick + 2);
+    assert_eq!(test.g.world.vehicles.len(), 3);
+
+    test.g.set_paused(false);
+    test.tick();
+    assert_eq!(test.g.get_tick(), tick + 3);
+}
+
+#[test]
+fn paused_commands_apply_on_unpause() {
+    let mut test = TestCtx::new();
+    test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(300.0, 0.0, 0.0)]);
+    test.tick();
+
+    let tick = test.g.get_tick();
+    test.g.set_paused(true);
+
+    let spawn = [WorldCommand::SpawnRandomCars { n_cars: 3 }];
+    test.g.tick(&mut test.sched, &spawn);
+    assert!(test.g.world.vehicles.is_empty());
+
+    test.g.set_paused(false);
+    test.tick();
+    assert_eq!(test.g.get_tick(), tick + 1);
+    assert_eq!(test.g.world.vehicles.len(), 3);
+
+    // queued commands are only applied once
+    test.tick();
+    assert_eq!(test.g.world.vehicles.len(), 3);
+}
+
+#[test]
+fn identical_runs_have_identical_checksums() {
+    let run = || {
+        let mut test = TestCtx::new();
+        test.g.set_checksum_interval(Some(5));
+        test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(300.0, 0.0, 0.0)]);
+
+        let spawn = [WorldCommand::SpawnRandomCars { n_cars: 5 }];
+        test.g.tick(&mut test.sched, &spawn);
+        for _ in 0..29 {
+            test.g
+                .tick(&mut test.sched, WorldCommands::default().as_ref());
+        }
+        test.g.checksums().to_vec()
+    };
+
+    let a = run();
+    assert_eq!(a.len(), 6);
+    assert_eq!(a, run());
+}
+
+#[test]
+fn checksums_dont_change_state() {
+    let mut test = TestCtx::new();
+    test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(300.0, 0.0, 0.0)]);
+    test.tick();
+
+    let before = test.g.hashes();
+    test.g.set_checksum_interval(Some(1));
+    let h = test.g.state_hash();
+    assert_eq!(h, test.g.state_hash());
+    assert_eq!(before, test.g.hashes());
+}
+
+#[test]
+fn merge_close_intersections() {
+    let mut test = TestCtx::new();
+    test.build_roads(&[
+        vec3(0.0, 0.0, 0.0),
+        vec3(100.0, 0.0, 0.0),
+        vec3(100.0, 100.0, 0.0),
+    ]);
+    test.build_roads(&[
+        vec3(112.0, -100.0, 0.0),
+        vec3(112.0, 0.0, 0.0),
+        vec3(212.0, 0.0, 0.0),
+    ]);
+    test.build_roads(&[vec3(100.0, 0.0, 0.0), vec3(112.0, 0.0, 0.0)]);
+
+    let (a, b, short) = {
+        let map = test.g.map();
+        let find = |pos: Vec2| {
+            map.intersections()
+                .values()
+                .find(|i| i.pos.xy().distance(pos) < 1.0)
+                .unwrap()
+                .id
+        };
+        let a = find(Vec2::new(100.0, 0.0));
+        let b = find(Vec2::new(112.0, 0.0));
+        (a, b, map.find_road(a, b).unwrap())
+    };
+
+    test.apply(&[WorldCommand::MapMergeIntersections { a, b }]);
+
+    {
+        let map = test.g.map();
+        assert!(!map.roads().contains_key(short));
+        assert!(!map.intersections().contains_key(b));
+        assert_eq!(map.intersections()[a].roads.len(), 4);
+        assert_eq!(map.roads().len(), 4);
+        for road in map.roads().values() {
+            assert_ne!(road.src, b);
+            assert_ne!(road.dst, b);
+            assert!(map.intersections()[a].roads.contains(&road.id));
+        }
+        assert!(map.validate().is_empty());
+    }
+
+    test.tick();
+}
+
+#[test]
+fn decision_focus_goes_through_commands_and_saves() {
+    use crate::souls::decision_lod::DecisionLod;
+    use common::saveload::CompressedBincode;
+
+    let mut test = TestCtx::new();
+    assert_eq!(test.g.read::<DecisionLod>().focus, None);
+
+    let focus = vec3(100.0, 50.0, 0.0);
+    test.apply(&[WorldCommand::SetDecisionFocus(Some(focus))]);
+    assert_eq!(test.g.read::<DecisionLod>().focus, Some(focus));
+
+    // a loaded game makes the same decisions
+    let save = CompressedBincode::encode(&test.g).unwrap();
+    let loaded: Simulation = CompressedBincode::decode(&save).unwrap();
+    assert_eq!(loaded.read::<DecisionLod>().focus, Some(focus));
+
+    test.apply(&[WorldCommand::SetDecisionFocus(None)]);
+    assert_eq!(test.g.read::<DecisionLod>().focus, None);
+}
+
+#[test]
+fn corrupt_save_fails_to_load() {
+    use crate::Simulation;
+    use common::saveload::{CheckedCompressedBincode, CompressedBincode, Encoder};
+    use std::io::ErrorKind;
+
+    let mut test = TestCtx::new();
+    test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(100.0, 0.0, 0.0)]);
+    test.tick();
+    let hash = test.g.state_hash();
+
+    let save = CheckedCompressedBincode::encode(&test.g).unwrap();
+    let loaded: Simulation = CheckedCompressedBincode::decode(&save).unwrap();
+    assert_eq!(loaded.state_hash(), hash);
+
+    let mut corrupted = save.clone();
+    let mid = corrupted.len() / 2;
+    corrupted[mid] ^= 0xFF;
+    let err = CheckedCompressedBincode::decode::<Simulation>(&corrupted)
+        .err()
+        .expect("corrupted save loaded");
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert!(err.to_string().contains("corrupt save"), "{}", err);
+
+    // saves from before the checksum still load
+    let legacy = CompressedBincode::encode(&test.g).unwrap();
+    let loaded: Simulation = CheckedCompressedBincode::decode(&legacy).unwrap();
+    assert_eq!(loaded.state_hash(), hash);
+}
+
+#[test]
+fn baseline_saves_are_migrated() {
+    use crate::map::procgen::ColoredMesh;
+    use crate::map::terrain::Environment;
+    use crate::map::{
+        BuildingID, BuildingKind, IntersectionID, LaneID, LaneKind, LightPolicy, Lots,
+        ParkingSpots, RoadID, RoadMaterial, RoundaboutPolicy, TrafficControl, Turn, Zone,
+    };
+    use crate::World;
+    use common::saveload::CheckedCompressedBincode;
+    use common::FastMap;
+    use geom::{vec2, PolyLine3, Vec3, OBB};
+    use serde::Serialize;
+    use slotmapd::HopSlotMap;
+    use std::collections::BTreeSet;
+
+    // The layouts as they were before saves were versioned, kept apart from the ones used to
+    // read them so that a mistake in those is caught.
+    #[derive(Serialize)]
+    struct SimulationSer<'a> {
+        world: &'a World,
+        version: String,
+        res: FastMap<String, Vec<u8>>,
+    }
+
+    #[derive(Serialize)]
+    struct SerializedMap {
+        roads: HopSlotMap<RoadID, Road>,
+        intersections: HopSlotMap<IntersectionID, Intersection>,
+        buildings: HopSlotMap<BuildingID, Building>,
+        lanes: HopSlotMap<LaneID, Lane>,
+        parking: ParkingSpots,
+        lots: Lots,
+        environment: Environment,
+        external_train_stations: Vec<BuildingID>,
+    }
+
+    #[derive(Serialize)]
+    struct Road {
+        id: RoadID,
+        src: IntersectionID,
+        dst: IntersectionID,
+        points: PolyLine3,
+        interfaced_points: PolyLine3,
+        width: f32,
+        connected_buildings: Vec<BuildingID>,
+        src_interface: f32,
+        dst_interface: f32,
+        lanes_forward: Vec<(LaneID, LaneKind)>,
+        lanes_backward: Vec<(LaneID, LaneKind)>,
+    }
+
+    #[derive(Serialize)]
+    struct Intersection {
+        id: IntersectionID,
+        pos: Vec3,
+        radius: f32,
+        turns: BTreeSet<Turn>,
+        roads: Vec<RoadID>,
+        turn_policy: TurnPolicy,
+        light_policy: LightPolicy,
+    }
+
+    #[derive(Serialize)]
+    struct TurnPolicy {
+        back_turns: bool,
+        left_turns: bool,
+        crosswalks: bool,
+        roundabout: Option<RoundaboutPolicy>,
+    }
+
+    #[derive(Serialize)]
+    struct Lane {
+        id: LaneID,
+        parent: RoadID,
+        src: IntersectionID,
+        dst: IntersectionID,
+        kind: LaneKind,
+        control: TrafficControl,
+        speed_limit: f32,
+        points: PolyLine3,
+        dist_from_bottom: f32,
+    }
+
+    #[derive(Serialize)]
+    struct Building {
+        id: BuildingID,
+        door_pos: Vec3,
+        kind: BuildingKind,
+        mesh: ColoredMesh,
+        obb: OBB,
+        height: f32,
+        zone: Option<Zone>,
+        connected_road: Option<RoadID>,
+    }
+
+    let (src, dst) = (vec3(0.0, 0.0, 0.0), vec3(100.0, 0.0, 0.0));
+    let mut map = SerializedMap {
+        roads: HopSlotMap::default(),
+        intersections: HopSlotMap::default(),
+        buildings: HopSlotMap::default(),
+        lanes: HopSlotMap::default(),
+        parking: ParkingSpots::default(),
+        lots: Lots::default(),
+        environment: Environment::default(),
+        external_train_stations: Vec::new(),
+    };
+    let mut inter = |pos| {
+        map.intersections.insert_with_key(|id| Intersection {
+            id,
+            pos,
+            radius: 5.0,
+            turns: BTreeSet::new(),
+            roads: Vec::new(),
+            turn_policy: TurnPolicy {
+                back_turns: false,
+                left_turns: false,
+                crosswalks: true,
+                roundabout: None,
+            },
+            light_policy: LightPolicy::StopSigns,
+        })
+    };
+    let (a, b) = (inter(src), inter(dst));
+    let road = map.roads.insert_with_key(|id| Road {
+        id,
+        src: a,
+        dst: b,
+        points: PolyLine3::new(vec![src, dst]),
+        interfaced_points: PolyLine3::new(vec![src, dst]),
+        width: 8.0,
+        connected_buildings: Vec::new(),
+        src_interface: 5.0,
+        dst_interface: 5.0,
+        lanes_forward: Vec::new(),
+        lanes_backward: Vec::new(),
+    });
+    for (kind, speed_limit, dist_from_bottom) in [
+        (LaneKind::Walking, 1.5, 1.0),
+        (LaneKind::Driving, 20.0, 4.0),
+    ] {
+        let lane = map.lanes.insert_with_key(|id| Lane {
+            id,
+            parent: road,
+            src: a,
+            dst: b,
+            kind,
+            control: TrafficControl::StopSign,
+            speed_limit,
+            points: PolyLine3::new(vec![src, dst]),
+            dist_from_bottom,
+        });
+        map.roads[road].lanes_forward.push((lane, kind));
+    }
+    let house = map.buildings.insert_with_key(|id| Building {
+        id,
+        door_pos: vec3(50.0, 10.0, 0.0),
+        kind: BuildingKind::House,
+        mesh: ColoredMesh::default(),
+        obb: OBB::new(vec2(50.0, 20.0), vec2(1.0, 0.0), 10.0, 10.0),
+        height: 5.0,
+        zone: None,
+        connected_road: Some(road),
+    });
+    map.roads[road].connected_buildings.push(house);
+    for id in [a, b] {
+        map.intersections[id].roads.push(road);
+    }
+
+    let mut res = FastMap::default();
+    res.insert("map".to_string(), Bincode::encode(&map).unwrap());
+    let save = CheckedCompressedBincode::encode(&SimulationSer {
+        world: &World::default(),
+        version: crate::VERSION.to_string(),
+        res,
+    })
+    .unwrap();
+
+    let loaded: Simulation = CheckedCompressedBincode::decode(&save).unwrap();
+    let loaded = loaded.map();
+
+    assert_eq!(loaded.roads().len(), 1);
+    let r = &loaded.roads()[road];
+    assert_eq!((r.src, r.dst), (a, b));
+    assert_eq!(r.width, 8.0);
+    assert_eq!(r.connected_buildings, vec![house]);
+    assert!(!r.closed);
+    assert_eq!(r.material, RoadMaterial::Asphalt);
+
+    assert_eq!(loaded.lanes().len(), 2);
+    for lane in loaded.lanes().values() {
+        assert_eq!(lane.parent, road);
+        assert!(lane.control.is_stop_sign());
+        assert_eq!(lane.width, lane.kind.width());
+        assert!(!lane.reversed);
+        let limit = match lane.kind {
+            LaneKind::Driving => 20.0,
+            _ => 1.5,
+        };
+        assert_eq!(lane.speed_limit, limit);
+    }
+
+    for id in [a, b] {
+        let i = &loaded.intersections()[id];
+        assert_eq!(i.roads, vec![road]);
+        assert_eq!(i.light_policy, LightPolicy::StopSigns);
+        assert!(!i.turn_policy.left_turns);
+        assert!(!i.turn_policy.protected_left_only);
+        assert_eq!(i.light_timing, None);
+        assert!(i.turn_restrictions.is_empty());
+    }
+
+    let h = &loaded.buildings()[house];
+    assert_eq!(h.connected_road, Some(road));
+    assert_eq!(h.door_offset, 0);
+}
+
+#[test]
+fn json_save_roundtrip() {
+    use crate::{SaveFormat, Simulation};
+    use prototypes::GameTime;
+
+    let mut test = TestCtx::new();
+    test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(100.0, 0.0, 0.0)]);
+    test.build_roads(&[vec3(100.0, 0.0, 0.0), vec3(100.0, 100.0, 0.0)]);
+    test.tick();
+
+    let save = test.g.encode(SaveFormat::JSON).unwrap();
+
+    // the map and the time are readable, not opaque bytes
+    let json: serde_json::Value = serde_json::from_slice(&save).unwrap();
+    assert_eq!(json["save_version"], crate::init::SAVE_VERSION);
+    assert!(json["res"]["map"]["Json"].is_object());
+    assert!(json["res"]["game_time"]["Json"]["tick"].is_number());
+    assert!(json["world"].is_object());
+
+    let loaded = Simulation::decode(&save, SaveFormat::JSON).unwrap();
+    assert_eq!(
+        loaded.read::<GameTime>().tick,
+        test.g.read::<GameTime>().tick
+    );
+    let (map, loaded_map) = (test.g.map(), loaded.map());
+    assert_eq!(loaded_map.roads().len(), map.roads().len());
+    assert_eq!(loaded_map.lanes().len(), map.lanes().len());
+    assert_eq!(loaded_map.intersections().len(), map.intersections().len());
+    for (id, lane) in map.lanes() {
+        assert_eq!(loaded_map.lanes()[id].kind, lane.kind);
+    }
+    assert!(loaded_map.validate().is_empty());
+}
+
+#[test]
+fn buildings_roundtrip_through_save() {
+    use crate::map::BuildingKind;
+    use crate::map_dynamic::BuildingInfos;
+    use crate::souls::human::spawn_human;
+    use crate::Simulation;
+    use common::saveload::{CheckedCompressedBincode, Encoder};
+    use geom::{vec2, OBB};
+    use prototypes::BuildingGen;
+
+    let mut test = TestCtx::new();
+    test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(0.0, 200.0, 0.0)]);
+    test.build_roads(&[vec3(60.0, 0.0, 0.0), vec3(60.0, 200.0, 0.0)]);
+
+    let houses = [
+        test.build_house_near(vec2(-20.0, 30.0)),
+        test.build_house_near(vec2(80.0, 170.0)),
+    ];
+    for &house in &houses {
+        spawn_human(&mut test.g, house).unwrap();
+    }
+
+    let special = |kind, center, door_offset| WorldCommand::MapBuildSpecialBuilding {
+        pos: OBB::new(center, vec2(1.0, 0.0), 20.0, 20.0),
+        kind,
+        gen: BuildingGen::CenteredDoor {
+            vertical_factor: 1.0,
+        },
+        zone: None,
+        connected_road: None,
+        door_offset,
+    };
+    test.apply(&[
+        special(BuildingKind::TrainStation, vec2(30.0, 100.0), 2),
+        special(BuildingKind::ExternalTrading, vec2(30.0, 150.0), 0),
+    ]);
+    test.tick();
+
+    let snapshot = |sim: &Simulation| {
+        let map = sim.map();
+        let binfos = sim.read::<BuildingInfos>();
+        map.buildings()
+            .iter()
+            .map(|(id, b)| {
+                let info = binfos.get(id).cloned().unwrap_or_default();
+                (
+                    id,
+                    b.kind,
+                    b.door_offset,
+                    info.owner,
+                    info.inside,
+                    map.building_access(id),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let before = snapshot(&test.g);
+    assert_eq!(before.len(), 4);
+    assert!(before.iter().filter(|b| b.3.is_some()).count() >= houses.len());
+
+    let save = CheckedCompressedBincode::encode(&test.g).unwrap();
+    let loaded: Simulation = CheckedCompressedBincode::decode(&save).unwrap();
+
+    assert_eq!(snapshot(&loaded), before);
+    assert_eq!(
+        loaded.map().external_train_stations,
+        test.g.map().external_train_stations
+    );
+}
+
+#[test]
+fn same_seed_same_world() {
+    let run = |seed: u64| {
+        let mut test = TestCtx::from_seed(seed);
+        test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(300.0, 0.0, 0.0)]);
+        test.apply(&[WorldCommand::SpawnRandomCars { n_cars: 5 }]);
+        for _ in 0..50 {
+            test.tick();
+        }
+
+        // the seed is saved so a reloaded simulation keeps the same random stream
+        let saved = Bincode::encode(&test.g).unwrap();
+        let loaded: Simulation = Bincode::decode(&saved).unwrap();
+        assert_eq!(loaded.read::<SimulationOptions>().seed, seed);
+
+        test.g
+            .world()
+            .vehicles
+            .values()
+            .map(|v| v.trans)
+            .collect::<Vec<_>>()
+    };
+
+    let a = run(7);
+    assert_eq!(a.len(), 5);
+    assert_eq!(a, run(7));
+    assert_ne!(a, run(8));
+}
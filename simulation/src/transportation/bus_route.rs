@@ -0,0 +1,155 @@
+use crate::transportation::VehicleKind;
+use crate::world::VehicleID;
+use crate::Simulation;
+use geom::Color;
+use serde::{Deserialize, Serialize};
+use slotmapd::{new_key_type, SlotMap};
+use std::collections::BTreeMap;
+
+new_key_type! {
+    pub struct BusRouteID;
+}
+
+/// A bus line, its buses are painted in its color
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct BusRoute {
+    pub line_number: u16,
+    pub color: Color,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct BusRoutes {
+    routes: SlotMap<BusRouteID, BusRoute>,
+    assigned: BTreeMap<VehicleID, BusRouteID>,
+}
+
+impl BusRoutes {
+    pub fn add(&mut self, line_number: u16, color: Color) -> BusRouteID {
+        self.routes.insert(BusRoute { line_number, color })
+    }
+
+    pub fn get(&self, id: BusRouteID) -> Option<&BusRoute> {
+        self.routes.get(id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (BusRouteID, &BusRoute)> {
+        self.routes.iter()
+    }
+
+    /// The route the bus is assigned to, if any
+    pub fn route_of(&self, bus: VehicleID) -> Option<(BusRouteID, &BusRoute)> {
+        let id = *self.assigned.get(&bus)?;
+        Some((id, self.routes.get(id)?))
+    }
+}
+
+/// Assigns the bus to the route and repaints it in the route's livery.
+/// None unassigns it and gives it back the default bus color.
+/// Returns false if the vehicle is not a bus or the route doesn't exist.
+pub fn assign_bus(sim: &mut Simulation, bus: VehicleID, route: Option<BusRouteID>) -> bool {
+    let Some(v) = sim.world.vehicles.get(bus) else {
+        return false;
+    };
+    if v.vehicle.kind != VehicleKind::Bus {
+        return false;
+    }
+
+    let mut routes = sim.write::<BusRoutes>();
+    let vehicles = &sim.world.vehicles;
+    routes.assigned.retain(|id, _| vehicles.contains_key(*id));
+    let tint = match route {
+        Some(route) => {
+            let Some(r) = routes.routes.get(route) else {
+                return false;
+            };
+            let color = r.color;
+            routes.assigned.insert(bus, route);
+            color
+        }
+        None => {
+            routes.assigned.remove(&bus);
+            VehicleKind::Bus.appearance().1
+        }
+    };
+    drop(routes);
+
+    sim.world.vehicles[bus].vehicle.tint = tint;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BusRoutes;
+    use crate::tests::TestCtx;
+    use crate::transportation::{spawn_parked_vehicle, VehicleKind};
+    use crate::WorldCommand;
+    use geom::{vec3, Color};
+
+    #[test]
+    fn buses_take_their_route_livery() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(300.0, 0.0, 0.0)]);
+        test.tick();
+
+        let bus_a = spawn_parked_vehicle(&mut test.g, VehicleKind::Bus, vec3(50.0, 0.0, 0.0))
+            .expect("couldn't spawn bus");
+        let bus_b = spawn_parked_vehicle(&mut test.g, VehicleKind::Bus, vec3(200.0, 0.0, 0.0))
+            .expect("couldn't spawn bus");
+
+        test.apply(&[
+            WorldCommand::AddBusRoute {
+                line_number: 1,
+                color: Color::RED,
+            },
+            WorldCommand::AddBusRoute {
+                line_number: 2,
+                color: Color::BLUE,
+            },
+        ]);
+        let (red, blue) = {
+            let routes = test.g.read::<BusRoutes>();
+            let find = |n| routes.iter().find(|(_, r)| r.line_number == n).unwrap().0;
+            (find(1), find(2))
+        };
+
+        test.apply(&[
+            WorldCommand::AssignBus {
+                bus: bus_a,
+                route: Some(red),
+            },
+            WorldCommand::AssignBus {
+                bus: bus_b,
+                route: Some(blue),
+            },
+        ]);
+        assert_eq!(test.g.world.vehicles[bus_a].vehicle.tint, Color::RED);
+        assert_eq!(test.g.world.vehicles[bus_b].vehicle.tint, Color::BLUE);
+        assert_eq!(
+            test.g
+                .read::<BusRoutes>()
+                .route_of(bus_a)
+                .unwrap()
+                .1
+                .line_number,
+            1
+        );
+
+        // moving a bus to another line repaints it
+        test.apply(&[WorldCommand::AssignBus {
+            bus: bus_a,
+            route: Some(blue),
+        }]);
+        assert_eq!(test.g.world.vehicles[bus_a].vehicle.tint, Color::BLUE);
+        assert_eq!(test.g.read::<BusRoutes>().route_of(bus_a).unwrap().0, blue);
+
+        test.apply(&[WorldCommand::AssignBus {
+            bus: bus_b,
+            route: None,
+        }]);
+        assert_eq!(
+            test.g.world.vehicles[bus_b].vehicle.tint,
+            VehicleKind::Bus.appearance().1
+        );
+        assert!(test.g.read::<BusRoutes>().route_of(bus_b).is_none());
+    }
+}
@@ -0,0 +1,255 @@
+use crate::map::{IntersectionID, LaneKind, Map, PathKind};
+use crate::map_dynamic::Itinerary;
+use crate::transportation::{
+    get_random_car_color, spawn_or_queue, SpawnQueue, Vehicle, VehicleKind,
+};
+use crate::utils::par_command_buffer::ParCommandBuffer;
+use crate::utils::rand_provider::RandProvider;
+use crate::world::{VehicleEnt, VehicleID};
+use crate::Simulation;
+use geom::{Transform, Vec3};
+use prototypes::{GameDuration, GameInstant, GameTime, Tick};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Marks a boundary intersection where through-traffic enters and leaves the city.
+/// Vehicles spawned at a portal drive to another portal and despawn there.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EdgePortal {
+    /// Time between two vehicles entering the map, None for an exit-only portal
+    pub spawn_interval: Option<GameDuration>,
+    last_spawn: Option<GameInstant>,
+}
+
+impl EdgePortal {
+    pub fn new(spawn_interval: Option<GameDuration>) -> Self {
+        Self {
+            spawn_interval,
+            last_spawn: None,
+        }
+    }
+
+    /// Is it time to let the next vehicle in
+    pub fn should_spawn(&self, time: &GameTime) -> bool {
+        let Some(interval) = self.spawn_interval else {
+            return false;
+        };
+        self.last_spawn
+            .map_or(true, |t| t.elapsed(time).0 >= interval.0)
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct EdgePortals {
+    portals: BTreeMap<IntersectionID, EdgePortal>,
+    /// Through-traffic vehicles currently on the map
+    vehicles: BTreeSet<VehicleID>,
+}
+
+impl EdgePortals {
+    /// Sets the portal of an intersection, None removes it
+    pub fn set(&mut self, inter: IntersectionID, portal: Option<EdgePortal>) {
+        match portal {
+            Some(p) => self.portals.insert(inter, p),
+            None => self.portals.remove(&inter),
+        };
+    }
+
+    pub fn get(&self, inter: IntersectionID) -> Option<&EdgePortal> {
+        self.portals.get(&inter)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (IntersectionID, &EdgePortal)> {
+        self.portals.iter().map(|(&id, p)| (id, p))
+    }
+
+    pub fn vehicles(&self) -> &BTreeSet<VehicleID> {
+        &self.vehicles
+    }
+
+    /// Despawns the vehicle once it reaches the end of its itinerary
+    pub(crate) fn track(&mut self, vehicle: VehicleID) {
+        self.vehicles.insert(vehicle);
+    }
+}
+
+/// Where vehicles enter the map at this portal, None if its road is gone
+fn portal_entry(map: &Map, inter: IntersectionID) -> Option<Transform> {
+    let i = map.intersections().get(inter)?;
+    let road = map.roads().get(*i.roads.first()?)?;
+    let (lane, _) = road
+        .outgoing_lanes_from(inter)
+        .iter()
+        .find(|(_, kind)| *kind == LaneKind::Driving)?;
+    let lane = map.lanes().get(*lane)?;
+    let (pos, dir) = lane.points.point_dir_along(0.0);
+    Some(Transform::new_dir(pos, dir))
+}
+
+/// Where vehicles leave the map at this portal, None if its road is gone
+fn portal_exit(map: &Map, inter: IntersectionID) -> Option<Vec3> {
+    let i = map.intersections().get(inter)?;
+    let road = map.roads().get(*i.roads.first()?)?;
+    let (lane, _) = road
+        .incoming_lanes_to(inter)
+        .iter()
+        .find(|(_, kind)| *kind == LaneKind::Driving)?;
+    map.lanes().get(*lane).map(|l| l.points.last())
+}
+
+/// Builds the route of a vehicle entering at `src`, towards one of the other portals
+fn through_route(
+    map: &Map,
+    portals: &EdgePortals,
+    src: IntersectionID,
+    tick: Tick,
+) -> Option<(Transform, Itinerary)> {
+    let entry = portal_entry(map, src)?;
+    let exits: Vec<Vec3> = portals
+        .portals
+        .keys()
+        .filter(|&&id| id != src)
+        .filter_map(|&id| portal_exit(map, id))
+        .collect();
+    if exits.is_empty() {
+        return None;
+    }
+    let exit = exits[common::hash_u64((tick.0, src)) as usize % exits.len()];
+    let it = Itinerary::route(tick, entry.pos, exit, map, PathKind::Vehicle)?;
+    Some((entry, it))
+}
+
+/// Spawns through-traffic at the portals and despawns it once it reached its exit
+pub fn edge_portal_system(sim: &mut Simulation) {
+    profiling::scope!("transportation::edge_portal_system");
+    if sim.read::<EdgePortals>().portals.is_empty() && sim.read::<EdgePortals>().vehicles.is_empty()
+    {
+        return;
+    }
+
+    // despawn the vehicles that arrived
+    {
+        let mut portals = sim.write::<EdgePortals>();
+        let cbuf = sim.read::<ParCommandBuffer<VehicleEnt>>();
+        let vehicles = &sim.world.vehicles;
+        portals.vehicles.retain(|&id| {
+            let Some(v) = vehicles.get(id) else {
+                return false;
+            };
+            if v.it.has_ended(0.0) {
+                cbuf.kill(id);
+                return false;
+            }
+            true
+        });
+    }
+
+    let time = *sim.read::<GameTime>();
+
+    // portals whose road was removed stop spawning
+    let to_spawn: Vec<(IntersectionID, Transform, Itinerary)> = {
+        let map = sim.map();
+        let mut portals = sim.write::<EdgePortals>();
+        portals.portals.retain(|&id, _| {
+            map.intersections()
+                .get(id)
+                .map_or(false, |i| !i.roads.is_empty())
+        });
+
+        // wait until the previous vehicle found room to enter
+        let queue = sim.read::<SpawnQueue>();
+        let ready: Vec<IntersectionID> = portals
+            .iter()
+            .filter(|&(id, p)| p.should_spawn(&time) && !queue.has_pending_at(id))
+            .map(|(id, _)| id)
+            .collect();
+
+        ready
+            .into_iter()
+            .filter_map(|id| {
+                let (trans, it) = through_route(&map, &portals, id, time.tick)?;
+                Some((id, trans, it))
+            })
+            .collect()
+    };
+
+    for (id, trans, it) in to_spawn {
+        let vehicle = {
+            let rng = &mut *sim.write::<RandProvider>();
+            let tint = get_random_car_color(rng);
+            Vehicle::new_driving(VehicleKind::Car, tint, rng)
+        };
+        spawn_or_queue(sim, trans, vehicle, it, Some(id));
+
+        let mut portals = sim.write::<EdgePortals>();
+        if let Some(p) = portals.portals.get_mut(&id) {
+            p.last_spawn = Some(time.instant());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EdgePortal, EdgePortals};
+    use crate::map::IntersectionID;
+    use crate::tests::TestCtx;
+    use geom::{vec3, Vec2};
+    use prototypes::GameDuration;
+
+    fn inter_at(test: &TestCtx, p: Vec2) -> IntersectionID {
+        test.g
+            .map()
+            .intersections()
+            .values()
+            .find(|i| i.pos.xy().distance(p) < 1.0)
+            .unwrap()
+            .id
+    }
+
+    #[test]
+    fn through_traffic_crosses_the_map() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(150.0, 0.0, 0.0)]);
+        let entry = inter_at(&test, Vec2::ZERO);
+        let exit = inter_at(&test, Vec2::new(150.0, 0.0));
+
+        {
+            let mut portals = test.g.write::<EdgePortals>();
+            portals.set(
+                entry,
+                Some(EdgePortal::new(Some(GameDuration::from_secs(1000)))),
+            );
+            portals.set(exit, Some(EdgePortal::new(None)));
+        }
+
+        test.tick();
+        let vehicles = test.g.read::<EdgePortals>().vehicles.clone();
+        assert_eq!(vehicles.len(), 1);
+        let v = *vehicles.iter().next().unwrap();
+        assert!(test.g.world.vehicles[v].trans.pos.xy().distance(Vec2::ZERO) < 20.0);
+
+        let mut max_x = 0.0f32;
+        for _ in 0..3000 {
+            test.tick();
+            let Some(ent) = test.g.world.vehicles.get(v) else {
+                break;
+            };
+            max_x = max_x.max(ent.trans.pos.x);
+        }
+        assert!(
+            test.g.world.vehicles.get(v).is_none(),
+            "vehicle did not despawn"
+        );
+        assert!(max_x > 100.0, "vehicle did not traverse: {}", max_x);
+        assert!(test.g.read::<EdgePortals>().vehicles.is_empty());
+
+        // the entry waits for its interval and the exit portal never spawns anything
+        assert!(test.g.world.vehicles.is_empty());
+
+        // removing the road disables the portals
+        let road = test.g.map().roads().keys().next().unwrap();
+        test.g.map_mut().remove_road(road);
+        test.tick();
+        assert!(test.g.read::<EdgePortals>().iter().next().is_none());
+    }
+}
@@ -0,0 +1,223 @@
+use crate::map::{IntersectionID, Map, Traversable, TraverseKind, TurnID, TurnKind};
+use crate::utils::resources::Resources;
+use crate::World;
+use geom::{PolyLine3, Vec3};
+use std::collections::BTreeMap;
+
+/// Distance under which a vehicle with the priority is always too close to go, in meters
+const GIVE_WAY_MARGIN: f32 = 5.0;
+/// A vehicle with the priority arriving at the yielding turn in less than this many seconds is too close
+const GIVE_WAY_TIME: f32 = 2.5;
+/// Only the end of a yielding turn is checked, where it merges with the traffic with the priority, in meters
+const MERGE_LENGTH: f32 = 12.0;
+/// Vehicles further than this from the intersection don't have to be given way to yet, in meters
+const APPROACH_DIST: f32 = 60.0;
+
+/// Vehicles having the priority over the yielding turns of each intersection:
+/// those already in the intersection and those arriving on a turn that doesn't yield.
+#[derive(Default)]
+pub struct GiveWay {
+    /// Position and speed of the vehicles, recomputed every tick before the vehicles decide
+    priority: BTreeMap<IntersectionID, Vec<(Vec3, f32)>>,
+}
+
+impl GiveWay {
+    /// Whether taking the yielding turn now would cut in front of a vehicle with the priority
+    pub fn must_yield(&self, map: &Map, turn: TurnID) -> bool {
+        let Some(vehicles) = self.priority.get(&turn.parent) else {
+            return false;
+        };
+        let Some(t) = map
+            .intersections()
+            .get(turn.parent)
+            .and_then(|i| i.find_turn(turn))
+            .filter(|t| t.kind == TurnKind::Yield)
+        else {
+            return false;
+        };
+        let conflict = conflict_zone(&t.points);
+
+        vehicles.iter().any(|&(pos, speed)| {
+            conflict.project_dist(pos) < GIVE_WAY_MARGIN + speed * GIVE_WAY_TIME
+        })
+    }
+}
+
+/// The last [`MERGE_LENGTH`] meters of a yielding turn, or the whole turn if it is shorter
+fn conflict_zone(turn: &PolyLine3) -> PolyLine3 {
+    turn.cut((turn.length() - MERGE_LENGTH).max(0.0), 0.0)
+}
+
+pub fn give_way_system(world: &mut World, resources: &mut Resources) {
+    profiling::scope!("transportation::give_way_system");
+    let give_way: &mut GiveWay = &mut resources.write();
+    let map: &Map = &resources.read();
+
+    give_way.priority.clear();
+    for v in world.vehicles.values() {
+        if v.collider.is_none() || !v.vehicle.state.is_on_road() {
+            continue;
+        }
+        let Some(cur) = v.it.get_travers() else {
+            continue;
+        };
+        let inter = match cur.kind {
+            TraverseKind::Turn(id) => id.parent,
+            TraverseKind::Lane(lane) => {
+                let Some(&Traversable {
+                    kind: TraverseKind::Turn(next),
+                    ..
+                }) = v.it.next_travers()
+                else {
+                    continue;
+                };
+                let arriving = map
+                    .intersections()
+                    .get(next.parent)
+                    .and_then(|i| i.find_turn(next))
+                    .map_or(false, |t| t.kind == TurnKind::Driving)
+                    && map.lanes().get(lane).map_or(false, |l| {
+                        l.points.last().distance(v.trans.pos) < APPROACH_DIST
+                    });
+                if !arriving {
+                    continue;
+                }
+                next.parent
+            }
+        };
+        give_way
+            .priority
+            .entry(inter)
+            .or_default()
+            .push((v.trans.pos, v.speed.0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{conflict_zone, MERGE_LENGTH};
+    use crate::map::{
+        IntersectionID, LanePatternBuilder, LightPolicy, PathKind, RoadID, RoadSegmentKind,
+        TraverseKind, TurnKind,
+    };
+    use crate::map_dynamic::Itinerary;
+    use crate::tests::TestCtx;
+    use crate::transportation::{make_vehicle_entity, Vehicle, VehicleKind, VehicleState};
+    use crate::world::VehicleID;
+    use geom::{vec3, Color, PolyLine3, Transform};
+    use prototypes::Tick;
+
+    #[test]
+    fn conflict_zone_is_the_merge_end() {
+        let turn = PolyLine3::new(vec![
+            vec3(0.0, 0.0, 0.0),
+            vec3(20.0, 0.0, 0.0),
+            vec3(20.0, 20.0, 0.0),
+        ]);
+        let zone = conflict_zone(&turn);
+        assert!((zone.length() - MERGE_LENGTH).abs() < 0.01);
+        assert!(zone.first().distance(vec3(20.0, 8.0, 0.0)) < 0.01);
+        assert_eq!(zone.last(), turn.last());
+
+        // short turns are checked whole
+        let short = PolyLine3::new(vec![vec3(0.0, 0.0, 0.0), vec3(5.0, 0.0, 0.0)]);
+        let zone = conflict_zone(&short);
+        assert_eq!(zone.first(), short.first());
+        assert_eq!(zone.last(), short.last());
+    }
+
+    /// A car driving from 10m before the end of `from` to the middle of `to`
+    fn spawn(test: &mut TestCtx, center: IntersectionID, from: RoadID, to: RoadID) -> VehicleID {
+        let map = test.g.map();
+        let src = map.lanes()[map.roads()[from].incoming_lanes_to(center)[0].0]
+            .points
+            .clone();
+        let dst = &map.lanes()[map.roads()[to].outgoing_lanes_from(center)[0].0].points;
+        let (pos, dir) = src.point_dir_along(src.length() - 10.0);
+        let end = dst.point_along(dst.length() * 0.5);
+        let it = Itinerary::route(Tick(0), pos, end, &map, PathKind::Vehicle).unwrap();
+        drop(map);
+
+        let vehicle = Vehicle::new_driving(
+            VehicleKind::Car,
+            Color::WHITE,
+            &mut test.g.write::<crate::RandProvider>(),
+        );
+        make_vehicle_entity(&mut test.g, Transform::new_dir(pos, dir), vehicle, it, true)
+    }
+
+    fn on_turn(test: &TestCtx, v: VehicleID) -> bool {
+        test.g.world.vehicles.get(v).map_or(false, |v| {
+            matches!(
+                v.it.get_travers().map(|t| t.kind),
+                Some(TraverseKind::Turn(_))
+            )
+        })
+    }
+
+    #[test]
+    fn roundabout_entry_waits_for_circulating_car() {
+        let mut test = TestCtx::new();
+        let pat = LanePatternBuilder::new().parking(false).build();
+        let (center, west, east, south, north) = {
+            let mut map = test.g.map_mut();
+            let center = map.add_intersection(vec3(500.0, 500.0, 0.3));
+            let mut connect = |x: f32, y: f32| {
+                let end = map.add_intersection(vec3(500.0 + x, 500.0 + y, 0.3));
+                map.connect(end, center, &pat, RoadSegmentKind::Straight)
+                    .unwrap()
+            };
+            let roads = (
+                connect(-150.0, 0.0),
+                connect(150.0, 0.0),
+                connect(0.0, -150.0),
+                connect(0.0, 150.0),
+            );
+            map.update_intersection(center, |i| {
+                i.turn_policy.roundabout = Some(Default::default());
+                i.light_policy = LightPolicy::NoLights;
+            });
+
+            // every vehicle entering the roundabout gives way
+            let inter = &map.intersections()[center];
+            assert!(inter.turns().any(|t| t.kind == TurnKind::Yield));
+            assert!(inter.turns().all(|t| t.kind != TurnKind::Driving));
+
+            (center, roads.0, roads.1, roads.2, roads.3)
+        };
+        test.tick();
+
+        let circulating = spawn(&mut test, center, west, east);
+        for _ in 0..1000 {
+            if on_turn(&test, circulating) {
+                break;
+            }
+            test.tick();
+        }
+        assert!(on_turn(&test, circulating));
+
+        // the circulating car goes past the south entry on its way east
+        let entering = spawn(&mut test, center, south, north);
+        let mut waited = false;
+        for _ in 0..1000 {
+            test.tick();
+            if let Some(c) = test.g.world.vehicles.get(circulating) {
+                let e = &test.g.world.vehicles[entering];
+                assert!(c.trans.pos.distance(e.trans.pos) > 4.0);
+            }
+            if on_turn(&test, entering) {
+                break;
+            }
+            if on_turn(&test, circulating)
+                && matches!(
+                    test.g.world.vehicles[entering].vehicle.state,
+                    VehicleState::Yielding
+                )
+            {
+                waited = true;
+            }
+        }
+        assert!(waited);
+        assert!(on_turn(&test, entering));
+    }
+}
@@ -3,18 +3,34 @@ use std::fmt::{Debug, Formatter};
 use flat_spatial::grid::GridHandle;
 use serde::{Deserialize, Serialize};
 
+pub use bus_route::*;
+pub use edge_portal::*;
 use egui_inspect::InspectVec2Rotation;
 use geom::{Transform, Vec2};
+pub use give_way::*;
+pub use overtaking::*;
 pub use pedestrian::*;
+pub use platoon::*;
+pub use ramp_meter::*;
+pub use shared_space::*;
+pub use stuck::*;
 pub use vehicle::*;
 
 use crate::map::BuildingID;
 use crate::utils::resources::Resources;
-use crate::world::VehicleID;
+use crate::world::{AnyEntity, VehicleID};
 use crate::{Simulation, World};
 
+mod bus_route;
+mod edge_portal;
+mod give_way;
+mod overtaking;
 pub mod pedestrian;
+mod platoon;
+mod ramp_meter;
 pub mod road;
+mod shared_space;
+mod stuck;
 pub mod testing_vehicles;
 pub mod train;
 mod vehicle;
@@ -54,6 +70,9 @@ pub struct TransportState {
     pub height: f32,
     pub group: TransportationGroup,
     pub flag: u64,
+    /// The entity this collider belongs to
+    #[inspect(skip)]
+    pub owner: Option<AnyEntity>,
 }
 
 impl Default for TransportState {
@@ -65,6 +84,7 @@ impl Default for TransportState {
             height: 0.0,
             group: TransportationGroup::Unknown,
             flag: 0,
+            owner: None,
         }
     }
 }
@@ -76,6 +96,13 @@ pub struct Transporter(pub GridHandle);
 debug_inspect_impl!(Transporter);
 
 impl Transporter {
+    /// Records which entity this collider belongs to, so that spatial queries can find it back
+    pub fn set_owner(self, grid: &mut TransportGrid, owner: AnyEntity) {
+        if let Some((_, state)) = grid.get_mut(self.0) {
+            state.owner = Some(owner);
+        }
+    }
+
     pub fn destroy(self) -> impl FnOnce(&mut Simulation) {
         move |sim| {
             let cw = &mut sim.write::<TransportGrid>();
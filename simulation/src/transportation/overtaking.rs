@@ -0,0 +1,355 @@
+use crate::map::{Lane, LaneID, Map, TraverseKind};
+use crate::transportation::{TransportGrid, TransportationGroup, VehicleState};
+use crate::utils::resources::Resources;
+use crate::world::{VehicleEnt, VehicleID};
+use crate::World;
+use flat_spatial::grid::GridHandle;
+use geom::Vec3;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Only vehicles slower than our desired speed by this much, in m/s, are worth overtaking
+pub const OVERTAKE_MIN_SPEED_DIFF: f32 = 3.0;
+/// Lateral distance under which something is in the way of an overtaking vehicle
+pub const OVERTAKE_SIDE_CLEARANCE: f32 = 2.0;
+/// Vehicles slower than this, in m/s, are stopped in traffic and never overtaken
+const OVERTAKE_MIN_LEAD_SPEED: f32 = 1.0;
+/// How far ahead in the same lane a slow vehicle is looked for
+const OVERTAKE_LOOKAHEAD: f32 = 20.0;
+/// Distance along the road taken to move to the other lane and back
+const LANE_CHANGE_DIST: f32 = 10.0;
+/// Gap kept in front of the overtaken vehicle when coming back
+const OVERTAKE_MARGIN: f32 = 6.0;
+/// The maneuver must end this far from the end of the lane, away from the intersection
+const LANE_END_MARGIN: f32 = 15.0;
+/// Overtakes taking longer than this, in seconds, are not attempted
+const MAX_OVERTAKE_TIME: f32 = 10.0;
+/// Distance between the points checked for oncoming traffic
+const ONCOMING_CHECK_STEP: f32 = 2.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Overtake {
+    lane: LaneID,
+    opposing: LaneID,
+    /// Points of the maneuver still in the itinerary
+    detour: Vec<Vec3>,
+    /// Distance along the lane where the vehicle is back in it
+    return_at: f32,
+    /// The oncoming lane must stay clear up to this distance along the lane
+    clear_until: f32,
+}
+
+/// Vehicles passing a slower one by borrowing the oncoming lane of two-way roads.
+/// An overtake is only started if the oncoming lane is clear for the whole maneuver, plus the distance
+/// oncoming traffic would drive in the meantime. It is aborted if something shows up anyway.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Overtakes {
+    active: BTreeMap<VehicleID, Overtake>,
+    pub n_completed: u32,
+    pub n_aborted: u32,
+}
+
+impl Overtakes {
+    pub fn is_overtaking(&self, v: VehicleID) -> bool {
+        self.active.contains_key(&v)
+    }
+}
+
+/// Distance along the lane of the point of the lane closest to p
+fn along(lane: &Lane, p: Vec3) -> f32 {
+    lane.points.length_at_proj(lane.points.project(p))
+}
+
+/// Point of the opposing lane next to the point at distance d along the lane
+fn opposite(lane: &Lane, opposing: &Lane, d: f32) -> Vec3 {
+    opposing.points.project(lane.points.point_along(d))
+}
+
+/// Whether no vehicle is on the opposing lane between the two distances along the lane
+fn oncoming_clear(
+    grid: &TransportGrid,
+    lane: &Lane,
+    opposing: &Lane,
+    from: f32,
+    to: f32,
+    me: GridHandle,
+) -> bool {
+    let n = ((to - from) / ONCOMING_CHECK_STEP).ceil().max(0.0) as usize;
+    (0..=n).all(|i| {
+        let p = opposite(lane, opposing, from + i as f32 * ONCOMING_CHECK_STEP).xy();
+        !grid
+            .query_around(p, OVERTAKE_SIDE_CLEARANCE + ONCOMING_CHECK_STEP)
+            .any(|(id, pos)| {
+                let Some((_, s)) = grid.get(id) else {
+                    return false;
+                };
+                id != me
+                    && matches!(s.group, TransportationGroup::Vehicles)
+                    && opposing.points.project(pos.z(s.height)).xy().distance(pos)
+                        < OVERTAKE_SIDE_CLEARANCE
+            })
+    })
+}
+
+/// Plans an overtake of the closest slow vehicle in front, None if there is none or it isn't safe
+fn plan(map: &Map, grid: &TransportGrid, v: &VehicleEnt) -> Option<Overtake> {
+    if !matches!(
+        v.vehicle.state,
+        VehicleState::Driving | VehicleState::Yielding
+    ) {
+        return None;
+    }
+    let me = v.collider?.0;
+    let TraverseKind::Lane(lane_id) = v.it.get_travers()?.kind else {
+        return None;
+    };
+    let lane = map.lanes().get(lane_id)?;
+    let opposing_id = map.roads().get(lane.parent)?.overtaking_lane(lane_id)?;
+    let opposing = map.lanes().get(opposing_id)?;
+
+    let desired_speed = map.lane_speed_limit(lane_id).unwrap_or(lane.speed_limit)
+        * v.vehicle.kind.speed_factor()
+        * v.vehicle.max_speed_multiplier;
+    let d0 = along(lane, v.trans.pos);
+    let lane_end = along(lane, *v.it.local_path().first()?);
+
+    // closest vehicle in front in the same lane
+    let (lead_along, lead) = grid
+        .query_around(v.trans.pos.xy(), OVERTAKE_LOOKAHEAD)
+        .filter(|&(id, _)| id != me)
+        .filter_map(|(id, pos)| {
+            let (_, s) = grid.get(id)?;
+            if !matches!(s.group, TransportationGroup::Vehicles) {
+                return None;
+            }
+            let pos = pos.z(s.height);
+            let proj = lane.points.project(pos);
+            let (_, dir) = lane.points.point_dir_along(along(lane, pos));
+            if proj.xy().distance(pos.xy()) > OVERTAKE_SIDE_CLEARANCE || s.dir.dot(dir.xy()) < 0.8 {
+                return None;
+            }
+            let d = along(lane, pos);
+            (d > d0).then_some((d, s))
+        })
+        .min_by(|a, b| a.0.total_cmp(&b.0))?;
+
+    // stopped vehicles are waiting for something, not slow
+    if lead.speed < OVERTAKE_MIN_LEAD_SPEED {
+        return None;
+    }
+    let relative_speed = desired_speed - lead.speed;
+    if relative_speed < OVERTAKE_MIN_SPEED_DIFF {
+        return None;
+    }
+
+    // distance lost to the lead while accelerating to the desired speed
+    let speed = v.speed.0.clamp(0.0, desired_speed);
+    let accel_time = (desired_speed - speed) / v.vehicle.kind.acceleration();
+    let accel_loss = (desired_speed - speed) * accel_time * 0.5;
+
+    // time to be a margin ahead of the lead, during which it keeps its speed
+    let to_pass = lead_along - d0
+        + (v.vehicle.kind.width() + lead.radius * 2.0) * 0.5
+        + OVERTAKE_MARGIN
+        + accel_loss;
+    let return_at =
+        (d0 + desired_speed * to_pass / relative_speed).max(d0 + 2.0 * LANE_CHANGE_DIST + 1.0);
+    let duration = (return_at - d0) / desired_speed;
+    if duration > MAX_OVERTAKE_TIME || return_at > lane_end - LANE_END_MARGIN {
+        return None;
+    }
+
+    // oncoming traffic drives towards us during the maneuver
+    let oncoming_speed = map
+        .lane_speed_limit(opposing_id)
+        .unwrap_or(opposing.speed_limit);
+    let clear_until =
+        (return_at + oncoming_speed * duration + OVERTAKE_MARGIN).min(lane.points.length());
+    if !oncoming_clear(grid, lane, opposing, d0, clear_until, me) {
+        return None;
+    }
+
+    Some(Overtake {
+        lane: lane_id,
+        opposing: opposing_id,
+        detour: vec![
+            opposite(lane, opposing, d0 + LANE_CHANGE_DIST),
+            opposite(lane, opposing, return_at - LANE_CHANGE_DIST),
+            lane.points.point_along(return_at),
+        ],
+        return_at,
+        clear_until,
+    })
+}
+
+/// Starts overtakes of slow vehicles and watches the oncoming lane during the ongoing ones
+pub fn overtaking_system(world: &mut World, resources: &mut Resources) {
+    profiling::scope!("transportation::overtaking_system");
+    let map: &Map = &resources.read();
+    let grid: &TransportGrid = &resources.read();
+    let overtakes: &mut Overtakes = &mut resources.write();
+    let vehicles = &mut world.vehicles;
+
+    overtakes.active.retain(|&id, o| {
+        let Some(v) = vehicles.get_mut(id) else {
+            return false;
+        };
+        let on_lane = matches!(v.it.get_travers().map(|t| t.kind), Some(TraverseKind::Lane(l)) if l == o.lane);
+        let in_detour = v.it.local_path().iter().any(|p| o.detour.contains(p));
+        let overtaking = matches!(v.vehicle.state, VehicleState::Overtaking);
+        if !on_lane || !in_detour || !overtaking {
+            if overtaking {
+                v.vehicle.set_state(VehicleState::Driving);
+            }
+            if !in_detour {
+                overtakes.n_completed += 1;
+            }
+            return false;
+        }
+
+        let (Some(lane), Some(opposing)) = (map.lanes().get(o.lane), map.lanes().get(o.opposing))
+        else {
+            return true;
+        };
+        let d = along(lane, v.trans.pos);
+        let Some(me) = v.collider else {
+            return true;
+        };
+        // already heading back, the oncoming lane is no longer needed
+        if d >= o.return_at - LANE_CHANGE_DIST {
+            return true;
+        }
+        if oncoming_clear(grid, lane, opposing, d, o.clear_until, me.0) {
+            return true;
+        }
+
+        // something is coming: get back in the lane right away
+        let back = lane.points.point_along((d + LANE_CHANGE_DIST).min(o.return_at));
+        let detour = std::mem::take(&mut o.detour);
+        let return_at = o.return_at;
+        v.it.detour(&[back], |p| {
+            detour.contains(&p) || along(lane, p) < return_at
+        });
+        v.vehicle.set_state(VehicleState::Driving);
+        overtakes.n_aborted += 1;
+        log::info!("overtake of {:?} aborted", id);
+        false
+    });
+
+    for (id, v) in vehicles.iter_mut() {
+        if overtakes.active.contains_key(&id) {
+            continue;
+        }
+        let Some(o) = plan(map, grid, v) else {
+            continue;
+        };
+        let lane = &map.lanes()[o.lane];
+        let return_at = o.return_at;
+        v.it.detour(&o.detour, |p| along(lane, p) < return_at);
+        v.vehicle.set_state(VehicleState::Overtaking);
+        overtakes.active.insert(id, o);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Overtakes;
+    use crate::map::{LaneKind, PathKind};
+    use crate::map_dynamic::Itinerary;
+    use crate::tests::TestCtx;
+    use crate::transportation::{make_vehicle_entity, Vehicle, VehicleKind, VehicleState};
+    use crate::world::VehicleID;
+    use crate::RandProvider;
+    use geom::{vec3, Color, Transform, Vec3};
+    use prototypes::Tick;
+
+    /// Spawns a vehicle driving towards +x or -x, going to the other end of the road
+    fn spawn(test: &mut TestCtx, x: f32, towards_x: bool, speed_mul: f32) -> VehicleID {
+        let map = test.g.map();
+        let sign = if towards_x { 1.0 } else { -1.0 };
+        let lane = map
+            .lanes()
+            .values()
+            .find(|l| {
+                l.kind == LaneKind::Driving
+                    && l.points.first_dir().map_or(false, |d| d.x * sign > 0.9)
+            })
+            .unwrap();
+        let pos = lane.points.project(vec3(x, 0.0, 0.0));
+        let end_x = if towards_x { 990.0 } else { 10.0 };
+        let end = lane.points.project(vec3(end_x, 0.0, 0.0));
+        let it = Itinerary::route(Tick(0), pos, end, &map, PathKind::Vehicle).unwrap();
+        drop(map);
+
+        let mut vehicle = Vehicle::new_driving(
+            VehicleKind::Car,
+            Color::WHITE,
+            &mut test.g.write::<RandProvider>(),
+        );
+        vehicle.max_speed_multiplier = speed_mul;
+        make_vehicle_entity(
+            &mut test.g,
+            Transform::new_dir(pos, Vec3::X * sign),
+            vehicle,
+            it,
+            true,
+        )
+    }
+
+    fn x(test: &TestCtx, v: VehicleID) -> f32 {
+        test.g.world.vehicles[v].trans.pos.x
+    }
+
+    #[test]
+    fn overtakes_slow_vehicle_on_clear_road() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(1000.0, 0.0, 0.0)]);
+
+        let lead = spawn(&mut test, 100.0, true, 0.3);
+        let follower = spawn(&mut test, 80.0, true, 1.0);
+
+        let mut overtook = false;
+        for _ in 0..1500 {
+            test.tick();
+            overtook |= matches!(
+                test.g.world.vehicles[follower].vehicle.state,
+                VehicleState::Overtaking
+            );
+        }
+
+        assert!(overtook);
+        assert!(x(&test, follower) > x(&test, lead) + 10.0);
+        assert_eq!(test.g.read::<Overtakes>().n_completed, 1);
+        assert_eq!(test.g.read::<Overtakes>().n_aborted, 0);
+
+        // back in its lane
+        let w = &test.g.world;
+        let dy = w.vehicles[follower].trans.pos.y - w.vehicles[lead].trans.pos.y;
+        assert!(dy.abs() < 0.5, "{}", dy);
+        assert!(matches!(
+            w.vehicles[follower].vehicle.state,
+            VehicleState::Driving
+        ));
+    }
+
+    #[test]
+    fn no_overtake_with_oncoming_traffic() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(1000.0, 0.0, 0.0)]);
+
+        let _lead = spawn(&mut test, 100.0, true, 0.3);
+        let follower = spawn(&mut test, 80.0, true, 1.0);
+        let oncoming = spawn(&mut test, 125.0, false, 1.0);
+
+        while x(&test, oncoming) > x(&test, follower) {
+            test.tick();
+            assert!(!test.g.read::<Overtakes>().is_overtaking(follower));
+            let w = &test.g.world;
+            let dist = w.vehicles[follower]
+                .trans
+                .pos
+                .distance(w.vehicles[oncoming].trans.pos);
+            assert!(dist > 3.0, "{}", dist);
+        }
+    }
+}
@@ -1,42 +1,108 @@
+use crate::map::{LaneKind, Map};
 use crate::map_dynamic::Itinerary;
 use crate::transportation::{
-    Speed, TransportGrid, TransportState, TransportationGroup, Transporter,
+    Location, SharedSpaces, Speed, TransportGrid, TransportState, TransportationGroup, Transporter,
 };
 use crate::utils::rand_provider::RandProvider;
 use crate::utils::resources::Resources;
+use crate::world::HumanID;
 use crate::World;
 use egui_inspect::Inspect;
-use geom::{angle_lerpxy, Color, Transform, Vec3};
-use prototypes::DELTA;
+use geom::{angle_lerpxy, Color, Radians, Transform, Vec2, Vec3};
+use prototypes::{Season, DELTA};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Inspect)]
 pub struct Pedestrian {
     pub walking_speed: f32,
     pub walk_anim: f32,
+    /// Where the pedestrian became idle, loitering stays around it
+    #[serde(default)]
+    #[inspect(skip)]
+    pub loiter_anchor: Option<Vec3>,
 }
 
 const PED_SIZE: f32 = 0.5;
 
+/// Pedestrians never walk slower than this, whatever the distribution gives
+pub const MIN_WALKING_SPEED: f32 = 0.3;
+
+/// Distribution the walking speed of new pedestrians is sampled from, in m/s
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct WalkingSpeedDistribution {
+    pub mean: f32,
+    pub stddev: f32,
+}
+
+impl Default for WalkingSpeedDistribution {
+    fn default() -> Self {
+        Self {
+            mean: 1.2,
+            stddev: 0.2,
+        }
+    }
+}
+
+impl WalkingSpeedDistribution {
+    pub fn sample(&self, r: &mut RandProvider) -> f32 {
+        r.next_normal(self.mean, self.stddev).max(MIN_WALKING_SPEED)
+    }
+}
+
+/// How much pedestrians prefer routes covered by trees or buildings over exposed ones
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct WalkingComfort {
+    /// The weight in summer, see [`WalkingComfort::weight`]. 0 means they always take the shortest path.
+    pub comfort_weight: f32,
+}
+
+impl Default for WalkingComfort {
+    fn default() -> Self {
+        Self {
+            comfort_weight: 1.0,
+        }
+    }
+}
+
+impl WalkingComfort {
+    /// The shade matters most in the heat of summer, half as much in spring and autumn and not in winter
+    pub fn weight(&self, season: Season) -> f32 {
+        let heat = match season {
+            Season::Summer => 1.0,
+            Season::Spring | Season::Autumn => 0.5,
+            Season::Winter => 0.0,
+        };
+        self.comfort_weight * heat
+    }
+}
+
+/// Idle pedestrians never wander further than this from their anchor
+pub const LOITER_RADIUS: f32 = 2.0;
+/// Chance per tick for an idle pedestrian to pick a new spot to wander to
+const LOITER_CHANCE: f32 = 0.005;
+
 pub fn put_pedestrian_in_transport_grid(
     transport_grid: &mut TransportGrid,
     pos: Vec3,
+    owner: HumanID,
 ) -> Transporter {
     Transporter(transport_grid.insert(
         pos.xy(),
         TransportState {
             radius: PED_SIZE * 0.6,
             group: TransportationGroup::Pedestrians,
+            owner: Some(owner.into()),
             ..Default::default()
         },
     ))
 }
 
 impl Pedestrian {
-    pub(crate) fn new(r: &mut RandProvider) -> Self {
+    pub(crate) fn new(r: &mut RandProvider, speeds: &WalkingSpeedDistribution) -> Self {
         Self {
-            walking_speed: (0.8 + r.next_f32() * 0.8),
+            walking_speed: speeds.sample(r),
             walk_anim: 0.0,
+            loiter_anchor: None,
         }
     }
 }
@@ -65,12 +131,77 @@ pub fn random_pedestrian_shirt_color(r: &mut RandProvider) -> Color {
     unreachable!();
 }
 
-pub fn pedestrian_decision_system(world: &mut World, _resources: &mut Resources) {
+pub fn pedestrian_decision_system(world: &mut World, resources: &mut Resources) {
     profiling::scope!("transportation::pedestrian_decision_system");
+    let map = &*resources.read::<Map>();
+    let rng = &mut *resources.write::<RandProvider>();
+    let shared = &*resources.read::<SharedSpaces>();
+
     world.humans
         .values_mut()
         //.par_bridge()
-        .for_each(|human| pedestrian_decision(&mut human.it, &mut human.trans, &mut human.speed, &mut human.pedestrian))
+        .for_each(|human| {
+            let idle = human.location == Location::Outside && human.router.is_idle();
+            pedestrian_loiter(idle, &mut human.it, &human.trans, &mut human.pedestrian, rng, map);
+            if human.location == Location::Outside {
+                if let Some(space) = shared.containing(human.trans.pos.xy()) {
+                    human.it.cut_through(map, human.trans.pos, |p| space.contains(p.xy()));
+                }
+            }
+            pedestrian_decision(&mut human.it, &mut human.trans, &mut human.speed, &mut human.pedestrian)
+        })
+}
+
+/// Makes idle pedestrians wander around the spot they became idle at instead of standing frozen
+pub fn pedestrian_loiter(
+    idle: bool,
+    it: &mut Itinerary,
+    trans: &Transform,
+    pedestrian: &mut Pedestrian,
+    rng: &mut RandProvider,
+    map: &Map,
+) {
+    if !idle {
+        pedestrian.loiter_anchor = None;
+        return;
+    }
+    let anchor = *pedestrian.loiter_anchor.get_or_insert(trans.pos);
+
+    if !it.has_ended(0.0) || rng.next_f32() >= LOITER_CHANCE {
+        return;
+    }
+
+    let Some(target) = loiter_target(anchor, rng, map) else {
+        return;
+    };
+    *it = Itinerary::simple(vec![target]);
+}
+
+/// Random point around the anchor, None if it falls on a lane vehicles drive on
+fn loiter_target(anchor: Vec3, rng: &mut RandProvider, map: &Map) -> Option<Vec3> {
+    let angle = rng.next_f32() * std::f32::consts::TAU;
+    let dist = rng.next_f32().sqrt() * LOITER_RADIUS;
+    let target = anchor + Vec2::from_angle(Radians(angle)).z0() * dist;
+
+    for kind in [
+        LaneKind::Driving,
+        LaneKind::Bus,
+        LaneKind::Rail,
+        LaneKind::Tram,
+    ] {
+        let Some(id) = map.nearest_lane(target, kind, Some(LOITER_RADIUS + 5.0)) else {
+            continue;
+        };
+        let Some(lane) = map.lanes().get(id) else {
+            continue;
+        };
+        let min_dist = kind.width() * 0.5 + PED_SIZE;
+        if lane.points.project_dist2(target) < min_dist * min_dist {
+            return None;
+        }
+    }
+
+    Some(target)
 }
 
 pub fn pedestrian_decision(
@@ -119,3 +250,98 @@ pub fn calc_decision(
     let desired_dir = dir_to_pos.normalize();
     (pedestrian.walking_speed, desired_dir)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        pedestrian_loiter, Pedestrian, WalkingComfort, WalkingSpeedDistribution, LOITER_RADIUS,
+        MIN_WALKING_SPEED,
+    };
+    use crate::map::{Map, PathfindOptions};
+    use crate::map_dynamic::Itinerary;
+    use crate::utils::rand_provider::RandProvider;
+    use geom::{vec3, Transform};
+    use prototypes::{Season, Tick, DELTA};
+
+    #[test]
+    fn shade_matters_most_in_summer() {
+        let comfort = WalkingComfort::default();
+        assert!(comfort.weight(Season::Summer) > comfort.weight(Season::Spring));
+        assert_eq!(
+            comfort.weight(Season::Spring),
+            comfort.weight(Season::Autumn)
+        );
+        assert!(comfort.weight(Season::Autumn) > 0.0);
+        assert_eq!(comfort.weight(Season::Winter), 0.0);
+    }
+
+    #[test]
+    fn loiter_stays_around_anchor() {
+        let map = Map::empty();
+        let mut rng = RandProvider::new(1);
+        let mut ped = Pedestrian::new(&mut rng, &WalkingSpeedDistribution::default());
+        let mut it = Itinerary::NONE;
+        let mut trans = Transform::new(vec3(10.0, 5.0, 0.0));
+        let anchor = trans.pos;
+
+        let mut moved = false;
+        for _ in 0..20000 {
+            pedestrian_loiter(true, &mut it, &trans, &mut ped, &mut rng, &map);
+            let before = trans.pos;
+            trans.pos = it.update(
+                trans.pos,
+                ped.walking_speed * DELTA,
+                Tick(0),
+                0,
+                &map,
+                PathfindOptions::default(),
+            );
+            moved |= before != trans.pos;
+
+            assert!(
+                trans.pos.distance(anchor) <= LOITER_RADIUS + 0.01,
+                "{:?} drifted away from {:?}",
+                trans.pos,
+                anchor
+            );
+        }
+        assert!(moved, "idle pedestrian never moved");
+        assert_eq!(ped.loiter_anchor, Some(anchor));
+
+        pedestrian_loiter(false, &mut it, &trans, &mut ped, &mut rng, &map);
+        assert!(ped.loiter_anchor.is_none());
+    }
+
+    #[test]
+    fn walking_speeds_vary_around_mean() {
+        let mut rng = RandProvider::new(1);
+        let speeds = WalkingSpeedDistribution {
+            mean: 1.4,
+            stddev: 0.3,
+        };
+
+        let samples: Vec<f32> = (0..10000)
+            .map(|_| Pedestrian::new(&mut rng, &speeds).walking_speed)
+            .collect();
+        let n = samples.len() as f32;
+        let mean = samples.iter().sum::<f32>() / n;
+        let variance = samples.iter().map(|s| (s - mean).powi(2)).sum::<f32>() / n;
+
+        assert!((mean - 1.4).abs() < 0.02, "mean: {}", mean);
+        assert!(
+            (variance.sqrt() - 0.3).abs() < 0.02,
+            "stddev: {}",
+            variance.sqrt()
+        );
+        assert!(samples.iter().all(|&s| s >= MIN_WALKING_SPEED));
+
+        // most of the distribution is below zero
+        let slow = WalkingSpeedDistribution {
+            mean: -1.0,
+            stddev: 0.5,
+        };
+        for _ in 0..1000 {
+            assert!(Pedestrian::new(&mut rng, &slow).walking_speed >= MIN_WALKING_SPEED);
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use crate::transportation::TransportGrid;
+use crate::utils::resources::Resources;
+use crate::world::VehicleID;
+use crate::World;
+use prototypes::DELTA;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Gap kept between two vehicles of a platoon, much smaller than the usual safe distance
+pub const PLATOON_GAP: f32 = 2.0;
+/// Past this distance a follower is no longer linked to the vehicle in front of it
+pub const PLATOON_MAX_LINK_DIST: f32 = 20.0;
+/// How much faster than the speed limit a follower may go to close the gap
+pub const PLATOON_CATCH_UP_SPEED: f32 = 1.0;
+/// Under this gap the follower brakes whatever the leader does
+const PLATOON_MIN_GAP: f32 = 0.5;
+/// Speed correction per tick for each meter of gap error
+const GAP_GAIN: f32 = 0.04;
+/// Speed correction per tick for each m/s of speed difference with the vehicle in front
+const SPEED_GAIN: f32 = 0.1;
+
+/// Connected vehicles driving together: the followers share the leader's intent
+/// so they can drive much closer than reactive braking would allow.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Platoon {
+    pub leader: VehicleID,
+    /// Followers, ordered from front to back
+    pub members: Vec<VehicleID>,
+    leader_speed: Option<f32>,
+}
+
+/// State of a follower whose link to the vehicle in front of it is intact
+#[derive(Debug, Copy, Clone)]
+pub struct PlatoonLink {
+    pub gap: f32,
+    pub predecessor_speed: f32,
+    pub leader_acc: f32,
+}
+
+impl PlatoonLink {
+    /// Speed the follower should aim for: it copies the leader's acceleration and corrects the gap
+    pub fn follow_speed(&self, speed: f32) -> f32 {
+        if self.gap < PLATOON_MIN_GAP {
+            return 0.0;
+        }
+        (speed
+            + self.leader_acc * DELTA
+            + (self.gap - PLATOON_GAP) * GAP_GAIN
+            + (self.predecessor_speed - speed) * SPEED_GAIN)
+            .max(0.0)
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Platoons {
+    platoons: Vec<Platoon>,
+    /// Recomputed every tick before the vehicles decide
+    #[serde(skip)]
+    links: BTreeMap<VehicleID, PlatoonLink>,
+}
+
+impl Platoons {
+    /// Forms a new platoon, the vehicles leave the platoons they were part of
+    pub fn form(&mut self, leader: VehicleID, members: Vec<VehicleID>) {
+        self.disband(leader);
+        for &m in &members {
+            self.disband(m);
+        }
+        self.platoons.push(Platoon {
+            leader,
+            members,
+            leader_speed: None,
+        });
+    }
+
+    /// Removes the vehicle from its platoon
+    pub fn disband(&mut self, v: VehicleID) {
+        for p in &mut self.platoons {
+            if p.leader == v && !p.members.is_empty() {
+                p.leader = p.members.remove(0);
+                p.leader_speed = None;
+            }
+            p.members.retain(|&m| m != v);
+        }
+        self.platoons.retain(|p| !p.members.is_empty());
+        self.links.remove(&v);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Platoon> {
+        self.platoons.iter()
+    }
+
+    /// Returns the link of a follower to the vehicle in front of it, None if it's broken
+    pub fn link(&self, v: VehicleID) -> Option<&PlatoonLink> {
+        self.links.get(&v)
+    }
+}
+
+/// Checks which followers are still right behind the vehicle in front of them
+pub fn platoon_system(world: &mut World, resources: &mut Resources) {
+    profiling::scope!("transportation::platoon_system");
+    let platoons: &mut Platoons = &mut resources.write();
+    platoons.links.clear();
+    if platoons.platoons.is_empty() {
+        return;
+    }
+    let grid: &TransportGrid = &resources.read();
+
+    platoons.platoons.retain_mut(|p| {
+        p.members.retain(|&m| world.vehicles.contains_key(m));
+        if !world.vehicles.contains_key(p.leader) {
+            if p.members.is_empty() {
+                return false;
+            }
+            p.leader = p.members.remove(0);
+            p.leader_speed = None;
+        }
+        if p.members.is_empty() {
+            return false;
+        }
+
+        let leader_speed = world.vehicles[p.leader].speed.0;
+        let leader_acc = p
+            .leader_speed
+            .map_or(0.0, |last| (leader_speed - last) / DELTA);
+        p.leader_speed = Some(leader_speed);
+
+        let mut pred = p.leader;
+        for &m in &p.members {
+            if let Some(link) = link_between(world, grid, pred, m, leader_acc) {
+                platoons.links.insert(m, link);
+            }
+            pred = m;
+        }
+        true
+    });
+}
+
+fn link_between(
+    world: &World,
+    grid: &TransportGrid,
+    pred: VehicleID,
+    follower: VehicleID,
+    leader_acc: f32,
+) -> Option<PlatoonLink> {
+    let p = world.vehicles.get(pred)?;
+    let f = world.vehicles.get(follower)?;
+    let p_handle = p.collider.as_ref()?.0;
+    let f_handle = f.collider.as_ref()?.0;
+
+    let pos = f.trans.pos.xy();
+    let (dir, dist) = (p.trans.pos.xy() - pos).dir_dist()?;
+    if dist > PLATOON_MAX_LINK_DIST || dir.dot(f.trans.dir.xy()) < 0.9 {
+        return None;
+    }
+
+    // somebody cut in
+    let cut_in = grid.query_around(pos, dist).any(|(id, his_pos)| {
+        if id == p_handle || id == f_handle {
+            return false;
+        }
+        let towards = his_pos - pos;
+        let along = towards.dot(dir);
+        along > 0.0 && along < dist && towards.perp_dot(dir).abs() < 2.5
+    });
+    if cut_in {
+        return None;
+    }
+
+    Some(PlatoonLink {
+        gap: dist - p.vehicle.kind.collider_radius() - f.vehicle.kind.collider_radius(),
+        predecessor_speed: p.speed.0,
+        leader_acc,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Platoons, PLATOON_GAP};
+    use crate::map::{LaneKind, PathKind};
+    use crate::map_dynamic::Itinerary;
+    use crate::tests::TestCtx;
+    use crate::transportation::{make_vehicle_entity, test_vehicle, VehicleKind};
+    use crate::world::VehicleID;
+    use geom::{vec3, Transform, Vec3};
+    use prototypes::Tick;
+
+    fn spawn(test: &mut TestCtx, x: f32, road_y: f32, speed: f32) -> VehicleID {
+        let map = test.g.map();
+        let lane = map
+            .lanes()
+            .values()
+            .find(|l| {
+                l.kind == LaneKind::Driving
+                    && (l.points.first().y - road_y).abs() < 10.0
+                    && l.points.first_dir().map_or(false, |d| d.x > 0.9)
+            })
+            .unwrap();
+        let pos = lane.points.project(vec3(x, road_y, 0.0));
+        let end = lane.points.project(vec3(990.0, road_y, 0.0));
+        let it = Itinerary::route(Tick(0), pos, end, &map, PathKind::Vehicle).unwrap();
+        drop(map);
+
+        let id = make_vehicle_entity(
+            &mut test.g,
+            Transform::new_dir(pos, Vec3::X),
+            test_vehicle(4),
+            it,
+            true,
+        );
+        test.g.world_mut_unchecked().vehicles[id].speed.0 = speed;
+        id
+    }
+
+    fn gap(test: &TestCtx, front: VehicleID, back: VehicleID) -> f32 {
+        let w = &test.g.world;
+        w.vehicles[front]
+            .trans
+            .pos
+            .distance(w.vehicles[back].trans.pos)
+            - VehicleKind::Car.collider_radius() * 2.0
+    }
+
+    #[test]
+    fn platoon_drives_closer_until_cut_in() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(1000.0, 0.0, 0.0)]);
+        test.build_roads(&[vec3(0.0, 200.0, 0.0), vec3(1000.0, 200.0, 0.0)]);
+
+        let leader = spawn(&mut test, 100.0, 0.0, 0.0);
+        let follower = spawn(&mut test, 92.0, 0.0, 0.0);
+        test.g.write::<Platoons>().form(leader, vec![follower]);
+
+        let front = spawn(&mut test, 100.0, 200.0, 0.0);
+        let back = spawn(&mut test, 92.0, 200.0, 0.0);
+
+        for _ in 0..600 {
+            test.tick();
+        }
+
+        let platoon_gap = gap(&test, leader, follower);
+        let normal_gap = gap(&test, front, back);
+        assert!(
+            platoon_gap < normal_gap,
+            "platoon: {} normal: {}",
+            platoon_gap,
+            normal_gap
+        );
+        assert!((platoon_gap - PLATOON_GAP).abs() < 1.0, "{}", platoon_gap);
+        assert!(test.g.read::<Platoons>().link(follower).is_some());
+
+        // make room for a car cutting in between the leader and the follower
+        let leader_pos = test.g.world.vehicles[leader].trans.pos;
+        test.g.world_mut_unchecked().vehicles[leader].trans.pos = leader_pos + Vec3::X * 7.0;
+        let speed = test.g.world.vehicles[follower].speed.0;
+        let intruder = spawn(&mut test, leader_pos.x, 0.0, speed);
+
+        for _ in 0..400 {
+            test.tick();
+        }
+
+        assert!(test.g.read::<Platoons>().link(follower).is_none());
+        let cut_in_gap = gap(&test, intruder, follower);
+        assert!(
+            cut_in_gap > platoon_gap + 2.0,
+            "cut in: {} platoon: {}",
+            cut_in_gap,
+            platoon_gap
+        );
+    }
+}
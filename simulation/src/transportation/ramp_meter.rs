@@ -0,0 +1,225 @@
+use crate::map::{LaneID, Map, TraverseKind};
+use crate::map_dynamic::OBJECTIVE_OK_DIST;
+use crate::utils::resources::Resources;
+use crate::world::VehicleID;
+use crate::World;
+use geom::Vec3;
+use prototypes::{GameDuration, GameInstant, GameTime};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Default free distance required on the main road after the merge, in meters
+pub const RAMP_METER_MIN_GAP: f32 = 15.0;
+
+/// Releases at most one vehicle per interval from a lane (typically a highway on-ramp).
+/// Vehicles are held as long as the main road right after the merge is not free.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RampMeter {
+    /// Minimum time between two released vehicles
+    pub interval: GameDuration,
+    /// Free distance required on the lanes after the merge before releasing a vehicle
+    pub min_gap: f32,
+    last_release: Option<GameInstant>,
+    last_vehicle: Option<VehicleID>,
+    gap_free: bool,
+}
+
+impl RampMeter {
+    pub fn new(interval: GameDuration) -> Self {
+        Self {
+            interval,
+            min_gap: RAMP_METER_MIN_GAP,
+            last_release: None,
+            last_vehicle: None,
+            gap_free: true,
+        }
+    }
+
+    /// Can the next vehicle go
+    pub fn is_open(&self, time: &GameTime) -> bool {
+        self.gap_free
+            && self
+                .last_release
+                .map_or(true, |t| t.elapsed(time).0 >= self.interval.0)
+    }
+
+    /// Records that the vehicle went through the meter, a vehicle is only counted once
+    pub fn release(&mut self, vehicle: VehicleID, time: &GameTime) {
+        if self.last_vehicle == Some(vehicle) {
+            return;
+        }
+        self.last_vehicle = Some(vehicle);
+        self.last_release = Some(time.instant());
+    }
+
+    pub fn last_release(&self) -> Option<GameInstant> {
+        self.last_release
+    }
+
+    /// Returns the speed the vehicle should aim for while on the metered lane.
+    /// The vehicle stops at the end of the lane if the meter is closed.
+    pub fn vehicle_speed(
+        &self,
+        map: &Map,
+        lane: LaneID,
+        time: &GameTime,
+        pos: Vec3,
+        stop_dist: f32,
+        desired_speed: f32,
+    ) -> f32 {
+        if self.is_open(time) {
+            return desired_speed;
+        }
+        let Some(l) = map.lanes().get(lane) else {
+            return desired_speed;
+        };
+        if l.control_point()
+            .is_close(pos, OBJECTIVE_OK_DIST * 1.05 + 2.0 + stop_dist)
+        {
+            return 0.0;
+        }
+        desired_speed
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct RampMeters {
+    meters: BTreeMap<LaneID, RampMeter>,
+    /// Lanes right after the merge for each meter, along with the map version they were computed at
+    #[serde(skip)]
+    downstream: Option<(u64, Vec<(LaneID, Vec<LaneID>)>)>,
+}
+
+impl RampMeters {
+    /// Sets the meter of a lane, None removes it
+    pub fn set(&mut self, lane: LaneID, meter: Option<RampMeter>) {
+        match meter {
+            Some(m) => self.meters.insert(lane, m),
+            None => self.meters.remove(&lane),
+        };
+        self.downstream = None;
+    }
+
+    pub fn get(&self, lane: LaneID) -> Option<&RampMeter> {
+        self.meters.get(&lane)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (LaneID, &RampMeter)> {
+        self.meters.iter().map(|(&id, m)| (id, m))
+    }
+}
+
+/// Records the vehicles going through each meter and whether the main road has room for the next one
+pub fn ramp_meter_system(world: &mut World, resources: &mut Resources) {
+    profiling::scope!("transportation::ramp_meter_system");
+    let meters: &mut RampMeters = &mut resources.write();
+    if meters.meters.is_empty() {
+        return;
+    }
+    let map: &Map = &resources.read();
+    let time: &GameTime = &resources.read();
+
+    let up_to_date = matches!(meters.downstream, Some((v, _)) if v == map.version());
+    if !up_to_date {
+        meters.meters.retain(|&id, _| map.lanes().contains_key(id));
+
+        // lanes right after the merge, for each meter
+        let downstream = meters
+            .meters
+            .keys()
+            .map(|&id| {
+                let lanes = map
+                    .lanes()
+                    .get(id)
+                    .and_then(|l| map.intersections().get(l.dst))
+                    .into_iter()
+                    .flat_map(|inter| inter.turns_from(id).map(|(t, _)| t.dst))
+                    .collect();
+                (id, lanes)
+            })
+            .collect();
+        meters.downstream = Some((map.version(), downstream));
+    }
+    let Some((_, downstream)) = &meters.downstream else {
+        return;
+    };
+
+    for m in meters.meters.values_mut() {
+        m.gap_free = true;
+    }
+
+    for (id, v) in world.vehicles.iter() {
+        let Some(t) = v.it.get_travers() else {
+            continue;
+        };
+        match t.kind {
+            TraverseKind::Turn(turn) => {
+                if let Some(m) = meters.meters.get_mut(&turn.src) {
+                    m.release(id, time);
+                    continue;
+                }
+                for (meter, lanes) in downstream {
+                    if lanes.contains(&turn.dst) {
+                        if let Some(m) = meters.meters.get_mut(meter) {
+                            m.gap_free = false;
+                        }
+                    }
+                }
+            }
+            TraverseKind::Lane(lane) => {
+                let Some(l) = map.lanes().get(lane) else {
+                    continue;
+                };
+                for (meter, lanes) in downstream {
+                    if !lanes.contains(&lane) {
+                        continue;
+                    }
+                    let Some(m) = meters.meters.get_mut(meter) else {
+                        continue;
+                    };
+                    if l.points.length_at_proj(v.trans.pos) < m.min_gap {
+                        m.gap_free = false;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RampMeter;
+    use crate::world::VehicleID;
+    use prototypes::{GameDuration, GameTime, Tick};
+    use slotmapd::SlotMap;
+
+    #[test]
+    fn releases_no_faster_than_interval() {
+        let mut ids = SlotMap::<VehicleID, ()>::with_key();
+        let interval = GameDuration::from_secs(10);
+        let mut meter = RampMeter::new(interval);
+
+        let mut releases = vec![];
+        for t in 0..5000 {
+            let time = GameTime::new(Tick(t));
+            // a queue of vehicles always waiting at the meter
+            if meter.is_open(&time) {
+                let v = ids.insert(());
+                meter.release(v, &time);
+                releases.push(t);
+            }
+        }
+
+        assert!(releases.len() > 2);
+        for w in releases.windows(2) {
+            assert!(w[1] - w[0] >= interval.0 .0);
+        }
+
+        // no gap on the main road: everybody waits
+        meter.gap_free = false;
+        let later = GameTime::new(Tick(10000));
+        assert!(!meter.is_open(&later));
+        meter.gap_free = true;
+        assert!(meter.is_open(&later));
+    }
+}
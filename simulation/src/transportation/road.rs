@@ -1,22 +1,43 @@
 use crate::map::{Map, TrafficBehavior, Traversable, TraverseKind};
 use crate::map_dynamic::{Itinerary, OBJECTIVE_OK_DIST};
 use crate::transportation::{
-    Speed, TransportGrid, TransportState, TransportationGroup, Transporter,
+    first_conflict, predict_trajectory, Vehicle, VehicleState, PREDICTION_STEP, TIME_TO_PARK,
+};
+use crate::transportation::{
+    GiveWay, PlatoonLink, Platoons, RampMeters, SharedSpaces, Speed, TransportGrid, TransportState,
+    TransportationGroup, Transporter, MAX_COLLIDER_RADIUS, OVERTAKE_SIDE_CLEARANCE,
+    PLATOON_CATCH_UP_SPEED, SHARED_SPACE_YIELD_DIST,
 };
-use crate::transportation::{Vehicle, VehicleState, TIME_TO_PARK};
 use crate::utils::resources::Resources;
 use crate::world::{VehicleEnt, VehicleID};
 use crate::ParCommandBuffer;
 use crate::World;
-use geom::{angle_lerpxy, Ray, Transform, Vec2, Vec3};
+use geom::{angle_lerpxy, vec2, PolyLine3, Radians, Transform, Vec2, Vec3};
 use prototypes::{GameTime, DELTA};
+use serde::{Deserialize, Serialize};
 use slotmapd::Key;
 
+/// Heading correction of the lateral controller in radians per meter away from the lane center
+pub const LATERAL_GAIN: f32 = 0.3;
+/// The lateral correction never exceeds this angle in radians, so that it doesn't oscillate in sharp curves
+pub const MAX_LATERAL_CORRECTION: f32 = 0.25;
+
+/// Distance to a stop sign under which a vehicle can stop to have made its stop, in meters
+const STOP_SIGN_DIST: f32 = 1.0;
+/// Speed under which a vehicle at a stop sign counts as stopped, in m/s
+const STOP_SIGN_SPEED: f32 = 0.3;
+/// How far ahead in seconds the paths of crossing vehicles are compared
+const ANTICIPATION_HORIZON: f32 = 4.0;
+
 pub fn vehicle_decision_system(world: &mut World, resources: &mut Resources) {
     profiling::scope!("transportation::vehicle_decision_system");
     let ra = &*resources.read();
     let rb = &*resources.read();
     let rc = &*resources.read();
+    let rd = &*resources.read();
+    let re = &*resources.read();
+    let rf = &*resources.read();
+    let rg = &*resources.read();
 
     world.vehicles.iter_mut().for_each(|(ent, v)| {
         let Some(ref coll) = v.collider else {
@@ -27,6 +48,10 @@ pub fn vehicle_decision_system(world: &mut World, resources: &mut Resources) {
             ra,
             rb,
             rc,
+            rd,
+            re,
+            rf,
+            rg,
             ent,
             &mut v.it,
             &mut v.trans,
@@ -41,6 +66,10 @@ pub fn vehicle_decision(
     map: &Map,
     time: &GameTime,
     cow: &TransportGrid,
+    shared: &SharedSpaces,
+    meters: &RampMeters,
+    platoons: &Platoons,
+    give_way: &GiveWay,
     me: VehicleID,
     it: &mut Itinerary,
     trans: &mut Transform,
@@ -52,19 +81,77 @@ pub fn vehicle_decision(
 
     let mut desired_speed = 0.0;
     let mut desired_dir = Vec3::ZERO;
-    if matches!(
-        vehicle.state,
-        VehicleState::Driving | VehicleState::Panicking(_)
-    ) {
+    if vehicle.state.is_on_road() {
         let danger_length =
             (self_obj.speed.powi(2) / (2.0 * vehicle.kind.deceleration())).min(100.0);
         let neighbors = cow.query_around(trans.pos.xy(), 12.0 + danger_length);
         let objs =
             neighbors.map(|(id, pos)| (pos, cow.get(id).expect("Handle not in transport grid").1));
 
-        let (s, d) = calc_decision(me, vehicle, map, time, trans, self_obj, it, objs);
+        let platoon = platoons.link(me);
+        let (s, d) = calc_decision(me, vehicle, map, time, trans, self_obj, it, platoon, objs);
         desired_speed = s;
         desired_dir = d;
+
+        // the lane followed is not the one driven on while overtaking
+        if let Some(&Traversable {
+            kind: TraverseKind::Lane(lane),
+            ..
+        }) = it
+            .get_travers()
+            .filter(|_| !matches!(vehicle.state, VehicleState::Overtaking))
+        {
+            if let Some(l) = map.lanes().get(lane) {
+                desired_dir = lateral_control(&l.points, trans.pos, desired_dir);
+            }
+        }
+
+        // Checked every tick so that a pedestrian entering while we're already inside is seen
+        if let Some(space) = shared.containing(trans.pos.xy()) {
+            let peds = cow
+                .query_around(trans.pos.xy(), SHARED_SPACE_YIELD_DIST + self_obj.radius)
+                .map(|(id, pos)| (pos, cow.get(id).expect("Handle not in transport grid").1));
+            desired_speed = space.vehicle_speed(trans, desired_speed, peds);
+        }
+
+        if let Some(&Traversable {
+            kind: TraverseKind::Lane(lane),
+            ..
+        }) = it.get_travers()
+        {
+            if let Some(meter) = meters.get(lane) {
+                let stop_dist = self_obj.speed.powi(2) / (2.0 * vehicle.kind.deceleration());
+                let speed =
+                    meter.vehicle_speed(map, lane, time, trans.pos, stop_dist, desired_speed);
+                if speed == 0.0 && desired_speed > 0.0 {
+                    vehicle.set_traffic_state(VehicleState::WaitingAtLight);
+                }
+                desired_speed = speed;
+            }
+        }
+
+        // wait at the end of the lane before a yielding turn, once in the intersection we have the priority
+        if let (
+            Some(&Traversable {
+                kind: TraverseKind::Lane(lane),
+                ..
+            }),
+            Some(&Traversable {
+                kind: TraverseKind::Turn(turn),
+                ..
+            }),
+        ) = (it.get_travers(), it.next_travers())
+        {
+            let stop_dist = self_obj.speed.powi(2) / (2.0 * vehicle.kind.deceleration());
+            let at_line = map.lanes().get(lane).map_or(false, |l| {
+                l.control_point()
+                    .is_close(trans.pos, OBJECTIVE_OK_DIST * 1.05 + 2.0 + stop_dist)
+            });
+            if desired_speed > 0.0 && at_line && give_way.must_yield(map, turn) {
+                vehicle.set_traffic_state(VehicleState::Yielding);
+                desired_speed = 0.0;
+            }
+        }
     }
 
     physics(
@@ -124,6 +211,15 @@ pub fn vehicle_state_update(
                 vehicle.state = VehicleState::Parked(spot);
             }
         }
+        VehicleState::ParkToRoad(_, ref mut t) => {
+            // Vehicle is on rails when leaving its spot.
+            *t += DELTA / TIME_TO_PARK;
+
+            if *t >= 1.0 {
+                kin.0 = 0.0;
+                vehicle.state = VehicleState::Driving;
+            }
+        }
         VehicleState::Parked(ref spot) => {
             if let Some(p) = spot.get(&map.parking) {
                 if p.trans != *trans {
@@ -158,6 +254,12 @@ fn physics(
             trans.dir = spline.derivative(t).normalize();
             return;
         }
+        VehicleState::ParkToRoad(spline, t) => {
+            trans.pos = spline.get(t);
+            // reversing, so the vehicle faces away from where it goes
+            trans.dir = -spline.derivative(t).normalize();
+            return;
+        }
         _ => {}
     }
 
@@ -177,12 +279,109 @@ fn physics(
         .min(4.0 * approx_angle)
         .min(max_ang_vel);
 
+    vehicle.update_wheels(trans.dir, desired_dir, speed, DELTA);
+
     trans.dir = angle_lerpxy(trans.dir, desired_dir, vehicle.ang_velocity * DELTA);
 
     kin.0 = speed;
 }
 
+/// Vehicles moving further than this in a single tick are moved in sub-steps, in meters.
+/// It is well above what regular traffic reaches, so their movement stays a single step.
+pub const DEFAULT_MAX_SUBSTEP_DIST: f32 = 2.0;
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct PhysicsSettings {
+    /// Longest distance a vehicle moves between two obstacle checks, None disables sub-stepping
+    pub max_substep_dist: Option<f32>,
+}
+
+impl Default for PhysicsSettings {
+    fn default() -> Self {
+        Self {
+            max_substep_dist: Some(DEFAULT_MAX_SUBSTEP_DIST),
+        }
+    }
+}
+
+/// Moves the vehicle along its itinerary by the distance it drives this tick.
+/// Fast vehicles move in sub-steps and stop at the last one before hitting an obstacle,
+/// so that they can't jump over it between two ticks.
+pub fn move_vehicle(
+    v: &mut VehicleEnt,
+    grid: &TransportGrid,
+    settings: &PhysicsSettings,
+    mut advance: impl FnMut(&mut Itinerary, Vec3, f32) -> Vec3,
+) {
+    let dist = v.speed.0 * DELTA;
+    let n_steps = match settings.max_substep_dist {
+        Some(max) if max > 0.0 && dist > max => (dist / max).ceil() as u32,
+        _ => 1,
+    };
+    if n_steps == 1 {
+        v.trans.pos = advance(&mut v.it, v.trans.pos, dist);
+        return;
+    }
+
+    let step = dist / n_steps as f32;
+    let radius = v.vehicle.kind.collider_radius();
+    for _ in 0..n_steps {
+        let mut it = v.it.clone();
+        let next = advance(&mut it, v.trans.pos, step);
+        if obstacle_ahead(grid, v.trans.pos.xy(), next.xy(), radius, v.collider) {
+            v.speed.0 = 0.0;
+            return;
+        }
+        v.it = it;
+        v.trans.pos = next;
+    }
+}
+
+/// Something other than ourselves, in front of us, overlaps us once we moved to `to`
+fn obstacle_ahead(
+    grid: &TransportGrid,
+    from: Vec2,
+    to: Vec2,
+    radius: f32,
+    me: Option<Transporter>,
+) -> bool {
+    let dir = to - from;
+    grid.query_around(to, radius + MAX_COLLIDER_RADIUS)
+        .any(|(id, his_pos)| {
+            if me.map_or(false, |m| m.0 == id) || (his_pos - from).dot(dir) <= 0.0 {
+                return false;
+            }
+            grid.get(id)
+                .map_or(false, |(_, s)| to.distance(his_pos) < radius + s.radius)
+        })
+}
+
+/// Signed distance from pos to the centerline, positive when pos is on the right of it
+pub fn cross_track_error(centerline: &PolyLine3, pos: Vec3) -> f32 {
+    let (proj, _, dir) = centerline.project_segment_dir(pos);
+    let right = vec2(dir.y, -dir.x);
+    (pos - proj).xy().dot(right)
+}
+
+/// Steers the desired direction back towards the centerline, proportionally to the cross-track error.
+/// The correction is bounded by [`MAX_LATERAL_CORRECTION`].
+pub fn lateral_control(centerline: &PolyLine3, pos: Vec3, desired_dir: Vec3) -> Vec3 {
+    if desired_dir == Vec3::ZERO {
+        return desired_dir;
+    }
+    let correction = (LATERAL_GAIN * cross_track_error(centerline, pos))
+        .clamp(-MAX_LATERAL_CORRECTION, MAX_LATERAL_CORRECTION);
+    // on the right of the center means turning left, which is counter-clockwise
+    desired_dir
+        .xy()
+        .rotated_by_angle(Radians(correction))
+        .z(desired_dir.z)
+}
+
 /// Decide the appropriate velocity and direction to aim for.
+/// Platoon followers trust the vehicle in front instead of braking reactively,
+/// but they still stop at lights and stop signs on their own.
+#[allow(clippy::too_many_arguments)]
 pub fn calc_decision<'a>(
     me: VehicleID,
     vehicle: &mut Vehicle,
@@ -191,6 +390,7 @@ pub fn calc_decision<'a>(
     trans: &Transform,
     self_obj: &TransportState,
     it: &Itinerary,
+    platoon: Option<&PlatoonLink>,
     neighs: impl Iterator<Item = (Vec2, &'a TransportState)>,
 ) -> (f32, Vec3) {
     let default_return = (0.0, trans.dir);
@@ -206,7 +406,8 @@ pub fn calc_decision<'a>(
 
     let cutoff = (0.8 + stop_dist).min(1.5);
 
-    let (front_dist, flag) = calc_front_dist(vehicle, trans, self_obj, it, neighs, cutoff);
+    let front = calc_front_dist(vehicle, map, trans, self_obj, it, neighs, cutoff);
+    let (front_dist, flag) = vehicle.perceive(front);
 
     let position = trans.pos;
     let dir_to_pos = unwrap_or!(
@@ -216,13 +417,17 @@ pub fn calc_decision<'a>(
 
     if let VehicleState::Panicking(since) = vehicle.state {
         if since.elapsed(time).seconds() > 200.0 {
-            vehicle.state = VehicleState::Driving;
+            vehicle.set_state(VehicleState::Driving);
         }
+    } else if platoon.is_some() {
+        // the gap to the vehicle in front is kept by the platoon link
     } else if speed.abs() < 0.2 && front_dist < 1.5 {
         let me_u64: u64 = me.data().as_ffi();
         if me_u64 == flag {
-            vehicle.state = VehicleState::Panicking(time.instant());
+            vehicle.set_state(VehicleState::Panicking(time.instant()));
             log::info!("gridlock!")
+        } else {
+            vehicle.set_traffic_state(VehicleState::Yielding);
         }
         vehicle.flag = if vehicle.flag | flag == 0 {
             me_u64
@@ -234,11 +439,13 @@ pub fn calc_decision<'a>(
     } else {
         // Stop at 80 cm of object in front
         if front_dist < 0.8 + stop_dist {
+            vehicle.set_traffic_state(VehicleState::Yielding);
             return (0.0, dir_to_pos);
         }
     }
 
     vehicle.flag = 0;
+    vehicle.set_traffic_state(VehicleState::Driving);
 
     if let Some(term_pos) = it.get_terminal() {
         if term_pos.is_close(position, stop_dist) {
@@ -254,9 +461,12 @@ pub fn calc_decision<'a>(
     }) = it.get_travers()
     {
         if let Some(l) = map.lanes().get(*l_id) {
-            speed = l.speed_limit;
+            speed = map.lane_speed_limit(*l_id).unwrap_or(l.speed_limit);
 
             let light = l.control_point();
+            if vehicle.stopped_at.map_or(false, |stopped| stopped != *l_id) {
+                vehicle.stopped_at = None;
+            }
 
             match l.control.get_behavior(time.seconds) {
                 TrafficBehavior::RED | TrafficBehavior::ORANGE => {
@@ -267,15 +477,21 @@ pub fn calc_decision<'a>(
                             + stop_dist
                             + (vehicle.kind.width() * 0.5 - OBJECTIVE_OK_DIST).max(0.0),
                     ) {
+                        vehicle.set_traffic_state(VehicleState::WaitingAtLight);
                         return (0.0, dir_to_pos);
                     }
                 }
-                TrafficBehavior::STOP => {
-                    if light.is_close_signed(position, stop_dist) && !light.is_close(position, 1.0)
+                TrafficBehavior::STOP if vehicle.stopped_at != Some(*l_id) => {
+                    // come to a full stop at the line, then go when nothing is in the way
+                    if light.is_close(position, STOP_SIGN_DIST) && self_obj.speed < STOP_SIGN_SPEED
                     {
+                        vehicle.stopped_at = Some(*l_id);
+                    } else if light.is_close_signed(position, stop_dist.max(STOP_SIGN_DIST)) {
+                        vehicle.set_traffic_state(VehicleState::Yielding);
                         return (0.0, dir_to_pos);
                     }
                 }
+                TrafficBehavior::STOP => {}
                 TrafficBehavior::GREEN => {
                     if light.is_close(position, stop_dist * 0.4) {
                         return (0.0, dir_to_pos);
@@ -286,21 +502,29 @@ pub fn calc_decision<'a>(
     }
 
     // Not facing the objective
-    if dir_to_pos.dot(trans.dir) < 0.8 {
-        return (6.0, dir_to_pos);
+    let speed = if dir_to_pos.dot(trans.dir) < 0.8 {
+        6.0
+    } else {
+        vehicle.kind.speed_factor() * vehicle.max_speed_multiplier * speed
+    };
+
+    if let Some(link) = platoon {
+        return (
+            link.follow_speed(self_obj.speed)
+                .min(speed + PLATOON_CATCH_UP_SPEED),
+            dir_to_pos,
+        );
     }
 
-    (
-        vehicle.kind.speed_factor() * vehicle.max_speed_multiplier * speed,
-        dir_to_pos,
-    )
+    (speed, dir_to_pos)
 }
 
 /// Calculates the distance to the closest problematic object in front of the car.
-/// It can be another car or a pedestrian, or it can be a potential collision point from a
-/// car coming perpendicularly.
+/// It can be another car or a pedestrian, or it can be a car coming perpendicularly
+/// whose predicted path meets ours along the itinerary.
 fn calc_front_dist<'a>(
     vehicle: &mut Vehicle,
+    map: &Map,
     trans: &Transform,
     self_obj: &TransportState,
     it: &Itinerary,
@@ -308,21 +532,27 @@ fn calc_front_dist<'a>(
     cutoff: f32,
 ) -> (f32, u64) {
     let position = trans.pos;
-    let direction = trans.dir;
     let pos2 = position.xy();
     let dir2 = trans.dir.xy();
 
     let mut min_front_dist: f32 = 50.0;
 
-    let my_ray = Ray {
-        from: position.xy() - direction.xy() * vehicle.kind.width() * 0.5,
-        dir: direction.xy(),
-    };
+    let mut my_trajectory = None;
 
     let my_radius = self_obj.radius;
     let speed = self_obj.speed;
 
-    let on_lane = it.get_travers().map_or(false, |t| t.kind.is_lane());
+    let lane_width = match it.get_travers().map(|t| t.kind) {
+        Some(TraverseKind::Lane(id)) => map.lanes().get(id).map(|l| l.width),
+        _ => None,
+    };
+    let on_lane = lane_width.is_some();
+    // the vehicle being overtaken is right next to us, only what's in our way matters
+    let max_side_dist = if matches!(vehicle.state, VehicleState::Overtaking) {
+        OVERTAKE_SIDE_CLEARANCE
+    } else {
+        3.0 + speed * 0.3
+    };
     let mut flag = 0;
     // Collision avoidance
     for (his_pos, nei_physics_obj) in neighs {
@@ -349,12 +579,20 @@ fn calc_front_dist<'a>(
 
         let is_vehicle = matches!(nei_physics_obj.group, TransportationGroup::Vehicles);
 
+        // pedestrians walking next to the lane are not in the way, only those stepping onto it are.
+        // In intersections, where the crossings are, every pedestrian in front counts
+        if matches!(nei_physics_obj.group, TransportationGroup::Pedestrians)
+            && lane_width.map_or(false, |w| dist_to_side > w * 0.5 + nei_physics_obj.radius)
+        {
+            continue;
+        }
+
         let cos_direction_angle = nei_physics_obj.dir.dot(dir2);
 
         // front cone
         if cos_angle > 0.85 - 0.015 * speed.min(10.0)
             && (!is_vehicle || cos_direction_angle > 0.0)
-            && (!on_lane || dist_to_side < 3.0 + speed * 0.3)
+            && (!on_lane || dist_to_side < max_side_dist)
         {
             let mut dist_to_obj = dist - my_radius - nei_physics_obj.radius;
             if !is_vehicle {
@@ -370,27 +608,44 @@ fn calc_front_dist<'a>(
             continue;
         }
 
-        // don't do ray checks for other things than cars
+        // only cars are anticipated
         if !is_vehicle {
             continue;
         }
 
-        // closest win
-        let his_ray = Ray {
-            from: his_pos - nei_physics_obj.radius * nei_physics_obj.dir,
-            dir: nei_physics_obj.dir,
-        };
-
-        let (my_dist, his_dist) = unwrap_or!(my_ray.both_dist_to_inter(&his_ray), continue);
-
-        if my_dist.max(his_dist) > 1000.0 {
-            continue;
-        }
-
         if nei_physics_obj.speed <= 0.01 {
             continue;
         }
 
+        // where our paths meet in the next few seconds, closest to it wins
+        let my_trajectory = my_trajectory.get_or_insert_with(|| {
+            predict_trajectory(
+                pos2,
+                dir2,
+                speed,
+                it.upcoming_points(map).map(|p| p.xy()),
+                ANTICIPATION_HORIZON,
+            )
+        });
+        let his_trajectory = predict_trajectory(
+            his_pos,
+            nei_physics_obj.dir,
+            nei_physics_obj.speed,
+            std::iter::empty(),
+            ANTICIPATION_HORIZON,
+        );
+        let t = unwrap_or!(
+            first_conflict(
+                my_trajectory,
+                &his_trajectory,
+                my_radius + nei_physics_obj.radius
+            ),
+            continue
+        );
+        let (_, meet) = his_trajectory[(t / PREDICTION_STEP).round() as usize];
+        let my_dist = pos2.distance(meet);
+        let his_dist = his_pos.distance(meet);
+
         if my_dist - speed.min(2.5) - my_radius
             < his_dist - nei_physics_obj.speed.min(2.5) - nei_physics_obj.radius
         {
@@ -405,3 +660,631 @@ fn calc_front_dist<'a>(
     }
     (min_front_dist, flag)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::{
+        LaneKind, LanePatternBuilder, PathKind, ProjectFilter, RoadMaterial, TrafficControl,
+        TrafficLightSchedule,
+    };
+    use crate::transportation::{test_vehicle, VehicleKind};
+    use geom::{vec3, Color};
+    use prototypes::Tick;
+
+    #[test]
+    fn waiting_at_light_then_driving_on_green() {
+        let mut map = Map::empty();
+        let pat = LanePatternBuilder::new().build();
+        for (a, b) in [
+            (vec3(0.0, 0.0, 0.0), vec3(100.0, 0.0, 0.0)),
+            (vec3(100.0, 0.0, 0.0), vec3(100.0, 100.0, 0.0)),
+        ] {
+            let a = map.project(a, 0.0, ProjectFilter::ALL);
+            let b = map.project(b, 0.0, ProjectFilter::ALL);
+            map.make_connection(a, b, None, &pat);
+        }
+
+        // the driving lane going east, towards the corner
+        let lane_id = map
+            .lanes()
+            .iter()
+            .find(|(_, l)| {
+                l.kind == LaneKind::Driving && l.points.last_dir().map_or(false, |d| d.x > 0.9)
+            })
+            .unwrap()
+            .0;
+        let lane = map.lanes.get_mut(lane_id).unwrap();
+        lane.control = TrafficControl::Light(TrafficLightSchedule::from_basic(10, 2, 10, 0));
+
+        let lane = &map.lanes()[lane_id];
+        let dir = lane.points.last_dir().unwrap();
+        let trans = Transform::new_dir(lane.control_point() - dir * 3.0, dir);
+
+        let it = Itinerary::route(
+            Tick(0),
+            trans.pos,
+            vec3(100.0, 80.0, 0.0),
+            &map,
+            PathKind::Vehicle,
+        )
+        .unwrap();
+
+        let mut vehicle = test_vehicle(4);
+        let self_obj = TransportState::default();
+
+        let find_time = |behavior: fn(TrafficBehavior) -> bool| {
+            (0..1000)
+                .map(|t| GameTime::new(Tick(t)))
+                .find(|time| behavior(lane.control.get_behavior(time.seconds)))
+                .unwrap()
+        };
+
+        let (speed, _) = calc_decision(
+            VehicleID::default(),
+            &mut vehicle,
+            &map,
+            &find_time(TrafficBehavior::is_red),
+            &trans,
+            &self_obj,
+            &it,
+            None,
+            std::iter::empty(),
+        );
+        assert_eq!(speed, 0.0);
+        assert!(matches!(vehicle.state, VehicleState::WaitingAtLight));
+
+        let (speed, _) = calc_decision(
+            VehicleID::default(),
+            &mut vehicle,
+            &map,
+            &find_time(|b| matches!(b, TrafficBehavior::GREEN)),
+            &trans,
+            &self_obj,
+            &it,
+            None,
+            std::iter::empty(),
+        );
+        assert!(speed > 0.0);
+        assert!(matches!(vehicle.state, VehicleState::Driving));
+    }
+
+    #[test]
+    fn platoon_followers_stop_at_red_lights() {
+        let mut map = Map::empty();
+        let pat = LanePatternBuilder::new().build();
+        for (a, b) in [
+            (vec3(0.0, 0.0, 0.0), vec3(100.0, 0.0, 0.0)),
+            (vec3(100.0, 0.0, 0.0), vec3(200.0, 0.0, 0.0)),
+        ] {
+            let a = map.project(a, 0.0, ProjectFilter::ALL);
+            let b = map.project(b, 0.0, ProjectFilter::ALL);
+            map.make_connection(a, b, None, &pat);
+        }
+
+        let lane_id = map
+            .lanes()
+            .iter()
+            .find(|(_, l)| {
+                l.kind == LaneKind::Driving
+                    && l.points.last().x < 150.0
+                    && l.points.last_dir().map_or(false, |d| d.x > 0.9)
+            })
+            .unwrap()
+            .0;
+        let lane = map.lanes.get_mut(lane_id).unwrap();
+        lane.control = TrafficControl::Light(TrafficLightSchedule::from_basic(10, 2, 10, 0));
+
+        let lane = &map.lanes()[lane_id];
+        let dir = lane.points.last_dir().unwrap();
+        let trans = Transform::new_dir(lane.control_point() - dir * 3.0, dir);
+        let it = Itinerary::route(
+            Tick(0),
+            trans.pos,
+            vec3(180.0, 0.0, 0.0),
+            &map,
+            PathKind::Vehicle,
+        )
+        .unwrap();
+
+        let self_obj = TransportState {
+            speed: 5.0,
+            ..Default::default()
+        };
+        // the leader went through just before the light turned red
+        let link = PlatoonLink {
+            gap: 10.0,
+            predecessor_speed: 10.0,
+            leader_acc: 0.0,
+        };
+        let red = (0..1000)
+            .map(|t| GameTime::new(Tick(t)))
+            .find(|time| lane.control.get_behavior(time.seconds).is_red())
+            .unwrap();
+
+        let mut vehicle = test_vehicle(4);
+        let (speed, _) = calc_decision(
+            VehicleID::default(),
+            &mut vehicle,
+            &map,
+            &red,
+            &trans,
+            &self_obj,
+            &it,
+            Some(&link),
+            std::iter::empty(),
+        );
+        assert_eq!(speed, 0.0);
+        assert!(matches!(vehicle.state, VehicleState::WaitingAtLight));
+    }
+
+    #[test]
+    fn full_stop_at_stop_sign_then_go() {
+        let mut map = Map::empty();
+        let pat = LanePatternBuilder::new().build();
+        for (a, b) in [
+            (vec3(0.0, 0.0, 0.0), vec3(100.0, 0.0, 0.0)),
+            (vec3(100.0, 0.0, 0.0), vec3(200.0, 0.0, 0.0)),
+        ] {
+            let a = map.project(a, 0.0, ProjectFilter::ALL);
+            let b = map.project(b, 0.0, ProjectFilter::ALL);
+            map.make_connection(a, b, None, &pat);
+        }
+
+        let lane_id = map
+            .lanes()
+            .iter()
+            .find(|(_, l)| {
+                l.kind == LaneKind::Driving
+                    && l.points.last().x < 150.0
+                    && l.points.last_dir().map_or(false, |d| d.x > 0.9)
+            })
+            .unwrap()
+            .0;
+        map.lanes.get_mut(lane_id).unwrap().control = TrafficControl::StopSign;
+
+        let lane = &map.lanes()[lane_id];
+        let dir = lane.points.last_dir().unwrap();
+        let trans = Transform::new_dir(lane.control_point() - dir * STOP_SIGN_DIST * 0.5, dir);
+        let it = Itinerary::route(
+            Tick(0),
+            trans.pos,
+            vec3(180.0, 0.0, 0.0),
+            &map,
+            PathKind::Vehicle,
+        )
+        .unwrap();
+
+        let mut vehicle = test_vehicle(4);
+        let decide = |vehicle: &mut Vehicle, speed: f32| {
+            calc_decision(
+                VehicleID::default(),
+                vehicle,
+                &map,
+                &GameTime::new(Tick(0)),
+                &trans,
+                &TransportState {
+                    speed,
+                    ..Default::default()
+                },
+                &it,
+                None,
+                std::iter::empty(),
+            )
+            .0
+        };
+
+        // rolling through the line is not a stop
+        assert_eq!(decide(&mut vehicle, STOP_SIGN_SPEED * 2.0), 0.0);
+        assert!(matches!(vehicle.state, VehicleState::Yielding));
+        assert_eq!(vehicle.stopped_at, None);
+
+        // stopped at the line: the stop is made and the car goes
+        assert!(decide(&mut vehicle, STOP_SIGN_SPEED * 0.5) > 0.0);
+        assert_eq!(vehicle.stopped_at, Some(lane_id));
+        assert!(matches!(vehicle.state, VehicleState::Driving));
+
+        // and keeps going while it speeds up
+        assert!(decide(&mut vehicle, STOP_SIGN_SPEED * 2.0) > 0.0);
+    }
+
+    #[test]
+    fn dirt_road_reduces_speed() {
+        let mut map = Map::empty();
+        let pat = LanePatternBuilder::new().build();
+        let a = map.project(vec3(0.0, 0.0, 0.0), 0.0, ProjectFilter::ALL);
+        let b = map.project(vec3(300.0, 0.0, 0.0), 0.0, ProjectFilter::ALL);
+        let (_, road) = map.make_connection(a, b, None, &pat).unwrap();
+
+        let trans = Transform::new_dir(vec3(50.0, 0.0, 0.0), Vec3::X);
+        let lane_id = map
+            .lanes()
+            .values()
+            .find(|l| {
+                l.kind == LaneKind::Driving && l.points.first_dir().map_or(false, |d| d.x > 0.9)
+            })
+            .unwrap()
+            .id;
+        let start = map.lanes()[lane_id].points.project(trans.pos);
+        let end = map.lanes()[lane_id].points.project(vec3(250.0, 0.0, 0.0));
+        let it = Itinerary::route(Tick(0), start, end, &map, PathKind::Vehicle).unwrap();
+
+        let mut vehicle = test_vehicle(4);
+        let self_obj = TransportState::default();
+        let time = GameTime::new(Tick(0));
+
+        let mut speed = |map: &Map| {
+            calc_decision(
+                VehicleID::default(),
+                &mut vehicle,
+                map,
+                &time,
+                &Transform::new_dir(start, Vec3::X),
+                &self_obj,
+                &it,
+                None,
+                std::iter::empty(),
+            )
+            .0
+        };
+
+        let asphalt = speed(&map);
+        assert!(asphalt > 0.0);
+
+        map.set_road_material(road, RoadMaterial::Dirt);
+        let points_before = map.roads()[road].points.as_slice().to_vec();
+        let dirt = speed(&map);
+
+        assert!(
+            (dirt - asphalt * RoadMaterial::Dirt.speed_factor()).abs() < 0.001,
+            "asphalt: {} dirt: {}",
+            asphalt,
+            dirt
+        );
+        assert!(dirt < asphalt);
+        assert_eq!(map.roads()[road].points.as_slice(), &*points_before);
+    }
+
+    #[test]
+    fn reaction_time_delays_braking() {
+        let mut map = Map::empty();
+        let pat = LanePatternBuilder::new().build();
+        let a = map.project(vec3(0.0, 0.0, 0.0), 0.0, ProjectFilter::ALL);
+        let b = map.project(vec3(300.0, 0.0, 0.0), 0.0, ProjectFilter::ALL);
+        map.make_connection(a, b, None, &pat).unwrap();
+
+        let lane = map
+            .lanes()
+            .values()
+            .find(|l| {
+                l.kind == LaneKind::Driving && l.points.first_dir().map_or(false, |d| d.x > 0.9)
+            })
+            .unwrap();
+        let start = lane.points.project(vec3(50.0, 0.0, 0.0));
+        let end = lane.points.project(vec3(250.0, 0.0, 0.0));
+        let it = Itinerary::route(Tick(0), start, end, &map, PathKind::Vehicle).unwrap();
+
+        let trans = Transform::new_dir(start, Vec3::X);
+        let self_obj = TransportState {
+            speed: 10.0,
+            radius: 1.0,
+            dir: Vec2::X,
+            ..Default::default()
+        };
+        let leader = TransportState {
+            radius: 1.0,
+            dir: Vec2::X,
+            group: TransportationGroup::Vehicles,
+            height: start.z,
+            ..Default::default()
+        };
+        const LEADER_BRAKES: u32 = 10;
+
+        // first tick at which the follower brakes
+        let brakes_at = |reaction_time: f32| {
+            let mut vehicle = test_vehicle(4);
+            vehicle.reaction_time = reaction_time;
+            (0..200)
+                .find(|&t| {
+                    // the leader suddenly stops right in front
+                    let gap = if t < LEADER_BRAKES { 40.0 } else { 5.0 };
+                    let leader_pos = start.xy() + Vec2::X * gap;
+                    let (speed, _) = calc_decision(
+                        VehicleID::default(),
+                        &mut vehicle,
+                        &map,
+                        &GameTime::new(Tick(t as u64)),
+                        &trans,
+                        &self_obj,
+                        &it,
+                        None,
+                        std::iter::once((leader_pos, &leader)),
+                    );
+                    speed == 0.0
+                })
+                .unwrap()
+        };
+
+        assert_eq!(brakes_at(0.0), LEADER_BRAKES);
+
+        let reaction_time = 0.5;
+        let lag = (reaction_time / DELTA).round() as u32;
+        assert_eq!(brakes_at(reaction_time), LEADER_BRAKES + lag);
+    }
+
+    #[test]
+    fn crossing_cars_are_anticipated_along_the_itinerary() {
+        let map = Map::empty();
+        let trans = Transform::new_dir(Vec3::ZERO, Vec3::X);
+        let self_obj = TransportState {
+            speed: 10.0,
+            radius: 1.0,
+            dir: Vec2::X,
+            ..Default::default()
+        };
+        // reaches (20, 0) at the same time as us when we go straight
+        let crossing = TransportState {
+            speed: 10.0,
+            radius: 1.0,
+            dir: -Vec2::Y,
+            group: TransportationGroup::Vehicles,
+            ..Default::default()
+        };
+
+        let front_dist = |it: &Itinerary| {
+            calc_front_dist(
+                &mut test_vehicle(4),
+                &map,
+                &trans,
+                &self_obj,
+                it,
+                std::iter::once((Vec2::new(20.0, 20.0), &crossing)),
+                1.5,
+            )
+            .0
+        };
+
+        let straight = Itinerary::simple(vec![vec3(100.0, 0.0, 0.0)]);
+        assert!(front_dist(&straight) < 25.0);
+
+        // we turn before its path, going straight ahead would have met it
+        let turning = Itinerary::simple(vec![vec3(5.0, 0.0, 0.0), vec3(5.0, -100.0, 0.0)]);
+        assert_eq!(front_dist(&turning), 50.0);
+    }
+
+    #[test]
+    fn pedestrians_on_the_sidewalk_are_ignored() {
+        let mut map = Map::empty();
+        let pat = LanePatternBuilder::new().parking(false).build();
+        let a = map.project(vec3(0.0, 0.0, 0.0), 0.0, ProjectFilter::ALL);
+        let b = map.project(vec3(300.0, 0.0, 0.0), 0.0, ProjectFilter::ALL);
+        map.make_connection(a, b, None, &pat).unwrap();
+
+        let lane = map
+            .lanes()
+            .values()
+            .find(|l| {
+                l.kind == LaneKind::Driving && l.points.first_dir().map_or(false, |d| d.x > 0.9)
+            })
+            .unwrap();
+        let start = lane.points.project(vec3(50.0, 0.0, 0.0));
+        let end = lane.points.project(vec3(250.0, 0.0, 0.0));
+        let it = Itinerary::route(Tick(0), start, end, &map, PathKind::Vehicle).unwrap();
+
+        // the sidewalk next to the lane
+        let ahead = start + Vec3::X * 6.0;
+        let sidewalk = map
+            .lanes()
+            .values()
+            .filter(|l| l.kind == LaneKind::Walking)
+            .map(|l| l.points.project(ahead))
+            .min_by(|x, y| x.distance(ahead).total_cmp(&y.distance(ahead)))
+            .unwrap();
+
+        let trans = Transform::new_dir(start, Vec3::X);
+        let self_obj = TransportState {
+            speed: 10.0,
+            radius: 1.0,
+            dir: Vec2::X,
+            ..Default::default()
+        };
+        let pedestrian = TransportState {
+            radius: 0.5,
+            dir: Vec2::X,
+            group: TransportationGroup::Pedestrians,
+            height: start.z,
+            ..Default::default()
+        };
+
+        let speed_with_pedestrian_at = |pos: Vec3| {
+            let mut vehicle = test_vehicle(4);
+            calc_decision(
+                VehicleID::default(),
+                &mut vehicle,
+                &map,
+                &GameTime::new(Tick(0)),
+                &trans,
+                &self_obj,
+                &it,
+                None,
+                std::iter::once((pos.xy(), &pedestrian)),
+            )
+            .0
+        };
+
+        assert!(speed_with_pedestrian_at(sidewalk) > 0.0);
+        // walking in the lane: emergency braking
+        assert_eq!(speed_with_pedestrian_at(ahead), 0.0);
+
+        // right next to a normal lane, but on a wider one
+        let beside = ahead + Vec3::Y * 3.0;
+        let free = speed_with_pedestrian_at(beside);
+        assert!(free > 0.0);
+        let lane_id = it.get_travers().map(|t| t.kind);
+        let Some(TraverseKind::Lane(lane_id)) = lane_id else {
+            panic!("not on a lane");
+        };
+        map.lanes[lane_id].width = 8.0;
+        let speed_with_pedestrian_at = |pos: Vec3| {
+            calc_decision(
+                VehicleID::default(),
+                &mut test_vehicle(4),
+                &map,
+                &GameTime::new(Tick(0)),
+                &trans,
+                &self_obj,
+                &it,
+                None,
+                std::iter::once((pos.xy(), &pedestrian)),
+            )
+            .0
+        };
+        assert!(speed_with_pedestrian_at(beside) < free);
+    }
+
+    #[test]
+    fn lateral_correction_is_bounded() {
+        let line = PolyLine3::new(vec![vec3(0.0, 0.0, 0.0), vec3(100.0, 0.0, 0.0)]);
+
+        // on the right of the center: steer left
+        let pos = vec3(50.0, -1.0, 0.0);
+        assert!((cross_track_error(&line, pos) - 1.0).abs() < 1e-4);
+        assert!(lateral_control(&line, pos, Vec3::X).y > 0.0);
+        assert!(lateral_control(&line, vec3(50.0, 1.0, 0.0), Vec3::X).y < 0.0);
+        assert_eq!(
+            lateral_control(&line, vec3(50.0, 0.0, 0.0), Vec3::X),
+            Vec3::X
+        );
+
+        let far = lateral_control(&line, vec3(50.0, -100.0, 0.0), Vec3::X);
+        let angle = far.y.atan2(far.x);
+        assert!((angle - MAX_LATERAL_CORRECTION).abs() < 1e-4);
+    }
+
+    #[test]
+    fn vehicles_track_curved_lanes() {
+        use crate::tests::TestCtx;
+        use crate::transportation::make_vehicle_entity;
+        use crate::utils::rand_provider::RandProvider;
+
+        let mut test = TestCtx::new();
+        {
+            let mut map = test.g.map_mut();
+            let a = map.project(vec3(0.0, 0.0, 0.0), 0.0, ProjectFilter::ALL);
+            let b = map.project(vec3(300.0, 0.0, 0.0), 0.0, ProjectFilter::ALL);
+            map.make_connection(
+                a,
+                b,
+                Some(vec2(150.0, 120.0)),
+                &LanePatternBuilder::new().build(),
+            )
+            .unwrap();
+        }
+
+        let map = test.g.map();
+        let lane = map
+            .lanes()
+            .values()
+            .find(|l| {
+                l.kind == LaneKind::Driving
+                    && l.points.first().x < 50.0
+                    && l.points.first_dir().unwrap().y > 0.0
+            })
+            .unwrap();
+        let lane_id = lane.id;
+        let (start, dir) = lane.points.point_dir_along(5.0);
+        let end = lane.points.point_along(lane.points.length() - 5.0);
+        let it = Itinerary::route(Tick(0), start, end, &map, PathKind::Vehicle).unwrap();
+        drop(map);
+
+        let vehicle =
+            Vehicle::new_driving(VehicleKind::Car, Color::WHITE, &mut RandProvider::new(1));
+        let v = make_vehicle_entity(
+            &mut test.g,
+            Transform::new_dir(start, dir),
+            vehicle,
+            it,
+            true,
+        );
+
+        let mut max_error: f32 = 0.0;
+        let mut ticks_on_lane = 0;
+        for _ in 0..3000 {
+            test.tick();
+            let ent = &test.g.world.vehicles[v];
+            if ent.it.has_ended(0.0) {
+                break;
+            }
+            if !matches!(ent.it.get_travers().map(|t| t.kind), Some(TraverseKind::Lane(l)) if l == lane_id)
+            {
+                continue;
+            }
+            ticks_on_lane += 1;
+            let map = test.g.map();
+            let err = cross_track_error(&map.lanes()[lane_id].points, ent.trans.pos);
+            max_error = max_error.max(err.abs());
+        }
+
+        assert!(ticks_on_lane > 100);
+        assert!(max_error < 0.3, "max cross-track error: {}", max_error);
+    }
+
+    #[test]
+    fn fast_vehicles_dont_tunnel_through_obstacles() {
+        use crate::tests::TestCtx;
+        use crate::transportation::make_vehicle_entity;
+        use crate::utils::rand_provider::RandProvider;
+
+        let run = |settings: PhysicsSettings| {
+            let mut test = TestCtx::new();
+            test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(1000.0, 0.0, 0.0)]);
+            *test.g.write::<PhysicsSettings>() = settings;
+
+            let mut spawn = |x: f32| {
+                let map = test.g.map();
+                let lane = map
+                    .lanes()
+                    .values()
+                    .find(|l| {
+                        l.kind == LaneKind::Driving
+                            && l.points.first_dir().map_or(false, |d| d.x > 0.9)
+                    })
+                    .unwrap();
+                let pos = lane.points.project(vec3(x, 0.0, 0.0));
+                let end = lane.points.project(vec3(990.0, 0.0, 0.0));
+                let it = Itinerary::route(Tick(0), pos, end, &map, PathKind::Vehicle).unwrap();
+                drop(map);
+
+                let vehicle =
+                    Vehicle::new_driving(VehicleKind::Car, Color::WHITE, &mut RandProvider::new(1));
+                make_vehicle_entity(
+                    &mut test.g,
+                    Transform::new_dir(pos, Vec3::X),
+                    vehicle,
+                    it,
+                    true,
+                )
+            };
+            let fast = spawn(100.0);
+            let obstacle = spawn(130.0);
+            test.tick();
+
+            let world = test.g.world_mut_unchecked();
+            let obs = &mut world.vehicles[obstacle];
+            obs.vehicle.wait_time = f32::MAX;
+            obs.vehicle.state = VehicleState::Yielding;
+            // 60 meters in a single tick
+            world.vehicles[fast].speed.0 = 60.0 / DELTA;
+            test.tick();
+
+            let obstacle_x = test.g.world.vehicles[obstacle].trans.pos.x;
+            test.g.world.vehicles[fast].trans.pos.x < obstacle_x
+        };
+
+        assert!(run(PhysicsSettings::default()));
+        // without sub-stepping, it jumps over the obstacle
+        assert!(!run(PhysicsSettings {
+            max_substep_dist: None,
+        }));
+    }
+}
@@ -0,0 +1,172 @@
+use crate::transportation::{TransportState, TransportationGroup};
+use geom::{Polygon, Shape, Transform, Vec2, AABB};
+use serde::{Deserialize, Serialize};
+
+/// Default speed cap for vehicles in a shared space, in m/s
+pub const SHARED_SPACE_MAX_SPEED: f32 = 3.0;
+/// Distance in front of a vehicle at which it yields to pedestrians in a shared space
+pub const SHARED_SPACE_YIELD_DIST: f32 = 6.0;
+
+/// An area (woonerf) where pedestrians and vehicles mix.
+/// Vehicles must drive slowly and yield to pedestrians, pedestrians walk straight across it
+/// instead of following the sidewalks and crossings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SharedSpace {
+    area: Polygon,
+    bbox: AABB,
+    pub max_speed: f32,
+}
+
+impl SharedSpace {
+    pub fn new(area: Polygon) -> Self {
+        Self {
+            bbox: area.bbox(),
+            area,
+            max_speed: SHARED_SPACE_MAX_SPEED,
+        }
+    }
+
+    pub fn area(&self) -> &Polygon {
+        &self.area
+    }
+
+    pub fn contains(&self, p: Vec2) -> bool {
+        self.bbox.contains(p) && self.area.contains(p)
+    }
+
+    /// Returns the speed the vehicle should aim for while inside the shared space.
+    /// The speed is capped and the vehicle stops if a pedestrian is right in front of it.
+    pub fn vehicle_speed<'a>(
+        &self,
+        trans: &Transform,
+        desired_speed: f32,
+        neighs: impl Iterator<Item = (Vec2, &'a TransportState)>,
+    ) -> f32 {
+        let pos = trans.pos.xy();
+        let dir = trans.dir.xy();
+        for (his_pos, obj) in neighs {
+            if !matches!(obj.group, TransportationGroup::Pedestrians) {
+                continue;
+            }
+            let (towards_dir, dist) = unwrap_or!((his_pos - pos).dir_dist(), continue);
+            if towards_dir.dot(dir) > 0.3 && dist - obj.radius < SHARED_SPACE_YIELD_DIST {
+                return 0.0;
+            }
+        }
+        desired_speed.min(self.max_speed)
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct SharedSpaces {
+    spaces: Vec<SharedSpace>,
+}
+
+impl SharedSpaces {
+    pub fn add(&mut self, space: SharedSpace) {
+        self.spaces.push(space);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &SharedSpace> {
+        self.spaces.iter()
+    }
+
+    /// Returns the shared space containing the given point, if any
+    pub fn containing(&self, p: Vec2) -> Option<&SharedSpace> {
+        self.spaces.iter().find(|s| s.contains(p))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::{LaneKind, LanePatternBuilder, Map, PathKind, ProjectFilter};
+    use crate::map_dynamic::Itinerary;
+    use geom::{vec2, vec3};
+    use prototypes::Tick;
+
+    fn pedestrian() -> TransportState {
+        TransportState {
+            radius: 0.3,
+            group: TransportationGroup::Pedestrians,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn vehicle_yields_to_crossing_pedestrian() {
+        let mut spaces = SharedSpaces::default();
+        spaces.add(SharedSpace::new(Polygon::centered_rect(
+            Vec2::ZERO,
+            100.0,
+            20.0,
+        )));
+
+        let trans = Transform::new(vec3(0.0, 0.0, 0.0));
+        let space = spaces.containing(trans.pos.xy()).unwrap();
+        assert!(spaces.containing(vec2(200.0, 0.0)).is_none());
+
+        let ped = pedestrian();
+
+        // nobody around: speed is only capped
+        assert_eq!(
+            space.vehicle_speed(&trans, 10.0, std::iter::empty()),
+            space.max_speed
+        );
+
+        // pedestrian crossing behind does not matter
+        let behind = [(vec2(-3.0, 0.0), &ped)];
+        assert_eq!(
+            space.vehicle_speed(&trans, 10.0, behind.into_iter()),
+            space.max_speed
+        );
+
+        // pedestrian crossing right in front: the vehicle yields
+        let front = [(vec2(4.0, 0.5), &ped)];
+        assert_eq!(space.vehicle_speed(&trans, 10.0, front.into_iter()), 0.0);
+
+        // pedestrian crossing far ahead: the vehicle can keep going slowly
+        let far = [(vec2(30.0, 0.0), &ped)];
+        assert_eq!(
+            space.vehicle_speed(&trans, 10.0, far.into_iter()),
+            space.max_speed
+        );
+    }
+
+    #[test]
+    fn pedestrians_walk_straight_across() {
+        let mut map = Map::empty();
+        let pat = LanePatternBuilder::new().build();
+        let a = map.project(vec3(0.0, 0.0, 0.0), 0.0, ProjectFilter::ALL);
+        let b = map.project(vec3(100.0, 0.0, 0.0), 0.0, ProjectFilter::ALL);
+        map.make_connection(a, b, None, &pat).unwrap();
+
+        // from one sidewalk to the other, through the crossing at the end of the road
+        let sidewalks: Vec<_> = map
+            .lanes()
+            .values()
+            .filter(|l| l.kind == LaneKind::Walking)
+            .map(|l| l.points.project(vec3(50.0, 0.0, 0.0)))
+            .collect();
+        let (start, end) = (sidewalks[0], sidewalks[1]);
+        let mut it = Itinerary::route(Tick(0), start, end, &map, PathKind::Pedestrian).unwrap();
+
+        let walked = |it: &Itinerary| {
+            let mut last = start;
+            it.upcoming_points(&map)
+                .map(|p| {
+                    let d = p.distance(last);
+                    last = p;
+                    d
+                })
+                .sum::<f32>()
+        };
+        assert!(walked(&it) > 50.0);
+
+        let space = SharedSpace::new(Polygon::centered_rect(vec2(50.0, 0.0), 200.0, 60.0));
+        it.cut_through(&map, start, |p| space.contains(p.xy()));
+
+        assert!(it.get_point().unwrap().distance(end) < 0.1);
+        assert!(walked(&it) < 30.0, "{}", walked(&it));
+    }
+}
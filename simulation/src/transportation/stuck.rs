@@ -0,0 +1,366 @@
+use crate::map::{Map, PathKind, TrafficBehavior, TraverseKind};
+use crate::map_dynamic::Itinerary;
+use crate::transportation::{find_spawn_spot, is_clear_except, TransportGrid, VehicleState};
+use crate::world::{VehicleEnt, VehicleID};
+use crate::Simulation;
+use geom::{Transform, Vec3};
+use prototypes::{GameTime, DELTA};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A vehicle that didn't make progress for this long, in seconds, is considered stuck
+pub const STUCK_TIMEOUT: f32 = 60.0;
+/// Moving less than this, in meters, is not progress
+const STUCK_PROGRESS_DIST: f32 = 1.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StuckState {
+    /// Where the vehicle was when it last made progress
+    anchor: Vec3,
+    /// Seconds since the last progress, not counting the time spent waiting for a light
+    time: f32,
+    /// The nudge was already tried, teleport next time
+    nudged: bool,
+}
+
+/// How recovering a stuck vehicle went
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StuckRecovery {
+    /// Moved forward to the next point of its itinerary
+    Nudged,
+    /// Moved to the nearest clear point of its lane and given a new route
+    Teleported,
+    /// Nowhere to go, tried again after another timeout
+    Failed,
+}
+
+/// Detects vehicles that stopped making progress on a valid route and gets them moving again
+#[derive(Default, Serialize, Deserialize)]
+pub struct StuckVehicles {
+    tracked: BTreeMap<VehicleID, StuckState>,
+    pub n_nudged: u32,
+    pub n_teleported: u32,
+}
+
+impl StuckVehicles {
+    /// Seconds since the vehicle last made progress
+    pub fn stuck_time(&self, v: VehicleID) -> f32 {
+        self.tracked.get(&v).map_or(0.0, |s| s.time)
+    }
+}
+
+/// Stopped at a red light or queuing behind one, however long it is.
+/// This pauses the stuck timer without resetting it, so a vehicle that doesn't move
+/// when the light is green is still found stuck after a few light phases
+fn waits_for_light(v: &VehicleEnt, map: &Map, time: &GameTime) -> bool {
+    if matches!(v.vehicle.state, VehicleState::WaitingAtLight) {
+        return true;
+    }
+    let Some(TraverseKind::Lane(lane)) = v.it.get_travers().map(|t| t.kind) else {
+        return false;
+    };
+    map.lanes().get(lane).map_or(false, |l| {
+        matches!(
+            l.control.get_behavior(time.seconds),
+            TrafficBehavior::RED | TrafficBehavior::ORANGE
+        )
+    })
+}
+
+pub fn stuck_vehicle_system(sim: &mut Simulation) {
+    profiling::scope!("transportation::stuck_vehicle_system");
+    let time = *sim.read::<GameTime>();
+    let mut to_recover = vec![];
+    {
+        let map = sim.map();
+        let mut stuck = sim.write::<StuckVehicles>();
+        let vehicles = &sim.world.vehicles;
+        stuck.tracked.retain(|id, _| vehicles.contains_key(*id));
+
+        for (id, v) in vehicles.iter() {
+            let on_route =
+                v.collider.is_some() && v.vehicle.state.is_on_road() && v.it.get_point().is_some();
+            if !on_route {
+                stuck.tracked.remove(&id);
+                continue;
+            }
+
+            let s = stuck.tracked.entry(id).or_insert(StuckState {
+                anchor: v.trans.pos,
+                time: 0.0,
+                nudged: false,
+            });
+            if v.trans.pos.distance(s.anchor) > STUCK_PROGRESS_DIST {
+                *s = StuckState {
+                    anchor: v.trans.pos,
+                    time: 0.0,
+                    nudged: false,
+                };
+                continue;
+            }
+            if waits_for_light(v, &map, &time) {
+                continue;
+            }
+            s.time += DELTA;
+            if s.time >= STUCK_TIMEOUT {
+                to_recover.push((id, s.nudged));
+            }
+        }
+    }
+
+    for (id, nudged) in to_recover {
+        let mut recovery = StuckRecovery::Failed;
+        if !nudged {
+            if nudge(sim, id) {
+                recovery = StuckRecovery::Nudged;
+            } else if teleport(sim, id) {
+                recovery = StuckRecovery::Teleported;
+            }
+        } else if teleport(sim, id) {
+            recovery = StuckRecovery::Teleported;
+        }
+        log::info!("vehicle {:?} was stuck: {:?}", id, recovery);
+
+        let pos = sim.world.vehicles[id].trans.pos;
+        let mut stuck = sim.write::<StuckVehicles>();
+        match recovery {
+            StuckRecovery::Nudged => stuck.n_nudged += 1,
+            StuckRecovery::Teleported => stuck.n_teleported += 1,
+            StuckRecovery::Failed => {}
+        }
+        stuck.tracked.insert(
+            id,
+            StuckState {
+                anchor: pos,
+                time: 0.0,
+                nudged: recovery == StuckRecovery::Nudged,
+            },
+        );
+    }
+}
+
+/// Forgets whatever the driver was waiting for
+fn reset_driver(v: &mut VehicleEnt) {
+    v.speed.0 = 0.0;
+    v.vehicle.wait_time = 0.0;
+    v.vehicle.flag = 0;
+    v.vehicle.perceived.clear();
+    if v.vehicle.state.is_on_road() && !matches!(v.vehicle.state, VehicleState::Driving) {
+        v.vehicle.set_state(VehicleState::Driving);
+    }
+}
+
+/// Moves the vehicle to the next point of its itinerary, if there is room there
+fn nudge(sim: &mut Simulation, id: VehicleID) -> bool {
+    let map = sim.map();
+    let grid = sim.read::<TransportGrid>();
+    let v = &sim.world.vehicles[id];
+    let mut points = v.it.upcoming_points(&map);
+    let Some(next) = points.next() else {
+        return false;
+    };
+    let dir = points
+        .next()
+        .and_then(|after| (after - next).try_normalize())
+        .unwrap_or(v.trans.dir);
+    if !is_clear_except(
+        &grid,
+        next.xy(),
+        v.vehicle.kind.collider_radius(),
+        v.collider,
+    ) {
+        return false;
+    }
+    drop((map, grid));
+
+    let v = &mut sim.world.vehicles[id];
+    v.trans = Transform::new_dir(next, dir);
+    reset_driver(v);
+    true
+}
+
+/// Last resort: moves the vehicle to the nearest clear point of its lane and computes a new route
+fn teleport(sim: &mut Simulation, id: VehicleID) -> bool {
+    let map = sim.map();
+    let v = &sim.world.vehicles[id];
+    let Some(spot) = find_spawn_spot(
+        &map,
+        &sim.read::<TransportGrid>(),
+        v.trans,
+        &v.it,
+        v.vehicle.kind.collider_radius(),
+        v.collider,
+    ) else {
+        return false;
+    };
+    let kind = v.it.path_kind().unwrap_or(PathKind::Vehicle);
+    let it = match v.it.end_pos() {
+        Some(end) => Itinerary::route(sim.read::<GameTime>().tick, spot.pos, end, &map, kind)
+            .unwrap_or_else(|| Itinerary::wait_for_reroute(kind, end)),
+        None => Itinerary::NONE,
+    };
+    drop(map);
+
+    let v = &mut sim.world.vehicles[id];
+    v.trans = spot;
+    v.it = it;
+    reset_driver(v);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{StuckVehicles, STUCK_TIMEOUT};
+    use crate::map::{LaneKind, PathKind};
+    use crate::map_dynamic::Itinerary;
+    use crate::tests::TestCtx;
+    use crate::transportation::{make_vehicle_entity, Vehicle, VehicleKind, VehicleState};
+    use crate::world::VehicleID;
+    use geom::{vec3, Color, Transform, Vec3};
+    use prototypes::{Tick, DELTA};
+
+    fn spawn(test: &mut TestCtx, x: f32) -> VehicleID {
+        let map = test.g.map();
+        let lane = map
+            .lanes()
+            .values()
+            .find(|l| {
+                l.kind == LaneKind::Driving && l.points.first_dir().map_or(false, |d| d.x > 0.9)
+            })
+            .unwrap();
+        let pos = lane.points.project(vec3(x, 0.0, 0.0));
+        let end = lane.points.project(vec3(990.0, 0.0, 0.0));
+        let it = Itinerary::route(Tick(0), pos, end, &map, PathKind::Vehicle).unwrap();
+        drop(map);
+
+        let vehicle = Vehicle::new_driving(
+            VehicleKind::Car,
+            Color::WHITE,
+            &mut test.g.write::<crate::RandProvider>(),
+        );
+        make_vehicle_entity(
+            &mut test.g,
+            Transform::new_dir(pos, Vec3::X),
+            vehicle,
+            it,
+            true,
+        )
+    }
+
+    /// The driver waits forever without anything in front
+    fn wedge(test: &mut TestCtx, v: VehicleID, state: VehicleState) {
+        let v = &mut test.g.world_mut_unchecked().vehicles[v];
+        v.vehicle.wait_time = f32::MAX;
+        v.vehicle.state = state;
+    }
+
+    #[test]
+    fn wedged_vehicle_recovers_after_timeout() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(1000.0, 0.0, 0.0)]);
+
+        let wedged = spawn(&mut test, 100.0);
+        let at_light = spawn(&mut test, 500.0);
+        test.tick();
+        wedge(&mut test, wedged, VehicleState::Yielding);
+        wedge(&mut test, at_light, VehicleState::WaitingAtLight);
+        let start = test.g.world.vehicles[wedged].trans.pos;
+        let light_start = test.g.world.vehicles[at_light].trans.pos;
+
+        let timeout_ticks = (STUCK_TIMEOUT / DELTA) as usize;
+        for _ in 0..timeout_ticks - 10 {
+            test.tick();
+        }
+        assert!(test.g.world.vehicles[wedged].trans.pos.distance(start) < 1.0);
+        assert!(test.g.read::<StuckVehicles>().stuck_time(wedged) > STUCK_TIMEOUT * 0.9);
+
+        for _ in 0..20 {
+            test.tick();
+        }
+        assert_eq!(test.g.read::<StuckVehicles>().n_nudged, 1);
+
+        // it drives again on its own
+        for _ in 0..200 {
+            test.tick();
+        }
+        let v = &test.g.world.vehicles[wedged];
+        assert!(
+            v.trans.pos.x > start.x + 10.0,
+            "{:?} {:?}",
+            v.trans.pos,
+            start
+        );
+        assert!(v.speed.0 > 1.0);
+
+        // a long red light is not being stuck
+        assert!(
+            test.g.world.vehicles[at_light]
+                .trans
+                .pos
+                .distance(light_start)
+                < 1.0
+        );
+        assert_eq!(test.g.read::<StuckVehicles>().stuck_time(at_light), 0.0);
+        assert_eq!(test.g.read::<StuckVehicles>().n_teleported, 0);
+    }
+
+    #[test]
+    fn stuck_time_adds_up_across_light_phases() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(1000.0, 0.0, 0.0)]);
+
+        let v = spawn(&mut test, 100.0);
+        test.tick();
+
+        // red for 10 seconds, then green for 10 seconds, and it never moves
+        let phase_ticks = (10.0 / DELTA) as usize;
+        let timeout_ticks = (STUCK_TIMEOUT / DELTA) as usize;
+        let recovered = |test: &TestCtx| {
+            let stuck = test.g.read::<StuckVehicles>();
+            stuck.n_nudged + stuck.n_teleported
+        };
+        for t in 0..timeout_ticks * 3 {
+            let state = if (t / phase_ticks) % 2 == 0 {
+                VehicleState::WaitingAtLight
+            } else {
+                VehicleState::Yielding
+            };
+            wedge(&mut test, v, state);
+            test.tick();
+            if t == timeout_ticks * 3 / 2 {
+                // only the green phases count
+                assert_eq!(recovered(&test), 0);
+                let time = test.g.read::<StuckVehicles>().stuck_time(v);
+                assert!(time > STUCK_TIMEOUT * 0.6, "{}", time);
+            }
+        }
+        assert!(recovered(&test) > 0);
+    }
+
+    #[test]
+    fn teleports_when_nudging_didnt_help() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(1000.0, 0.0, 0.0)]);
+
+        let v = spawn(&mut test, 100.0);
+        test.tick();
+        let end = test.g.world.vehicles[v].it.end_pos().unwrap();
+
+        // still wedged after being nudged
+        let timeout_ticks = (STUCK_TIMEOUT / DELTA) as usize;
+        for _ in 0..timeout_ticks * 2 + 10 {
+            wedge(&mut test, v, VehicleState::Yielding);
+            test.tick();
+        }
+
+        let stuck = test.g.read::<StuckVehicles>();
+        assert_eq!(stuck.n_nudged, 1);
+        assert_eq!(stuck.n_teleported, 1);
+        drop(stuck);
+
+        // rerouted to the same destination
+        let ent = &test.g.world.vehicles[v];
+        assert!(ent.it.end_pos().unwrap().distance(end) < 1.0);
+        assert!(ent.it.get_point().is_some());
+    }
+}
@@ -1,33 +1,114 @@
+use crate::map::{BuildingID, IntersectionID, LaneID, LaneKind, Map, TraverseKind};
 use crate::map_dynamic::{Itinerary, ParkingManagement, SpotReservation};
-use crate::transportation::{TransportGrid, TransportState, TransportationGroup, Transporter};
+use crate::transportation::{
+    EdgePortals, TransportGrid, TransportState, TransportationGroup, Transporter,
+};
 use crate::utils::rand_provider::RandProvider;
 use crate::world::{VehicleEnt, VehicleID};
-use crate::Simulation;
+use crate::{Simulation, SoulID};
 use egui_inspect::Inspect;
 use geom::Transform;
-use geom::{Color, Spline3, Vec3};
-use prototypes::GameInstant;
+use geom::{abs_lerp, Color, Spline3, Vec2, Vec3};
+use ordered_float::OrderedFloat;
+use prototypes::{GameDuration, GameInstant, GameTime, DELTA};
 use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::f32::consts::TAU;
 
 /// The duration for the parking animation.
 pub const TIME_TO_PARK: f32 = 4.0;
 
+/// How far along its lane a spawning vehicle can be moved to find room
+pub const SPAWN_SEARCH_DIST: f32 = 30.0;
+/// Distance between two candidate spawn positions
+const SPAWN_SEARCH_STEP: f32 = 1.0;
+/// Maximum number of vehicles waiting for room to spawn, further spawns are dropped
+pub const MAX_QUEUED_SPAWNS: usize = 100;
+/// How long a vehicle waits for room to spawn before its spawn is dropped
+pub const MAX_SPAWN_WAIT: GameDuration = GameDuration::from_minutes(5);
+/// Larger than the collider radius of any vehicle
+pub(crate) const MAX_COLLIDER_RADIUS: f32 = 10.0;
+
+/// Time between two points of a predicted trajectory, in seconds
+pub const PREDICTION_STEP: f32 = 0.25;
+
+/// Maximum angle of the front wheels, in radians
+pub const MAX_STEER_ANGLE: f32 = 0.6;
+/// How fast the front wheels turn, in radians per second
+pub const STEER_SPEED: f32 = 1.5;
+/// Used to turn the traveled distance into wheel rotation
+pub const WHEEL_RADIUS: f32 = 0.35;
+
+/// Reaction time of the quickest drivers, in seconds
+pub const MIN_REACTION_TIME: f32 = 0.2;
+/// Reaction time of the slowest drivers, in seconds
+pub const MAX_REACTION_TIME: f32 = 0.6;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum VehicleState {
     Parked(SpotReservation),
     Driving,
-    /// Panicked when it notices it's in a gridlock
+    /// Stopped in front of a red or orange light
+    WaitingAtLight,
+    /// Stopped to let something go first: a vehicle or pedestrian in front, or a stop sign
+    Yielding,
+    /// Stranded: panicked when it notices it's in a gridlock
     Panicking(GameInstant),
+    /// Parking maneuver, on rails along the spline
     RoadToPark(Spline3, f32, SpotReservation),
+    /// Passing a slower vehicle using the oncoming lane
+    Overtaking,
+    /// Backing out of an angled slot, on rails along the spline
+    ParkToRoad(Spline3, f32),
 }
 
 debug_inspect_impl!(VehicleState);
 
+impl VehicleState {
+    /// Whether the vehicle is on the road network, driving or stopped in traffic
+    pub fn is_on_road(&self) -> bool {
+        matches!(
+            self,
+            VehicleState::Driving
+                | VehicleState::WaitingAtLight
+                | VehicleState::Yielding
+                | VehicleState::Panicking(_)
+                | VehicleState::Overtaking
+        )
+    }
+
+    pub fn can_transition_to(&self, next: &VehicleState) -> bool {
+        use VehicleState::*;
+        match (self, next) {
+            (Parked(_), Driving) => true,
+            (Parked(_), ParkToRoad(..)) => true,
+            (ParkToRoad(..), Driving) => true,
+            (RoadToPark(..), Parked(_)) => true,
+            (a, RoadToPark(..)) => a.is_on_road(),
+            (a, b) => a.is_on_road() && b.is_on_road(),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            VehicleState::Parked(_) => "Parked",
+            VehicleState::Driving => "Driving",
+            VehicleState::WaitingAtLight => "WaitingAtLight",
+            VehicleState::Yielding => "Yielding",
+            VehicleState::Panicking(_) => "Panicking",
+            VehicleState::RoadToPark(..) => "RoadToPark",
+            VehicleState::Overtaking => "Overtaking",
+            VehicleState::ParkToRoad(..) => "ParkToRoad",
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, Serialize, Deserialize, Inspect)]
 pub enum VehicleKind {
     Car,
     Truck,
     Bus,
+    Emergency,
 }
 
 #[derive(Debug, Serialize, Deserialize, Inspect)]
@@ -42,6 +123,29 @@ pub struct Vehicle {
 
     /// Used to detect gridlock
     pub flag: u64,
+
+    /// Angle of the front wheels in radians, positive when turning left
+    pub steer_angle: f32,
+    /// Rotation of the wheels in radians, in [0; TAU)
+    pub wheel_phase: f32,
+
+    /// Maximum number of souls inside the vehicle, driver included
+    pub capacity: u32,
+    pub passengers: Vec<SoulID>,
+
+    /// Time it takes the driver to react to what happens in front, in seconds.
+    /// Sampled per driver between [`MIN_REACTION_TIME`] and [`MAX_REACTION_TIME`]
+    #[serde(default)]
+    pub reaction_time: f32,
+    /// What the driver saw in front during the last `reaction_time`, oldest first
+    #[serde(default)]
+    #[inspect(skip)]
+    pub perceived: VecDeque<(f32, u64)>,
+    /// Stop sign lane the vehicle already came to a full stop at, it can then go.
+    /// Not saved, a vehicle loaded at a stop sign stops again
+    #[serde(skip)]
+    #[inspect(skip)]
+    pub stopped_at: Option<LaneID>,
 }
 
 #[must_use]
@@ -58,31 +162,54 @@ pub fn put_vehicle_in_transport_grid(sim: &Simulation, w: f32, trans: Transform)
 }
 
 impl VehicleKind {
+    /// Length of the vehicle, it should match the scaled mesh
     pub fn width(self) -> f32 {
         match self {
             VehicleKind::Car => 4.5,
             VehicleKind::Truck => 6.0,
             VehicleKind::Bus => 9.0,
+            VehicleKind::Emergency => 5.4,
+        }
+    }
+
+    /// Scale applied to the vehicle's mesh and its default tint.
+    /// Buses reuse the truck mesh and emergency vehicles the car mesh.
+    /// The instanced renderer only supports uniform scaling.
+    pub fn appearance(self) -> (Vec3, Color) {
+        match self {
+            VehicleKind::Car => (Vec3::splat(1.0), Color::WHITE),
+            VehicleKind::Truck => (Vec3::splat(1.0), Color::WHITE),
+            VehicleKind::Bus => (Vec3::splat(1.5), Color::from_hex(0xf2_b7_05)),
+            VehicleKind::Emergency => (Vec3::splat(1.2), Color::RED),
         }
     }
 
+    /// Radius of the vehicle in the transport grid, bigger vehicles take more space
+    /// Must match the radius given by `put_vehicle_in_transport_grid`
+    pub fn collider_radius(self) -> f32 {
+        self.width() * 0.5
+    }
+
     pub fn acceleration(self) -> f32 {
         match self {
             VehicleKind::Car => 3.0,
             VehicleKind::Truck => 2.5,
             VehicleKind::Bus => 2.0,
+            VehicleKind::Emergency => 3.5,
         }
     }
 
     pub fn deceleration(self) -> f32 {
         match self {
-            VehicleKind::Car | VehicleKind::Bus | VehicleKind::Truck => 6.0,
+            VehicleKind::Car | VehicleKind::Bus | VehicleKind::Truck | VehicleKind::Emergency => {
+                6.0
+            }
         }
     }
 
     pub fn min_turning_radius(self) -> f32 {
         match self {
-            VehicleKind::Car => 0.5,
+            VehicleKind::Car | VehicleKind::Emergency => 0.5,
             VehicleKind::Truck => 3.0,
             VehicleKind::Bus => 4.0,
         }
@@ -92,12 +219,21 @@ impl VehicleKind {
         match self {
             VehicleKind::Car => 1.0,
             VehicleKind::Truck | VehicleKind::Bus => 0.8,
+            VehicleKind::Emergency => 1.2,
+        }
+    }
+
+    pub fn capacity(self) -> u32 {
+        match self {
+            VehicleKind::Car | VehicleKind::Emergency => 4,
+            VehicleKind::Truck => 2,
+            VehicleKind::Bus => 40,
         }
     }
 
     pub fn ang_acc(self) -> f32 {
         match self {
-            VehicleKind::Car => 1.0,
+            VehicleKind::Car | VehicleKind::Emergency => 1.0,
             VehicleKind::Truck => 0.9,
             VehicleKind::Bus => 0.8,
         }
@@ -112,12 +248,18 @@ pub fn unpark(sim: &mut Simulation, vehicle: VehicleID) {
     if let VehicleState::Parked(spot) =
         std::mem::replace(&mut v.vehicle.state, VehicleState::Driving)
     {
+        let exit = spot.exit_path(&sim.map());
         sim.write::<ParkingManagement>().free(spot);
+        if let Some(exit) = exit {
+            let v = unwrap_ret!(sim.world.vehicles.get_mut(vehicle));
+            v.vehicle.state = VehicleState::ParkToRoad(exit, 0.0);
+        }
     } else {
         log::warn!("Trying to unpark {:?} that wasn't parked", vehicle);
     }
 
     let coll = put_vehicle_in_transport_grid(sim, w, trans);
+    coll.set_owner(&mut sim.write::<TransportGrid>(), vehicle.into());
 
     let v = unwrap_ret!(sim.world.vehicles.get_mut(vehicle));
     v.collider = Some(coll);
@@ -146,16 +288,42 @@ pub fn spawn_parked_vehicle_with_spot(
     let pos = spot_id.get(&map.parking).unwrap().trans; // Unwrap ok: Gotten using reserve_near
     drop(map);
 
-    let tint = match kind {
-        VehicleKind::Car => get_random_car_color(&mut sim.write::<RandProvider>()),
-        _ => Color::WHITE,
-    };
-
+    let tint = random_tint(sim, kind);
     let vehicle = Vehicle::new(kind, spot_id, tint, &mut sim.write::<RandProvider>());
 
     Some(make_vehicle_entity(sim, pos, vehicle, it, false))
 }
 
+fn random_tint(sim: &Simulation, kind: VehicleKind) -> Color {
+    match kind {
+        VehicleKind::Car => get_random_car_color(&mut sim.write::<RandProvider>()),
+        _ => kind.appearance().1,
+    }
+}
+
+/// Spawns a vehicle parked on the road the building is connected to, as close as possible to its entrance.
+/// It waits there for someone to drive it. Returns None if the building has no road access
+/// or if there is no free parking spot nearby.
+pub fn spawn_vehicle_at_building(
+    sim: &mut Simulation,
+    kind: VehicleKind,
+    building: BuildingID,
+) -> Option<VehicleID> {
+    let map = sim.map();
+    let b = map.buildings().get(building)?;
+    let road = map.roads().get(b.connected_road?)?;
+    let access = map.building_access_point(building).unwrap_or(b.door_pos);
+    let lane = road
+        .lanes_iter()
+        .filter(|&(_, kind)| kind == LaneKind::Driving)
+        .filter_map(|(id, _)| map.lanes().get(id))
+        .min_by_key(|l| OrderedFloat(l.points.project_dist2(access)))?;
+    let pos = lane.points.project(access);
+    drop(map);
+
+    spawn_parked_vehicle(sim, kind, pos)
+}
+
 pub fn make_vehicle_entity(
     sim: &mut Simulation,
     trans: Transform,
@@ -169,13 +337,258 @@ pub fn make_vehicle_entity(
     if mk_collider {
         collider = Some(put_vehicle_in_transport_grid(sim, w, trans));
     }
-    sim.world.insert(VehicleEnt {
+    let id = sim.world.insert(VehicleEnt {
         trans,
         speed: Default::default(),
         vehicle,
         it,
         collider,
-    })
+    });
+    if let Some(collider) = collider {
+        collider.set_owner(&mut sim.write::<TransportGrid>(), id.into());
+    }
+    id
+}
+
+/// Vehicles waiting for room to spawn, retried every tick in order.
+/// Holds at most [`MAX_QUEUED_SPAWNS`] vehicles, each waiting at most [`MAX_SPAWN_WAIT`]
+#[derive(Default, Serialize, Deserialize)]
+pub struct SpawnQueue {
+    pending: VecDeque<QueuedSpawn>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct QueuedSpawn {
+    trans: Transform,
+    vehicle: Vehicle,
+    it: Itinerary,
+    /// Portal the vehicle enters the map at, it is tracked by [`EdgePortals`] once spawned
+    portal: Option<IntersectionID>,
+    queued_at: GameInstant,
+}
+
+impl SpawnQueue {
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Whether a vehicle entering at this portal is still waiting for room
+    pub fn has_pending_at(&self, portal: IntersectionID) -> bool {
+        self.pending.iter().any(|s| s.portal == Some(portal))
+    }
+}
+
+/// Whether a vehicle of this radius can be put at pos without overlapping anything in the grid
+pub fn is_spawn_clear(grid: &TransportGrid, pos: Vec2, radius: f32) -> bool {
+    is_clear_except(grid, pos, radius, None)
+}
+
+/// Same as [`is_spawn_clear`], ignoring the collider of a vehicle that is being moved
+pub(crate) fn is_clear_except(
+    grid: &TransportGrid,
+    pos: Vec2,
+    radius: f32,
+    except: Option<Transporter>,
+) -> bool {
+    !grid
+        .query_around(pos, radius + MAX_COLLIDER_RADIUS)
+        .any(|(id, his_pos)| {
+            if except.map_or(false, |e| e.0 == id) {
+                return false;
+            }
+            grid.get(id)
+                .map_or(false, |(_, s)| pos.distance(his_pos) < radius + s.radius)
+        })
+}
+
+/// Closest position to trans where the vehicle fits, moving along its current lane
+/// (or straight ahead/behind if it isn't on a lane) by at most SPAWN_SEARCH_DIST
+pub(crate) fn find_spawn_spot(
+    map: &Map,
+    grid: &TransportGrid,
+    trans: Transform,
+    it: &Itinerary,
+    radius: f32,
+    except: Option<Transporter>,
+) -> Option<Transform> {
+    let lane = match it.get_travers().map(|t| t.kind) {
+        Some(TraverseKind::Lane(id)) => map.lanes().get(id),
+        _ => None,
+    };
+    let start = lane.map_or(0.0, |l| l.points.length_at_proj(trans.pos));
+
+    let n_steps = (SPAWN_SEARCH_DIST / SPAWN_SEARCH_STEP) as i32;
+    (0..=n_steps)
+        .flat_map(|i| [i, -i])
+        .map(|i| i as f32 * SPAWN_SEARCH_STEP)
+        .filter_map(|off| match lane {
+            Some(l) => {
+                let d = start + off;
+                if d < 0.0 || d > l.points.length() {
+                    return None;
+                }
+                let (pos, dir) = l.points.point_dir_along(d);
+                Some(Transform::new_dir(pos, dir))
+            }
+            None => Some(Transform::new_dir(trans.pos + trans.dir * off, trans.dir)),
+        })
+        .find(|t| is_clear_except(grid, t.pos.xy(), radius, except))
+}
+
+/// Spawns a driving vehicle as close as possible to trans without overlapping another one.
+/// If there is no room nearby, the spawn is deferred to the [`SpawnQueue`] and None is returned.
+pub fn spawn_driving_vehicle(
+    sim: &mut Simulation,
+    trans: Transform,
+    vehicle: Vehicle,
+    it: Itinerary,
+) -> Option<VehicleID> {
+    spawn_or_queue(sim, trans, vehicle, it, None)
+}
+
+/// Same as [`spawn_driving_vehicle`], for through-traffic entering the map at a portal
+pub(crate) fn spawn_or_queue(
+    sim: &mut Simulation,
+    trans: Transform,
+    vehicle: Vehicle,
+    it: Itinerary,
+    portal: Option<IntersectionID>,
+) -> Option<VehicleID> {
+    let queued_at = sim.read::<GameTime>().instant();
+    let spawn = QueuedSpawn {
+        trans,
+        vehicle,
+        it,
+        portal,
+        queued_at,
+    };
+    match try_spawn(sim, spawn) {
+        Ok(id) => Some(id),
+        Err(spawn) => {
+            let mut queue = sim.write::<SpawnQueue>();
+            if queue.len() >= MAX_QUEUED_SPAWNS {
+                log::warn!("spawn queue is full, dropping a vehicle spawn");
+                return None;
+            }
+            queue.pending.push_back(spawn);
+            None
+        }
+    }
+}
+
+fn try_spawn(sim: &mut Simulation, spawn: QueuedSpawn) -> Result<VehicleID, QueuedSpawn> {
+    let spot = find_spawn_spot(
+        &sim.map(),
+        &sim.read::<TransportGrid>(),
+        spawn.trans,
+        &spawn.it,
+        spawn.vehicle.kind.collider_radius(),
+        None,
+    );
+    let Some(spot) = spot else {
+        return Err(spawn);
+    };
+    let id = make_vehicle_entity(sim, spot, spawn.vehicle, spawn.it, true);
+    if spawn.portal.is_some() {
+        sim.write::<EdgePortals>().track(id);
+    }
+    Ok(id)
+}
+
+/// Spawns the deferred vehicles that now have room, and drops the ones that waited too long
+pub fn spawn_queue_system(sim: &mut Simulation) {
+    profiling::scope!("transportation::spawn_queue_system");
+    if sim.read::<SpawnQueue>().is_empty() {
+        return;
+    }
+    let time = *sim.read::<GameTime>();
+    let pending = std::mem::take(&mut sim.write::<SpawnQueue>().pending);
+    for spawn in pending {
+        if spawn.queued_at.elapsed(&time) > MAX_SPAWN_WAIT {
+            continue;
+        }
+        // goes back in the queue if still no room
+        if let Err(spawn) = try_spawn(sim, spawn) {
+            sim.write::<SpawnQueue>().pending.push_back(spawn);
+        }
+    }
+}
+
+impl VehicleEnt {
+    /// Where the vehicle will be in the next `horizon` seconds, see [`predict_trajectory`]
+    pub fn predict_trajectory(&self, map: &Map, horizon: f32) -> Vec<(f32, Vec2)> {
+        predict_trajectory(
+            self.trans.pos.xy(),
+            self.trans.dir.xy(),
+            self.speed.0,
+            self.it.upcoming_points(map).map(|p| p.xy()),
+            horizon,
+        )
+    }
+}
+
+/// Where something at `pos` will be in the next `horizon` seconds, as (time, position) pairs every
+/// [`PREDICTION_STEP`]. It is assumed to keep its speed along `path` and to stop at its end.
+/// Without path, it keeps going straight ahead along `dir`.
+pub fn predict_trajectory(
+    pos: Vec2,
+    dir: Vec2,
+    speed: f32,
+    path: impl Iterator<Item = Vec2>,
+    horizon: f32,
+) -> Vec<(f32, Vec2)> {
+    let speed = speed.max(0.0);
+    let n_steps = (horizon / PREDICTION_STEP).floor().max(0.0) as usize;
+
+    let mut path = path.peekable();
+    let straight = path.peek().is_none();
+    let mut next = path.next();
+
+    let mut pos = pos;
+    let dir = dir.try_normalize().unwrap_or(Vec2::X);
+    let mut travelled = 0.0;
+
+    let mut trajectory = Vec::with_capacity(n_steps + 1);
+    for i in 0..=n_steps {
+        let t = i as f32 * PREDICTION_STEP;
+        let mut remaining = speed * t - travelled;
+        while remaining > 0.0 {
+            let Some(p) = next else {
+                if straight {
+                    pos += dir * remaining;
+                }
+                travelled += remaining;
+                break;
+            };
+            let Some((towards, dist)) = (p - pos).dir_dist() else {
+                next = path.next();
+                continue;
+            };
+            if dist > remaining {
+                pos += towards * remaining;
+                travelled += remaining;
+                break;
+            }
+            pos = p;
+            travelled += dist;
+            remaining -= dist;
+            next = path.next();
+        }
+        trajectory.push((t, pos));
+    }
+    trajectory
+}
+
+/// First time at which two trajectories sampled at the same times come closer than `dist`
+pub fn first_conflict(a: &[(f32, Vec2)], b: &[(f32, Vec2)], dist: f32) -> Option<f32> {
+    a.iter()
+        .zip(b)
+        .find(|((_, pa), (_, pb))| pa.distance(*pb) < dist)
+        .map(|((t, _), _)| *t)
 }
 
 pub fn get_random_car_color(r: &mut RandProvider) -> Color {
@@ -205,20 +618,539 @@ pub fn get_random_car_color(r: &mut RandProvider) -> Color {
 }
 
 impl Vehicle {
+    /// Changes the state of the vehicle if the transition is valid.
+    /// Invalid transitions are logged and ignored.
+    /// Returns whether the state was changed.
+    pub fn set_state(&mut self, next: VehicleState) -> bool {
+        if !self.state.can_transition_to(&next) {
+            log::warn!(
+                "invalid vehicle state transition {} -> {}",
+                self.state.name(),
+                next.name()
+            );
+            return false;
+        }
+        self.state = next;
+        true
+    }
+
+    /// Sets the traffic state of a vehicle on the road, unless it is panicking or overtaking.
+    pub(crate) fn set_traffic_state(&mut self, next: VehicleState) {
+        if matches!(
+            self.state,
+            VehicleState::Panicking(_) | VehicleState::Overtaking
+        ) {
+            return;
+        }
+        self.set_state(next);
+    }
+
     pub fn new(
         kind: VehicleKind,
         spot: SpotReservation,
         tint: Color,
         rng: &mut RandProvider,
+    ) -> Vehicle {
+        Self::with_state(kind, VehicleState::Parked(spot), tint, rng)
+    }
+
+    /// A vehicle already on the road, for traffic coming from outside the map
+    pub fn new_driving(kind: VehicleKind, tint: Color, rng: &mut RandProvider) -> Vehicle {
+        Self::with_state(kind, VehicleState::Driving, tint, rng)
+    }
+
+    fn with_state(
+        kind: VehicleKind,
+        state: VehicleState,
+        tint: Color,
+        rng: &mut RandProvider,
     ) -> Vehicle {
         Self {
             ang_velocity: 0.0,
             wait_time: 0.0,
             max_speed_multiplier: 0.95 + 0.1 * rng.next_f32(),
-            state: VehicleState::Parked(spot),
+            state,
             kind,
             tint,
             flag: 0,
+            steer_angle: 0.0,
+            wheel_phase: 0.0,
+            capacity: kind.capacity(),
+            passengers: Vec::new(),
+            reaction_time: MIN_REACTION_TIME
+                + (MAX_REACTION_TIME - MIN_REACTION_TIME) * rng.next_f32(),
+            perceived: VecDeque::new(),
+            stopped_at: None,
+        }
+    }
+
+    /// Takes what the driver sees in front right now (distance and gridlock flag)
+    /// and returns what they react to, which is what they saw `reaction_time` ago
+    pub(crate) fn perceive(&mut self, front: (f32, u64)) -> (f32, u64) {
+        let delay = (self.reaction_time / DELTA).round() as usize;
+        if delay == 0 {
+            self.perceived.clear();
+            return front;
+        }
+        self.perceived.push_back(front);
+        while self.perceived.len() > delay + 1 {
+            self.perceived.pop_front();
+        }
+        self.perceived.front().copied().unwrap_or(front)
+    }
+
+    /// Updates the wheel animation state from the current and desired directions.
+    /// A stopped vehicle keeps its wheel phase and straightens its wheels.
+    pub fn update_wheels(&mut self, dir: Vec3, desired_dir: Vec3, speed: f32, dt: f32) {
+        let target = if speed.abs() < 0.01 || desired_dir.xy().mag2() < 0.0001 {
+            0.0
+        } else {
+            dir.xy()
+                .angle(desired_dir.xy())
+                .clamp(-MAX_STEER_ANGLE, MAX_STEER_ANGLE)
+        };
+        self.steer_angle = abs_lerp(self.steer_angle, target, STEER_SPEED * dt);
+
+        self.wheel_phase = (self.wheel_phase + speed * dt / WHEEL_RADIUS).rem_euclid(TAU);
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.passengers.len() >= self.capacity as usize
+    }
+
+    /// Whether the soul is already inside or there is room for it
+    pub fn can_board(&self, soul: SoulID) -> bool {
+        self.passengers.contains(&soul) || !self.is_full()
+    }
+
+    /// Adds the soul to the passengers. Returns false if the vehicle is full.
+    pub fn board(&mut self, soul: SoulID) -> bool {
+        if !self.can_board(soul) {
+            return false;
+        }
+        if !self.passengers.contains(&soul) {
+            self.passengers.push(soul);
+        }
+        true
+    }
+
+    pub fn alight(&mut self, soul: SoulID) {
+        self.passengers.retain(|&x| x != soul);
+    }
+}
+
+/// A white car driving, without passengers nor reaction time
+#[cfg(test)]
+pub(crate) fn test_vehicle(capacity: u32) -> Vehicle {
+    Vehicle {
+        ang_velocity: 0.0,
+        wait_time: 0.0,
+        max_speed_multiplier: 1.0,
+        state: VehicleState::Driving,
+        kind: VehicleKind::Car,
+        tint: Color::WHITE,
+        flag: 0,
+        steer_angle: 0.0,
+        wheel_phase: 0.0,
+        capacity,
+        passengers: vec![],
+        reaction_time: 0.0,
+        perceived: Default::default(),
+        stopped_at: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        first_conflict, make_vehicle_entity, spawn_driving_vehicle, spawn_parked_vehicle,
+        spawn_queue_system, spawn_vehicle_at_building, test_vehicle, unpark, SpawnQueue, Vehicle,
+        VehicleKind, VehicleState, MAX_QUEUED_SPAWNS, MAX_REACTION_TIME, MAX_SPAWN_WAIT,
+        MIN_REACTION_TIME, PREDICTION_STEP, SPAWN_SEARCH_DIST, TIME_TO_PARK,
+    };
+    use crate::map::{LaneKind, LanePatternBuilder, Map, MapProject, ParkingStyle, PathKind};
+    use crate::map_dynamic::Itinerary;
+    use crate::souls::human::spawn_pedestrian_at_building;
+    use crate::tests::TestCtx;
+    use crate::transportation::TransportGrid;
+    use crate::transportation::{Location, Speed};
+    use crate::world::VehicleEnt;
+    use crate::world::{AnyEntity, HumanID};
+    use crate::{RandProvider, SoulID};
+    use geom::{vec2, vec3, Color, Transform, Vec2, Vec3, AABB};
+    use prototypes::{GameTime, Tick, DELTA};
+    use slotmapd::SlotMap;
+
+    #[test]
+    fn board_until_full() {
+        let mut humans = SlotMap::<HumanID, ()>::with_key();
+        let souls: Vec<_> = (0..3).map(|_| SoulID::Human(humans.insert(()))).collect();
+
+        let mut vehicle = test_vehicle(2);
+
+        assert!(vehicle.board(souls[0]));
+        assert!(vehicle.board(souls[1]));
+        assert!(vehicle.is_full());
+
+        // the third one is rejected and has to wait for a seat
+        assert!(!vehicle.can_board(souls[2]));
+        assert!(!vehicle.board(souls[2]));
+        assert_eq!(vehicle.passengers, vec![souls[0], souls[1]]);
+
+        // boarding again is a no-op for someone already inside
+        assert!(vehicle.board(souls[1]));
+
+        vehicle.alight(souls[0]);
+        assert!(vehicle.board(souls[2]));
+        assert_eq!(vehicle.passengers, vec![souls[1], souls[2]]);
+    }
+
+    #[test]
+    fn drivers_have_their_own_reaction_time() {
+        let mut rng = RandProvider::new(1);
+        let times: Vec<f32> = (0..20)
+            .map(|_| Vehicle::new_driving(VehicleKind::Car, Color::WHITE, &mut rng).reaction_time)
+            .collect();
+
+        assert!(times
+            .iter()
+            .all(|&t| (MIN_REACTION_TIME..=MAX_REACTION_TIME).contains(&t)));
+        assert!(times.iter().any(|&t| t != times[0]));
+    }
+
+    #[test]
+    fn collider_radius_depends_on_kind() {
+        let ctx = TestCtx::new();
+        ctx.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(300.0, 0.0, 0.0)]);
+        let mut sim = ctx.g;
+
+        let kinds = [
+            VehicleKind::Car,
+            VehicleKind::Truck,
+            VehicleKind::Bus,
+            VehicleKind::Emergency,
+        ];
+
+        let mut radii = vec![];
+        for (i, kind) in kinds.into_iter().enumerate() {
+            let v = spawn_parked_vehicle(&mut sim, kind, vec3(50.0 + 60.0 * i as f32, 0.0, 0.0))
+                .unwrap();
+            unpark(&mut sim, v);
+
+            let coll = sim.world.vehicles[v].collider.unwrap();
+            let radius = sim.read::<TransportGrid>().get(coll.0).unwrap().1.radius;
+            assert_eq!(radius, kind.collider_radius());
+            radii.push(radius);
+        }
+
+        assert!(
+            radii[0] < radii[3],
+            "emergency vehicles are bigger than cars"
+        );
+        assert!(
+            radii[3] < radii[1],
+            "trucks are bigger than emergency vehicles"
+        );
+        assert!(radii[1] < radii[2], "buses are the biggest");
+    }
+
+    #[test]
+    fn steer_angle_tracks_direction() {
+        let mut vehicle = test_vehicle(4);
+        let dir = vec3(1.0, 0.0, 0.0);
+        let left = Vec2::from_angle(geom::Radians(0.3)).z0();
+        let right = Vec2::from_angle(geom::Radians(-0.3)).z0();
+
+        for _ in 0..100 {
+            vehicle.update_wheels(dir, left, 5.0, DELTA);
+        }
+        assert!((vehicle.steer_angle - 0.3).abs() < 0.01);
+        assert!(vehicle.wheel_phase > 0.0);
+
+        for _ in 0..100 {
+            vehicle.update_wheels(dir, right, 5.0, DELTA);
+        }
+        assert!((vehicle.steer_angle + 0.3).abs() < 0.01);
+
+        // stopped: wheels straighten but don't roll
+        let phase = vehicle.wheel_phase;
+        for _ in 0..100 {
+            vehicle.update_wheels(dir, right, 0.0, DELTA);
+        }
+        assert_eq!(vehicle.wheel_phase, phase);
+        assert!(vehicle.steer_angle.abs() < 0.01);
+    }
+
+    #[test]
+    fn spawns_never_overlap() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(300.0, 0.0, 0.0)]);
+
+        let map = test.g.map();
+        let lane = map
+            .lanes()
+            .values()
+            .find(|l| l.kind == LaneKind::Driving && l.points.first_dir().unwrap().x > 0.9)
+            .unwrap();
+        let pos = lane.points.project(vec3(150.0, 0.0, 0.0));
+        let end = lane.points.project(vec3(280.0, 0.0, 0.0));
+        let it = || Itinerary::route(Tick(0), pos, end, &map, PathKind::Vehicle).unwrap();
+        let its = (0..60).map(|_| it()).collect::<Vec<_>>();
+        drop(map);
+
+        let mut spawned = vec![];
+        for it in its {
+            let trans = Transform::new_dir(pos, Vec3::X);
+            spawned.extend(spawn_driving_vehicle(
+                &mut test.g,
+                trans,
+                test_vehicle(4),
+                it,
+            ));
+        }
+
+        // 60 cars don't fit in 60m of lane, some have to wait
+        let queued = test.g.read::<SpawnQueue>().len();
+        assert!(queued > 0);
+        assert_eq!(spawned.len() + queued, 60);
+
+        let r = VehicleKind::Car.collider_radius();
+        for (i, &a) in spawned.iter().enumerate() {
+            let pa = test.g.world.vehicles[a].trans.pos;
+            assert!(pa.distance(pos) <= SPAWN_SEARCH_DIST + 0.01);
+            for &b in &spawned[i + 1..] {
+                let pb = test.g.world.vehicles[b].trans.pos;
+                assert!(pa.distance(pb) >= 2.0 * r, "{:?} and {:?} overlap", pa, pb);
+            }
         }
     }
+
+    #[test]
+    fn spawn_queue_is_capped_and_expires() {
+        let mut test = TestCtx::new();
+
+        // vehicles without itinerary stay where they are, so the spot never frees up
+        for _ in 0..MAX_QUEUED_SPAWNS * 2 {
+            spawn_driving_vehicle(
+                &mut test.g,
+                Transform::new_dir(vec3(100.0, 100.0, 0.0), Vec3::X),
+                test_vehicle(4),
+                Itinerary::NONE,
+            );
+        }
+        assert_eq!(test.g.read::<SpawnQueue>().len(), MAX_QUEUED_SPAWNS);
+
+        spawn_queue_system(&mut test.g);
+        assert_eq!(test.g.read::<SpawnQueue>().len(), MAX_QUEUED_SPAWNS);
+
+        let now = test.g.read::<GameTime>().tick;
+        *test.g.write::<GameTime>() = GameTime::new(Tick(now.0 + MAX_SPAWN_WAIT.0 .0 + 1));
+        spawn_queue_system(&mut test.g);
+        assert!(test.g.read::<SpawnQueue>().is_empty());
+    }
+
+    #[test]
+    fn entities_in_aabb_uses_colliders() {
+        let mut test = TestCtx::new();
+
+        let mut spawn = |pos: Vec3| {
+            let v = make_vehicle_entity(
+                &mut test.g,
+                Transform::new(pos),
+                test_vehicle(4),
+                Itinerary::NONE,
+                true,
+            );
+            AnyEntity::from(v)
+        };
+        let inside = spawn(vec3(50.0, 50.0, 0.0));
+        let on_corner = spawn(vec3(100.0, 100.0, 0.0));
+        let on_edge = spawn(vec3(0.0, 70.0, 0.0));
+        let outside = spawn(vec3(150.0, 50.0, 0.0));
+        let _far = spawn(vec3(-50.0, -50.0, 0.0));
+
+        let mut found = test
+            .g
+            .entities_in_aabb(AABB::new_ll_ur(Vec2::ZERO, Vec2::splat(100.0)));
+        found.sort_by_key(|e| format!("{:?}", e));
+        let mut expected = vec![inside, on_corner, on_edge];
+        expected.sort_by_key(|e| format!("{:?}", e));
+
+        assert_eq!(found, expected);
+        assert!(!found.contains(&outside));
+    }
+
+    fn moving_vehicle(trans: Transform, speed: f32, it: Itinerary) -> VehicleEnt {
+        VehicleEnt {
+            trans,
+            speed: Speed(speed),
+            vehicle: test_vehicle(4),
+            it,
+            collider: None,
+        }
+    }
+
+    #[test]
+    fn predict_straight_trajectory() {
+        let map = Map::empty();
+        let trans = Transform::new_dir(vec3(10.0, 5.0, 0.0), Vec3::X);
+
+        let v = moving_vehicle(trans, 8.0, Itinerary::simple(vec![vec3(60.0, 5.0, 0.0)]));
+        let traj = v.predict_trajectory(&map, 10.0);
+        assert_eq!(traj.len(), 41);
+        for &(t, pos) in &traj {
+            // x = x0 + v * t until the end of the itinerary at x = 60
+            let expected = Vec2::new((10.0 + 8.0 * t).min(60.0), 5.0);
+            assert!(pos.distance(expected) < 1e-3, "{} {:?}", t, pos);
+        }
+        assert!((traj[1].0 - PREDICTION_STEP).abs() < 1e-6);
+
+        // no itinerary: keeps going straight
+        let v = moving_vehicle(trans, 8.0, Itinerary::NONE);
+        let (t, last) = *v.predict_trajectory(&map, 10.0).last().unwrap();
+        assert_eq!(t, 10.0);
+        assert!(last.distance(Vec2::new(90.0, 5.0)) < 1e-3);
+
+        // stopped vehicles stay where they are
+        let v = moving_vehicle(trans, 0.0, Itinerary::simple(vec![vec3(60.0, 5.0, 0.0)]));
+        assert!(v
+            .predict_trajectory(&map, 5.0)
+            .iter()
+            .all(|&(_, pos)| pos == trans.pos.xy()));
+
+        // head-on vehicles conflict when they meet
+        let other = moving_vehicle(
+            Transform::new_dir(vec3(90.0, 5.0, 0.0), -Vec3::X),
+            8.0,
+            Itinerary::NONE,
+        );
+        let v = moving_vehicle(trans, 8.0, Itinerary::NONE);
+        let conflict = first_conflict(
+            &v.predict_trajectory(&map, 10.0),
+            &other.predict_trajectory(&map, 10.0),
+            4.0,
+        )
+        .unwrap();
+        assert!((conflict - 5.0).abs() <= PREDICTION_STEP, "{}", conflict);
+    }
+
+    #[test]
+    fn predict_trajectory_follows_curved_lane() {
+        let mut map = Map::empty();
+        map.make_connection(
+            MapProject::ground(vec3(0.0, 0.0, 0.0)),
+            MapProject::ground(vec3(300.0, 0.0, 0.0)),
+            Some(Vec2::new(150.0, 120.0)),
+            &LanePatternBuilder::new().build(),
+        )
+        .unwrap();
+
+        let lane = map
+            .lanes()
+            .values()
+            .find(|l| {
+                l.kind == LaneKind::Driving
+                    && l.points.first().x < 50.0
+                    && l.points.first_dir().unwrap().y > 0.0
+            })
+            .unwrap();
+        let (start, dir) = lane.points.point_dir_along(10.0);
+        let end = lane.points.point_along(lane.points.length() - 10.0);
+        let it = Itinerary::route(Tick(0), start, end, &map, PathKind::Vehicle).unwrap();
+
+        let speed = 12.0;
+        let v = moving_vehicle(Transform::new_dir(start, dir), speed, it);
+        let traj = v.predict_trajectory(&map, 15.0);
+
+        let start_along = lane.points.length_at_proj(start);
+        for &(t, pos) in &traj {
+            let pos = pos.z(start.z);
+            assert!(lane.points.project_dist(pos) < 0.1, "off the lane at {}", t);
+            let along = lane.points.length_at_proj(pos) - start_along;
+            assert!((along - speed * t).abs() < 1.0, "{} {}", along, speed * t);
+        }
+        let (_, last) = *traj.last().unwrap();
+        assert!(last.y > 40.0, "the lane curves up: {:?}", last);
+    }
+
+    #[test]
+    fn spawn_at_building_access() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(300.0, 0.0, 0.0)]);
+        let house = test.build_house_near(vec2(150.0, 30.0));
+        let access = test.g.map().building_access_point(house).unwrap();
+
+        let human = spawn_pedestrian_at_building(&mut test.g, house).unwrap();
+        let h = &test.g.world.humans[human];
+        assert_eq!(h.location, Location::Outside);
+        assert!(h.trans.pos.distance(access) < 0.1);
+        assert!(h.collider.is_some());
+
+        // parked in the closest spot, across the sidewalk from the access point
+        let car = spawn_vehicle_at_building(&mut test.g, VehicleKind::Car, house).unwrap();
+        let v = &test.g.world.vehicles[car];
+        assert!(matches!(v.vehicle.state, VehicleState::Parked(_)));
+        let dist = v.trans.pos.xy().distance(access.xy());
+        assert!(dist < 5.0, "{}", dist);
+        test.tick();
+
+        // no road access: no vehicle, the pedestrian waits at the door
+        test.g.map_mut().buildings[house].connected_road = None;
+        assert!(spawn_vehicle_at_building(&mut test.g, VehicleKind::Car, house).is_none());
+        let door = test.g.map().buildings()[house].door_pos;
+        let human = spawn_pedestrian_at_building(&mut test.g, house).unwrap();
+        assert_eq!(test.g.world.humans[human].trans.pos, door);
+    }
+
+    #[test]
+    fn backs_out_of_angled_slots() {
+        let mut test = TestCtx::new();
+        test.g.map_mut().make_connection(
+            MapProject::ground(vec3(0.0, 0.0, 0.0)),
+            MapProject::ground(vec3(300.0, 0.0, 0.0)),
+            None,
+            &LanePatternBuilder::new()
+                .one_way(true)
+                .parking_style(ParkingStyle::Angled(60.0))
+                .build(),
+        );
+
+        let car =
+            spawn_parked_vehicle(&mut test.g, VehicleKind::Car, vec3(150.0, 0.0, 0.0)).unwrap();
+        let slot = test.g.world.vehicles[car].trans;
+        assert!(slot.dir.x.abs() < 0.9, "the slot is angled: {:?}", slot.dir);
+
+        unpark(&mut test.g, car);
+        assert!(matches!(
+            test.g.world.vehicles[car].vehicle.state,
+            VehicleState::ParkToRoad(..)
+        ));
+        test.tick();
+
+        // reverses out along the slot, still facing the curb
+        let v = &test.g.world.vehicles[car];
+        assert!(v.trans.dir.dot(slot.dir) > 0.95, "{:?}", v.trans.dir);
+        assert!((v.trans.pos - slot.pos).dot(slot.dir) < 0.0);
+
+        let mut ticks = 0;
+        while !matches!(
+            test.g.world.vehicles[car].vehicle.state,
+            VehicleState::Driving
+        ) {
+            test.tick();
+            ticks += 1;
+            assert!(ticks as f32 <= TIME_TO_PARK / DELTA, "never left the slot");
+        }
+
+        // ends up on the driving lane, facing down the road
+        let v = &test.g.world.vehicles[car];
+        assert!(v.trans.dir.x > 0.95, "{:?}", v.trans.dir);
+        let map = test.g.map();
+        let lane = map
+            .lanes()
+            .values()
+            .find(|l| l.kind == LaneKind::Driving)
+            .unwrap();
+        assert!(lane.points.project_dist(v.trans.pos) < 0.5);
+    }
 }
@@ -0,0 +1,253 @@
+//! Periodic saves of the simulation, rotating between a fixed number of files.
+//!
+//! Saving is split in two: [`snapshot`] encodes the simulation in memory and must be done while
+//! holding it, [`write_autosave`] compresses and writes the snapshot and can run on a background thread.
+use crate::Simulation;
+use common::saveload::{Bincode, CheckedCompressedBincode, Encoder};
+use prototypes::TICKS_PER_SECOND;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub const AUTOSAVE_PREFIX: &str = "autosave_";
+
+/// What time the autosave interval is measured in
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AutosaveClock {
+    Real,
+    Game,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Autosave {
+    /// None disables autosaving
+    pub interval: Option<Duration>,
+    pub clock: AutosaveClock,
+    /// Number of autosaves kept on disk, the oldest ones are deleted
+    pub keep: u32,
+}
+
+/// Decides when the next autosave is due
+#[derive(Debug, Default)]
+pub struct AutosaveTimer {
+    elapsed: Duration,
+    last_tick: Option<u64>,
+}
+
+impl AutosaveTimer {
+    /// Advances the timer by a frame of `real_dt`, the simulation being at `tick`.
+    /// Returns true when an autosave is due.
+    pub fn advance(&mut self, config: &Autosave, real_dt: Duration, tick: u64) -> bool {
+        let game_ticks = tick.saturating_sub(self.last_tick.unwrap_or(tick));
+        self.last_tick = Some(tick);
+
+        let Some(interval) = config.interval.filter(|_| config.keep > 0) else {
+            self.elapsed = Duration::ZERO;
+            return false;
+        };
+
+        self.elapsed += match config.clock {
+            AutosaveClock::Real => real_dt,
+            AutosaveClock::Game => {
+                Duration::from_secs_f64(game_ticks as f64 / TICKS_PER_SECOND as f64)
+            }
+        };
+        if self.elapsed < interval {
+            return false;
+        }
+        self.elapsed = Duration::ZERO;
+        true
+    }
+}
+
+/// Encodes the simulation in memory, this is the only part of autosaving that needs the simulation
+pub fn snapshot(sim: &Simulation) -> Option<Vec<u8>> {
+    Bincode::encode(sim)
+        .map_err(|e| log::error!("failed to snapshot the simulation: {}", e))
+        .ok()
+}
+
+/// Autosaves in `dir` with their number, oldest first
+fn autosaves(dir: &Path) -> Vec<(u64, PathBuf)> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut saves: Vec<(u64, PathBuf)> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let n = path
+                .file_stem()?
+                .to_str()?
+                .strip_prefix(AUTOSAVE_PREFIX)?
+                .parse()
+                .ok()?;
+            Some((n, path))
+        })
+        .collect();
+    saves.sort();
+    saves
+}
+
+/// Most recent autosave in `dir`
+pub fn latest_autosave(dir: &Path) -> Option<PathBuf> {
+    autosaves(dir).pop().map(|(_, path)| path)
+}
+
+/// The latest autosave of `dir` if it was written after the `main` save, which happens when the game
+/// crashed or was closed without saving since then. None if the main save is the newest.
+pub fn autosave_newer_than(main: &Path, dir: &Path) -> Option<PathBuf> {
+    let autosave = latest_autosave(dir)?;
+    let modified = |p: &Path| std::fs::metadata(p).and_then(|m| m.modified()).ok();
+    match (modified(main), modified(&autosave)) {
+        (Some(main), Some(auto)) if main >= auto => None,
+        _ => Some(autosave),
+    }
+}
+
+fn load_autosave(path: &Path) -> Option<Simulation> {
+    let data = std::fs::read(path)
+        .map_err(|e| log::error!("failed to read autosave {}: {}", path.display(), e))
+        .ok()?;
+    let sim: Simulation = CheckedCompressedBincode::decode(&data)
+        .map_err(|e| log::error!("failed to load autosave {}: {}", path.display(), e))
+        .ok()?;
+    if sim.map().environment.size().0 == 0 {
+        return None;
+    }
+    log::info!("loaded autosave {}", path.display());
+    Some(sim)
+}
+
+/// Loads the save named `save_name`, or the latest autosave instead if it is more recent so that
+/// the progress made since the last save isn't lost after a crash.
+pub fn load_newest(save_name: &str) -> Option<Simulation> {
+    let main = PathBuf::from(CheckedCompressedBincode::filename(save_name));
+    let dir = main.parent().unwrap_or(Path::new("world"));
+    if let Some(sim) = autosave_newer_than(&main, dir).and_then(|p| load_autosave(&p)) {
+        return Some(sim);
+    }
+    Simulation::load_from_disk(save_name)
+}
+
+/// Writes a snapshot as the newest autosave of `dir`, then deletes the oldest ones so that only `keep` remain.
+/// The previous autosaves are only removed once the new one is written.
+pub fn write_autosave(snapshot: &[u8], dir: &Path, keep: u32) -> Option<PathBuf> {
+    let _ = std::fs::create_dir_all(dir);
+
+    let next = autosaves(dir).last().map_or(0, |(n, _)| n + 1);
+    let path = dir.join(format!(
+        "{}{}.{}",
+        AUTOSAVE_PREFIX,
+        next,
+        CheckedCompressedBincode::EXTENSION
+    ));
+
+    std::fs::write(&path, CheckedCompressedBincode::from_bincode(snapshot))
+        .map_err(|e| log::error!("failed to write autosave {}: {}", path.display(), e))
+        .ok()?;
+    log::info!("autosaved to {}", path.display());
+
+    let saves = autosaves(dir);
+    let n_old = saves.len().saturating_sub(keep.max(1) as usize);
+    for (_, old) in &saves[..n_old] {
+        if let Err(e) = std::fs::remove_file(old) {
+            log::error!("failed to remove old autosave {}: {}", old.display(), e);
+        }
+    }
+
+    Some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        autosave_newer_than, autosaves, latest_autosave, write_autosave, Autosave, AutosaveClock,
+        AutosaveTimer,
+    };
+    use common::saveload::{Bincode, CheckedCompressedBincode, Encoder};
+    use prototypes::TICKS_PER_SECOND;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn timer_fires_every_interval() {
+        let mut config = Autosave {
+            interval: Some(Duration::from_secs(60)),
+            clock: AutosaveClock::Real,
+            keep: 3,
+        };
+        let mut timer = AutosaveTimer::default();
+        let frame = Duration::from_secs(1);
+
+        let fired = (0..180)
+            .filter(|&i| timer.advance(&config, frame, i))
+            .count();
+        assert_eq!(fired, 3);
+
+        // in game time, a paused game never autosaves
+        config.clock = AutosaveClock::Game;
+        assert!(!(0..1000).any(|_| timer.advance(&config, frame, 0)));
+        let minute = 60 * TICKS_PER_SECOND;
+        assert!(!timer.advance(&config, frame, minute - 1));
+        assert!(timer.advance(&config, frame, minute));
+
+        config.interval = None;
+        assert!(!timer.advance(&config, frame, 100 * minute));
+    }
+
+    #[test]
+    fn autosaves_are_rotated() {
+        let dir = std::env::temp_dir().join(format!("egregoria_autosave_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        for i in 0..5u32 {
+            let snapshot = Bincode::encode(&i).unwrap();
+            let path = write_autosave(&snapshot, &dir, 3).unwrap();
+            assert!(path.exists());
+        }
+
+        // only the 3 most recent remain, the oldest were deleted
+        let kept: Vec<u64> = autosaves(&dir).into_iter().map(|(n, _)| n).collect();
+        assert_eq!(kept, vec![2, 3, 4]);
+
+        let latest = std::fs::read(latest_autosave(&dir).unwrap()).unwrap();
+        assert_eq!(CheckedCompressedBincode::decode::<u32>(&latest).unwrap(), 4);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn newer_autosave_is_preferred_to_the_save() {
+        let dir = std::env::temp_dir().join(format!("egregoria_newest_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let main = dir.join("world.zip");
+
+        // no autosave
+        assert_eq!(autosave_newer_than(&main, &dir), None);
+
+        let snapshot = Bincode::encode(&0u32).unwrap();
+        let autosave = write_autosave(&snapshot, &dir, 3).unwrap();
+        // no main save
+        assert_eq!(autosave_newer_than(&main, &dir), Some(autosave.clone()));
+
+        let set_modified = |p: &std::path::Path, t: SystemTime| {
+            std::fs::File::options()
+                .write(true)
+                .open(p)
+                .unwrap()
+                .set_modified(t)
+                .unwrap();
+        };
+        let now = SystemTime::now();
+        std::fs::write(&main, b"save").unwrap();
+
+        // crashed after the autosave
+        set_modified(&main, now - Duration::from_secs(60));
+        set_modified(&autosave, now);
+        assert_eq!(autosave_newer_than(&main, &dir), Some(autosave.clone()));
+
+        // saved after the autosave
+        set_modified(&main, now + Duration::from_secs(60));
+        assert_eq!(autosave_newer_than(&main, &dir), None);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,5 +1,7 @@
+pub mod autosave;
 pub mod par_command_buffer;
 pub mod rand_provider;
 pub mod replay;
 pub mod resources;
 pub mod scheduler;
+pub mod undo;
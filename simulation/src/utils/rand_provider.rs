@@ -26,6 +26,14 @@ impl RandProvider {
         f32::from_bits(0x3f800000 | (0x7fffff & self.next_u32())) - 1.0
     }
 
+    /// Normally distributed value using the Box-Muller transform
+    pub fn next_normal(&mut self, mean: f32, stddev: f32) -> f32 {
+        let u1 = 1.0 - self.next_f32(); // in ]0, 1] to avoid ln(0)
+        let u2 = self.next_f32();
+        let z = (-2.0 * u1.ln()).sqrt() * (std::f32::consts::TAU * u2).cos();
+        mean + z * stddev
+    }
+
     #[inline]
     pub fn next_u32(&mut self) -> u32 {
         let x = self.x;
@@ -366,3 +366,24 @@ impl From<InvalidBorrow> for CantGetResource {
         CantGetResource::InvalidBorrow(error)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Resources;
+
+    #[derive(Default, Debug, PartialEq)]
+    struct Counter(u32);
+
+    #[test]
+    fn write_or_default_keeps_existing() {
+        let mut res = Resources::default();
+
+        assert!(!res.contains::<Counter>());
+        assert_eq!(*res.write_or_default::<Counter>(), Counter(0));
+        assert!(res.contains::<Counter>());
+
+        res.write::<Counter>().0 = 5;
+        assert_eq!(*res.write_or_default::<Counter>(), Counter(5));
+        assert_eq!(*res.read::<Counter>(), Counter(5));
+    }
+}
@@ -0,0 +1,242 @@
+use crate::multiplayer::PlayerID;
+use crate::world_command::WorldCommand;
+use crate::Simulation;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+
+/// Number of commands that can be undone, the oldest ones are forgotten
+pub const MAX_UNDO_DEPTH: usize = 128;
+
+/// Inverses of the last applied map commands, so that they can be undone and redone.
+/// The inverse of a connection removes the road and the intersections it created and brings back
+/// the roads it split, the inverse of a removal builds the roads again with their saved geometry.
+/// Each player has its own history, undoing never reverts what another player did.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UndoStack {
+    players: BTreeMap<PlayerID, History>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct History {
+    undo: VecDeque<WorldCommand>,
+    redo: Vec<WorldCommand>,
+}
+
+impl History {
+    fn push_undo(&mut self, inverse: WorldCommand) {
+        self.undo.push_back(inverse);
+        if self.undo.len() > MAX_UNDO_DEPTH {
+            self.undo.pop_front();
+        }
+    }
+}
+
+impl UndoStack {
+    /// Records the inverse of a command the player just applied, what they undid can't be redone anymore
+    pub fn push(&mut self, player: PlayerID, inverse: WorldCommand) {
+        let history = self.players.entry(player).or_default();
+        history.redo.clear();
+        history.push_undo(inverse);
+    }
+
+    pub fn can_undo(&self, player: PlayerID) -> bool {
+        self.players
+            .get(&player)
+            .map_or(false, |h| !h.undo.is_empty())
+    }
+
+    pub fn can_redo(&self, player: PlayerID) -> bool {
+        self.players
+            .get(&player)
+            .map_or(false, |h| !h.redo.is_empty())
+    }
+}
+
+pub(crate) fn undo(sim: &mut Simulation, player: PlayerID) {
+    let Some(inverse) = sim
+        .write::<UndoStack>()
+        .players
+        .get_mut(&player)
+        .and_then(|h| h.undo.pop_back())
+    else {
+        return;
+    };
+    if let Some(redo) = inverse.apply_inner(sim, player) {
+        let mut stack = sim.write::<UndoStack>();
+        stack.players.entry(player).or_default().redo.push(redo);
+    }
+}
+
+pub(crate) fn redo(sim: &mut Simulation, player: PlayerID) {
+    let Some(command) = sim
+        .write::<UndoStack>()
+        .players
+        .get_mut(&player)
+        .and_then(|h| h.redo.pop())
+    else {
+        return;
+    };
+    if let Some(inverse) = command.apply_inner(sim, player) {
+        let mut stack = sim.write::<UndoStack>();
+        stack.players.entry(player).or_default().push_undo(inverse);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{UndoStack, MAX_UNDO_DEPTH};
+    use crate::map::{LanePatternBuilder, MapProject, ProjectFilter};
+    use crate::multiplayer::PlayerID;
+    use crate::tests::TestCtx;
+    use crate::WorldCommand;
+    use geom::{vec2, vec3};
+
+    #[test]
+    fn connections_and_removals_are_undone() {
+        let mut test = TestCtx::new();
+        let counts = |test: &TestCtx| {
+            let map = test.g.map();
+            (map.roads().len(), map.intersections().len())
+        };
+
+        test.apply(&[WorldCommand::MapMakeConnection {
+            from: MapProject::ground(vec3(0.0, 0.0, 0.0)),
+            to: MapProject::ground(vec3(100.0, 50.0, 0.0)),
+            inter: Some(vec2(100.0, 0.0)),
+            pat: LanePatternBuilder::new().build(),
+        }]);
+        assert_eq!(counts(&test), (1, 2));
+        let points = test.g.map().roads().values().next().unwrap().points.clone();
+
+        // undoing the connection removes the intersections it created
+        test.apply(&[WorldCommand::Undo]);
+        assert_eq!(counts(&test), (0, 0));
+        test.apply(&[WorldCommand::Redo]);
+        assert_eq!(counts(&test), (1, 2));
+        assert!(!test.g.read::<UndoStack>().can_redo(PlayerID::LOCAL));
+
+        let id = test.g.map().roads().keys().next().unwrap();
+        test.apply(&[WorldCommand::MapRemoveRoad(id)]);
+        assert_eq!(counts(&test).0, 0);
+
+        // the removed road comes back with the same shape
+        test.apply(&[WorldCommand::Undo]);
+        assert_eq!(counts(&test), (1, 2));
+        let map = test.g.map();
+        let restored = &map.roads().values().next().unwrap().points;
+        assert_eq!(restored.len(), points.len());
+        for (a, b) in restored.iter().zip(points.iter()) {
+            assert!(a.distance(*b) < 0.01);
+        }
+        drop(map);
+
+        // a new command can't be redone over
+        test.apply(&[WorldCommand::Redo, WorldCommand::Undo]);
+        assert!(test.g.read::<UndoStack>().can_redo(PlayerID::LOCAL));
+        let id = test.g.map().roads().keys().next().unwrap();
+        test.apply(&[WorldCommand::MapRemoveRoad(id)]);
+        assert!(!test.g.read::<UndoStack>().can_redo(PlayerID::LOCAL));
+
+        for i in 0..MAX_UNDO_DEPTH + 10 {
+            let y = 200.0 + i as f32 * 10.0;
+            test.apply(&[WorldCommand::MapMakeConnection {
+                from: MapProject::ground(vec3(0.0, y, 0.0)),
+                to: MapProject::ground(vec3(50.0, y, 0.0)),
+                inter: None,
+                pat: LanePatternBuilder::new().build(),
+            }]);
+        }
+        assert_eq!(
+            test.g.read::<UndoStack>().players[&PlayerID::LOCAL]
+                .undo
+                .len(),
+            MAX_UNDO_DEPTH
+        );
+    }
+
+    #[test]
+    fn undoing_a_connection_joins_the_split_road() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(200.0, 0.0, 0.0)]);
+        let counts = |test: &TestCtx| {
+            let map = test.g.map();
+            (map.roads().len(), map.intersections().len())
+        };
+        assert_eq!(counts(&test), (1, 2));
+
+        let from = test
+            .g
+            .map()
+            .project(vec3(100.0, 0.0, 0.0), 0.0, ProjectFilter::ROAD);
+        assert!(matches!(from.kind, crate::map::ProjectKind::Road(_)));
+        test.apply(&[WorldCommand::MapMakeConnection {
+            from,
+            to: MapProject::ground(vec3(100.0, 100.0, 0.0)),
+            inter: None,
+            pat: LanePatternBuilder::new().build(),
+        }]);
+        assert_eq!(counts(&test), (3, 4));
+
+        // no intersection is left in the middle of the road
+        test.apply(&[WorldCommand::Undo]);
+        assert_eq!(counts(&test), (1, 2));
+        let map = test.g.map();
+        for inter in map.intersections().values() {
+            let x = inter.pos.x;
+            assert!(x.abs() < 1.0 || (x - 200.0).abs() < 1.0, "{:?}", inter.pos);
+        }
+        assert!(map.validate().is_empty());
+        drop(map);
+
+        test.apply(&[WorldCommand::Redo]);
+        assert_eq!(counts(&test), (3, 4));
+        assert!(test.g.map().validate().is_empty());
+    }
+
+    #[test]
+    fn players_undo_their_own_commands() {
+        let mut test = TestCtx::new();
+        let (alice, bob) = (PlayerID(1), PlayerID(2));
+        let connect = |player, y: f32| {
+            WorldCommand::FromPlayer(
+                player,
+                Box::new(WorldCommand::MapMakeConnection {
+                    from: MapProject::ground(vec3(0.0, y, 0.0)),
+                    to: MapProject::ground(vec3(100.0, y, 0.0)),
+                    inter: None,
+                    pat: LanePatternBuilder::new().build(),
+                }),
+            )
+        };
+        let has_road_at = |test: &TestCtx, y: f32| {
+            test.g
+                .map()
+                .roads()
+                .values()
+                .any(|r| (r.points.first().y - y).abs() < 1.0)
+        };
+
+        test.apply(&[connect(alice, 0.0), connect(bob, 200.0)]);
+
+        // undoing the last command only reverts the player's own road
+        test.apply(&[WorldCommand::FromPlayer(
+            alice,
+            Box::new(WorldCommand::Undo),
+        )]);
+        assert!(!has_road_at(&test, 0.0));
+        assert!(has_road_at(&test, 200.0));
+        assert!(!test.g.read::<UndoStack>().can_undo(alice));
+        assert!(test.g.read::<UndoStack>().can_undo(bob));
+
+        // a player without history does nothing
+        test.apply(&[WorldCommand::Undo]);
+        assert!(has_road_at(&test, 200.0));
+
+        test.apply(&[WorldCommand::FromPlayer(
+            alice,
+            Box::new(WorldCommand::Redo),
+        )]);
+        assert!(has_road_at(&test, 0.0));
+        assert!(!test.g.read::<UndoStack>().can_redo(bob));
+    }
+}
@@ -1,7 +1,7 @@
 use crate::economy::{Bought, Market, Sold, Workers};
 use crate::map_dynamic::{
-    DispatchID, Dispatcher, Itinerary, ItineraryFollower, ItineraryLeader, ParkingManagement,
-    Router,
+    eject_from_vehicle, DispatchID, Dispatcher, Itinerary, ItineraryFollower, ItineraryLeader,
+    ParkingManagement, Router,
 };
 use crate::souls::desire::{BuyFood, Home, Work};
 use crate::souls::freight_station::FreightStation;
@@ -13,7 +13,7 @@ use crate::transportation::{
 };
 use crate::utils::par_command_buffer::SimDrop;
 use crate::utils::resources::Resources;
-use crate::{impl_entity, impl_trans, SoulID};
+use crate::{impl_entity, impl_trans, ParCommandBuffer, SoulID};
 use common::iter::chain;
 use derive_more::{From, TryInto};
 use geom::{Transform, Vec2, Vec3};
@@ -45,7 +45,7 @@ impl_trans!(WagonID);
 impl_trans!(FreightStationID);
 impl_trans!(CompanyID);
 
-#[derive(PartialEq, Eq, Copy, Clone, Debug, From, TryInto)]
+#[derive(PartialEq, Eq, Copy, Clone, Debug, From, TryInto, Serialize, Deserialize)]
 pub enum AnyEntity {
     VehicleID(VehicleID),
     TrainID(TrainID),
@@ -80,6 +80,14 @@ impl SimDrop for VehicleEnt {
             res.write::<Dispatcher>()
                 .unregister(DispatchID::SmallTruck(id))
         }
+
+        let exit_pos = self.trans.pos + self.trans.dir.cross(Vec3::Z) * 2.0;
+        let cbuf = res.read::<ParCommandBuffer<HumanEnt>>();
+        for soul in self.vehicle.passengers {
+            if let SoulID::Human(human) = soul {
+                cbuf.exec_ent(human, move |sim| eject_from_vehicle(sim, human, exit_pos));
+            }
+        }
     }
 }
 
@@ -111,6 +119,14 @@ impl SimDrop for HumanEnt {
 
         res.write::<Market>().remove(SoulID::Human(id));
 
+        if let Location::Vehicle(vehicle) = self.location {
+            res.read::<ParCommandBuffer<VehicleEnt>>()
+                .exec_ent(vehicle, move |sim| {
+                    let v = unwrap_ret!(sim.world.vehicles.get_mut(vehicle));
+                    v.vehicle.alight(SoulID::Human(id));
+                });
+        }
+
         self.router
             .clear_steps(&mut res.write::<ParkingManagement>())
     }
@@ -263,6 +279,7 @@ impl World {
         ))
     }
 
+    /// Vehicles are not included, they move with sub-steps in `move_vehicle`
     #[rustfmt::skip]
     pub fn query_it_trans_speed(
         &mut self,
@@ -270,7 +287,6 @@ impl World {
         chain((
             self.humans  .values_mut().map(|h| (&mut h.it, &mut h.trans, h.speed.0)),
             self.trains  .values_mut().map(|h| (&mut h.it, &mut h.trans, h.speed.0)),
-            self.vehicles.values_mut().map(|h| (&mut h.it, &mut h.trans, h.speed.0)),
         ))
     }
 
@@ -4,24 +4,31 @@ use std::time::Instant;
 use prototypes::RollingStockID;
 use serde::{Deserialize, Serialize};
 
-use geom::{vec3, Vec2, Vec3, OBB};
+use geom::{vec3, Color, Vec2, Vec3, AABB, OBB};
 use prototypes::BuildingGen;
-use prototypes::GameTime;
+use prototypes::{GameDuration, GameTime};
 use WorldCommand::*;
 
 use crate::economy::Government;
-use crate::map::procgen::{load_parismap, load_testfield};
+use crate::map::procgen::{import_osm, load_parismap, load_testfield};
 use crate::map::{
-    BuildingID, BuildingKind, Environment, IntersectionID, LaneID, LanePattern, LanePatternBuilder,
-    LightPolicy, LotID, Map, MapProject, ProjectKind, RoadID, TerraformKind, TurnPolicy, Zone,
+    BuildingID, BuildingKind, Environment, IntersectionID, LaneDirection, LaneID, LanePattern,
+    LanePatternBuilder, LightPolicy, LightTiming, LotID, Map, MapProject, ProjectKind, RoadID,
+    RoadMaterial, SavedRoad, TerraformKind, TurnPolicy, Zone,
 };
-use crate::map_dynamic::{BuildingInfos, ParkingManagement};
+use crate::map_dynamic::{BuildingInfos, Itinerary, ParkingManagement};
 use crate::multiplayer::chat::Message;
-use crate::multiplayer::MultiplayerState;
+use crate::multiplayer::{MultiplayerState, PlayerID};
+use crate::souls::decision_lod::DecisionLod;
 use crate::transportation::testing_vehicles::RandomVehicles;
 use crate::transportation::train::{spawn_train, RailWagonKind};
-use crate::transportation::{spawn_parked_vehicle_with_spot, unpark, VehicleKind};
+use crate::transportation::{
+    assign_bus, spawn_parked_vehicle_with_spot, unpark, BusRouteID, BusRoutes, EdgePortal,
+    EdgePortals, RampMeter, RampMeters, VehicleKind,
+};
 use crate::utils::rand_provider::RandProvider;
+use crate::utils::undo::{redo, undo, UndoStack};
+use crate::world::VehicleID;
 use crate::{Replay, Simulation, SimulationOptions};
 
 #[derive(Clone, Default)]
@@ -35,7 +42,20 @@ defer_serialize!(WorldCommands, Vec<WorldCommand>);
 pub enum WorldCommand {
     Init(Box<SimulationOptions>),
     MapRemoveIntersection(IntersectionID),
+    /// Merges b into a
+    MapMergeIntersections {
+        a: IntersectionID,
+        b: IntersectionID,
+    },
     MapRemoveRoad(RoadID),
+    /// Removes the roads and the intersections they leave without roads
+    MapUnbuildRoads(Vec<RoadID>),
+    /// Builds removed roads again
+    MapRestoreRoads(Vec<SavedRoad>),
+    /// Removes the roads and intersections touching the box
+    MapRemoveArea {
+        aabb: AABB,
+    },
     MapRemoveBuilding(BuildingID),
     MapBuildHouse(LotID),
     Terraform {
@@ -77,6 +97,52 @@ pub enum WorldCommand {
         turn: TurnPolicy,
         light: LightPolicy,
     },
+    /// None goes back to the automatic timing
+    SetLightTiming {
+        inter: IntersectionID,
+        timing: Option<LightTiming>,
+    },
+    SetRoadClosed {
+        road: RoadID,
+        closed: bool,
+    },
+    SetRoadMaterial {
+        road: RoadID,
+        material: RoadMaterial,
+    },
+    SetLaneSpeedLimit {
+        lane: LaneID,
+        limit: f32,
+    },
+    /// Only for the lanes next to the middle of the road, vehicles going through it are rerouted
+    MapSetLaneDirection {
+        lane: LaneID,
+        dir: LaneDirection,
+    },
+    /// None makes it a regular intersection again, the radius is raised if the roads don't fit around the ring
+    MapSetRoundabout {
+        inter: IntersectionID,
+        radius: Option<f32>,
+    },
+    /// None removes the meter
+    SetRampMeter {
+        lane: LaneID,
+        interval: Option<GameDuration>,
+    },
+    /// None removes the portal, a portal without spawn interval only lets vehicles out
+    SetEdgePortal {
+        intersection: IntersectionID,
+        portal: Option<Option<GameDuration>>,
+    },
+    AddBusRoute {
+        line_number: u16,
+        color: Color,
+    },
+    /// None unassigns the bus
+    AssignBus {
+        bus: VehicleID,
+        route: Option<BusRouteID>,
+    },
     MapBuildSpecialBuilding {
         pos: OBB,
         kind: BuildingKind,
@@ -85,6 +151,8 @@ pub enum WorldCommand {
         zone: Option<Zone>,
         #[serde(default)]
         connected_road: Option<RoadID>,
+        #[serde(default)]
+        door_offset: u8,
     },
     MapLoadParis,
     MapLoadTestField {
@@ -97,6 +165,28 @@ pub enum WorldCommand {
         zone: Zone,
     },
     SetGameTime(GameTime),
+    BatchRoadGrid {
+        origin: Vec2,
+        nx: u32,
+        ny: u32,
+        spacing: f32,
+        pattern: LanePattern,
+    },
+    Undo,
+    Redo,
+    /// Removes the roads and the intersections they leave without roads, then builds the saved ones again.
+    /// Undoes a connection that split roads by bringing back the roads it split.
+    MapReplaceRoads {
+        remove: Vec<RoadID>,
+        restore: Vec<SavedRoad>,
+    },
+    /// A command sent by a player in multiplayer, it is undone and redone with that player's own history
+    FromPlayer(PlayerID, Box<WorldCommand>),
+    /// Builds the roads of an OSM extract (XML or PBF). The content of the file travels with the
+    /// command so every client and replays build the same roads.
+    MapLoadOsm(Vec<u8>),
+    /// Moves the point souls are simulated in full detail around, see [`DecisionLod`]
+    SetDecisionFocus(Option<Vec3>),
 }
 
 impl AsRef<[WorldCommand]> for WorldCommands {
@@ -122,6 +212,17 @@ impl WorldCommands {
         self.commands.iter()
     }
 
+    /// Tags the commands as sent by the player so that they go in their undo history
+    pub fn sent_by(self, player: PlayerID) -> Self {
+        Self {
+            commands: self
+                .commands
+                .into_iter()
+                .map(|c| FromPlayer(player, Box::new(c)))
+                .collect(),
+        }
+    }
+
     pub fn is_empty(&self) -> bool {
         self.commands.is_empty()
     }
@@ -134,6 +235,31 @@ impl WorldCommands {
         self.commands.push(MapLoadTestField { pos, size, spacing })
     }
 
+    pub fn map_load_osm(&mut self, extract: Vec<u8>) {
+        self.commands.push(MapLoadOsm(extract))
+    }
+
+    pub fn set_decision_focus(&mut self, focus: Option<Vec3>) {
+        self.commands.push(SetDecisionFocus(focus))
+    }
+
+    pub fn batch_road_grid(
+        &mut self,
+        origin: Vec2,
+        nx: u32,
+        ny: u32,
+        spacing: f32,
+        pattern: LanePattern,
+    ) {
+        self.commands.push(BatchRoadGrid {
+            origin,
+            nx,
+            ny,
+            spacing,
+            pattern,
+        })
+    }
+
     pub fn set_game_time(&mut self, gt: GameTime) {
         self.commands.push(SetGameTime(gt))
     }
@@ -153,6 +279,7 @@ impl WorldCommands {
         gen: BuildingGen,
         zone: Option<Zone>,
         connected_road: Option<RoadID>,
+        door_offset: u8,
     ) {
         self.commands.push(MapBuildSpecialBuilding {
             pos: obb,
@@ -160,6 +287,7 @@ impl WorldCommands {
             gen,
             zone,
             connected_road,
+            door_offset,
         })
     }
 
@@ -167,10 +295,27 @@ impl WorldCommands {
         self.commands.push(MapRemoveIntersection(id))
     }
 
+    pub fn map_merge_intersections(&mut self, a: IntersectionID, b: IntersectionID) {
+        self.commands.push(MapMergeIntersections { a, b })
+    }
+
     pub fn map_remove_road(&mut self, id: RoadID) {
         self.commands.push(MapRemoveRoad(id))
     }
 
+    pub fn map_remove_area(&mut self, aabb: AABB) {
+        self.commands.push(MapRemoveArea { aabb })
+    }
+
+    /// Undoes the last map command that can be undone, see [`UndoStack`]
+    pub fn undo(&mut self) {
+        self.commands.push(Undo)
+    }
+
+    pub fn redo(&mut self) {
+        self.commands.push(Redo)
+    }
+
     pub fn map_remove_building(&mut self, id: BuildingID) {
         self.commands.push(MapRemoveBuilding(id))
     }
@@ -194,6 +339,22 @@ impl WorldCommands {
         })
     }
 
+    /// Same as [`Self::map_make_connection`] using a preset from [`LanePatternBuilder::PRESETS`].
+    /// Returns false and does nothing if the preset doesn't exist.
+    pub fn map_make_connection_preset(
+        &mut self,
+        from: MapProject,
+        to: MapProject,
+        interpoint: Option<Vec2>,
+        preset: &str,
+    ) -> bool {
+        let Some(builder) = LanePatternBuilder::preset(preset) else {
+            return false;
+        };
+        self.map_make_connection(from, to, interpoint, builder.build());
+        true
+    }
+
     pub fn map_update_intersection_policy(
         &mut self,
         id: IntersectionID,
@@ -206,6 +367,53 @@ impl WorldCommands {
             light: lp,
         })
     }
+
+    pub fn set_light_timing(&mut self, inter: IntersectionID, timing: Option<LightTiming>) {
+        self.commands.push(SetLightTiming { inter, timing })
+    }
+
+    pub fn set_road_closed(&mut self, road: RoadID, closed: bool) {
+        self.commands.push(SetRoadClosed { road, closed })
+    }
+
+    pub fn set_road_material(&mut self, road: RoadID, material: RoadMaterial) {
+        self.commands.push(SetRoadMaterial { road, material })
+    }
+
+    pub fn set_lane_speed_limit(&mut self, lane: LaneID, limit: f32) {
+        self.commands.push(SetLaneSpeedLimit { lane, limit })
+    }
+
+    pub fn map_set_lane_direction(&mut self, lane: LaneID, dir: LaneDirection) {
+        self.commands.push(MapSetLaneDirection { lane, dir })
+    }
+
+    pub fn map_set_roundabout(&mut self, inter: IntersectionID, radius: Option<f32>) {
+        self.commands.push(MapSetRoundabout { inter, radius })
+    }
+
+    pub fn set_ramp_meter(&mut self, lane: LaneID, interval: Option<GameDuration>) {
+        self.commands.push(SetRampMeter { lane, interval })
+    }
+
+    pub fn set_edge_portal(
+        &mut self,
+        intersection: IntersectionID,
+        portal: Option<Option<GameDuration>>,
+    ) {
+        self.commands.push(SetEdgePortal {
+            intersection,
+            portal,
+        })
+    }
+
+    pub fn add_bus_route(&mut self, line_number: u16, color: Color) {
+        self.commands.push(AddBusRoute { line_number, color })
+    }
+
+    pub fn assign_bus(&mut self, bus: VehicleID, route: Option<BusRouteID>) {
+        self.commands.push(AssignBus { bus, route })
+    }
 }
 
 impl WorldCommand {
@@ -215,8 +423,19 @@ impl WorldCommand {
             self,
             MapBuildHouse(_)
                 | MapUpdateIntersectionPolicy { .. }
+                | SetLightTiming { .. }
+                | SetRoadClosed { .. }
+                | SetRoadMaterial { .. }
+                | SetLaneSpeedLimit { .. }
+                | MapSetLaneDirection { .. }
+                | MapSetRoundabout { .. }
+                | SetRampMeter { .. }
+                | SetEdgePortal { .. }
+                | AddBusRoute { .. }
+                | AssignBus { .. }
                 | UpdateZone { .. }
                 | SetGameTime(_)
+                | SetDecisionFocus(_)
         )
     }
 
@@ -231,9 +450,68 @@ impl WorldCommand {
         }
         drop(rep);
 
+        let (player, command) = match *self {
+            FromPlayer(player, ref command) => (player, &**command),
+            _ => (PlayerID::LOCAL, self),
+        };
+        if let Some(inverse) = command.apply_inner(sim, player) {
+            sim.write::<UndoStack>().push(player, inverse);
+        }
+    }
+
+    /// Applies the command without recording it, `player` is the one whose history is undone and redone.
+    /// Returns the command undoing it if it can be undone.
+    pub(crate) fn apply_inner(
+        &self,
+        sim: &mut Simulation,
+        player: PlayerID,
+    ) -> Option<WorldCommand> {
+        let mut inverse = None;
         match *self {
-            MapRemoveIntersection(id) => sim.map_mut().remove_intersection(id),
-            MapRemoveRoad(id) => drop(sim.map_mut().remove_road(id)),
+            MapRemoveIntersection(id) => {
+                let mut map = sim.map_mut();
+                let saved: Vec<_> = map
+                    .intersections()
+                    .get(id)
+                    .map(|i| i.roads.iter().filter_map(|&r| map.save_road(r)).collect())
+                    .unwrap_or_default();
+                map.remove_intersection(id);
+                inverse = (!saved.is_empty()).then_some(MapRestoreRoads(saved));
+            }
+            MapMergeIntersections { a, b } => drop(sim.map_mut().merge_intersections(a, b)),
+            MapRemoveRoad(id) => {
+                let mut map = sim.map_mut();
+                let saved = map.save_road(id);
+                if map.remove_road(id).is_some() {
+                    inverse = saved.map(|s| MapRestoreRoads(vec![s]));
+                }
+            }
+            MapUnbuildRoads(ref roads) => {
+                let mut map = sim.map_mut();
+                let saved: Vec<_> = roads.iter().filter_map(|&r| map.unbuild_road(r)).collect();
+                inverse = (!saved.is_empty()).then_some(MapRestoreRoads(saved));
+            }
+            MapRestoreRoads(ref saved) => {
+                let mut map = sim.map_mut();
+                let roads: Vec<_> = saved.iter().filter_map(|s| map.restore_road(s)).collect();
+                inverse = (!roads.is_empty()).then_some(MapUnbuildRoads(roads));
+            }
+            MapReplaceRoads {
+                ref remove,
+                ref restore,
+            } => {
+                let mut map = sim.map_mut();
+                let removed: Vec<_> = remove.iter().filter_map(|&r| map.unbuild_road(r)).collect();
+                let restored: Vec<_> = restore.iter().filter_map(|s| map.restore_road(s)).collect();
+                inverse = Some(MapReplaceRoads {
+                    remove: restored,
+                    restore: removed,
+                });
+            }
+            Undo => undo(sim, player),
+            Redo => redo(sim, player),
+            FromPlayer(player, ref command) => inverse = command.apply_inner(sim, player),
+            MapRemoveArea { aabb } => sim.map_mut().remove_area(aabb),
             MapRemoveBuilding(id) => drop(sim.map_mut().remove_building(id)),
             MapBuildHouse(id) => {
                 if let Some(build) = sim.map_mut().build_house(id) {
@@ -247,7 +525,38 @@ impl WorldCommand {
                 inter,
                 ref pat,
             } => {
-                sim.write::<Map>().make_connection(from, to, inter, pat);
+                let mut map = sim.map_mut();
+                let split: Vec<_> = [from, to]
+                    .iter()
+                    .filter_map(|p| match p.kind {
+                        ProjectKind::Road(r) => map.save_road(r),
+                        _ => None,
+                    })
+                    .collect();
+                let r = map.make_connection(from, to, inter, pat);
+                inverse = r.map(|(_, r)| {
+                    if split.is_empty() {
+                        return MapUnbuildRoads(vec![r]);
+                    }
+                    let Some(road) = map.roads().get(r) else {
+                        return MapUnbuildRoads(vec![r]);
+                    };
+                    // the halves of the split roads are replaced by the roads as they were
+                    let mut remove = vec![r];
+                    for (end, p) in [(road.src, from), (road.dst, to)] {
+                        if !matches!(p.kind, ProjectKind::Road(_)) {
+                            continue;
+                        }
+                        let Some(inter) = map.intersections().get(end) else {
+                            continue;
+                        };
+                        remove.extend(inter.roads.iter().filter(|&&half| half != r));
+                    }
+                    MapReplaceRoads {
+                        remove,
+                        restore: split,
+                    }
+                });
             }
             MapMakeMultipleConnections(ref projects, ref links) => {
                 let mut map = sim.map_mut();
@@ -281,12 +590,63 @@ impl WorldCommand {
                 i.light_policy = lp;
                 i.turn_policy = tp;
             }),
+            SetLightTiming { inter, timing } => {
+                sim.map_mut().update_intersection(inter, move |i| {
+                    i.light_timing = timing.map(LightTiming::clamped)
+                })
+            }
+            SetRoadClosed { road, closed } => sim.map_mut().set_road_closed(road, closed),
+            SetRoadMaterial { road, material } => sim.map_mut().set_road_material(road, material),
+            SetLaneSpeedLimit { lane, limit } => sim.map_mut().set_lane_speed_limit(lane, limit),
+            MapSetLaneDirection { lane, dir } => {
+                if sim.map_mut().set_lane_direction(lane, dir) {
+                    for v in sim.world.vehicles.values_mut() {
+                        let (Some(kind), Some(end)) = (v.it.path_kind(), v.it.end_pos()) else {
+                            continue;
+                        };
+                        if v.it.goes_through(lane) {
+                            v.it = Itinerary::wait_for_reroute(kind, end);
+                        }
+                    }
+                    inverse = Some(MapSetLaneDirection {
+                        lane,
+                        dir: dir.opposite(),
+                    });
+                }
+            }
+            MapSetRoundabout { inter, radius } => {
+                let mut map = sim.map_mut();
+                if let Some(i) = map.intersections().get(inter) {
+                    let previous = i.turn_policy.roundabout.map(|rb| rb.radius);
+                    map.set_roundabout(inter, radius);
+                    inverse = Some(MapSetRoundabout {
+                        inter,
+                        radius: previous,
+                    });
+                }
+            }
+            SetRampMeter { lane, interval } => sim
+                .write::<RampMeters>()
+                .set(lane, interval.map(RampMeter::new)),
+            SetEdgePortal {
+                intersection,
+                portal,
+            } => sim
+                .write::<EdgePortals>()
+                .set(intersection, portal.map(EdgePortal::new)),
+            AddBusRoute { line_number, color } => {
+                sim.write::<BusRoutes>().add(line_number, color);
+            }
+            AssignBus { bus, route } => {
+                assign_bus(sim, bus, route);
+            }
             MapBuildSpecialBuilding {
                 pos: obb,
                 kind,
                 gen,
                 ref zone,
                 connected_road,
+                door_offset,
             } => {
                 if let Some(id) = sim.write::<Map>().build_special_building(
                     &obb,
@@ -294,11 +654,13 @@ impl WorldCommand {
                     gen,
                     zone.clone(),
                     connected_road,
+                    door_offset,
                 ) {
                     sim.write::<BuildingInfos>().insert(id);
                 }
             }
             SetGameTime(gt) => *sim.write::<GameTime>() = gt,
+            SetDecisionFocus(focus) => sim.write::<DecisionLod>().focus = focus,
             AddTrain {
                 dist: _,
                 n_wagons: _,
@@ -313,9 +675,22 @@ impl WorldCommand {
             }
 
             MapLoadParis => load_parismap(&mut sim.map_mut()),
+            MapLoadOsm(ref extract) => match import_osm(&mut sim.map_mut(), &**extract) {
+                Ok(stats) => info!("imported osm extract: {:?}", stats),
+                Err(e) => log::error!("could not import osm extract: {}", e),
+            },
             MapLoadTestField { pos, size, spacing } => {
                 load_testfield(&mut sim.map_mut(), pos, size, spacing)
             }
+            BatchRoadGrid {
+                origin,
+                nx,
+                ny,
+                spacing,
+                ref pattern,
+            } => sim
+                .map_mut()
+                .build_road_grid(origin, nx, ny, spacing, pattern),
             Init(ref opts) => {
                 if opts.save_replay {
                     let mut rep = sim.resources.write::<Replay>();
@@ -324,6 +699,8 @@ impl WorldCommand {
                     rep.push(tick, Init(opts.clone()));
                 }
 
+                *sim.write::<RandProvider>() = RandProvider::new(opts.seed);
+
                 if opts.terrain_size > 0 {
                     generate_terrain(sim, opts.terrain_size);
                 }
@@ -376,6 +753,7 @@ impl WorldCommand {
                     .terraform(tick, kind, center, radius, amount, level, slope);
             }
         }
+        inverse
     }
 }
 
@@ -410,6 +788,7 @@ fn generate_terrain(sim: &mut Simulation, size: u16) {
             },
             None,
             None,
+            0,
         )
         .is_none()
     {